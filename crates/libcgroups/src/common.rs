@@ -12,6 +12,7 @@ use oci_spec::runtime::{
     LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
 };
 
+use super::delegation::Delegation;
 use super::stats::Stats;
 use super::{systemd, v1, v2};
 
@@ -46,6 +47,10 @@ pub trait CgroupManager {
     /// Sets the freezer cgroup to the specified state
     fn freeze(&self, state: FreezerState) -> Result<(), Self::Error>;
 
+    /// Gets the current state of the freezer cgroup. A cgroup which is still
+    /// freezing counts as frozen.
+    fn freezer_state(&self) -> Result<FreezerState, Self::Error>;
+
     /// Retrieve statistics for the cgroup
     fn stats(&self) -> Result<Stats, Self::Error>;
 
@@ -105,6 +110,14 @@ impl CgroupManager for AnyCgroupManager {
         }
     }
 
+    fn freezer_state(&self) -> Result<FreezerState, Self::Error> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(m.freezer_state()?),
+            AnyCgroupManager::V1(m) => Ok(m.freezer_state()?),
+            AnyCgroupManager::V2(m) => Ok(m.freezer_state()?),
+        }
+    }
+
     fn stats(&self) -> Result<Stats, Self::Error> {
         match self {
             AnyCgroupManager::Systemd(m) => Ok(m.stats()?),
@@ -122,6 +135,18 @@ impl CgroupManager for AnyCgroupManager {
     }
 }
 
+impl AnyCgroupManager {
+    /// Probes the controllers which are delegated to the cgroup on the unified
+    /// hierarchy. Returns None for cgroup v1, where nothing is delegated.
+    pub fn delegation(&self) -> Result<Option<Delegation>, AnyManagerError> {
+        match self {
+            AnyCgroupManager::Systemd(m) => Ok(Some(m.delegation()?)),
+            AnyCgroupManager::V1(_) => Ok(None),
+            AnyCgroupManager::V2(m) => Ok(Some(m.delegation()?)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum CgroupSetup {
     Hybrid,
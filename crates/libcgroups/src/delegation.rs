@@ -0,0 +1,234 @@
+//! Detection of the controllers which are delegated to an unprivileged user on
+//! the unified hierarchy. A rootless container can only be restricted by the
+//! controllers its cgroup gets, so the resources of its spec which need other
+//! controllers are skipped instead of failing to create the container.
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use nix::unistd::{access, AccessFlags};
+use oci_spec::runtime::LinuxResources;
+
+use crate::common::{self, WrappedIoError};
+
+const CGROUP_CONTROLLERS: &str = "cgroup.controllers";
+const CGROUP_SUBTREE_CONTROL: &str = "cgroup.subtree_control";
+
+/// The controllers a cgroup can use
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Delegation {
+    controllers: BTreeSet<String>,
+}
+
+impl Delegation {
+    pub fn new<I, S>(controllers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            controllers: controllers.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Probes the controllers a cgroup, which may not exist yet, can use. If
+    /// the cgroup does not exist, its nearest existing ancestor decides: the
+    /// controllers it has can be enabled for its children if its
+    /// `cgroup.subtree_control` is writable, otherwise only the controllers
+    /// which are enabled already can be used.
+    pub fn probe(cgroup: &Path) -> Result<Self, WrappedIoError> {
+        let Some(ancestor) = cgroup
+            .ancestors()
+            .find(|path| path.join(CGROUP_CONTROLLERS).exists())
+        else {
+            return Ok(Self::default());
+        };
+
+        let controllers = common::read_cgroup_file(ancestor.join(CGROUP_CONTROLLERS))?;
+        if ancestor == cgroup {
+            return Ok(Self::new(controllers.split_whitespace()));
+        }
+
+        let subtree_control = ancestor.join(CGROUP_SUBTREE_CONTROL);
+        let writable = access(&subtree_control, AccessFlags::W_OK).is_ok();
+        if writable {
+            Ok(Self::new(controllers.split_whitespace()))
+        } else {
+            tracing::debug!(?subtree_control, "subtree control is not writable");
+            let enabled = common::read_cgroup_file(&subtree_control)?;
+            Ok(Self::new(enabled.split_whitespace()))
+        }
+    }
+
+    pub fn controllers(&self) -> impl Iterator<Item = &str> {
+        self.controllers.iter().map(String::as_str)
+    }
+
+    pub fn is_delegated(&self, controller: &str) -> bool {
+        self.controllers.contains(controller)
+    }
+
+    /// Removes the resources which need a controller that is not delegated.
+    /// Returns the resources which can be applied and a description of each
+    /// skipped one.
+    pub fn restrict(&self, resources: &LinuxResources) -> (LinuxResources, Vec<String>) {
+        let mut applied = resources.clone();
+        let mut skipped = Vec::new();
+        let mut skip = |what: &str, controller: &str| {
+            skipped.push(format!("{what} (requires the {controller} controller)"));
+        };
+
+        if let Some(cpu) = applied.cpu_mut() {
+            let has_cpu = cpu.shares().is_some()
+                || cpu.quota().is_some()
+                || cpu.period().is_some()
+                || cpu.burst().is_some()
+                || cpu.idle().is_some()
+                || cpu.realtime_runtime().is_some()
+                || cpu.realtime_period().is_some();
+            if has_cpu && !self.is_delegated("cpu") {
+                skip("cpu", "cpu");
+                cpu.set_shares(None)
+                    .set_quota(None)
+                    .set_period(None)
+                    .set_burst(None)
+                    .set_idle(None)
+                    .set_realtime_runtime(None)
+                    .set_realtime_period(None);
+            }
+            let has_cpuset = cpu.cpus().is_some() || cpu.mems().is_some();
+            if has_cpuset && !self.is_delegated("cpuset") {
+                skip("cpu.cpus and cpu.mems", "cpuset");
+                cpu.set_cpus(None).set_mems(None);
+            }
+        }
+        if applied.memory().is_some() && !self.is_delegated("memory") {
+            skip("memory", "memory");
+            applied.set_memory(None);
+        }
+        if applied.pids().is_some() && !self.is_delegated("pids") {
+            skip("pids", "pids");
+            applied.set_pids(None);
+        }
+        if applied.block_io().is_some() && !self.is_delegated("io") {
+            skip("blockIO", "io");
+            applied.set_block_io(None);
+        }
+        if applied.hugepage_limits().is_some() && !self.is_delegated("hugetlb") {
+            skip("hugepageLimits", "hugetlb");
+            applied.set_hugepage_limits(None);
+        }
+        if applied.rdma().is_some() && !self.is_delegated("rdma") {
+            skip("rdma", "rdma");
+            applied.set_rdma(None);
+        }
+        if let Some(unified) = applied.unified_mut() {
+            unified.retain(|key, _| {
+                // Files of the cgroup itself, e.g. cgroup.max.depth, don't
+                // need a controller
+                let controller = key.split('.').next().unwrap_or_default();
+                if controller == "cgroup" || self.is_delegated(controller) {
+                    return true;
+                }
+                skip(&format!("unified {key}"), controller);
+                false
+            });
+        }
+
+        (applied, skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+
+    use anyhow::Result;
+    use oci_spec::runtime::{
+        LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResourcesBuilder,
+    };
+
+    use super::*;
+    use crate::test::set_fixture;
+
+    #[test]
+    fn test_probe_existing_cgroup() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), CGROUP_CONTROLLERS, "cpu memory pids\n")?;
+
+        let delegation = Delegation::probe(tmp.path())?;
+        assert_eq!(
+            delegation.controllers().collect::<Vec<_>>(),
+            vec!["cpu", "memory", "pids"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_new_cgroup() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        set_fixture(tmp.path(), CGROUP_CONTROLLERS, "cpu io memory pids")?;
+        set_fixture(tmp.path(), CGROUP_SUBTREE_CONTROL, "memory pids")?;
+        let cgroup = tmp.path().join("user.slice").join("youki");
+
+        let delegation = Delegation::probe(&cgroup)?;
+        // The subtree control of the ancestor is writable for the tests
+        assert!(delegation.is_delegated("io"));
+        assert!(delegation.is_delegated("cpu"));
+
+        let slice = tmp.path().join("user.slice");
+        fs::create_dir(&slice)?;
+        set_fixture(&slice, CGROUP_CONTROLLERS, "pids")?;
+        set_fixture(&slice, CGROUP_SUBTREE_CONTROL, "")?;
+        let delegation = Delegation::probe(&cgroup)?;
+        assert!(!delegation.is_delegated("cpu"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_without_cgroup() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let delegation = Delegation::probe(&tmp.path().join("youki"))?;
+        assert_eq!(delegation, Delegation::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restrict() -> Result<()> {
+        let resources = LinuxResourcesBuilder::default()
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(1024u64)
+                    .cpus("0-1")
+                    .build()?,
+            )
+            .memory(LinuxMemoryBuilder::default().limit(1 << 30).build()?)
+            .pids(LinuxPidsBuilder::default().limit(100).build()?)
+            .unified(HashMap::from([
+                ("memory.high".to_owned(), "1G".to_owned()),
+                ("io.weight".to_owned(), "100".to_owned()),
+                ("cgroup.max.depth".to_owned(), "2".to_owned()),
+            ]))
+            .build()?;
+
+        let delegation = Delegation::new(["cpu", "memory", "pids"]);
+        let (applied, skipped) = delegation.restrict(&resources);
+
+        let cpu = applied.cpu().as_ref().unwrap();
+        assert_eq!(cpu.shares(), Some(1024));
+        assert_eq!(cpu.cpus(), &None);
+        assert!(applied.memory().is_some());
+        assert!(applied.pids().is_some());
+        let unified = applied.unified().as_ref().unwrap();
+        assert!(unified.contains_key("memory.high"));
+        assert!(unified.contains_key("cgroup.max.depth"));
+        assert!(!unified.contains_key("io.weight"));
+        assert_eq!(skipped.len(), 2);
+
+        let (applied, skipped) = Delegation::default().restrict(&resources);
+        assert!(applied.memory().is_none());
+        assert!(applied.pids().is_none());
+        assert_eq!(skipped.len(), 6);
+        Ok(())
+    }
+}
@@ -13,7 +13,9 @@ extern crate mockall;
 mod test;
 
 pub mod common;
+pub mod delegation;
 pub mod stats;
+pub mod sub_cgroup;
 #[cfg(feature = "systemd")]
 pub mod systemd;
 #[cfg(not(feature = "systemd"))]
@@ -9,6 +9,10 @@ pub enum SystemdManagerError {
 pub struct Manager {}
 
 impl Manager {
+    pub fn delegation(&self) -> Result<crate::delegation::Delegation, SystemdManagerError> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::Systemd(Box::new(self))
     }
@@ -33,6 +37,10 @@ impl CgroupManager for Manager {
         Err(SystemdManagerError::NotEnabled)
     }
 
+    fn freezer_state(&self) -> Result<crate::common::FreezerState, Self::Error> {
+        Err(SystemdManagerError::NotEnabled)
+    }
+
     fn stats(&self) -> Result<crate::stats::Stats, Self::Error> {
         Err(SystemdManagerError::NotEnabled)
     }
@@ -33,6 +33,10 @@ impl CgroupManager for Manager {
         Err(V1ManagerError::NotEnabled)
     }
 
+    fn freezer_state(&self) -> Result<crate::common::FreezerState, Self::Error> {
+        Err(V1ManagerError::NotEnabled)
+    }
+
     fn stats(&self) -> Result<crate::stats::Stats, Self::Error> {
         Err(V1ManagerError::NotEnabled)
     }
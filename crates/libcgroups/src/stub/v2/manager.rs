@@ -9,6 +9,10 @@ pub enum V2ManagerError {
 pub struct Manager {}
 
 impl Manager {
+    pub fn delegation(&self) -> Result<crate::delegation::Delegation, V2ManagerError> {
+        Err(V2ManagerError::NotEnabled)
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         crate::common::AnyCgroupManager::V2(self)
     }
@@ -33,6 +37,10 @@ impl CgroupManager for Manager {
         Err(V2ManagerError::NotEnabled)
     }
 
+    fn freezer_state(&self) -> Result<crate::common::FreezerState, Self::Error> {
+        Err(V2ManagerError::NotEnabled)
+    }
+
     fn stats(&self) -> Result<crate::stats::Stats, Self::Error> {
         Err(V2ManagerError::NotEnabled)
     }
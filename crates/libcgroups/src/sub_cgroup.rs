@@ -0,0 +1,332 @@
+//! Sub-cgroups of a container, e.g. to account processes which are executed in
+//! a running container separately from the container's own processes. The
+//! sub-cgroup is resolved relative to the cgroups a process currently belongs
+//! to, so it works the same no matter which cgroup manager placed the process.
+use std::fmt::Display;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use nix::unistd::Pid;
+use procfs::process::{MountInfo, Process};
+use procfs::{ProcError, ProcessCGroup};
+
+use crate::common::{self, WrappedIoError, CGROUP_PROCS};
+
+#[derive(thiserror::Error, Debug)]
+pub enum SubCgroupError {
+    #[error("sub-cgroup path must not be empty")]
+    EmptyPath,
+    #[error("sub-cgroup path {0:?} must stay below the cgroup of the container")]
+    InvalidPath(PathBuf),
+    #[error("controllers can only be specified for cgroup v1 hierarchies")]
+    ControllersOnUnified,
+    #[error("process is not in a cgroup v1 hierarchy with the {0} controller")]
+    UnknownController(String),
+    #[error("could not find the cgroup of the process")]
+    NoCgroup,
+    #[error("could not find the mount point of the cgroup hierarchy of {0:?}")]
+    NoMountPoint(Vec<String>),
+    #[error("failed to read cgroups of the process: {0}")]
+    Proc(#[from] ProcError),
+    #[error("failed to create sub-cgroup {path:?}: {err}")]
+    Create { path: PathBuf, err: std::io::Error },
+    #[error("io error: {0}")]
+    WrappedIo(#[from] WrappedIoError),
+}
+
+/// A cgroup below the cgroup of a container. It is given either as a path,
+/// which applies to the unified hierarchy or to all cgroup v1 hierarchies, or
+/// as `controller[,controller]:path` to only use the hierarchies of the
+/// specified cgroup v1 controllers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubCgroup {
+    controllers: Option<Vec<String>>,
+    path: PathBuf,
+}
+
+impl SubCgroup {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, SubCgroupError> {
+        let path = path.into();
+        if path.as_os_str().is_empty() {
+            return Err(SubCgroupError::EmptyPath);
+        }
+        // a path without a normal component, like "/", would be the cgroup
+        // of the container itself
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::RootDir))
+            || !path.components().any(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(SubCgroupError::InvalidPath(path));
+        }
+
+        Ok(Self {
+            controllers: None,
+            path,
+        })
+    }
+
+    pub fn with_controllers(mut self, controllers: Vec<String>) -> Self {
+        self.controllers = Some(controllers);
+        self
+    }
+
+    pub fn controllers(&self) -> Option<&[String]> {
+        self.controllers.as_deref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves a process into the sub-cgroup, relative to the cgroups the process
+    /// currently belongs to. The sub-cgroup is created if it doesn't exist.
+    pub fn join(&self, pid: Pid) -> Result<(), SubCgroupError> {
+        let process = Process::new(pid.as_raw())?;
+        let cgroups = process.cgroups()?.0;
+        let mounts = Process::myself()?.mountinfo()?.0;
+
+        for path in self.resolve(&cgroups, &mounts)? {
+            fs::create_dir_all(&path).map_err(|err| SubCgroupError::Create {
+                path: path.clone(),
+                err,
+            })?;
+            common::write_cgroup_file(path.join(CGROUP_PROCS), pid)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the directories of the sub-cgroup in all hierarchies it applies to
+    fn resolve(
+        &self,
+        cgroups: &[ProcessCGroup],
+        mounts: &[MountInfo],
+    ) -> Result<Vec<PathBuf>, SubCgroupError> {
+        // Named hierarchies like name=systemd don't have a controller attached
+        let legacy: Vec<&ProcessCGroup> = cgroups
+            .iter()
+            .filter(|cgroup| {
+                cgroup.hierarchy != 0 && cgroup.controllers.iter().any(|c| !c.starts_with("name="))
+            })
+            .collect();
+
+        if legacy.is_empty() {
+            if self.controllers.is_some() {
+                return Err(SubCgroupError::ControllersOnUnified);
+            }
+            let cgroup = cgroups
+                .iter()
+                .find(|cgroup| cgroup.hierarchy == 0)
+                .ok_or(SubCgroupError::NoCgroup)?;
+            let mount = mounts
+                .iter()
+                .find(|m| m.fs_type == "cgroup2")
+                .ok_or_else(|| SubCgroupError::NoMountPoint(Vec::new()))?;
+            return Ok(vec![self.cgroup_dir(mount, cgroup)]);
+        }
+
+        let selected: Vec<&ProcessCGroup> = match &self.controllers {
+            None => legacy,
+            Some(controllers) => {
+                for controller in controllers {
+                    if !legacy.iter().any(|c| c.controllers.contains(controller)) {
+                        return Err(SubCgroupError::UnknownController(controller.to_owned()));
+                    }
+                }
+                legacy
+                    .into_iter()
+                    .filter(|cgroup| cgroup.controllers.iter().any(|c| controllers.contains(c)))
+                    .collect()
+            }
+        };
+
+        selected
+            .into_iter()
+            .map(|cgroup| {
+                let mount = mounts
+                    .iter()
+                    .find(|m| {
+                        m.fs_type == "cgroup"
+                            && cgroup
+                                .controllers
+                                .iter()
+                                .all(|c| m.super_options.contains_key(c))
+                    })
+                    .ok_or_else(|| SubCgroupError::NoMountPoint(cgroup.controllers.clone()))?;
+                Ok(self.cgroup_dir(mount, cgroup))
+            })
+            .collect()
+    }
+
+    fn cgroup_dir(&self, mount: &MountInfo, cgroup: &ProcessCGroup) -> PathBuf {
+        // The cgroup path is relative to the root of the mounted hierarchy
+        let cgroup_path = Path::new(&cgroup.pathname);
+        let cgroup_path = cgroup_path.strip_prefix(&mount.root).unwrap_or(cgroup_path);
+        let sub_path = self.path.strip_prefix("/").unwrap_or(&self.path);
+        mount
+            .mount_point
+            .join(cgroup_path.strip_prefix("/").unwrap_or(cgroup_path))
+            .join(sub_path)
+    }
+}
+
+impl FromStr for SubCgroup {
+    type Err = SubCgroupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((controllers, path)) => {
+                let controllers: Vec<String> = controllers
+                    .split(',')
+                    .filter(|c| !c.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                let sub_cgroup = Self::new(path)?;
+                if controllers.is_empty() {
+                    Ok(sub_cgroup)
+                } else {
+                    Ok(sub_cgroup.with_controllers(controllers))
+                }
+            }
+            None => Self::new(s),
+        }
+    }
+}
+
+impl Display for SubCgroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(controllers) = &self.controllers {
+            write!(f, "{}:", controllers.join(","))?;
+        }
+        write!(f, "{}", self.path.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cgroup(hierarchy: u32, controllers: &[&str], pathname: &str) -> ProcessCGroup {
+        ProcessCGroup {
+            hierarchy,
+            controllers: controllers.iter().map(|c| c.to_string()).collect(),
+            pathname: pathname.to_owned(),
+        }
+    }
+
+    fn mount(line: &str) -> MountInfo {
+        MountInfo::from_line(line).unwrap()
+    }
+
+    #[test]
+    fn test_parse_sub_cgroup() {
+        let sub_cgroup: SubCgroup = "probes".parse().unwrap();
+        assert_eq!(sub_cgroup.controllers(), None);
+        assert_eq!(sub_cgroup.path(), Path::new("probes"));
+
+        let sub_cgroup: SubCgroup = "cpu,memory:probes/liveness".parse().unwrap();
+        assert_eq!(
+            sub_cgroup.controllers(),
+            Some(["cpu".to_owned(), "memory".to_owned()].as_slice())
+        );
+        assert_eq!(sub_cgroup.path(), Path::new("probes/liveness"));
+        assert_eq!(sub_cgroup.to_string(), "cpu,memory:probes/liveness");
+
+        assert!(matches!(
+            "cpu:".parse::<SubCgroup>(),
+            Err(SubCgroupError::EmptyPath)
+        ));
+        assert!(matches!(
+            "../escape".parse::<SubCgroup>(),
+            Err(SubCgroupError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_new_rejects_container_cgroup_and_escapes() {
+        assert!(matches!(SubCgroup::new(""), Err(SubCgroupError::EmptyPath)));
+        for path in [
+            "/",
+            "//",
+            ".",
+            "./",
+            "..",
+            "probes/../..",
+            "probes/../liveness",
+        ] {
+            assert!(
+                matches!(SubCgroup::new(path), Err(SubCgroupError::InvalidPath(_))),
+                "{path:?} should be rejected"
+            );
+        }
+
+        assert_eq!(
+            SubCgroup::new("/probes").unwrap().path(),
+            Path::new("/probes")
+        );
+        assert!(matches!(
+            "memory:/".parse::<SubCgroup>(),
+            Err(SubCgroupError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_unified() {
+        let cgroups = vec![cgroup(0, &[], "/youki/container")];
+        let mounts = vec![mount(
+            "35 24 0:30 / /sys/fs/cgroup rw,nosuid,nodev,noexec,relatime shared:9 - cgroup2 cgroup2 rw",
+        )];
+
+        let sub_cgroup: SubCgroup = "probes".parse().unwrap();
+        assert_eq!(
+            sub_cgroup.resolve(&cgroups, &mounts).unwrap(),
+            vec![PathBuf::from("/sys/fs/cgroup/youki/container/probes")]
+        );
+
+        let sub_cgroup: SubCgroup = "memory:probes".parse().unwrap();
+        assert!(matches!(
+            sub_cgroup.resolve(&cgroups, &mounts),
+            Err(SubCgroupError::ControllersOnUnified)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_legacy() {
+        let cgroups = vec![
+            cgroup(4, &["cpu", "cpuacct"], "/youki/container"),
+            cgroup(3, &["memory"], "/youki/container"),
+            cgroup(1, &["name=systemd"], "/youki/container"),
+            cgroup(0, &[], "/"),
+        ];
+        let mounts = vec![
+            mount("30 25 0:26 / /sys/fs/cgroup/cpu,cpuacct rw,nosuid shared:12 - cgroup cgroup rw,cpu,cpuacct"),
+            mount("31 25 0:27 / /sys/fs/cgroup/memory rw,nosuid shared:13 - cgroup cgroup rw,memory"),
+            mount("32 25 0:28 / /sys/fs/cgroup/systemd rw,nosuid shared:14 - cgroup cgroup rw,xattr,name=systemd"),
+        ];
+
+        let sub_cgroup: SubCgroup = "probes".parse().unwrap();
+        assert_eq!(
+            sub_cgroup.resolve(&cgroups, &mounts).unwrap(),
+            vec![
+                PathBuf::from("/sys/fs/cgroup/cpu,cpuacct/youki/container/probes"),
+                PathBuf::from("/sys/fs/cgroup/memory/youki/container/probes"),
+            ]
+        );
+
+        let sub_cgroup: SubCgroup = "memory:probes".parse().unwrap();
+        assert_eq!(
+            sub_cgroup.resolve(&cgroups, &mounts).unwrap(),
+            vec![PathBuf::from(
+                "/sys/fs/cgroup/memory/youki/container/probes"
+            )]
+        );
+
+        let sub_cgroup: SubCgroup = "pids:probes".parse().unwrap();
+        assert!(matches!(
+            sub_cgroup.resolve(&cgroups, &mounts),
+            Err(SubCgroupError::UnknownController(controller)) if controller == "pids"
+        ));
+    }
+}
@@ -30,9 +30,10 @@ pub enum Variant {
     U64(u64),
     ArrayU32(Vec<u32>),
     ArrayU64(Vec<u64>),
+    ArrayStructString(Vec<Structure<String>>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Structure<T: DbusSerialize> {
     key: String,
     val: T,
@@ -430,6 +431,14 @@ impl DbusSerialize for Variant {
                 buf.push(0);
                 v.serialize(buf);
             }
+            Self::ArrayStructString(v) => {
+                let sub_type = <Vec<Structure<String>>>::get_signature();
+                let signature_length = sub_type.len() as u8; // signature length must be < 256
+                buf.push(signature_length);
+                buf.extend_from_slice(sub_type.as_bytes());
+                buf.push(0);
+                v.serialize(buf);
+            }
             Self::Bool(b) => {
                 let sub_type = bool::get_signature();
                 let signature_length = sub_type.len() as u8; // signature length must be < 256
@@ -470,6 +479,7 @@ impl DbusSerialize for Variant {
         let vec32_signature = <Vec<u32>>::get_signature();
         let vec64_signature = <Vec<u64>>::get_signature();
         let u64_signature = u64::get_signature();
+        let vec_struct_string_signature = <Vec<Structure<String>>>::get_signature();
 
         if signature == string_signature {
             Ok(Self::String(String::deserialize(buf, counter)?))
@@ -481,6 +491,10 @@ impl DbusSerialize for Variant {
             Ok(Self::ArrayU64(<Vec<u64>>::deserialize(buf, counter)?))
         } else if signature == u64_signature {
             Ok(Self::U64(u64::deserialize(buf, counter)?))
+        } else if signature == vec_struct_string_signature {
+            Ok(Self::ArrayStructString(
+                <Vec<Structure<String>>>::deserialize(buf, counter)?,
+            ))
         } else {
             return Err(DbusError::IncompleteImplementation(format!(
                 "unsupported value signature {}",
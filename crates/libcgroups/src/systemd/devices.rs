@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fs;
+
+use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
+
+use super::controller::Controller;
+use super::dbus_native::serialize::{Structure, Variant};
+use crate::common::{
+    default_allow_devices, default_devices, ControllerOpt, WrapIoResult, WrappedIoError,
+};
+
+pub const DEVICE_POLICY: &str = "DevicePolicy";
+pub const DEVICE_ALLOW: &str = "DeviceAllow";
+
+const PROC_DEVICES: &str = "/proc/devices";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SystemdDevicesError {
+    #[error("failed to read device groups: {0}")]
+    DeviceGroups(#[from] WrappedIoError),
+}
+
+pub struct Devices {}
+
+impl Controller for Devices {
+    type Error = SystemdDevicesError;
+
+    fn apply(
+        options: &ControllerOpt,
+        _: u32,
+        properties: &mut HashMap<&str, Variant>,
+    ) -> Result<(), Self::Error> {
+        tracing::debug!("Applying devices resource restrictions");
+        // The default rules are added after those of the spec, like the v2
+        // manager does it, so that the program of systemd allows the same
+        // devices as ours would
+        let mut rules = options.resources.devices().clone().unwrap_or_default();
+        rules.extend(default_devices().iter().map(|d| d.into()));
+        rules.extend(default_allow_devices());
+
+        Self::apply(&rules, properties)
+    }
+}
+
+impl Devices {
+    fn apply(
+        rules: &[LinuxDeviceCgroup],
+        properties: &mut HashMap<&str, Variant>,
+    ) -> Result<(), SystemdDevicesError> {
+        let allowed = match allow_list(rules) {
+            AllowList::All => {
+                // Without any entries, systemd doesn't restrict the devices
+                properties.insert(DEVICE_POLICY, Variant::String("auto".to_owned()));
+                properties.insert(DEVICE_ALLOW, Variant::ArrayStructString(Vec::new()));
+                return Ok(());
+            }
+            AllowList::Devices(allowed) => allowed,
+            AllowList::DenyList => {
+                tracing::warn!(
+                    "systemd can't deny devices when all other devices are allowed, \
+                    not setting device properties"
+                );
+                return Ok(());
+            }
+        };
+
+        let groups = fs::read_to_string(PROC_DEVICES).wrap_read(PROC_DEVICES)?;
+        let entries = allowed
+            .iter()
+            .filter_map(|rule| device_allow_entry(rule, &groups))
+            .collect();
+
+        properties.insert(DEVICE_POLICY, Variant::String("strict".to_owned()));
+        properties.insert(DEVICE_ALLOW, Variant::ArrayStructString(entries));
+        Ok(())
+    }
+}
+
+/// The devices the rules allow, in terms systemd understands
+#[derive(Debug, PartialEq)]
+enum AllowList {
+    All,
+    Devices(Vec<LinuxDeviceCgroup>),
+    /// All devices but the denied ones, which systemd can't express
+    DenyList,
+}
+
+/// Reduces the rules to the allowed devices. The rules are applied like the
+/// BPF program of the v2 manager does it: a rule of type `a` switches between
+/// allowing and denying all devices, and later rules take precedence over
+/// earlier ones.
+fn allow_list(rules: &[LinuxDeviceCgroup]) -> AllowList {
+    let mut default_allow = false;
+    let mut effective: Vec<&LinuxDeviceCgroup> = Vec::new();
+    for rule in rules {
+        if rule.typ().unwrap_or_default() == LinuxDeviceType::A {
+            default_allow = rule.allow();
+            effective.clear();
+        } else if rule.access().is_some() {
+            effective.push(rule);
+        }
+    }
+
+    if default_allow {
+        return if effective.iter().any(|rule| !rule.allow()) {
+            AllowList::DenyList
+        } else {
+            AllowList::All
+        };
+    }
+
+    // systemd only knows allowed devices, so the access a later rule denies
+    // is removed from the rules it covers
+    let mut denied: Vec<&LinuxDeviceCgroup> = Vec::new();
+    let mut allowed = Vec::new();
+    for rule in effective.into_iter().rev() {
+        if !rule.allow() {
+            denied.push(rule);
+            continue;
+        }
+
+        let mut access = rule.access().clone().unwrap_or_default();
+        for deny in &denied {
+            if covers(deny, rule) {
+                let deny_access = deny.access().as_deref().unwrap_or_default();
+                access.retain(|c| !deny_access.contains(c));
+            } else if covers(rule, deny) {
+                tracing::warn!(
+                    ?rule,
+                    ?deny,
+                    "systemd doesn't support denying part of allowed devices, ignoring deny rule"
+                );
+            }
+        }
+        if !access.is_empty() {
+            let mut rule = rule.clone();
+            rule.set_access(Some(access));
+            allowed.push(rule);
+        }
+    }
+    allowed.reverse();
+
+    AllowList::Devices(allowed)
+}
+
+/// Whether all devices `inner` matches are matched by `outer`
+fn covers(outer: &LinuxDeviceCgroup, inner: &LinuxDeviceCgroup) -> bool {
+    let matches = |outer: Option<i64>, inner: Option<i64>| match (wildcard(outer), wildcard(inner))
+    {
+        (None, _) => true,
+        (Some(outer), Some(inner)) => outer == inner,
+        (Some(_), None) => false,
+    };
+    outer.typ() == inner.typ()
+        && matches(outer.major(), inner.major())
+        && matches(outer.minor(), inner.minor())
+}
+
+fn wildcard(number: Option<i64>) -> Option<i64> {
+    number.filter(|n| *n >= 0)
+}
+
+/// Converts a rule to an entry of DeviceAllow, i.e. the device and its access
+fn device_allow_entry(rule: &LinuxDeviceCgroup, groups: &str) -> Option<Structure<String>> {
+    let (kind, node_dir) = match rule.typ() {
+        Some(LinuxDeviceType::C) | Some(LinuxDeviceType::U) => ("char", "/dev/char"),
+        Some(LinuxDeviceType::B) => ("block", "/dev/block"),
+        _ => return None,
+    };
+    let access = rule.access().clone().unwrap_or_default();
+
+    let device = match (wildcard(rule.major()), wildcard(rule.minor())) {
+        (None, None) => format!("{kind}-*"),
+        (None, Some(_)) => {
+            tracing::warn!(
+                ?rule,
+                "systemd doesn't support device rules for all majors, ignoring rule"
+            );
+            return None;
+        }
+        (Some(major), Some(minor)) => format!("{node_dir}/{major}:{minor}"),
+        (Some(major), None) => match device_group(groups, kind, major) {
+            Some(group) => format!("{kind}-{group}"),
+            None => {
+                tracing::warn!(
+                    ?rule,
+                    "no device group of major {major} found, ignoring rule"
+                );
+                return None;
+            }
+        },
+    };
+
+    Some(Structure::new(device, access))
+}
+
+/// Finds the name of the device group of a major in /proc/devices
+fn device_group(groups: &str, kind: &str, major: i64) -> Option<String> {
+    let header = match kind {
+        "char" => "Character devices:",
+        _ => "Block devices:",
+    };
+
+    groups
+        .lines()
+        .skip_while(|line| *line != header)
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        .find_map(|line| {
+            let (number, name) = line.trim().split_once(' ')?;
+            (number.parse::<i64>().ok()? == major).then(|| name.to_owned())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::LinuxDeviceCgroupBuilder;
+
+    use super::super::dbus_native::serialize::DbusSerialize;
+    use super::*;
+    use crate::recast;
+
+    const GROUPS: &str = "Character devices:
+  1 mem
+  4 /dev/vc/0
+ 10 misc
+136 pts
+
+Block devices:
+  7 loop
+  8 sd
+";
+
+    fn rule(
+        allow: bool,
+        typ: LinuxDeviceType,
+        major: i64,
+        minor: i64,
+        access: &str,
+    ) -> LinuxDeviceCgroup {
+        LinuxDeviceCgroupBuilder::default()
+            .allow(allow)
+            .typ(typ)
+            .major(major)
+            .minor(minor)
+            .access(access)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let rules = vec![
+            rule(true, LinuxDeviceType::C, 1, 5, "rwm"),
+            rule(false, LinuxDeviceType::A, -1, -1, "rwm"),
+            rule(true, LinuxDeviceType::C, 1, 3, "rwm"),
+            rule(true, LinuxDeviceType::C, 10, -1, "rwm"),
+            rule(false, LinuxDeviceType::C, 1, 3, "w"),
+            rule(true, LinuxDeviceType::B, 8, 0, "r"),
+            rule(false, LinuxDeviceType::B, 8, 0, "r"),
+        ];
+
+        assert_eq!(
+            allow_list(&rules),
+            AllowList::Devices(vec![
+                rule(true, LinuxDeviceType::C, 1, 3, "rm"),
+                rule(true, LinuxDeviceType::C, 10, -1, "rwm"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_allow_list_allow_all() {
+        let rules = vec![rule(true, LinuxDeviceType::A, -1, -1, "rwm")];
+        assert_eq!(allow_list(&rules), AllowList::All);
+
+        let rules = vec![
+            rule(true, LinuxDeviceType::A, -1, -1, "rwm"),
+            rule(false, LinuxDeviceType::C, 1, 3, "rwm"),
+        ];
+        assert_eq!(allow_list(&rules), AllowList::DenyList);
+    }
+
+    #[test]
+    fn test_device_allow_entry() {
+        let entry = |rule| device_allow_entry(&rule, GROUPS);
+
+        assert_eq!(
+            entry(rule(true, LinuxDeviceType::C, 1, 3, "rwm")),
+            Some(Structure::new("/dev/char/1:3".into(), "rwm".into()))
+        );
+        assert_eq!(
+            entry(rule(true, LinuxDeviceType::B, 8, 0, "r")),
+            Some(Structure::new("/dev/block/8:0".into(), "r".into()))
+        );
+        assert_eq!(
+            entry(rule(true, LinuxDeviceType::C, 136, -1, "rw")),
+            Some(Structure::new("char-pts".into(), "rw".into()))
+        );
+        assert_eq!(
+            entry(rule(true, LinuxDeviceType::B, -1, -1, "m")),
+            Some(Structure::new("block-*".into(), "m".into()))
+        );
+        assert_eq!(entry(rule(true, LinuxDeviceType::C, 200, -1, "rw")), None);
+        assert_eq!(entry(rule(true, LinuxDeviceType::C, -1, 3, "rw")), None);
+    }
+
+    #[test]
+    fn test_device_group() {
+        assert_eq!(device_group(GROUPS, "char", 4), Some("/dev/vc/0".into()));
+        assert_eq!(device_group(GROUPS, "block", 7), Some("loop".into()));
+        assert_eq!(device_group(GROUPS, "block", 1), None);
+    }
+
+    #[test]
+    fn test_devices_properties() -> Result<()> {
+        let mut properties = HashMap::new();
+        Devices::apply(
+            &[rule(true, LinuxDeviceType::A, -1, -1, "rwm")],
+            &mut properties,
+        )?;
+        assert_eq!(properties[DEVICE_POLICY], Variant::String("auto".into()));
+
+        // Denying devices while allowing all others can't be expressed, which
+        // leaves the device properties unset instead of failing
+        let mut deny_list_properties = HashMap::new();
+        Devices::apply(
+            &[
+                rule(true, LinuxDeviceType::A, -1, -1, "rwm"),
+                rule(false, LinuxDeviceType::C, 1, 3, "rwm"),
+            ],
+            &mut deny_list_properties,
+        )?;
+        assert!(deny_list_properties.is_empty());
+
+        let rules = vec![
+            rule(false, LinuxDeviceType::A, -1, -1, "rwm"),
+            rule(true, LinuxDeviceType::C, 1, 3, "rwm"),
+        ];
+        Devices::apply(&rules, &mut properties)?;
+        assert_eq!(properties[DEVICE_POLICY], Variant::String("strict".into()));
+        let device_allow = &properties[DEVICE_ALLOW];
+        assert_eq!(
+            recast!(device_allow, Variant)?,
+            Variant::ArrayStructString(vec![Structure::new("/dev/char/1:3".into(), "rwm".into())])
+        );
+        Ok(())
+    }
+}
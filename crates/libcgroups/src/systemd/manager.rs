@@ -15,12 +15,14 @@ use super::cpuset::CpuSet;
 use super::dbus_native::client::SystemdClient;
 use super::dbus_native::dbus::DbusConnection;
 use super::dbus_native::utils::SystemdClientError;
+use super::devices::Devices;
 use super::memory::Memory;
 use super::pids::Pids;
 use crate::common::{
     self, AnyCgroupManager, CgroupManager, ControllerOpt, FreezerState, JoinSafelyError,
     PathBufExt, WrapIoResult, WrappedIoError,
 };
+use crate::delegation::Delegation;
 use crate::stats::Stats;
 use crate::systemd::dbus_native::serialize::Variant;
 use crate::systemd::unified::Unified;
@@ -164,6 +166,8 @@ pub enum SystemdManagerError {
     Memory(#[from] super::memory::SystemdMemoryError),
     #[error("in pids controller: {0}")]
     Pids(Infallible),
+    #[error("in devices controller: {0}")]
+    Devices(#[from] super::devices::SystemdDevicesError),
     #[error("in pids unified controller: {0}")]
     Unified(#[from] super::unified::SystemdUnifiedError),
 }
@@ -338,6 +342,12 @@ impl Manager {
         Ok(())
     }
 
+    /// Probes the controllers which are delegated to the cgroup of the
+    /// transient unit, which is below the delegation boundary
+    pub fn delegation(&self) -> Result<Delegation, SystemdManagerError> {
+        Ok(Delegation::probe(&self.full_path)?)
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::Systemd(Box::new(self))
     }
@@ -394,6 +404,9 @@ impl CgroupManager for Manager {
             };
         }
 
+        // Devices aren't a controller of cgroup v2, they are always restricted
+        Devices::apply(controller_opt, systemd_version, &mut properties)?;
+
         tracing::debug!("applying properties {:?}", properties);
         Unified::apply(controller_opt, systemd_version, &mut properties)?;
 
@@ -420,6 +433,10 @@ impl CgroupManager for Manager {
         Ok(self.fs_manager.freeze(state)?)
     }
 
+    fn freezer_state(&self) -> Result<FreezerState, Self::Error> {
+        Ok(self.fs_manager.freezer_state()?)
+    }
+
     fn stats(&self) -> Result<Stats, Self::Error> {
         Ok(self.fs_manager.stats()?)
     }
@@ -43,6 +43,10 @@ impl CgroupManager for TestManager {
         unimplemented!()
     }
 
+    fn freezer_state(&self) -> Result<FreezerState, Infallible> {
+        unimplemented!()
+    }
+
     fn stats(&self) -> Result<Stats, Infallible> {
         unimplemented!()
     }
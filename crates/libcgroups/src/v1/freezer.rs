@@ -114,6 +114,16 @@ impl Freezer {
         Ok(())
     }
 
+    pub(crate) fn state(cgroup_root: &Path) -> Result<FreezerState, V1FreezerControllerError> {
+        match Self::read_freezer_state(cgroup_root)?.trim() {
+            FREEZER_STATE_THAWED => Ok(FreezerState::Thawed),
+            FREEZER_STATE_FROZEN | FREEZER_STATE_FREEZING => Ok(FreezerState::Frozen),
+            state => Err(V1FreezerControllerError::UnexpectedState {
+                state: state.to_owned(),
+            }),
+        }
+    }
+
     fn read_freezer_state(cgroup_root: &Path) -> Result<String, WrappedIoError> {
         let path = cgroup_root.join(CGROUP_FREEZER_STATE);
         let mut content = String::new();
@@ -175,6 +185,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_freezer_state() {
+        let tmp = tempfile::tempdir().unwrap();
+        set_fixture(tmp.path(), CGROUP_FREEZER_STATE, "FREEZING\n")
+            .expect("Set fixure for freezer state");
+        assert_eq!(Freezer::state(tmp.path()).unwrap(), FreezerState::Frozen);
+
+        set_fixture(tmp.path(), CGROUP_FREEZER_STATE, "THAWED\n")
+            .expect("Set fixure for freezer state");
+        assert_eq!(Freezer::state(tmp.path()).unwrap(), FreezerState::Thawed);
+    }
+
     #[test]
     fn test_add_and_apply() {
         let tmp = tempfile::tempdir().unwrap();
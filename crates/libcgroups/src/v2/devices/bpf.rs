@@ -17,10 +17,13 @@ pub mod prog {
     use std::os::unix::io::RawFd;
     use std::ptr;
 
-    use libbpf_sys::{bpf_insn, BPF_CGROUP_DEVICE, BPF_F_ALLOW_MULTI, BPF_PROG_TYPE_CGROUP_DEVICE};
+    use libbpf_sys::{
+        bpf_insn, BPF_CGROUP_DEVICE, BPF_F_ALLOW_MULTI, BPF_F_REPLACE, BPF_PROG_TYPE_CGROUP_DEVICE,
+    };
     #[cfg(not(test))]
     use libbpf_sys::{
-        bpf_prog_attach, bpf_prog_detach2, bpf_prog_get_fd_by_id, bpf_prog_load, bpf_prog_query,
+        bpf_prog_attach, bpf_prog_attach_opts, bpf_prog_detach2, bpf_prog_get_fd_by_id,
+        bpf_prog_get_info_by_fd, bpf_prog_load, bpf_prog_query,
     };
     #[cfg(not(test))]
     use libc::setrlimit;
@@ -30,7 +33,8 @@ pub mod prog {
     // TODO: consider use of #[mockall_double]
     #[cfg(test)]
     use crate::v2::devices::mocks::mock_libbpf_sys::{
-        bpf_prog_attach, bpf_prog_detach2, bpf_prog_get_fd_by_id, bpf_prog_load, bpf_prog_query,
+        bpf_prog_attach, bpf_prog_attach_opts, bpf_prog_detach2, bpf_prog_get_fd_by_id,
+        bpf_prog_get_info_by_fd, bpf_prog_load, bpf_prog_query,
     };
     // mocks
     // TODO: consider use of #[mockall_double]
@@ -136,6 +140,72 @@ pub mod prog {
         Ok(())
     }
 
+    /// Attaches a program in place of an attached one, atomically, so that
+    /// there is no moment where neither or both of them are in effect.
+    /// Kernels before 5.6 don't support it and fail with EINVAL.
+    pub fn attach_replace(
+        prog_fd: RawFd,
+        cgroup_fd: RawFd,
+        replace_prog_fd: RawFd,
+    ) -> Result<(), super::BpfError> {
+        let mut opts = libbpf_sys::bpf_prog_attach_opts {
+            sz: std::mem::size_of::<libbpf_sys::bpf_prog_attach_opts>() as libbpf_sys::size_t,
+            flags: BPF_F_ALLOW_MULTI | BPF_F_REPLACE,
+            ..Default::default()
+        };
+        opts.__bindgen_anon_1.replace_prog_fd = replace_prog_fd;
+
+        #[allow(unused_unsafe)]
+        let ret = unsafe {
+            bpf_prog_attach_opts(
+                prog_fd,
+                cgroup_fd,
+                BPF_CGROUP_DEVICE,
+                &opts as *const libbpf_sys::bpf_prog_attach_opts,
+            )
+        };
+        if ret != 0 {
+            return Err(errno::errno().into());
+        }
+        Ok(())
+    }
+
+    /// Returns the name of a program and its instructions as the kernel
+    /// translated them. Reading the instructions needs CAP_SYS_ADMIN.
+    pub fn info(prog_fd: RawFd) -> Result<(String, Vec<u8>), super::BpfError> {
+        let mut info = libbpf_sys::bpf_prog_info::default();
+        let mut info_len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+        #[allow(unused_unsafe)]
+        let ret = unsafe { bpf_prog_get_info_by_fd(prog_fd, &mut info, &mut info_len) };
+        if ret != 0 {
+            return Err(errno::errno().into());
+        }
+
+        // the instructions are only copied with a second call, once their
+        // length is known
+        let mut insns = vec![0_u8; info.xlated_prog_len as usize];
+        let name: String = info
+            .name
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8 as char)
+            .collect();
+        let mut info = libbpf_sys::bpf_prog_info {
+            xlated_prog_len: insns.len() as u32,
+            xlated_prog_insns: insns.as_mut_ptr() as u64,
+            ..Default::default()
+        };
+        let mut info_len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+        #[allow(unused_unsafe)]
+        let ret = unsafe { bpf_prog_get_info_by_fd(prog_fd, &mut info, &mut info_len) };
+        if ret != 0 {
+            return Err(errno::errno().into());
+        }
+        insns.truncate(info.xlated_prog_len as usize);
+
+        Ok((name, insns))
+    }
+
     pub fn bump_memlock_rlimit() -> Result<(), super::BpfError> {
         let rlimit = rlimit {
             rlim_cur: 128 << 20,
@@ -198,6 +268,32 @@ mod tests {
         assert!(r.is_ok());
     }
 
+    #[test]
+    #[serial(libbpf_sys)] // mock contexts are shared
+    fn test_bpf_attach_replace() {
+        // arrange
+        let attach_opts = mock_libbpf_sys::bpf_prog_attach_opts_context();
+
+        // expect
+        attach_opts.expect().once().returning(
+            |_, _, _, opts: *const libbpf_sys::bpf_prog_attach_opts| {
+                let opts = unsafe { &*opts };
+                assert_eq!(
+                    opts.flags,
+                    libbpf_sys::BPF_F_ALLOW_MULTI | libbpf_sys::BPF_F_REPLACE
+                );
+                assert_eq!(unsafe { opts.__bindgen_anon_1.replace_prog_fd }, 7);
+                0
+            },
+        );
+
+        // act
+        let r = prog::attach_replace(0, 0, 7);
+
+        // assert
+        assert!(r.is_ok());
+    }
+
     #[test]
     #[serial(libbpf_sys)] // mock contexts are shared
     fn test_bpf_load_error() {
@@ -373,6 +469,40 @@ mod tests {
         assert!(r.is_err());
     }
 
+    #[test]
+    #[serial(libbpf_sys)] // mock contexts are shared
+    fn test_bpf_info() {
+        // arrange
+        let get_info = mock_libbpf_sys::bpf_prog_get_info_by_fd_context();
+
+        // expect
+        get_info
+            .expect()
+            .times(2)
+            .returning(|_, info: *mut libbpf_sys::bpf_prog_info, _| {
+                let info = unsafe { &mut *info };
+                if info.xlated_prog_insns == 0 {
+                    info.xlated_prog_len = 8;
+                    for (dst, src) in info.name.iter_mut().zip(b"sd_devices") {
+                        *dst = *src as std::os::raw::c_char;
+                    }
+                } else {
+                    let insns = unsafe {
+                        std::slice::from_raw_parts_mut(info.xlated_prog_insns as *mut u8, 8)
+                    };
+                    insns.copy_from_slice(&[0x95, 0, 0, 0, 0, 0, 0, 0]);
+                }
+                0
+            });
+
+        // act
+        let (name, insns) = prog::info(0).expect("Able to get program info");
+
+        // assert
+        assert_eq!(name, "sd_devices");
+        assert_eq!(insns, vec![0x95, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
     #[test]
     #[serial(libc)] // mock contexts are shared
     fn test_bump_memlock_rlimit() {
@@ -79,19 +79,6 @@ impl Devices {
         bpf_prog::bump_memlock_rlimit()?;
         let prog_fd = bpf_prog::load(LICENSE, prog.bytecodes())?;
 
-        // FIXME: simple way to attach BPF program
-        //  1. get list of existing attached programs
-        //  2. attach this program (not use BPF_F_REPLACE, see below)
-        //  3. detach all programs of 1
-        //
-        // runc will use BPF_F_REPLACE to replace currently attached program if:
-        //   1. BPF_F_REPLACE is supported by kernel
-        //   2. there is exactly one attached program
-        // https://github.com/opencontainers/runc/blob/8e6871a3b14bb74e0ef358aca3b9f8f9cb80f041/libcontainer/cgroups/ebpf/ebpf_linux.go#L165
-        //
-        // IMHO, this is too complicated, and in most cases, we just attach program once without
-        // already attached programs.
-
         // get the fd of the cgroup root
         let fd = nix::dir::Dir::open(
             cgroup_root.as_os_str(),
@@ -100,10 +87,35 @@ impl Devices {
         )?;
 
         // collect the programs attached to this cgroup
-        let old_progs = bpf_prog::query(fd.as_raw_fd())?;
-        // attach our new program
-        bpf_prog::attach(prog_fd, fd.as_raw_fd())?;
-        // detach all previous programs
+        let mut old_progs = bpf_prog::query(fd.as_raw_fd())?;
+
+        // When the rules of a running container are updated, the program
+        // attached last is replaced atomically. Attaching the new program
+        // next to it instead would restrict the container to the rules both
+        // programs allow until the old one is detached. Like runc, fall back
+        // to that on kernels without BPF_F_REPLACE.
+        // https://github.com/opencontainers/runc/blob/8e6871a3b14bb74e0ef358aca3b9f8f9cb80f041/libcontainer/cgroups/ebpf/ebpf_linux.go#L165
+        let replaced = match old_progs.last() {
+            Some(old_prog) => {
+                match bpf_prog::attach_replace(prog_fd, fd.as_raw_fd(), old_prog.fd) {
+                    Ok(()) => true,
+                    Err(BpfError::Errno(errno::Errno(libc::EINVAL))) => {
+                        tracing::debug!("BPF_F_REPLACE is not supported, attach next to program");
+                        false
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            None => false,
+        };
+        if replaced {
+            old_progs.pop();
+        } else {
+            bpf_prog::attach(prog_fd, fd.as_raw_fd())?;
+        }
+
+        // detach the stale programs, e.g. left behind by an earlier update
+        // which failed halfway
         for old_prog in old_progs {
             bpf_prog::detach2(old_prog.fd, fd.as_raw_fd())?;
         }
@@ -172,6 +184,7 @@ mod tests {
         let load = mock_prog::load_context();
         let query = mock_prog::query_context();
         let attach = mock_prog::attach_context();
+        let attach_replace = mock_prog::attach_replace_context();
         let detach2 = mock_prog::detach2_context();
         bump_memlock_rlimit.expect().once().returning(|| Ok(()));
         load.expect()
@@ -181,10 +194,58 @@ mod tests {
             .expect()
             .once()
             .returning(move |_| Ok(vec![existing_program_1.clone()]));
+        attach_replace
+            .expect()
+            .once()
+            .returning(|_, _, _| Err(BpfError::Errno(errno::Errno(libc::EINVAL))));
         attach.expect().once().returning(|_, _| Ok(()));
         detach2.expect().once().returning(|_, _| Ok(()));
 
         // act
         Devices::apply_devices(tmp.path(), &Some(vec![a_type])).expect("Could not apply devices");
     }
+
+    #[test]
+    #[serial(bpf)] // mock contexts are shared
+    fn test_replace_program() {
+        // arrange
+        let (tmp, _) = setup("some.value");
+        let a_type = LinuxDeviceCgroupBuilder::default()
+            .typ(LinuxDeviceType::A)
+            .build()
+            .unwrap();
+        let file_descriptor: RawFd = 6;
+        let stale_program = bpf::ProgramInfo { id: 1, fd: 7 };
+        let existing_program = bpf::ProgramInfo { id: 2, fd: 8 };
+
+        // expect
+        let bump_memlock_rlimit = mock_prog::bump_memlock_rlimit_context();
+        let load = mock_prog::load_context();
+        let query = mock_prog::query_context();
+        let attach = mock_prog::attach_context();
+        let attach_replace = mock_prog::attach_replace_context();
+        let detach2 = mock_prog::detach2_context();
+        bump_memlock_rlimit.expect().once().returning(|| Ok(()));
+        load.expect()
+            .once()
+            .returning(move |_, _| Ok(file_descriptor));
+        query
+            .expect()
+            .once()
+            .returning(move |_| Ok(vec![stale_program.clone(), existing_program.clone()]));
+        attach_replace
+            .expect()
+            .withf(|prog_fd, _, replace_prog_fd| *prog_fd == 6 && *replace_prog_fd == 8)
+            .once()
+            .returning(|_, _, _| Ok(()));
+        attach.expect().never();
+        detach2
+            .expect()
+            .withf(|prog_fd, _| *prog_fd == 7)
+            .once()
+            .returning(|_, _| Ok(()));
+
+        // act
+        Devices::apply_devices(tmp.path(), &Some(vec![a_type])).expect("Could not apply devices");
+    }
 }
@@ -1,4 +1,5 @@
 use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
+use serde::Serialize;
 
 // For cgroup v1 compatibility, runc implements a device emulator to calculate the final rules given
 // a list of user-defined rules.
@@ -14,6 +15,7 @@ use oci_spec::runtime::{LinuxDeviceCgroup, LinuxDeviceType};
 //
 
 // FIXME: should we use runc's implementation?
+#[derive(Debug, Clone, Serialize)]
 pub struct Emulator {
     pub default_allow: bool,
     pub rules: Vec<LinuxDeviceCgroup>,
@@ -0,0 +1,164 @@
+//! Inspection of the programs attached to the device hook of a cgroup, to
+//! find out why a device access is denied
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+#[cfg(test)]
+use bpf::mock_prog as bpf_prog;
+#[cfg(not(test))]
+use bpf::prog as bpf_prog;
+use nix::fcntl::OFlag;
+use nix::sys::stat::Mode;
+use serde::Serialize;
+
+use super::controller::DevicesControllerError;
+use super::emulator::Emulator;
+use super::program::Program;
+use super::*;
+
+/// A program attached to the device hook of a cgroup
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachedProgram {
+    pub id: u32,
+    pub name: String,
+    /// The rules of a program youki generated, `None` for a foreign one,
+    /// e.g. one of systemd
+    pub rules: Option<Emulator>,
+}
+
+impl AttachedProgram {
+    pub fn is_foreign(&self) -> bool {
+        self.rules.is_none()
+    }
+}
+
+/// Lists the programs attached to the device hook of the cgroup at
+/// `cgroup_path`, e.g. /sys/fs/cgroup/youki/74f1a4cb3801, with the rules of
+/// those youki generated.
+///
+/// # Example
+///
+/// ```no_run
+/// use libcgroups::v2::devices::inspect::attached_programs;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// for prog in attached_programs("/sys/fs/cgroup/youki/74f1a4cb3801".as_ref())? {
+///     println!("{} {} foreign: {}", prog.id, prog.name, prog.is_foreign());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn attached_programs(
+    cgroup_path: &Path,
+) -> Result<Vec<AttachedProgram>, DevicesControllerError> {
+    let fd = nix::dir::Dir::open(
+        cgroup_path.as_os_str(),
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+        Mode::from_bits(0o600).unwrap(),
+    )?;
+
+    // every fd is closed before any error is returned, so none are leaked
+    let infos: Vec<_> = bpf_prog::query(fd.as_raw_fd())?
+        .into_iter()
+        .map(|prog| {
+            let info = bpf_prog::info(prog.fd);
+            let _ = nix::unistd::close(prog.fd);
+            (prog.id, info)
+        })
+        .collect();
+
+    infos
+        .into_iter()
+        .map(|(id, info)| -> Result<_, DevicesControllerError> {
+            let (name, bytecodes) = info?;
+            Ok(AttachedProgram {
+                id,
+                name,
+                rules: Program::decompile(&bytecodes),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bpf::mock_prog;
+    use oci_spec::runtime::{LinuxDeviceCgroupBuilder, LinuxDeviceType};
+    use serial_test::serial;
+
+    use super::*;
+    use crate::test::setup;
+
+    #[test]
+    #[serial(bpf)] // mock contexts are shared
+    fn test_attached_programs() {
+        // arrange
+        let (tmp, _) = setup("some.value");
+        let rules = vec![LinuxDeviceCgroupBuilder::default()
+            .allow(true)
+            .typ(LinuxDeviceType::C)
+            .major(1)
+            .minor(3)
+            .access("rwm")
+            .build()
+            .unwrap()];
+        let youki_prog = Program::from_rules(&rules, false)
+            .unwrap()
+            .bytecodes()
+            .to_vec();
+
+        // expect
+        let query = mock_prog::query_context();
+        let info = mock_prog::info_context();
+        query.expect().once().returning(|_| {
+            Ok(vec![
+                bpf::ProgramInfo { id: 1, fd: -1 },
+                bpf::ProgramInfo { id: 2, fd: -2 },
+            ])
+        });
+        info.expect().times(2).returning(move |fd| match fd {
+            -1 => Ok(("sd_devices".to_owned(), vec![0x95, 0, 0, 0, 0, 0, 0, 0])),
+            _ => Ok((String::new(), youki_prog.clone())),
+        });
+
+        // act
+        let programs = attached_programs(tmp.path()).expect("Could not inspect programs");
+
+        // assert
+        assert_eq!(programs.len(), 2);
+        assert!(programs[0].is_foreign());
+        assert_eq!(programs[0].name, "sd_devices");
+        let emulator = programs[1].rules.as_ref().expect("program of youki");
+        assert!(!emulator.default_allow);
+        assert_eq!(emulator.rules, rules);
+    }
+
+    #[test]
+    #[serial(bpf)] // mock contexts are shared
+    fn test_attached_programs_info_fails() {
+        // arrange
+        let (tmp, _) = setup("some.value");
+
+        // expect
+        let query = mock_prog::query_context();
+        let info = mock_prog::info_context();
+        query.expect().once().returning(|_| {
+            Ok(vec![
+                bpf::ProgramInfo { id: 1, fd: -1 },
+                bpf::ProgramInfo { id: 2, fd: -2 },
+            ])
+        });
+        // the programs after a failing one are still visited, so their fds
+        // are closed
+        info.expect().times(2).returning(|fd| match fd {
+            -1 => Err(bpf::BpfError::Errno(errno::Errno(libc::EPERM))),
+            _ => Ok((String::new(), Vec::new())),
+        });
+
+        // act
+        let result = attached_programs(tmp.path());
+
+        // assert
+        assert!(matches!(result, Err(DevicesControllerError::Bpf(_))));
+    }
+}
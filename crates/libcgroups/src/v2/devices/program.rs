@@ -1,7 +1,13 @@
 use oci_spec::runtime::*;
 use rbpf::disassembler::disassemble;
+use rbpf::ebpf;
 use rbpf::insn_builder::{Arch as RbpfArch, *};
 
+use super::emulator::Emulator;
+
+/// count of the instructions `init` generates
+const INIT_INSTRUCTIONS: usize = 6;
+
 pub struct Program {
     prog: BpfCode,
 }
@@ -39,6 +45,78 @@ impl Program {
         self.prog.into_bytes()
     }
 
+    /// Recovers the rules of a program from its bytecodes, if it was
+    /// generated by [`Program::from_rules`]. Programs of others, e.g. those
+    /// systemd attaches, give `None`.
+    pub fn decompile(bytecodes: &[u8]) -> Option<Emulator> {
+        if bytecodes.len() % ebpf::INSN_SIZE != 0 {
+            return None;
+        }
+        let insns: Vec<ebpf::Insn> = (0..bytecodes.len() / ebpf::INSN_SIZE)
+            .map(|idx| ebpf::get_insn(bytecodes, idx))
+            .collect();
+
+        // the rules are checked in reversed order
+        let mut rules = Vec::new();
+        let mut idx = INIT_INSTRUCTIONS;
+        let default_allow = loop {
+            let insn = insns.get(idx)?;
+            if insn.opc == ebpf::MOV32_IMM && insn.dst == 0 {
+                break insn.imm != 0;
+            }
+
+            // if (R2 != dev_type) goto next rule
+            if insn.opc != ebpf::JNE_IMM || insn.dst != 2 {
+                return None;
+            }
+            let typ = match insn.imm as u32 {
+                libbpf_sys::BPF_DEVCG_DEV_CHAR => LinuxDeviceType::C,
+                libbpf_sys::BPF_DEVCG_DEV_BLOCK => LinuxDeviceType::B,
+                _ => return None,
+            };
+            let mut rule = LinuxDeviceCgroupBuilder::default()
+                .typ(typ)
+                .access("rwm")
+                .build()
+                .ok()?;
+            idx += 1;
+
+            // R1 = R3 & access
+            if insns.get(idx)?.opc == ebpf::MOV32_REG {
+                let and = insns.get(idx + 1)?;
+                if and.opc != ebpf::AND32_IMM {
+                    return None;
+                }
+                rule.set_access(Some(access_string(and.imm as u32)));
+                idx += 3;
+            }
+            if insns.get(idx)?.opc == ebpf::JNE_IMM && insns.get(idx)?.dst == 4 {
+                rule.set_major(Some(insns[idx].imm as i64));
+                idx += 1;
+            }
+            if insns.get(idx)?.opc == ebpf::JNE_IMM && insns.get(idx)?.dst == 5 {
+                rule.set_minor(Some(insns[idx].imm as i64));
+                idx += 1;
+            }
+
+            let allow = insns.get(idx)?;
+            if allow.opc != ebpf::MOV32_IMM || insns.get(idx + 1)?.opc != ebpf::EXIT {
+                return None;
+            }
+            rule.set_allow(allow.imm != 0);
+            rules.push(rule);
+            idx += 2;
+        };
+        rules.reverse();
+
+        // Only a program generated exactly from the rules is one of ours
+        let prog = Program::from_rules(&rules, default_allow).ok()?;
+        (prog.bytecodes() == bytecodes).then_some(Emulator {
+            default_allow,
+            rules,
+        })
+    }
+
     fn finalize(&mut self, default_allow: bool) {
         self.prog
             .mov(Source::Imm, RbpfArch::X32)
@@ -233,6 +311,18 @@ fn bpf_access(access: String) -> Result<u32, ProgramError> {
     Ok(v)
 }
 
+fn access_string(access: u32) -> String {
+    [
+        (libbpf_sys::BPF_DEVCG_ACC_READ, 'r'),
+        (libbpf_sys::BPF_DEVCG_ACC_WRITE, 'w'),
+        (libbpf_sys::BPF_DEVCG_ACC_MKNOD, 'm'),
+    ]
+    .iter()
+    .filter(|(bit, _)| access & bit != 0)
+    .map(|(_, c)| c)
+    .collect()
+}
+
 fn bpf_cgroup_dev_ctx(
     typ: LinuxDeviceType,
     major: u32,
@@ -466,4 +556,34 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_decompile() {
+        let rules = vec![
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(LinuxDeviceType::C)
+                .minor(20)
+                .access("rw")
+                .build()
+                .unwrap(),
+            LinuxDeviceCgroupBuilder::default()
+                .allow(false)
+                .typ(LinuxDeviceType::B)
+                .major(10)
+                .access("rwm")
+                .build()
+                .unwrap(),
+        ];
+        let prog = Program::from_rules(&rules, false).unwrap();
+
+        let emulator = Program::decompile(prog.bytecodes()).expect("program is decompiled");
+        assert!(!emulator.default_allow);
+        assert_eq!(emulator.rules, rules);
+
+        // a program which doesn't end like ours is foreign
+        let mut bytecodes = prog.bytecodes().to_vec();
+        bytecodes.truncate(bytecodes.len() - ebpf::INSN_SIZE);
+        assert!(Program::decompile(&bytecodes).is_none());
+    }
 }
@@ -1,4 +1,4 @@
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 use std::io::{BufRead, BufReader, Read, Seek, Write};
 use std::path::Path;
 use std::str::{self, Utf8Error};
@@ -80,6 +80,24 @@ impl Freezer {
         Ok(())
     }
 
+    /// Reads the requested state of the freezer, without waiting for a cgroup
+    /// which is still freezing
+    pub(crate) fn state(path: &Path) -> Result<FreezerState, V2FreezerError> {
+        let target = path.join(CGROUP_FREEZE);
+        match fs::read_to_string(&target) {
+            Ok(state) => match state.trim() {
+                "0" => Ok(FreezerState::Thawed),
+                "1" => Ok(FreezerState::Frozen),
+                state => Err(V2FreezerError::UnknownState {
+                    state: state.into(),
+                }),
+            },
+            // The root cgroup can't be frozen
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(FreezerState::Undefined),
+            Err(err) => Err(WrappedIoError::Read { err, path: target }.into()),
+        }
+    }
+
     fn read_freezer_state(path: &Path) -> Result<FreezerState, V2FreezerError> {
         let target = path.join(CGROUP_FREEZE);
         let mut buf = [0; 1];
@@ -197,6 +215,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_freezer_state() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert_eq!(Freezer::state(tmp.path()).unwrap(), FreezerState::Undefined);
+
+        set_fixture(tmp.path(), CGROUP_FREEZE, "1\n").expect("Set fixure for freezer state");
+        assert_eq!(Freezer::state(tmp.path()).unwrap(), FreezerState::Frozen);
+
+        set_fixture(tmp.path(), CGROUP_FREEZE, "0\n").expect("Set fixure for freezer state");
+        assert_eq!(Freezer::state(tmp.path()).unwrap(), FreezerState::Thawed);
+    }
+
     #[test]
     fn test_set_freezer_state_error() {
         let tmp = tempfile::tempdir().unwrap();
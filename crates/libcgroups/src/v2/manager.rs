@@ -25,6 +25,7 @@ use crate::common::{
     self, AnyCgroupManager, CgroupManager, ControllerOpt, FreezerState, JoinSafelyError,
     PathBufExt, WrapIoResult, WrappedIoError, CGROUP_PROCS,
 };
+use crate::delegation::Delegation;
 use crate::stats::{PidStatsError, Stats, StatsProvider};
 
 pub const CGROUP_KILL: &str = "cgroup.kill";
@@ -137,6 +138,11 @@ impl Manager {
         Ok(())
     }
 
+    /// Probes the controllers which are delegated to the cgroup
+    pub fn delegation(&self) -> Result<Delegation, V2ManagerError> {
+        Ok(Delegation::probe(&self.full_path)?)
+    }
+
     pub fn any(self) -> AnyCgroupManager {
         AnyCgroupManager::V2(self)
     }
@@ -217,6 +223,10 @@ impl CgroupManager for Manager {
         Ok(Freezer::apply(&controller_opt, &self.full_path)?)
     }
 
+    fn freezer_state(&self) -> Result<FreezerState, Self::Error> {
+        Ok(Freezer::state(&self.full_path)?)
+    }
+
     fn stats(&self) -> Result<Stats, Self::Error> {
         let mut stats = Stats::default();
 
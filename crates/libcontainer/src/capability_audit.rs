@@ -0,0 +1,415 @@
+//! Audit of the capabilities a workload actually uses, to derive a least
+//! privilege capability set for its spec. It is enabled by annotation:
+//!
+//! ```json
+//! "annotations": {
+//!     "run.oci.youki.capabilities.audit": "true"
+//! }
+//! ```
+//!
+//! A helper process traces the capability checks of the kernel with a kprobe
+//! on `cap_capable` in a tracefs instance, which only follows the init process
+//! of the container and its descendants. When the init process exits, the
+//! helper writes the capabilities of the spec which were checked to
+//! `capabilities.json` in the bundle, where it stays after the container is
+//! deleted. Checks which
+//! the kernel doesn't audit, i.e. which only probe for a capability, don't
+//! count. Tracing needs the privileges to write to tracefs, so it is not
+//! available for rootless containers.
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use caps::Capability as CapsCapability;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal::{self, SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use nix::unistd::{self, Pid};
+use oci_spec::runtime::{
+    Capabilities, Capability as SpecCapability, LinuxCapabilities, LinuxCapabilitiesBuilder,
+};
+
+use crate::capabilities::CapabilityExt;
+use crate::helper_process::{self, HelperProcessError, Readiness};
+
+/// Enables the capability audit with "true"
+pub const AUDIT_ANNOTATION: &str = "run.oci.youki.capabilities.audit";
+/// The file in the bundle the suggestion is written to
+pub const SUGGESTION_FILE: &str = "capabilities.json";
+
+const HELPER_NAME: &str = "youki:[CAPS]";
+const TRACEFS_PATHS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+/// CAP_OPT_NOAUDIT of the options of cap_capable
+const CAP_OPT_NOAUDIT: u32 = 1 << 1;
+/// How long the helper sleeps at most when the init process can't be
+/// watched with a pidfd
+const POLL_TIMEOUT_MS: u16 = 500;
+/// How long deleting a container waits for the suggestion to be written
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CapabilityAuditError {
+    #[error("tracefs is not mounted")]
+    NoTracefs,
+    #[error("failed to set up tracing in {path:?}")]
+    Tracefs { path: PathBuf, source: io::Error },
+    #[error("failed to trace capability checks")]
+    Trace(#[source] nix::Error),
+    #[error("failed to write capability suggestion to {path:?}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("capability audit helper failed")]
+    Helper(#[from] HelperProcessError),
+}
+
+type Result<T> = std::result::Result<T, CapabilityAuditError>;
+
+/// Checks if the annotations of a spec enable the audit
+pub fn enabled(annotations: Option<&std::collections::HashMap<String, String>>) -> bool {
+    annotations
+        .and_then(|annotations| annotations.get(AUDIT_ANNOTATION))
+        .map_or(false, |value| value == "true")
+}
+
+/// Starts tracing the capability checks of the init process of a container
+/// and its descendants. Returns the pid of the helper process, which writes
+/// the suggestion to the bundle once the init process exits.
+pub fn start(bundle: &Path, init_pid: Pid, requested: Option<&LinuxCapabilities>) -> Result<Pid> {
+    let tracefs = TRACEFS_PATHS
+        .iter()
+        .map(Path::new)
+        .find(|path| path.join("kprobe_events").exists())
+        .ok_or(CapabilityAuditError::NoTracefs)?;
+
+    let pid = helper_process::spawn(HELPER_NAME, &[], |readiness| {
+        run(tracefs, bundle, init_pid, requested, readiness).map_err(|err| err.to_string())
+    })?;
+    Ok(pid)
+}
+
+/// Waits for the audit helper to write its suggestion, which it does once the
+/// init process is gone. If it doesn't finish in time, it is stopped without
+/// writing one.
+pub fn wait(pid: Pid) -> Result<()> {
+    if !helper_process::wait(pid, HELPER_NAME, WAIT_TIMEOUT)? {
+        tracing::warn!(?pid, "capability audit didn't finish in time, stopping it");
+        helper_process::stop(pid, HELPER_NAME)?;
+    }
+    Ok(())
+}
+
+/// A kprobe event on cap_capable and a tracefs instance which only traces
+/// the processes of the container. Both are removed on drop.
+struct Tracing {
+    tracefs: PathBuf,
+    instance: PathBuf,
+    event: String,
+}
+
+impl Tracing {
+    fn new(tracefs: &Path, init_pid: Pid) -> Result<Self> {
+        // Event and instance are named after the helper, so that several
+        // containers can be audited at the same time
+        let name = format!("youki_{}", unistd::getpid());
+        let event = format!("{name}/cap_capable");
+        append(
+            &tracefs.join("kprobe_events"),
+            &format!("p:{event} cap_capable cap=$arg3:s32 opts=$arg4:u32\n"),
+        )?;
+
+        let mut tracing = Self {
+            tracefs: tracefs.to_owned(),
+            instance: PathBuf::new(),
+            event,
+        };
+        let instance = tracefs.join("instances").join(&name);
+        fs::create_dir(&instance).map_err(|err| CapabilityAuditError::Tracefs {
+            path: instance.clone(),
+            source: err,
+        })?;
+        tracing.instance = instance;
+
+        // Processes forked by traced processes are traced too
+        write(&tracing.instance.join("options/event-fork"), "1")?;
+        write(
+            &tracing.instance.join("set_event_pid"),
+            &init_pid.to_string(),
+        )?;
+        write(
+            &tracing
+                .instance
+                .join("events")
+                .join(&tracing.event)
+                .join("enable"),
+            "1",
+        )?;
+        Ok(tracing)
+    }
+
+    fn trace_pipe(&self) -> Result<File> {
+        let path = self.instance.join("trace_pipe");
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .map_err(|err| CapabilityAuditError::Tracefs { path, source: err })
+    }
+}
+
+impl Drop for Tracing {
+    fn drop(&mut self) {
+        if !self.instance.as_os_str().is_empty() {
+            let enable = self
+                .instance
+                .join("events")
+                .join(&self.event)
+                .join("enable");
+            let _ = write(&enable, "0");
+            let _ = fs::remove_dir(&self.instance);
+        }
+        let _ = append(
+            &self.tracefs.join("kprobe_events"),
+            &format!("-:{}\n", self.event),
+        );
+    }
+}
+
+fn write(path: &Path, value: &str) -> Result<()> {
+    fs::write(path, value).map_err(|err| CapabilityAuditError::Tracefs {
+        path: path.to_owned(),
+        source: err,
+    })
+}
+
+fn append(path: &Path, value: &str) -> Result<()> {
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(value.as_bytes()))
+        .map_err(|err| CapabilityAuditError::Tracefs {
+            path: path.to_owned(),
+            source: err,
+        })
+}
+
+/// Returns the capability checked by a line of the trace, e.g.
+/// `cat-42 [001] ..... 12.34: cap_capable: (cap_capable+0x0/0x80) cap=21 opts=0`
+fn parse_check(line: &str) -> Option<CapsCapability> {
+    let field = |name: &str| {
+        line.split_whitespace()
+            .find_map(|field| field.strip_prefix(name))
+    };
+    let cap: u8 = field("cap=")?.parse().ok()?;
+    let opts: u32 = field("opts=")?.parse().ok()?;
+    if opts & CAP_OPT_NOAUDIT != 0 {
+        return None;
+    }
+    caps::all().into_iter().find(|c| c.index() == cap)
+}
+
+/// Restricts each requested set to the checked capabilities. Without
+/// requested capabilities, the checked ones are suggested.
+fn suggest(
+    checked: &HashSet<CapsCapability>,
+    requested: Option<&LinuxCapabilities>,
+) -> LinuxCapabilities {
+    let checked: Capabilities = checked
+        .iter()
+        .map(|c| SpecCapability::from_cap(*c))
+        .collect();
+    let restrict = |set: &Option<Capabilities>| -> Capabilities {
+        set.as_ref()
+            .map(|set| set.intersection(&checked).copied().collect())
+            .unwrap_or_default()
+    };
+
+    let mut builder = LinuxCapabilitiesBuilder::default();
+    builder = match requested {
+        Some(requested) => builder
+            .bounding(restrict(requested.bounding()))
+            .effective(restrict(requested.effective()))
+            .permitted(restrict(requested.permitted()))
+            .inheritable(restrict(requested.inheritable()))
+            .ambient(restrict(requested.ambient())),
+        None => builder
+            .bounding(checked.clone())
+            .effective(checked.clone())
+            .permitted(checked)
+            .inheritable(Capabilities::new())
+            .ambient(Capabilities::new()),
+    };
+    builder.build().unwrap_or_default()
+}
+
+fn run(
+    tracefs: &Path,
+    bundle: &Path,
+    init_pid: Pid,
+    requested: Option<&LinuxCapabilities>,
+    readiness: &Readiness,
+) -> Result<()> {
+    // SIGTERM stops the audit, the tracing has to be removed then
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGTERM);
+    mask.thread_block().map_err(CapabilityAuditError::Trace)?;
+    let sigfd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+        .map_err(CapabilityAuditError::Trace)?;
+
+    let pidfd = helper_process::pidfd_open(init_pid);
+    let tracing = Tracing::new(tracefs, init_pid)?;
+    let mut pipe = tracing.trace_pipe()?;
+    readiness.ready()?;
+
+    let mut checked = HashSet::new();
+    let mut pending = String::new();
+    let mut read_checks = |pipe: &mut File| {
+        let mut buf = [0u8; 4096];
+        loop {
+            match pipe.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => pending.push_str(&String::from_utf8_lossy(&buf[..n])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+        while let Some(end) = pending.find('\n') {
+            if let Some(cap) = parse_check(&pending[..end]) {
+                checked.insert(cap);
+            }
+            pending.drain(..=end);
+        }
+    };
+
+    loop {
+        let (stopped, init_exited) = {
+            let mut fds = vec![
+                PollFd::new(pipe.as_fd(), PollFlags::POLLIN),
+                PollFd::new(sigfd.as_fd(), PollFlags::POLLIN),
+            ];
+            if let Some(pidfd) = &pidfd {
+                fds.push(PollFd::new(pidfd.as_fd(), PollFlags::POLLIN));
+            }
+            match poll(&mut fds, PollTimeout::from(POLL_TIMEOUT_MS)) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(CapabilityAuditError::Trace(err)),
+            }
+
+            let ready = |fd: &PollFd| fd.revents().map_or(false, |revents| !revents.is_empty());
+            let init_exited = match &pidfd {
+                Some(_) => ready(&fds[2]),
+                None => signal::kill(init_pid, None) == Err(Errno::ESRCH),
+            };
+            (ready(&fds[1]), init_exited)
+        };
+        read_checks(&mut pipe);
+
+        if stopped {
+            return Ok(());
+        }
+        if init_exited {
+            break;
+        }
+    }
+    drop(tracing);
+
+    write_suggestion(bundle, &suggest(&checked, requested))
+}
+
+fn write_suggestion(bundle: &Path, suggestion: &LinuxCapabilities) -> Result<()> {
+    let path = bundle.join(SUGGESTION_FILE);
+    let json =
+        serde_json::to_vec_pretty(&suggestion).map_err(|err| CapabilityAuditError::Write {
+            path: path.clone(),
+            source: err.into(),
+        })?;
+    fs::write(&path, json).map_err(|err| CapabilityAuditError::Write { path, source: err })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_check() {
+        let line =
+            "ping-4242 [003] ..... 1234.5678: cap_capable: (cap_capable+0x0/0x80) cap=13 opts=0";
+        assert_eq!(parse_check(line), Some(CapsCapability::CAP_NET_RAW));
+        // Checks which are not audited only probe for the capability
+        let line = "ls-1 [000] ..... 1.0: cap_capable: (cap_capable+0x0/0x80) cap=21 opts=2";
+        assert_eq!(parse_check(line), None);
+        assert_eq!(parse_check("# tracer: nop"), None);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let checked = HashSet::from([CapsCapability::CAP_NET_RAW, CapsCapability::CAP_SYS_ADMIN]);
+        let requested = LinuxCapabilitiesBuilder::default()
+            .bounding(HashSet::from([
+                SpecCapability::NetRaw,
+                SpecCapability::Chown,
+            ]))
+            .effective(HashSet::from([SpecCapability::NetRaw]))
+            .build()
+            .unwrap();
+
+        let suggestion = suggest(&checked, Some(&requested));
+        assert_eq!(
+            suggestion.bounding(),
+            &Some(HashSet::from([SpecCapability::NetRaw]))
+        );
+        assert_eq!(
+            suggestion.effective(),
+            &Some(HashSet::from([SpecCapability::NetRaw]))
+        );
+        assert_eq!(suggestion.ambient(), &Some(HashSet::new()));
+
+        let suggestion = suggest(&checked, None);
+        assert_eq!(suggestion.permitted().as_ref().map(HashSet::len), Some(2));
+    }
+
+    #[test]
+    fn test_enabled() {
+        let mut annotations = std::collections::HashMap::new();
+        assert!(!enabled(None));
+        assert!(!enabled(Some(&annotations)));
+        annotations.insert(AUDIT_ANNOTATION.to_owned(), "true".to_owned());
+        assert!(enabled(Some(&annotations)));
+    }
+
+    #[test]
+    fn test_suggestion_survives_delete() -> anyhow::Result<()> {
+        use crate::container::{Container, ContainerStatus};
+
+        let bundle = tempfile::tempdir()?;
+        let root = tempfile::tempdir()?;
+        let container_root = root.path().join("audited");
+        fs::create_dir(&container_root)?;
+        let mut container = Container::new(
+            "audited",
+            ContainerStatus::Stopped,
+            None,
+            bundle.path(),
+            &container_root,
+        )?;
+        // Any process but the helper counts as finished
+        container.set_capability_audit_pid(Some(unistd::getpid()));
+        container.save()?;
+
+        let checked = HashSet::from([CapsCapability::CAP_NET_RAW]);
+        write_suggestion(bundle.path(), &suggest(&checked, None))?;
+        container.delete(false)?;
+
+        assert!(!container_root.exists());
+        let suggestion: LinuxCapabilities =
+            serde_json::from_slice(&fs::read(bundle.path().join(SUGGESTION_FILE))?)?;
+        assert_eq!(
+            suggestion.bounding(),
+            &Some(HashSet::from([SpecCapability::NetRaw]))
+        );
+        Ok(())
+    }
+}
@@ -31,11 +31,22 @@ pub enum ConfigError {
     },
     #[error("missing linux in spec")]
     MissingLinux,
+    #[error("failed to save effective spec to {path:?}")]
+    SaveSpec {
+        source: oci_spec::OciSpecError,
+        path: PathBuf,
+    },
+    #[error("failed to load effective spec from {path:?}")]
+    LoadSpec {
+        source: oci_spec::OciSpecError,
+        path: PathBuf,
+    },
 }
 
 type Result<T> = std::result::Result<T, ConfigError>;
 
 const YOUKI_CONFIG_NAME: &str = "youki_config.json";
+const EFFECTIVE_SPEC_NAME: &str = "spec.json";
 
 /// A configuration for passing information obtained during container creation to other commands.
 /// Keeping the information to a minimum improves performance.
@@ -44,6 +55,14 @@ const YOUKI_CONFIG_NAME: &str = "youki_config.json";
 pub struct YoukiConfig {
     pub hooks: Option<Hooks>,
     pub cgroup_path: PathBuf,
+    /// If the cgroup is managed by systemd. It is kept here as well as in the
+    /// state, so that a container with a corrupt state can still be cleaned up.
+    #[serde(default)]
+    pub use_systemd: bool,
+    /// Timeout in seconds of the hooks which don't set a timeout themselves.
+    /// It is kept here rather than in the state, which the hooks are given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook_timeout: Option<u64>,
 }
 
 impl YoukiConfig {
@@ -57,6 +76,8 @@ impl YoukiConfig {
                     .cgroups_path(),
                 container_id,
             ),
+            use_systemd: false,
+            hook_timeout: None,
         })
     }
 
@@ -96,6 +117,28 @@ impl YoukiConfig {
     }
 }
 
+/// The spec a container was created with, after youki resolved and adjusted
+/// it, e.g. the absolute rootfs, the cgroup path and the resources a rootless
+/// container could apply. Commands which run after create use it instead of
+/// the config.json of the bundle, which may have changed or be gone since.
+pub fn save_effective_spec<P: AsRef<Path>>(spec: &Spec, path: P) -> Result<()> {
+    let path = path.as_ref().join(EFFECTIVE_SPEC_NAME);
+    spec.save(&path)
+        .map_err(|err| ConfigError::SaveSpec { source: err, path })
+}
+
+/// Loads the effective spec of a container. Returns `None` for containers
+/// which were created before youki saved it.
+pub fn load_effective_spec<P: AsRef<Path>>(path: P) -> Result<Option<Spec>> {
+    let path = path.as_ref().join(EFFECTIVE_SPEC_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Spec::load(&path)
+        .map(Some)
+        .map_err(|err| ConfigError::LoadSpec { source: err, path })
+}
+
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
@@ -121,10 +164,22 @@ mod tests {
         let container_id = "sample";
         let tmp = tempfile::tempdir().expect("create temp dir");
         let spec = Spec::default();
-        let config = YoukiConfig::from_spec(&spec, container_id)?;
+        let mut config = YoukiConfig::from_spec(&spec, container_id)?;
+        config.hook_timeout = Some(30);
         config.save(&tmp)?;
         let act = YoukiConfig::load(&tmp)?;
         assert_eq!(act, config);
         Ok(())
     }
+
+    #[test]
+    fn test_effective_spec_save_and_load() -> Result<()> {
+        let tmp = tempfile::tempdir().expect("create temp dir");
+        assert_eq!(load_effective_spec(&tmp)?, None);
+
+        let spec = Spec::default();
+        save_effective_spec(&spec, &tmp)?;
+        assert_eq!(load_effective_spec(&tmp)?, Some(spec));
+        Ok(())
+    }
 }
@@ -0,0 +1,311 @@
+//! Receiving side of the console socket. The init process sends the master of
+//! the pty of the container over the console socket (see [`crate::tty`]),
+//! the caller of [`with_console_socket`] can use these helpers to receive it
+//! and to drive the terminal.
+//!
+//! [`with_console_socket`]: crate::container::builder::ContainerBuilder::with_console_socket
+use std::fs::{self, File};
+use std::io::{self, IoSliceMut, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags, UnixAddr};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConsoleError {
+    #[error("failed to bind console socket {path:?}")]
+    Bind { path: PathBuf, source: io::Error },
+    #[error("failed to accept connection on console socket")]
+    Accept(#[source] io::Error),
+    #[error("failed to receive pty master")]
+    Receive(#[source] nix::Error),
+    #[error("no pty master was sent over the console socket")]
+    NoPtyMaster,
+    #[error("failed to duplicate pty master")]
+    Clone(#[source] io::Error),
+    #[error("failed to get window size")]
+    GetWindowSize(#[source] nix::Error),
+    #[error("failed to set window size")]
+    SetWindowSize(#[source] nix::Error),
+    #[error("failed to poll pty master")]
+    Poll(#[source] nix::Error),
+    #[error("failed to copy terminal I/O")]
+    Copy(#[source] io::Error),
+}
+
+type Result<T> = std::result::Result<T, ConsoleError>;
+
+/// A listening console socket, which is removed when dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::console::ConsoleSocket;
+///
+/// let socket = ConsoleSocket::bind("/run/example/console.sock")?;
+/// // Pass socket.path() to ContainerBuilder::with_console_socket and create
+/// // the container, then
+/// let console = socket.accept()?;
+/// console.copy_output(&mut std::io::stdout())?;
+/// # Ok::<(), libcontainer::console::ConsoleError>(())
+/// ```
+#[derive(Debug)]
+pub struct ConsoleSocket {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl ConsoleSocket {
+    pub fn bind<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let listener = UnixListener::bind(&path).map_err(|err| ConsoleError::Bind {
+            path: path.clone(),
+            source: err,
+        })?;
+
+        Ok(Self { path, listener })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts the connection of the container process and receives the pty
+    /// master over it. The pty master is sent right after connecting, so this
+    /// can be called once the container has been created.
+    pub fn accept(&self) -> Result<Console> {
+        let (stream, _) = self.listener.accept().map_err(ConsoleError::Accept)?;
+        receive_pty_master(&stream).map(Console::new)
+    }
+}
+
+impl Drop for ConsoleSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Receives the pty master from a connected console socket.
+pub fn receive_pty_master<F: AsRawFd>(socket: &F) -> Result<OwnedFd> {
+    // The name of the pty is sent along with the fd, it isn't used
+    let mut buf = [0u8; 4096];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+    let msg = recvmsg::<UnixAddr>(
+        socket.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buf),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(ConsoleError::Receive)?;
+
+    let mut master = None;
+    for cmsg in msg.cmsgs().map_err(ConsoleError::Receive)? {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg {
+            for fd in fds {
+                // SAFETY: the fds were just received and are owned by nobody
+                // else. Only the first one is kept, the others are closed.
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                master.get_or_insert(fd);
+            }
+        }
+    }
+
+    master.ok_or(ConsoleError::NoPtyMaster)
+}
+
+/// Size of a terminal window in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+nix::ioctl_read_bad!(get_window_size, libc::TIOCGWINSZ, libc::winsize);
+nix::ioctl_write_ptr_bad!(set_window_size, libc::TIOCSWINSZ, libc::winsize);
+
+/// Returns the window size of a terminal, e.g. the one on stdin
+pub fn window_size<F: AsFd>(terminal: F) -> Result<WindowSize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    unsafe { get_window_size(terminal.as_fd().as_raw_fd(), &mut size) }
+        .map_err(ConsoleError::GetWindowSize)?;
+
+    Ok(WindowSize {
+        rows: size.ws_row,
+        cols: size.ws_col,
+    })
+}
+
+/// The pty master of a container process
+#[derive(Debug)]
+pub struct Console {
+    master: File,
+}
+
+impl Console {
+    pub fn new(master: OwnedFd) -> Self {
+        Self {
+            master: File::from(master),
+        }
+    }
+
+    pub fn master(&self) -> BorrowedFd<'_> {
+        self.master.as_fd()
+    }
+
+    /// Returns a console for the same pty master, e.g. to copy input and
+    /// output from different threads
+    pub fn try_clone(&self) -> Result<Self> {
+        let master = self.master.try_clone().map_err(ConsoleError::Clone)?;
+        Ok(Self { master })
+    }
+
+    pub fn window_size(&self) -> Result<WindowSize> {
+        window_size(&self.master)
+    }
+
+    pub fn resize(&self, size: WindowSize) -> Result<()> {
+        let size = libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        unsafe { set_window_size(self.master.as_raw_fd(), &size) }
+            .map_err(ConsoleError::SetWindowSize)?;
+        Ok(())
+    }
+
+    /// Applies the window size of another terminal, e.g. after a SIGWINCH
+    pub fn resize_from<F: AsFd>(&self, terminal: F) -> Result<()> {
+        self.resize(window_size(terminal)?)
+    }
+
+    /// Copies the input to the terminal until the reader is exhausted.
+    pub fn copy_input<R: Read>(&self, reader: &mut R) -> Result<u64> {
+        io::copy(reader, &mut &self.master).map_err(ConsoleError::Copy)
+    }
+
+    /// Copies the output of the terminal until it is hung up, i.e. all
+    /// processes holding the pty slave have closed it.
+    pub fn copy_output<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let mut buf = [0u8; 4096];
+        let mut copied = 0;
+        loop {
+            let n = match (&self.master).read(&mut buf) {
+                Ok(0) => return Ok(copied),
+                Ok(n) => n,
+                // Reading the master fails with EIO once the slave is closed
+                Err(err) if err.raw_os_error() == Some(libc::EIO) => return Ok(copied),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ConsoleError::Copy(err)),
+            };
+            writer.write_all(&buf[..n]).map_err(ConsoleError::Copy)?;
+            writer.flush().map_err(ConsoleError::Copy)?;
+            copied += n as u64;
+        }
+    }
+
+    /// Checks if the terminal was hung up, without waiting.
+    pub fn is_hung_up(&self) -> Result<bool> {
+        let mut fds = [PollFd::new(self.master.as_fd(), PollFlags::empty())];
+        poll(&mut fds, PollTimeout::ZERO).map_err(ConsoleError::Poll)?;
+        Ok(fds[0]
+            .revents()
+            .map_or(false, |revents| revents.contains(PollFlags::POLLHUP)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use anyhow::Result;
+    use nix::pty::{openpty, OpenptyResult};
+    use nix::sys::socket::{sendmsg, ControlMessage};
+
+    use super::*;
+
+    fn send_pty_master(path: &Path, pty: &OpenptyResult) -> Result<()> {
+        let stream = UnixStream::connect(path)?;
+        let fds = [pty.master.as_raw_fd()];
+        sendmsg::<UnixAddr>(
+            stream.as_raw_fd(),
+            &[io::IoSlice::new(b"/dev/ptmx")],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let socket = ConsoleSocket::bind(tmp.path().join("console.sock"))?;
+        let pty = openpty(None, None)?;
+        send_pty_master(socket.path(), &pty)?;
+
+        let console = socket.accept()?;
+        let mut slave = File::from(pty.slave);
+        slave.write_all(b"hello")?;
+        let mut buf = [0u8; 5];
+        (&console.master).read_exact(&mut buf)?;
+        assert_eq!(&buf, b"hello");
+
+        let path = socket.path().to_owned();
+        drop(socket);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_pty_master() -> Result<()> {
+        let (sender, receiver) = UnixStream::pair()?;
+        sendmsg::<UnixAddr>(
+            sender.as_raw_fd(),
+            &[io::IoSlice::new(b"/dev/ptmx")],
+            &[] as &[ControlMessage],
+            MsgFlags::empty(),
+            None,
+        )?;
+        assert!(matches!(
+            receive_pty_master(&receiver),
+            Err(ConsoleError::NoPtyMaster)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resize() -> Result<()> {
+        let pty = openpty(None, None)?;
+        let console = Console::new(pty.master);
+        let size = WindowSize {
+            rows: 42,
+            cols: 120,
+        };
+        console.resize(size)?;
+        assert_eq!(console.window_size()?, size);
+        // The slave sees the size set on the master
+        assert_eq!(window_size(&pty.slave)?, size);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_output_until_hangup() -> Result<()> {
+        let pty = openpty(None, None)?;
+        let console = Console::new(pty.master);
+        let mut slave = File::from(pty.slave);
+        slave.write_all(b"output")?;
+        assert!(!console.is_hung_up()?);
+        drop(slave);
+        assert!(console.is_hung_up()?);
+
+        let mut output = Vec::new();
+        assert_eq!(console.copy_output(&mut output)?, 6);
+        assert_eq!(output, b"output");
+        Ok(())
+    }
+}
@@ -31,6 +31,8 @@ pub struct ContainerBuilder {
     pub stdout: Option<OwnedFd>,
     // RawFd set to stderr of the container init process.
     pub stderr: Option<OwnedFd>,
+    /// W3C trace context (traceparent) the container processes should join
+    pub(super) trace_context: Option<String>,
 }
 
 /// Builder that can be used to configure the common properties of
@@ -80,6 +82,7 @@ impl ContainerBuilder {
             stdin: None,
             stdout: None,
             stderr: None,
+            trace_context: None,
         }
     }
 
@@ -336,17 +339,42 @@ impl ContainerBuilder {
         self.stderr = Some(stderr.into());
         self
     }
+
+    /// Sets the W3C trace context (`traceparent`) of the caller. It is passed
+    /// to the intermediate and init processes, which record it on their root
+    /// spans so that a tracing exporter can join them to the caller's trace.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .with_trace_context(Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"));
+    /// ```
+    pub fn with_trace_context<S: Into<String>>(mut self, trace_context: Option<S>) -> Self {
+        self.trace_context = trace_context.map(|c| c.into());
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::os::fd::AsRawFd;
     use std::path::PathBuf;
 
     use anyhow::{Context, Result};
     use nix::unistd::pipe;
+    use oci_spec::runtime::{LinuxNamespaceBuilder, LinuxNamespaceType, Spec};
 
     use crate::container::builder::ContainerBuilder;
+    use crate::id_allocator::IdAllocator;
+    use crate::network;
+    use crate::subid::{IdMappingPolicy, SubIdRange};
     use crate::syscall::syscall::SyscallType;
 
     #[test]
@@ -446,4 +474,48 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_failed_create_releases_ids() -> Result<()> {
+        let root_path = tempfile::tempdir()?;
+        let bundle = tempfile::tempdir()?;
+        std::fs::create_dir(bundle.path().join("rootfs"))?;
+        let mut spec = Spec::default();
+        let linux = spec.linux_mut().as_mut().context("no linux in spec")?;
+        let mut namespaces = linux.namespaces().clone().unwrap_or_default();
+        namespaces.push(
+            LinuxNamespaceBuilder::default()
+                .typ(LinuxNamespaceType::User)
+                .build()?,
+        );
+        linux.set_namespaces(Some(namespaces));
+        // creating the container fails once the ids are allocated
+        spec.set_annotations(Some(HashMap::from([(
+            network::NETWORK_ANNOTATION.to_owned(),
+            "bridge".to_owned(),
+        )])));
+        spec.save(bundle.path().join("config.json"))?;
+
+        // the pool has room for a single block only
+        let allocator = || {
+            IdAllocator::new(
+                root_path.path(),
+                vec![SubIdRange {
+                    start: 100000,
+                    count: 10,
+                }],
+            )
+            .with_block_size(10)
+        };
+        let result = ContainerBuilder::new("failed".to_owned(), SyscallType::default())
+            .with_root_path(root_path.path())?
+            .as_init(bundle.path())
+            .with_id_mapping_policy(IdMappingPolicy::Auto(allocator()))
+            .build();
+
+        assert!(result.is_err());
+        assert!(!root_path.path().join("failed").exists());
+        assert!(allocator().allocate("another").is_ok());
+        Ok(())
+    }
 }
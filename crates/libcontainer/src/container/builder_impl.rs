@@ -4,19 +4,25 @@ use std::os::fd::{AsRawFd, OwnedFd};
 use std::path::PathBuf;
 use std::rc::Rc;
 
-use libcgroups::common::CgroupManager;
+use libcgroups::common::{CgroupConfig, CgroupManager};
+use libcgroups::sub_cgroup::SubCgroup;
+use nix::sys::signal::{self, Signal};
 use nix::unistd::Pid;
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{LinuxNamespaceType, LinuxResources, Spec};
 
 use super::{Container, ContainerStatus};
 use crate::error::{CreateContainerError, LibcontainerError, MissingSpecError};
+use crate::hooks::HookPhase;
+use crate::network::{self, NetworkError, SlirpConfig};
 use crate::notify_socket::NotifyListener;
 use crate::process::args::{ContainerArgs, ContainerType};
 use crate::process::intel_rdt::delete_resctrl_subdirectory;
 use crate::process::{self};
 use crate::syscall::syscall::SyscallType;
+use crate::timing::{Phase, PhaseProcess, StartupTimings};
 use crate::user_ns::UserNamespaceConfig;
 use crate::workload::Executor;
+use crate::{capability_audit, config, id_allocator};
 use crate::{hooks, utils};
 
 pub(super) struct ContainerBuilderImpl {
@@ -59,12 +65,21 @@ pub(super) struct ContainerBuilderImpl {
     pub stderr: Option<OwnedFd>,
     // Indicate if the init process should be a sibling of the main process.
     pub as_sibling: bool,
+    /// W3C trace context the container processes should join
+    pub trace_context: Option<String>,
+    /// Sub-cgroup of the container a tenant process should be placed in
+    pub sub_cgroup: Option<SubCgroup>,
+    /// If the cgroup of the container is frozen
+    pub cgroup_frozen: bool,
 }
 
 impl ContainerBuilderImpl {
-    pub(super) fn create(&mut self) -> Result<Pid, LibcontainerError> {
-        match self.run_container() {
-            Ok(pid) => Ok(pid),
+    /// Creates the container process and returns its pid, together with the
+    /// startup phases it went through
+    pub(super) fn create(&mut self) -> Result<(Pid, StartupTimings), LibcontainerError> {
+        let mut timings = StartupTimings::new(PhaseProcess::Main);
+        match self.run_container(&mut timings) {
+            Ok(pid) => Ok((pid, timings)),
             Err(outer) => {
                 // Only the init container should be cleaned up in the case of
                 // an error.
@@ -83,7 +98,7 @@ impl ContainerBuilderImpl {
         matches!(self.container_type, ContainerType::InitContainer)
     }
 
-    fn run_container(&mut self) -> Result<Pid, LibcontainerError> {
+    fn run_container(&mut self, timings: &mut StartupTimings) -> Result<Pid, LibcontainerError> {
         let linux = self.spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
         let cgroup_config = libcgroups::common::CgroupConfig {
@@ -97,13 +112,54 @@ impl ContainerBuilderImpl {
             .as_ref()
             .ok_or(MissingSpecError::Process)?;
 
+        // A rootless container can only be restricted by the controllers
+        // delegated to the user, the resources which need other controllers
+        // are skipped instead of failing to create the container.
+        let rootless = utils::rootless_required().map_err(LibcontainerError::OtherIO)?;
+        let (resources, applied_resources) = match linux.resources() {
+            Some(resources) if rootless && self.is_init_container() => {
+                let applied = delegated_resources(&cgroup_config, resources);
+                (Some(applied.clone()), Some(applied))
+            }
+            resources => (resources.clone(), None),
+        };
+
+        // The built-in network is set up for the init container only, exec'ed
+        // processes share its network namespace.
+        let network = match (self.is_init_container(), self.spec.annotations()) {
+            (true, Some(annotations)) => SlirpConfig::from_annotations(annotations)?,
+            _ => None,
+        };
+        if network.is_some() {
+            let new_network_ns = linux.namespaces().as_ref().map_or(false, |namespaces| {
+                namespaces
+                    .iter()
+                    .any(|ns| ns.typ() == LinuxNamespaceType::Network && ns.path().is_none())
+            });
+            if !new_network_ns {
+                return Err(NetworkError::NoNetworkNamespace.into());
+            }
+        }
+
+        let audit =
+            self.is_init_container() && capability_audit::enabled(self.spec.annotations().as_ref());
+        let audit = if audit && rootless {
+            tracing::warn!("capability audit is not available for rootless containers");
+            false
+        } else {
+            audit
+        };
+
         if matches!(self.container_type, ContainerType::InitContainer) {
             if let Some(hooks) = self.spec.hooks() {
-                hooks::run_hooks(
-                    hooks.create_runtime().as_ref(),
-                    self.container.as_ref(),
-                    None,
-                )?
+                timings.measure(Phase::CreateRuntimeHooks, || {
+                    hooks::run_hooks(
+                        hooks.create_runtime().as_ref(),
+                        HookPhase::CreateRuntime,
+                        self.container.as_ref(),
+                        None,
+                    )
+                })?
             }
         }
 
@@ -168,6 +224,9 @@ impl ContainerBuilderImpl {
             container: self.container.to_owned(),
             user_ns_config: self.user_ns_config.to_owned(),
             cgroup_config,
+            resources,
+            sub_cgroup: self.sub_cgroup.clone(),
+            cgroup_frozen: self.cgroup_frozen,
             detached: self.detached,
             executor: self.executor.clone(),
             no_pivot: self.no_pivot,
@@ -175,15 +234,48 @@ impl ContainerBuilderImpl {
             stdout: self.stdout.as_ref().map(|x| x.as_raw_fd()),
             stderr: self.stderr.as_ref().map(|x| x.as_raw_fd()),
             as_sibling: self.as_sibling,
+            network: network.clone(),
+            trace_context: self.trace_context.clone(),
         };
 
-        let (init_pid, need_to_clean_up_intel_rdt_dir) =
-            process::container_main_process::container_main_process(&container_args).map_err(
-                |err| {
+        let (init_pid, need_to_clean_up_intel_rdt_dir, network_tap) =
+            process::container_main_process::container_main_process(&container_args, timings)
+                .map_err(|err| {
                     tracing::error!("failed to run container process {}", err);
-                    LibcontainerError::MainProcess(err)
-                },
-            )?;
+                    LibcontainerError::from(err)
+                })?;
+
+        let network_helper_pid = match (&network, network_tap) {
+            (Some(config), Some(tap)) => {
+                let pid = network::start(config, tap, init_pid).map_err(|err| {
+                    tracing::error!(?err, "failed to start network helper");
+                    // The init process would wait forever for the start
+                    let _ = signal::kill(init_pid, Signal::SIGKILL);
+                    err
+                })?;
+                Some(pid)
+            }
+            _ => None,
+        };
+
+        // The audit starts before the container does, so that it sees all
+        // capability checks of the workload
+        let capability_audit_pid = match &self.container {
+            Some(container) if audit => {
+                let pid = capability_audit::start(
+                    container.bundle(),
+                    init_pid,
+                    process.capabilities().as_ref(),
+                )
+                .map_err(|err| {
+                    tracing::error!(?err, "failed to start capability audit");
+                    let _ = signal::kill(init_pid, Signal::SIGKILL);
+                    err
+                })?;
+                Some(pid)
+            }
+            _ => None,
+        };
 
         // if file to write the pid to is specified, write pid of the child
         if let Some(pid_file) = &self.pid_file {
@@ -194,12 +286,30 @@ impl ContainerBuilderImpl {
         }
 
         if let Some(container) = &mut self.container {
+            // Later commands use the spec as it was applied, the config.json
+            // of the bundle may change or be removed
+            let mut effective_linux = linux.clone();
+            effective_linux
+                .set_cgroups_path(Some(container_args.cgroup_config.cgroup_path.clone()));
+            if applied_resources.is_some() {
+                effective_linux.set_resources(applied_resources.clone());
+            }
+            let mut effective_spec = (*self.spec).clone();
+            effective_spec.set_linux(Some(effective_linux));
+            config::save_effective_spec(&effective_spec, &container.root).map_err(|err| {
+                tracing::error!(?err, "failed to save effective spec");
+                err
+            })?;
+
             // update status and pid of the container process
             container
                 .set_status(ContainerStatus::Created)
                 .set_creator(nix::unistd::geteuid().as_raw())
                 .set_pid(init_pid.as_raw())
                 .set_clean_up_intel_rdt_directory(need_to_clean_up_intel_rdt_dir)
+                .set_network_helper_pid(network_helper_pid)
+                .set_capability_audit_pid(capability_audit_pid)
+                .set_applied_resources(applied_resources)
                 .save()?;
         }
 
@@ -209,16 +319,16 @@ impl ContainerBuilderImpl {
     fn cleanup_container(&self) -> Result<(), LibcontainerError> {
         let linux = self.spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
         let cgroups_path = utils::get_cgroup_path(linux.cgroups_path(), &self.container_id);
-        let cmanager =
-            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
-                cgroup_path: cgroups_path,
-                systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
-                container_name: self.container_id.to_string(),
-            })?;
-
         let mut errors = Vec::new();
 
-        if let Err(e) = cmanager.remove() {
+        let removed = libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+            cgroup_path: cgroups_path,
+            systemd_cgroup: self.use_systemd || self.user_ns_config.is_some(),
+            container_name: self.container_id.to_string(),
+        })
+        .map_err(LibcontainerError::from)
+        .and_then(|cmanager| cmanager.remove().map_err(LibcontainerError::from));
+        if let Err(e) = removed {
             tracing::error!(error = ?e, "failed to remove cgroup manager");
             errors.push(e.to_string());
         }
@@ -231,6 +341,24 @@ impl ContainerBuilderImpl {
                 }
             }
 
+            // the ids of an automatic user namespace are allocated before the
+            // container is created
+            if let Some(root_path) = container.root.parent() {
+                if let Err(e) = id_allocator::release(root_path, container.id()) {
+                    tracing::error!(id = ?container.id(), error = ?e, "failed to release user namespace ids");
+                    errors.push(e.to_string());
+                }
+            }
+
+            // the overlay of a shifted rootfs must be unmounted before the
+            // directory holding it is removed
+            if let Some(shifted) = container.shifted_rootfs() {
+                if let Err(e) = shifted.remove() {
+                    tracing::error!(id = ?container.id(), error = ?e, "failed to remove shifted rootfs");
+                    errors.push(e.to_string());
+                }
+            }
+
             if container.root.exists() {
                 if let Err(e) = fs::remove_dir_all(&container.root) {
                     tracing::error!(container_root = ?container.root, error = ?e, "failed to delete container root");
@@ -249,3 +377,30 @@ impl ContainerBuilderImpl {
         Ok(())
     }
 }
+
+/// Restricts the resources to those the controllers delegated to the cgroup
+/// can enforce. If the delegation can't be probed, the resources are applied
+/// as requested, as they were before.
+fn delegated_resources(cgroup_config: &CgroupConfig, resources: &LinuxResources) -> LinuxResources {
+    let delegation = libcgroups::common::create_cgroup_manager(cgroup_config.clone())
+        .map_err(|err| err.to_string())
+        .and_then(|manager| manager.delegation().map_err(|err| err.to_string()));
+    let delegation = match delegation {
+        Ok(Some(delegation)) => delegation,
+        // Nothing is delegated on cgroup v1
+        Ok(None) => return resources.clone(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to probe delegated cgroup controllers");
+            return resources.clone();
+        }
+    };
+
+    let (applied, skipped) = delegation.restrict(resources);
+    for resource in skipped {
+        tracing::warn!(
+            controllers = ?delegation.controllers().collect::<Vec<_>>(),
+            "skipping {resource}, the controller is not delegated to the rootless container"
+        );
+    }
+    applied
+}
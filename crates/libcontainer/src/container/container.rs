@@ -2,15 +2,19 @@ use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use nix::unistd::Pid;
+use oci_spec::runtime::{LinuxResources, Spec};
 use procfs::process::Process;
 
-use crate::config::YoukiConfig;
+use crate::config::{self, YoukiConfig};
 use crate::container::{ContainerStatus, State};
 use crate::error::LibcontainerError;
+use crate::rootfs::ShiftedRootfs;
 use crate::syscall::syscall::create_syscall;
+use crate::timing::{PhaseProcess, StartupTimings};
 
 /// Structure representing the container data
 #[derive(Debug, Clone)]
@@ -19,6 +23,12 @@ pub struct Container {
     pub state: State,
     // indicated the directory for the root path in the container
     pub root: PathBuf,
+    // startup phases the container went through in this process, they are
+    // not persisted
+    pub(crate) startup_timings: StartupTimings,
+    // timeout of the hooks which don't set a timeout themselves, it is
+    // persisted in the config of the container
+    pub(crate) hook_timeout: Option<Duration>,
 }
 
 impl Default for Container {
@@ -26,6 +36,8 @@ impl Default for Container {
         Self {
             state: State::default(),
             root: PathBuf::from("/run/youki"),
+            startup_timings: StartupTimings::new(PhaseProcess::Main),
+            hook_timeout: None,
         }
     }
 }
@@ -51,6 +63,8 @@ impl Container {
         Ok(Self {
             state,
             root: container_root,
+            startup_timings: StartupTimings::new(PhaseProcess::Main),
+            hook_timeout: None,
         })
     }
 
@@ -58,6 +72,12 @@ impl Container {
         &self.state.id
     }
 
+    /// Returns the startup phases the container went through when it was
+    /// created or started by this process
+    pub fn startup_timings(&self) -> &StartupTimings {
+        &self.startup_timings
+    }
+
     pub fn can_start(&self) -> bool {
         self.state.status.can_start()
     }
@@ -139,6 +159,58 @@ impl Container {
         self.state.clean_up_intel_rdt_subdirectory
     }
 
+    pub fn set_hook_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.hook_timeout = timeout;
+        self
+    }
+
+    /// Timeout of the hooks which don't set a timeout themselves
+    pub fn hook_timeout(&self) -> Option<Duration> {
+        self.hook_timeout
+    }
+
+    pub fn set_network_helper_pid(&mut self, pid: Option<Pid>) -> &mut Self {
+        self.state.network_helper_pid = pid.map(Pid::as_raw);
+        self
+    }
+
+    /// Pid of the helper process providing the built-in network
+    pub fn network_helper_pid(&self) -> Option<Pid> {
+        self.state.network_helper_pid.map(Pid::from_raw)
+    }
+
+    pub fn set_capability_audit_pid(&mut self, pid: Option<Pid>) -> &mut Self {
+        self.state.capability_audit_pid = pid.map(Pid::as_raw);
+        self
+    }
+
+    /// Pid of the helper process auditing the capabilities of the container
+    pub fn capability_audit_pid(&self) -> Option<Pid> {
+        self.state.capability_audit_pid.map(Pid::from_raw)
+    }
+
+    pub fn set_applied_resources(&mut self, resources: Option<LinuxResources>) -> &mut Self {
+        self.state.applied_resources = resources;
+        self
+    }
+
+    /// Resources applied to the cgroup of a rootless container, if they had
+    /// to be restricted to the delegated controllers
+    pub fn applied_resources(&self) -> Option<&LinuxResources> {
+        self.state.applied_resources.as_ref()
+    }
+
+    pub fn set_shifted_rootfs(&mut self, shifted: Option<ShiftedRootfs>) -> &mut Self {
+        self.state.shifted_rootfs = shifted;
+        self
+    }
+
+    /// Rootfs whose ownership was shifted for the user namespace of the
+    /// container, which is removed on delete
+    pub fn shifted_rootfs(&self) -> Option<&ShiftedRootfs> {
+        self.state.shifted_rootfs.as_ref()
+    }
+
     pub fn status(&self) -> ContainerStatus {
         self.state.status
     }
@@ -196,6 +268,8 @@ impl Container {
         let mut container = Self {
             state,
             root: container_root,
+            startup_timings: StartupTimings::new(PhaseProcess::Main),
+            hook_timeout: None,
         };
         container.refresh_status()?;
         Ok(container)
@@ -212,6 +286,23 @@ impl Container {
         let spec = YoukiConfig::load(&self.root)?;
         Ok(spec)
     }
+
+    /// The spec the container was created with, after youki applied its
+    /// defaults and adjustments. For containers created before it was
+    /// saved, the config.json of the bundle is loaded instead.
+    pub fn effective_spec(&self) -> Result<Spec, LibcontainerError> {
+        if let Some(spec) = config::load_effective_spec(&self.root)? {
+            return Ok(spec);
+        }
+
+        let spec_path = self.bundle().join("config.json");
+        let mut spec = Spec::load(&spec_path).map_err(|err| {
+            tracing::error!(path = ?spec_path, ?err, "failed to load spec");
+            err
+        })?;
+        spec.canonicalize_rootfs(self.bundle())?;
+        Ok(spec)
+    }
 }
 
 /// Checkpoint parameter structure
@@ -231,6 +322,7 @@ mod tests {
     use serial_test::serial;
 
     use super::*;
+    use crate::rootfs::RootfsShift;
 
     #[test]
     fn test_get_set_pid() {
@@ -282,6 +374,54 @@ mod tests {
         assert_eq!(container.state.annotations, Some(annotations));
     }
 
+    #[test]
+    fn test_get_set_hook_timeout() {
+        let mut container = Container::default();
+        assert_eq!(container.hook_timeout(), None);
+        container.set_hook_timeout(Some(Duration::from_secs(30)));
+        assert_eq!(container.hook_timeout(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_get_set_network_helper_pid() {
+        let mut container = Container::default();
+        assert_eq!(container.network_helper_pid(), None);
+        container.set_network_helper_pid(Some(Pid::from_raw(42)));
+        assert_eq!(container.network_helper_pid(), Some(Pid::from_raw(42)));
+    }
+
+    #[test]
+    fn test_get_set_capability_audit_pid() {
+        let mut container = Container::default();
+        assert_eq!(container.capability_audit_pid(), None);
+        container.set_capability_audit_pid(Some(Pid::from_raw(42)));
+        assert_eq!(container.capability_audit_pid(), Some(Pid::from_raw(42)));
+    }
+
+    #[test]
+    fn test_get_set_shifted_rootfs() {
+        let mut container = Container::default();
+        assert!(container.shifted_rootfs().is_none());
+        let shifted = ShiftedRootfs {
+            mode: RootfsShift::Overlay,
+            dir: PathBuf::from("/run/youki/container/rootfs-shift"),
+            rootfs: PathBuf::from("/run/youki/container/rootfs-shift/merged"),
+        };
+        container.set_shifted_rootfs(Some(shifted.clone()));
+        assert_eq!(container.shifted_rootfs(), Some(&shifted));
+    }
+
+    #[test]
+    fn test_get_set_applied_resources() {
+        let mut container = Container::default();
+        assert!(container.applied_resources().is_none());
+        container.set_applied_resources(Some(LinuxResources::default()));
+        assert_eq!(
+            container.applied_resources(),
+            Some(&LinuxResources::default())
+        );
+    }
+
     #[test]
     fn test_get_set_systemd() {
         let mut container = Container::default();
@@ -343,6 +483,33 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_effective_spec() -> Result<()> {
+        let bundle = tempfile::tempdir()?;
+        fs::create_dir(bundle.path().join("rootfs"))?;
+        Spec::default().save(bundle.path().join("config.json"))?;
+        let root = tempfile::tempdir()?;
+        let container = Container::new(
+            "container_id",
+            ContainerStatus::Created,
+            None,
+            bundle.path(),
+            root.path(),
+        )?;
+
+        // Without a saved spec, the one of the bundle is used
+        let spec = container.effective_spec()?;
+        let rootfs = spec.root().as_ref().unwrap().path();
+        assert_eq!(rootfs, &fs::canonicalize(bundle.path().join("rootfs"))?);
+
+        let mut effective = Spec::default();
+        effective.set_hostname(Some("effective".to_owned()));
+        config::save_effective_spec(&effective, root.path())?;
+        fs::remove_file(bundle.path().join("config.json"))?;
+        assert_eq!(container.effective_spec()?, effective);
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_get_set_refresh_status() -> Result<()> {
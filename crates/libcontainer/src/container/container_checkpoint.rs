@@ -5,7 +5,6 @@ use std::os::unix::io::AsRawFd;
 use libcgroups::common::CgroupSetup::{Hybrid, Legacy};
 #[cfg(feature = "v1")]
 use libcgroups::common::DEFAULT_CGROUP_ROOT;
-use oci_spec::runtime::Spec;
 
 use super::{Container, ContainerStatus};
 use crate::container::container::CheckpointOptions;
@@ -42,9 +41,8 @@ impl Container {
         // if it does not know that these bind mounts are coming from the outside of the container.
         // This information is needed during restore again. The external location of the bind
         // mounts can change and CRIU will just mount whatever we tell it to mount based on
-        // information found in 'config.json'.
-        let source_spec_path = self.bundle().join("config.json");
-        let spec = Spec::load(source_spec_path)?;
+        // information found in the spec of the container.
+        let spec = self.effective_spec()?;
         let mounts = spec.mounts().clone();
         for m in mounts.unwrap_or_default() {
             match m.typ().as_deref() {
@@ -1,14 +1,19 @@
 use std::fs;
+use std::time::Duration;
 
 use libcgroups::common::CgroupManager;
 use libcgroups::{self};
 use nix::sys::signal;
 
 use super::{Container, ContainerStatus};
+use crate::capability_audit;
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
-use crate::hooks;
+use crate::hooks::{self, HookPhase};
+use crate::id_allocator;
+use crate::network;
 use crate::process::intel_rdt::delete_resctrl_subdirectory;
+use crate::rootfs::RootfsError;
 
 impl Container {
     /// Deletes the container
@@ -77,34 +82,26 @@ impl Container {
             }
         }
 
+        if let Some(pid) = self.network_helper_pid() {
+            if let Err(err) = network::stop(pid) {
+                tracing::warn!("failed to stop network helper due to: {err:?}, continue to delete");
+            }
+        }
+
+        // The init process is gone, so the audit writes its suggestion now
+        if let Some(pid) = self.capability_audit_pid() {
+            if let Err(err) = capability_audit::wait(pid) {
+                tracing::warn!(
+                    "failed to wait for capability audit due to: {err:?}, continue to delete"
+                );
+            }
+        }
+
         if self.root.exists() {
             match YoukiConfig::load(&self.root) {
                 Ok(config) => {
                     tracing::debug!("config: {:?}", config);
-
-                    // remove the cgroup created for the container
-                    // check https://man7.org/linux/man-pages/man7/cgroups.7.html
-                    // creating and removing cgroups section for more information on cgroups
-                    let cmanager = libcgroups::common::create_cgroup_manager(
-                        libcgroups::common::CgroupConfig {
-                            cgroup_path: config.cgroup_path.to_owned(),
-                            systemd_cgroup: self.systemd(),
-                            container_name: self.id().to_string(),
-                        },
-                    )?;
-                    cmanager.remove().map_err(|err| {
-                        tracing::error!(cgroup_path = ?config.cgroup_path, "failed to remove cgroup due to: {err:?}");
-                        err
-                    })?;
-
-                    if let Some(hooks) = config.hooks.as_ref() {
-                        hooks::run_hooks(hooks.poststop().as_ref(), Some(self), None).map_err(
-                            |err| {
-                                tracing::error!(err = ?err, "failed to run post stop hooks");
-                                err
-                            },
-                        )?;
-                    }
+                    self.clean_up_config(&config, force)?;
                 }
                 Err(err) => {
                     // There is a brief window where the container state is
@@ -117,6 +114,23 @@ impl Container {
                 }
             }
 
+            // the overlay of a shifted rootfs must be unmounted before the
+            // directory holding it is removed
+            if let Some(shifted) = self.shifted_rootfs() {
+                shifted.remove().map_err(|err| {
+                    tracing::error!(?err, "failed to remove shifted rootfs");
+                    RootfsError::from(err)
+                })?;
+            }
+
+            if let Some(root_path) = self.root.parent() {
+                if let Err(err) = id_allocator::release(root_path, self.id()) {
+                    tracing::warn!(
+                        "failed to release user namespace ids due to: {err:?}, continue to delete"
+                    );
+                }
+            }
+
             // remove the directory storing container state
             tracing::debug!("remove dir {:?}", self.root);
             fs::remove_dir_all(&self.root).map_err(|err| {
@@ -127,4 +141,61 @@ impl Container {
 
         Ok(())
     }
+
+    /// Removes the cgroup of the container and runs its poststop hooks, as
+    /// recorded in its config. If forced, failures are only logged, so that a
+    /// container which is half gone can still be deleted.
+    pub(super) fn clean_up_config(
+        &mut self,
+        config: &YoukiConfig,
+        force: bool,
+    ) -> Result<(), LibcontainerError> {
+        self.set_hook_timeout(config.hook_timeout.map(Duration::from_secs));
+
+        let tolerate = |err: LibcontainerError, what: &str| {
+            if force {
+                tracing::warn!("failed to {what} due to: {err:?}, continue to delete");
+                Ok(())
+            } else {
+                Err(err)
+            }
+        };
+
+        // remove the cgroup created for the container
+        // check https://man7.org/linux/man-pages/man7/cgroups.7.html
+        // creating and removing cgroups section for more information on cgroups
+        let removed = libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+            cgroup_path: config.cgroup_path.to_owned(),
+            systemd_cgroup: self.systemd(),
+            container_name: self.id().to_string(),
+        })
+        .map_err(LibcontainerError::from)
+        .and_then(|cmanager| {
+            cmanager.remove().map_err(|err| {
+                tracing::error!(cgroup_path = ?config.cgroup_path, "failed to remove cgroup due to: {err:?}");
+                LibcontainerError::from(err)
+            })
+        });
+        if let Err(err) = removed {
+            tolerate(err, "remove cgroup")?;
+        }
+
+        if let Some(hooks) = config.hooks.as_ref() {
+            let ran = hooks::run_hooks(
+                hooks.poststop().as_ref(),
+                HookPhase::Poststop,
+                Some(self),
+                None,
+            )
+            .map_err(|err| {
+                tracing::error!(err = ?err, "failed to run post stop hooks");
+                LibcontainerError::from(err)
+            });
+            if let Err(err) = ran {
+                tolerate(err, "run post stop hooks")?;
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,394 @@
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use nix::errno::Errno;
+use nix::mount::MntFlags;
+use nix::sched::CloneFlags;
+use nix::sys::stat::{self, SFlag};
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{self, ForkResult};
+use oci_spec::runtime::{
+    LinuxDevice, LinuxDeviceBuilder, LinuxDeviceCgroup, LinuxDeviceCgroupBuilder, LinuxDeviceType,
+    LinuxNamespaceType, LinuxResources,
+};
+
+use super::Container;
+use crate::config;
+use crate::error::LibcontainerError;
+use crate::rootfs::Device;
+use crate::syscall::syscall::create_syscall;
+use crate::utils;
+
+/// MOVE_MOUNT_F_EMPTY_PATH of move_mount(2), which libc doesn't define yet
+const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeviceHotplugError {
+    #[error("device hot-plug is not supported for rootless containers")]
+    Rootless,
+    #[error("device path {0:?} is not absolute")]
+    RelativePath(PathBuf),
+    #[error("{0:?} is not a device")]
+    NotADevice(PathBuf),
+    #[error("device {0:?} is not in the container")]
+    UnknownDevice(PathBuf),
+    #[error("failed to inspect device {path:?}")]
+    Stat { path: PathBuf, source: nix::Error },
+    #[error("failed to clone the mount of device {path:?}")]
+    CloneMount { path: PathBuf, source: nix::Error },
+    #[error("failed to open the mount namespace of the container")]
+    MountNamespace(#[source] std::io::Error),
+    #[error("failed to fork into the mount namespace of the container")]
+    Fork(#[source] nix::Error),
+    #[error("failed to change device in the container: {0}")]
+    Container(String),
+}
+
+type Result<T> = std::result::Result<T, DeviceHotplugError>;
+
+impl Container {
+    /// Adds a device of the host to the running container, at the same path,
+    /// which has to be absolute. The device node is created in the mount
+    /// namespace of the container, or bind mounted there if the container
+    /// has a user namespace, in which device nodes can't be used. Joining
+    /// the mount namespace requires root, so rootless containers are
+    /// rejected. The device cgroup of the container allows
+    /// the device afterwards, and the device is recorded in the effective
+    /// spec of the container.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// container.add_device("/dev/fuse".as_ref())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_device(&mut self, host_path: &Path) -> std::result::Result<(), LibcontainerError> {
+        self.refresh_status()?;
+        if !self.can_kill() {
+            tracing::error!(status = ?self.status(), id = ?self.id(), "cannot add device to container");
+            return Err(LibcontainerError::IncorrectStatus);
+        }
+        ensure_root()?;
+
+        let device = host_device(host_path)?;
+        let spec = self.effective_spec()?;
+        let bind = spec
+            .linux()
+            .as_ref()
+            .and_then(|linux| linux.namespaces().as_ref())
+            .map_or(false, |namespaces| {
+                namespaces
+                    .iter()
+                    .any(|ns| ns.typ() == LinuxNamespaceType::User)
+            });
+
+        let pid = self.pid().ok_or(LibcontainerError::Other(
+            "container process pid not found in state".into(),
+        ))?;
+        if bind {
+            // The device has to be mounted from the mount namespace of the
+            // host, it isn't visible in the one of the container
+            let tree = clone_mount(host_path).map_err(|err| DeviceHotplugError::CloneMount {
+                path: host_path.to_owned(),
+                source: err,
+            })?;
+            let path = device.path().to_owned();
+            in_mount_namespace(pid, || bind_device(&tree, &path))?;
+        } else {
+            let device = device.clone();
+            in_mount_namespace(pid, || {
+                Device::new()
+                    .create_devices(Path::new("/"), [&device], false)
+                    .map_err(|err| format!("{err:?}"))
+            })?;
+        }
+
+        // A device node the cgroup doesn't allow yet can't be opened, so it
+        // is allowed only once the node is there
+        let mut rules = device_rules(&spec);
+        rules.retain(|rule| !matches_device(rule, &device));
+        rules.push(device_rule(&device, true));
+        if let Err(err) = self.update_device_rules(rules) {
+            let path = device.path().to_owned();
+            let _ = in_mount_namespace(pid, || remove_device_node(&path));
+            return Err(err);
+        }
+
+        self.record_device(Some(device), host_path)?;
+        tracing::debug!("device {:?} added to container {}", host_path, self.id());
+        Ok(())
+    }
+
+    /// Removes a device from the running container. The device cgroup of
+    /// the container denies the device before its node is removed.
+    pub fn remove_device(&mut self, path: &Path) -> std::result::Result<(), LibcontainerError> {
+        self.refresh_status()?;
+        if !self.can_kill() {
+            tracing::error!(status = ?self.status(), id = ?self.id(), "cannot remove device from container");
+            return Err(LibcontainerError::IncorrectStatus);
+        }
+        ensure_root()?;
+
+        let spec = self.effective_spec()?;
+        let device = spec
+            .linux()
+            .as_ref()
+            .and_then(|linux| linux.devices().as_ref())
+            .and_then(|devices| devices.iter().find(|device| device.path() == path))
+            .cloned()
+            .ok_or_else(|| DeviceHotplugError::UnknownDevice(path.to_owned()))?;
+
+        let mut rules = device_rules(&spec);
+        rules.retain(|rule| !matches_device(rule, &device));
+        rules.push(device_rule(&device, false));
+        self.update_device_rules(rules)?;
+
+        let pid = self.pid().ok_or(LibcontainerError::Other(
+            "container process pid not found in state".into(),
+        ))?;
+        let path = device.path().to_owned();
+        in_mount_namespace(pid, || remove_device_node(&path))?;
+
+        self.record_device(None, &path)?;
+        tracing::debug!("device {:?} removed from container {}", path, self.id());
+        Ok(())
+    }
+
+    fn update_device_rules(
+        &mut self,
+        rules: Vec<LinuxDeviceCgroup>,
+    ) -> std::result::Result<(), LibcontainerError> {
+        let mut resources = LinuxResources::default();
+        resources.set_devices(Some(rules));
+        self.update(&resources)
+    }
+
+    /// Adds a device to the effective spec, or removes the one at `path`
+    fn record_device(
+        &self,
+        device: Option<LinuxDevice>,
+        path: &Path,
+    ) -> std::result::Result<(), LibcontainerError> {
+        let mut spec = self.effective_spec()?;
+        if let Some(linux) = spec.linux_mut() {
+            let mut devices = linux.devices().clone().unwrap_or_default();
+            devices.retain(|d| d.path() != path);
+            devices.extend(device);
+            linux.set_devices(Some(devices));
+        }
+        config::save_effective_spec(&spec, &self.root)?;
+        Ok(())
+    }
+}
+
+/// Rootless callers can neither join the mount namespace of the container
+/// nor change its device cgroup
+fn ensure_root() -> std::result::Result<(), LibcontainerError> {
+    if utils::rootless_required().map_err(LibcontainerError::OtherIO)? {
+        tracing::error!("device hot-plug requires root");
+        return Err(DeviceHotplugError::Rootless.into());
+    }
+    Ok(())
+}
+
+/// Describes a device node of the host
+fn host_device(path: &Path) -> Result<LinuxDevice> {
+    // The device is added at the same path in the container
+    if !path.is_absolute() {
+        return Err(DeviceHotplugError::RelativePath(path.to_owned()));
+    }
+    let st = stat::stat(path).map_err(|err| DeviceHotplugError::Stat {
+        path: path.to_owned(),
+        source: err,
+    })?;
+    let typ = match SFlag::from_bits_truncate(st.st_mode & SFlag::S_IFMT.bits()) {
+        SFlag::S_IFCHR => LinuxDeviceType::C,
+        SFlag::S_IFBLK => LinuxDeviceType::B,
+        _ => return Err(DeviceHotplugError::NotADevice(path.to_owned())),
+    };
+
+    LinuxDeviceBuilder::default()
+        .path(path)
+        .typ(typ)
+        .major(stat::major(st.st_rdev) as i64)
+        .minor(stat::minor(st.st_rdev) as i64)
+        .file_mode(st.st_mode & 0o7777)
+        .uid(st.st_uid)
+        .gid(st.st_gid)
+        .build()
+        .map_err(|err| DeviceHotplugError::Container(err.to_string()))
+}
+
+fn device_rules(spec: &oci_spec::runtime::Spec) -> Vec<LinuxDeviceCgroup> {
+    spec.linux()
+        .as_ref()
+        .and_then(|linux| linux.resources().as_ref())
+        .and_then(|resources| resources.devices().clone())
+        .unwrap_or_default()
+}
+
+fn device_rule(device: &LinuxDevice, allow: bool) -> LinuxDeviceCgroup {
+    LinuxDeviceCgroupBuilder::default()
+        .allow(allow)
+        .typ(device.typ())
+        .major(device.major())
+        .minor(device.minor())
+        .access("rwm")
+        .build()
+        .expect("device rule is complete")
+}
+
+fn matches_device(rule: &LinuxDeviceCgroup, device: &LinuxDevice) -> bool {
+    rule.typ() == Some(device.typ())
+        && rule.major() == Some(device.major())
+        && rule.minor() == Some(device.minor())
+}
+
+/// Runs `f` in a child process which joined the mount namespace of the init
+/// process of the container. Joining it sets the root of the child to the
+/// root of the container, so `f` works on paths in the container.
+fn in_mount_namespace<F>(pid: nix::unistd::Pid, f: F) -> Result<()>
+where
+    F: FnOnce() -> std::result::Result<(), String>,
+{
+    let ns =
+        File::open(format!("/proc/{pid}/ns/mnt")).map_err(DeviceHotplugError::MountNamespace)?;
+    let (reader, writer) = unistd::pipe().map_err(DeviceHotplugError::Fork)?;
+
+    match unsafe { unistd::fork() }.map_err(DeviceHotplugError::Fork)? {
+        ForkResult::Child => {
+            drop(reader);
+            let result = create_syscall()
+                .set_ns(ns.as_raw_fd(), CloneFlags::CLONE_NEWNS)
+                .map_err(|err| format!("failed to join mount namespace: {err}"))
+                .and_then(|_| f());
+            let status = match result {
+                Ok(()) => 0,
+                Err(err) => {
+                    let _ = unistd::write(&writer, err.as_bytes());
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
+        ForkResult::Parent { child } => {
+            drop(writer);
+            let mut err = String::new();
+            let _ = File::from(reader).read_to_string(&mut err);
+            match waitpid(child, None).map_err(DeviceHotplugError::Fork)? {
+                WaitStatus::Exited(_, 0) => Ok(()),
+                _ => Err(DeviceHotplugError::Container(err)),
+            }
+        }
+    }
+}
+
+fn bind_device(tree: &OwnedFd, path: &Path) -> std::result::Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("failed to create {parent:?}: {err}"))?;
+    }
+    File::create(path).map_err(|err| format!("failed to create {path:?}: {err}"))?;
+    move_mount(tree, path).map_err(|err| format!("failed to mount {path:?}: {err}"))
+}
+
+fn remove_device_node(path: &Path) -> std::result::Result<(), String> {
+    // Bind mounted devices have to be unmounted first
+    match nix::mount::umount2(path, MntFlags::MNT_DETACH) {
+        Ok(()) | Err(Errno::EINVAL) => {}
+        Err(err) => return Err(format!("failed to unmount {path:?}: {err}")),
+    }
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(format!("failed to remove {path:?}: {err}")),
+    }
+}
+
+/// Clones the mount of a path into a detached mount, which can be attached
+/// in another mount namespace
+fn clone_mount(path: &Path) -> std::result::Result<OwnedFd, nix::Error> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| Errno::EINVAL)?;
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::OPEN_TREE_CLONE | libc::OPEN_TREE_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(Errno::last());
+    }
+    // SAFETY: open_tree returned a new fd
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+fn move_mount(tree: &OwnedFd, target: &Path) -> std::result::Result<(), nix::Error> {
+    let empty = CString::default();
+    let target = CString::new(target.as_os_str().as_bytes()).map_err(|_| Errno::EINVAL)?;
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            tree.as_raw_fd(),
+            empty.as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH,
+        )
+    };
+    if ret < 0 {
+        return Err(Errno::last());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_host_device() -> Result<()> {
+        let device = host_device(Path::new("/dev/null"))?;
+        assert_eq!(device.typ(), LinuxDeviceType::C);
+        assert_eq!((device.major(), device.minor()), (1, 3));
+
+        let tmp = tempfile::tempdir()?;
+        assert!(matches!(
+            host_device(tmp.path()),
+            Err(DeviceHotplugError::NotADevice(_))
+        ));
+        assert!(matches!(
+            host_device(Path::new("dev/null")),
+            Err(DeviceHotplugError::RelativePath(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_rule() -> Result<()> {
+        let device = host_device(Path::new("/dev/null"))?;
+        let rule = device_rule(&device, false);
+        assert!(!rule.allow());
+        assert!(matches_device(&rule, &device));
+        assert_eq!(rule.to_string(), "c 1:3 rwm");
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
+use std::time::Duration;
+
 use nix::sys::signal;
 
 use super::{Container, ContainerStatus};
 use crate::config::YoukiConfig;
 use crate::error::LibcontainerError;
-use crate::hooks;
+use crate::hooks::{self, HookPhase};
 use crate::notify_socket::{NotifySocket, NOTIFY_FILE};
+use crate::timing::Phase;
 
 impl Container {
     /// Starts a previously created container
@@ -28,6 +31,18 @@ impl Container {
     /// # }
     /// ```
     pub fn start(&mut self) -> Result<(), LibcontainerError> {
+        self.start_container(false)
+    }
+
+    /// Starts a previously created container like [`Container::start`], but
+    /// waits until the container process is about to exec the payload, so
+    /// that the startup phases after the start signal are included in the
+    /// [`Container::startup_timings`] too
+    pub fn start_with_timings(&mut self) -> Result<(), LibcontainerError> {
+        self.start_container(true)
+    }
+
+    fn start_container(&mut self, with_timings: bool) -> Result<(), LibcontainerError> {
         self.refresh_status()?;
 
         if !self.can_start() {
@@ -43,22 +58,39 @@ impl Container {
             );
             err
         })?;
+        self.set_hook_timeout(config.hook_timeout.map(Duration::from_secs));
         if let Some(hooks) = config.hooks.as_ref() {
             // While prestart is marked as deprecated in the OCI spec, the docker and integration test still
             // uses it.
+            let mut timings = self.startup_timings.clone();
             #[allow(deprecated)]
-            hooks::run_hooks(hooks.prestart().as_ref(), Some(self), None).map_err(|err| {
-                tracing::error!("failed to run pre start hooks: {}", err);
-                // In the case where prestart hook fails, the runtime must
-                // stop the container before generating an error and exiting.
-                let _ = self.kill(signal::Signal::SIGKILL, true);
+            timings
+                .measure(Phase::PrestartHooks, || {
+                    hooks::run_hooks(
+                        hooks.prestart().as_ref(),
+                        HookPhase::Prestart,
+                        Some(self),
+                        None,
+                    )
+                })
+                .map_err(|err| {
+                    tracing::error!("failed to run pre start hooks: {}", err);
+                    // In the case where prestart hook fails, the runtime must
+                    // stop the container before generating an error and exiting.
+                    let _ = self.kill(signal::Signal::SIGKILL, true);
 
-                err
-            })?;
+                    err
+                })?;
+            self.startup_timings = timings;
         }
 
         let mut notify_socket = NotifySocket::new(self.root.join(NOTIFY_FILE));
-        notify_socket.notify_container_start()?;
+        if with_timings {
+            let phases = notify_socket.notify_container_start_with_report()?;
+            self.startup_timings.extend(phases);
+        } else {
+            notify_socket.notify_container_start()?;
+        }
         self.set_status(ContainerStatus::Running)
             .save()
             .map_err(|err| {
@@ -69,12 +101,16 @@ impl Container {
         // Run post start hooks. It runs after the container process is started.
         // It is called in the runtime namespace.
         if let Some(hooks) = config.hooks.as_ref() {
-            hooks::run_hooks(hooks.poststart().as_ref(), Some(self), Some(&self.root)).map_err(
-                |err| {
-                    tracing::error!("failed to run post start hooks: {}", err);
-                    err
-                },
-            )?;
+            hooks::run_hooks(
+                hooks.poststart().as_ref(),
+                HookPhase::Poststart,
+                Some(self),
+                Some(&self.root),
+            )
+            .map_err(|err| {
+                tracing::error!("failed to run post start hooks: {}", err);
+                err
+            })?;
         }
 
         Ok(())
@@ -0,0 +1,258 @@
+use libcgroups::common::{CgroupManager, ControllerOpt};
+use oci_spec::runtime::{LinuxBlockIo, LinuxCpu, LinuxMemory, LinuxResources};
+
+use super::Container;
+use crate::config;
+use crate::error::LibcontainerError;
+
+impl Container {
+    /// Updates the resources of the container. The fields of the resources
+    /// which are set replace those of the container, the others are kept. The
+    /// effective
+    /// spec of the container is updated as well.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use libcontainer::container::builder::ContainerBuilder;
+    /// use libcontainer::syscall::syscall::SyscallType;
+    /// use oci_spec::runtime::{LinuxPidsBuilder, LinuxResourcesBuilder};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut container = ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .build()?;
+    ///
+    /// let resources = LinuxResourcesBuilder::default()
+    ///     .pids(LinuxPidsBuilder::default().limit(100).build()?)
+    ///     .build()?;
+    /// container.update(&resources)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update(&mut self, resources: &LinuxResources) -> Result<(), LibcontainerError> {
+        // A container whose spec can't be loaded anymore can still be
+        // updated, it only misses the record of the update
+        let spec = self
+            .effective_spec()
+            .map_err(|err| {
+                tracing::warn!(?err, id = ?self.id(), "failed to load effective spec, not recording update");
+            })
+            .ok();
+        let current = spec
+            .as_ref()
+            .and_then(|spec| spec.linux().as_ref())
+            .and_then(|linux| linux.resources().clone())
+            .unwrap_or_default();
+
+        // The device rules are compiled into a single program, which replaces
+        // the one of the container. Without new rules, the current ones have
+        // to be kept instead of falling back to the default devices.
+        let mut applied = resources.clone();
+        if applied.devices().is_none() {
+            applied.set_devices(current.devices().clone());
+        }
+
+        let cmanager =
+            libcgroups::common::create_cgroup_manager(libcgroups::common::CgroupConfig {
+                cgroup_path: self.spec()?.cgroup_path,
+                systemd_cgroup: self.systemd(),
+                container_name: self.id().to_string(),
+            })?;
+        cmanager.apply(&ControllerOpt {
+            resources: &applied,
+            disable_oom_killer: false,
+            oom_score_adj: None,
+            freezer_state: None,
+        })?;
+
+        let Some(mut spec) = spec else {
+            return Ok(());
+        };
+        if let Some(linux) = spec.linux_mut() {
+            linux.set_resources(Some(merge_resources(current, resources)));
+        }
+        config::save_effective_spec(&spec, &self.root).map_err(|err| {
+            tracing::error!(?err, id = ?self.id(), "failed to save effective spec");
+            err
+        })?;
+
+        tracing::debug!("container {} updated", self.id());
+        Ok(())
+    }
+}
+
+/// Merges an update into the current resources, field by field, so that the
+/// fields the update doesn't set keep their value. Lists, such as devices,
+/// are replaced as a whole.
+fn merge_resources(mut current: LinuxResources, update: &LinuxResources) -> LinuxResources {
+    if update.devices().is_some() {
+        current.set_devices(update.devices().clone());
+    }
+    if let Some(memory) = update.memory() {
+        let merged = merge_memory(current.memory().unwrap_or_default(), memory);
+        current.set_memory(Some(merged));
+    }
+    if let Some(cpu) = update.cpu() {
+        let merged = merge_cpu(current.cpu().clone().unwrap_or_default(), cpu);
+        current.set_cpu(Some(merged));
+    }
+    if update.pids().is_some() {
+        current.set_pids(*update.pids());
+    }
+    if let Some(block_io) = update.block_io() {
+        let merged = merge_block_io(current.block_io().clone().unwrap_or_default(), block_io);
+        current.set_block_io(Some(merged));
+    }
+    if update.hugepage_limits().is_some() {
+        current.set_hugepage_limits(update.hugepage_limits().clone());
+    }
+    if let Some(network) = update.network() {
+        let mut merged = current.network().clone().unwrap_or_default();
+        merged.set_class_id(network.class_id().or(merged.class_id()));
+        if network.priorities().is_some() {
+            merged.set_priorities(network.priorities().clone());
+        }
+        current.set_network(Some(merged));
+    }
+    if let Some(rdma) = update.rdma() {
+        let mut merged = current.rdma().clone().unwrap_or_default();
+        merged.extend(rdma.clone());
+        current.set_rdma(Some(merged));
+    }
+    if let Some(unified) = update.unified() {
+        let mut merged = current.unified().clone().unwrap_or_default();
+        merged.extend(unified.clone());
+        current.set_unified(Some(merged));
+    }
+    current
+}
+
+fn merge_memory(mut current: LinuxMemory, update: &LinuxMemory) -> LinuxMemory {
+    current.set_limit(update.limit().or(current.limit()));
+    current.set_reservation(update.reservation().or(current.reservation()));
+    current.set_swap(update.swap().or(current.swap()));
+    current.set_kernel(update.kernel().or(current.kernel()));
+    current.set_kernel_tcp(update.kernel_tcp().or(current.kernel_tcp()));
+    current.set_swappiness(update.swappiness().or(current.swappiness()));
+    current.set_disable_oom_killer(update.disable_oom_killer().or(current.disable_oom_killer()));
+    current.set_use_hierarchy(update.use_hierarchy().or(current.use_hierarchy()));
+    current.set_check_before_update(
+        update
+            .check_before_update()
+            .or(current.check_before_update()),
+    );
+    current
+}
+
+fn merge_cpu(mut current: LinuxCpu, update: &LinuxCpu) -> LinuxCpu {
+    current.set_shares(update.shares().or(current.shares()));
+    current.set_quota(update.quota().or(current.quota()));
+    current.set_idle(update.idle().or(current.idle()));
+    current.set_burst(update.burst().or(current.burst()));
+    current.set_period(update.period().or(current.period()));
+    current.set_realtime_runtime(update.realtime_runtime().or(current.realtime_runtime()));
+    current.set_realtime_period(update.realtime_period().or(current.realtime_period()));
+    if update.cpus().is_some() {
+        current.set_cpus(update.cpus().clone());
+    }
+    if update.mems().is_some() {
+        current.set_mems(update.mems().clone());
+    }
+    current
+}
+
+fn merge_block_io(mut current: LinuxBlockIo, update: &LinuxBlockIo) -> LinuxBlockIo {
+    current.set_weight(update.weight().or(current.weight()));
+    current.set_leaf_weight(update.leaf_weight().or(current.leaf_weight()));
+    if update.weight_device().is_some() {
+        current.set_weight_device(update.weight_device().clone());
+    }
+    if update.throttle_read_bps_device().is_some() {
+        current.set_throttle_read_bps_device(update.throttle_read_bps_device().clone());
+    }
+    if update.throttle_write_bps_device().is_some() {
+        current.set_throttle_write_bps_device(update.throttle_write_bps_device().clone());
+    }
+    if update.throttle_read_iops_device().is_some() {
+        current.set_throttle_read_iops_device(update.throttle_read_iops_device().clone());
+    }
+    if update.throttle_write_iops_device().is_some() {
+        current.set_throttle_write_iops_device(update.throttle_write_iops_device().clone());
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Result;
+    use oci_spec::runtime::{
+        LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResourcesBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_merge_resources() -> Result<()> {
+        let current = LinuxResourcesBuilder::default()
+            .memory(LinuxMemoryBuilder::default().limit(1 << 30).build()?)
+            .pids(LinuxPidsBuilder::default().limit(10).build()?)
+            .unified(HashMap::from([
+                ("memory.high".to_owned(), "1G".to_owned()),
+                ("io.weight".to_owned(), "100".to_owned()),
+            ]))
+            .build()?;
+        let update = LinuxResourcesBuilder::default()
+            .pids(LinuxPidsBuilder::default().limit(100).build()?)
+            .unified(HashMap::from([("io.weight".to_owned(), "200".to_owned())]))
+            .build()?;
+
+        let merged = merge_resources(current, &update);
+        assert_eq!(merged.memory().as_ref().unwrap().limit(), Some(1 << 30));
+        assert_eq!(merged.pids().as_ref().unwrap().limit(), 100);
+        let unified = merged.unified().as_ref().unwrap();
+        assert_eq!(unified["memory.high"], "1G");
+        assert_eq!(unified["io.weight"], "200");
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_resources_fields() -> Result<()> {
+        let current = LinuxResourcesBuilder::default()
+            .cpu(
+                LinuxCpuBuilder::default()
+                    .shares(1024u64)
+                    .quota(50000i64)
+                    .period(100000u64)
+                    .cpus("0-1")
+                    .build()?,
+            )
+            .memory(
+                LinuxMemoryBuilder::default()
+                    .limit(1 << 30)
+                    .swap(2i64 << 30)
+                    .build()?,
+            )
+            .build()?;
+        let update = LinuxResourcesBuilder::default()
+            .cpu(LinuxCpuBuilder::default().shares(512u64).build()?)
+            .memory(LinuxMemoryBuilder::default().limit(1 << 29).build()?)
+            .build()?;
+
+        let merged = merge_resources(current, &update);
+        let cpu = merged.cpu().as_ref().unwrap();
+        assert_eq!(cpu.shares(), Some(512));
+        assert_eq!(cpu.quota(), Some(50000));
+        assert_eq!(cpu.period(), Some(100000));
+        assert_eq!(cpu.cpus().as_deref(), Some("0-1"));
+        let memory = merged.memory().as_ref().unwrap();
+        assert_eq!(memory.limit(), Some(1 << 29));
+        assert_eq!(memory.swap(), Some(2 << 30));
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+//! Garbage collection of the containers in a root path which are gone, but
+//! whose directory was left behind, e.g. because their processes were killed
+//! without `delete`, their bundle was removed or their state got corrupted.
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::{Container, ContainerStatus, State};
+use crate::config::YoukiConfig;
+use crate::error::LibcontainerError;
+use crate::id_allocator;
+use crate::process::intel_rdt::delete_resctrl_subdirectory;
+use crate::rootfs::shift::SHIFT_DIR;
+use crate::rootfs::{RootfsShift, ShiftedRootfs};
+
+/// Containers which are still being created have no pid yet, they aren't
+/// collected until their state is this old.
+const CREATING_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Why a container is considered dead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadReason {
+    /// The state of the container can't be loaded
+    CorruptState,
+    /// The init process of the container is gone
+    Exited,
+    /// The init process of the container is gone, and so is its bundle
+    MissingBundle,
+}
+
+impl Display for DeadReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::CorruptState => "corrupt state",
+            Self::Exited => "exited",
+            Self::MissingBundle => "missing bundle",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+/// A container which can be collected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadContainer {
+    pub id: String,
+    /// The directory of the container in the root path
+    pub root: PathBuf,
+    pub reason: DeadReason,
+}
+
+/// Finds the dead containers in a root path
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::container::gc;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// for dead in gc::find_dead("/run/youki".as_ref())? {
+///     println!("{} is dead: {}", dead.id, dead.reason);
+///     gc::collect(&dead)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn find_dead(root_path: &Path) -> Result<Vec<DeadContainer>, LibcontainerError> {
+    let mut dead = Vec::new();
+    for entry in fs::read_dir(root_path).map_err(LibcontainerError::OtherIO)? {
+        let root = entry.map_err(LibcontainerError::OtherIO)?.path();
+        // Only the directories with a state are containers
+        if !root.is_dir() || !State::file_path(&root).exists() {
+            continue;
+        }
+        if let Some(reason) = dead_reason(&root)? {
+            dead.push(DeadContainer {
+                id: container_id(&root),
+                root,
+                reason,
+            });
+        }
+    }
+    dead.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(dead)
+}
+
+fn dead_reason(container_root: &Path) -> Result<Option<DeadReason>, LibcontainerError> {
+    let state = match State::load(container_root) {
+        Ok(state) => state,
+        Err(err) => {
+            tracing::debug!(?container_root, ?err, "failed to load container state");
+            return Ok(Some(DeadReason::CorruptState));
+        }
+    };
+    if state.status == ContainerStatus::Creating && state.pid.is_none() {
+        let age = fs::metadata(State::file_path(container_root))
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| {
+                SystemTime::now()
+                    .duration_since(modified)
+                    .unwrap_or_default()
+            })
+            .map_err(LibcontainerError::OtherIO)?;
+        if age < CREATING_GRACE_PERIOD {
+            return Ok(None);
+        }
+    }
+
+    // A container whose init process runs is alive, even if its bundle was
+    // removed
+    let container = Container::load(container_root.to_owned())?;
+    if container.status() != ContainerStatus::Stopped {
+        Ok(None)
+    } else if !container.bundle().exists() {
+        Ok(Some(DeadReason::MissingBundle))
+    } else {
+        Ok(Some(DeadReason::Exited))
+    }
+}
+
+/// Collects a dead container. Its processes are killed, its cgroup is
+/// removed and its poststop hooks are run, like on a forced delete.
+pub fn collect(dead: &DeadContainer) -> Result<(), LibcontainerError> {
+    match dead.reason {
+        DeadReason::CorruptState => remove_corrupt(&dead.root),
+        DeadReason::Exited | DeadReason::MissingBundle => {
+            Container::load(dead.root.to_owned())?.delete(true)
+        }
+    }
+}
+
+/// Removes a container whose state can't be loaded. What its config records,
+/// i.e. its cgroup and poststop hooks, is cleaned up as far as possible, the
+/// hooks get a state with the id of the container only.
+pub fn remove_corrupt(container_root: &Path) -> Result<(), LibcontainerError> {
+    let id = container_id(container_root);
+    tracing::debug!(?container_root, "remove container with corrupt state");
+
+    match YoukiConfig::load(container_root) {
+        Ok(config) => {
+            let mut container = Container {
+                state: State::new(&id, ContainerStatus::Stopped, None, PathBuf::new()),
+                root: container_root.to_owned(),
+                ..Default::default()
+            };
+            container.set_systemd(config.use_systemd);
+            container.clean_up_config(&config, true)?;
+        }
+        Err(err) => {
+            tracing::warn!("skipping loading youki config due to: {err:?}, continue to delete");
+        }
+    }
+
+    // A resctrl subdirectory may belong to someone else, so it is only
+    // removed if what is left of the state shows that youki created it
+    if created_resctrl_subdirectory(container_root) {
+        if let Err(err) = delete_resctrl_subdirectory(&id) {
+            tracing::warn!(
+                "failed to delete resctrl subdirectory due to: {err:?}, continue to delete"
+            );
+        }
+    }
+
+    // The state recording a shifted rootfs is lost, but it is always in the
+    // same place. Unmounting it if it isn't an overlay is harmless.
+    let shift_dir = container_root.join(SHIFT_DIR);
+    if shift_dir.exists() {
+        if let Err(err) = ShiftedRootfs::new(RootfsShift::Overlay, &shift_dir).remove() {
+            tracing::warn!("failed to remove shifted rootfs due to: {err:?}, continue to delete");
+        }
+    }
+
+    if let Some(root_path) = container_root.parent() {
+        if let Err(err) = id_allocator::release(root_path, &id) {
+            tracing::warn!(
+                "failed to release user namespace ids due to: {err:?}, continue to delete"
+            );
+        }
+    }
+
+    fs::remove_dir_all(container_root).map_err(|err| {
+        tracing::error!(?err, path = ?container_root, "failed to remove container dir");
+        LibcontainerError::OtherIO(err)
+    })
+}
+
+/// Whether a state, which can't be loaded as a whole, records that youki
+/// created a resctrl subdirectory for the container
+fn created_resctrl_subdirectory(container_root: &Path) -> bool {
+    fs::read(State::file_path(container_root))
+        .ok()
+        .and_then(|content| serde_json::from_slice::<serde_json::Value>(&content).ok())
+        .and_then(|state| state.get("cleanUpIntelRdtSubdirectory")?.as_bool())
+        .unwrap_or(false)
+}
+
+fn container_id(container_root: &Path) -> String {
+    container_root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn create_container(root_path: &Path, id: &str, status: ContainerStatus) -> Result<PathBuf> {
+        let container_root = root_path.join(id);
+        fs::create_dir(&container_root)?;
+        Container::new(id, status, None, root_path, &container_root)?.save()?;
+        Ok(container_root)
+    }
+
+    #[test]
+    fn test_find_dead() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        create_container(tmp.path(), "exited", ContainerStatus::Stopped)?;
+        // The bundle doesn't make a container whose init process runs dead
+        let bundle = tmp.path().join("removed-bundle");
+        fs::create_dir(&bundle)?;
+        let running = tmp.path().join("running");
+        fs::create_dir(&running)?;
+        Container::new(
+            "running",
+            ContainerStatus::Running,
+            Some(nix::unistd::getpid().as_raw()),
+            &bundle,
+            &running,
+        )?
+        .save()?;
+        let removed = tmp.path().join("removed");
+        fs::create_dir(&removed)?;
+        Container::new("removed", ContainerStatus::Created, None, &bundle, &removed)?.save()?;
+        fs::remove_dir(&bundle)?;
+        create_container(tmp.path(), "creating", ContainerStatus::Creating)?;
+        let corrupt = create_container(tmp.path(), "corrupt", ContainerStatus::Created)?;
+        fs::write(State::file_path(&corrupt), "{")?;
+        fs::write(tmp.path().join("not-a-container"), "")?;
+
+        let dead = find_dead(tmp.path())?;
+        let reasons: Vec<_> = dead.iter().map(|d| (d.id.as_str(), d.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("corrupt", DeadReason::CorruptState),
+                ("exited", DeadReason::Exited),
+                ("removed", DeadReason::MissingBundle),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_corrupt() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let container_root = create_container(tmp.path(), "corrupt", ContainerStatus::Created)?;
+        fs::write(State::file_path(&container_root), "")?;
+
+        remove_corrupt(&container_root)?;
+        assert!(!container_root.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_created_resctrl_subdirectory() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let container_root = create_container(tmp.path(), "corrupt", ContainerStatus::Created)?;
+        assert!(!created_resctrl_subdirectory(&container_root));
+
+        // e.g. a status youki doesn't know
+        fs::write(
+            State::file_path(&container_root),
+            r#"{"status": "unknown", "cleanUpIntelRdtSubdirectory": true}"#,
+        )?;
+        assert!(State::load(&container_root).is_err());
+        assert!(created_resctrl_subdirectory(&container_root));
+
+        fs::write(State::file_path(&container_root), "{")?;
+        assert!(!created_resctrl_subdirectory(&container_root));
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{LinuxNamespaceType, Spec};
 use user_ns::UserNamespaceConfig;
 
 use super::builder::ContainerBuilder;
@@ -12,7 +13,10 @@ use crate::config::YoukiConfig;
 use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
 use crate::notify_socket::NOTIFY_FILE;
 use crate::process::args::ContainerType;
-use crate::{apparmor, tty, user_ns, utils};
+use crate::rootfs::shift::SHIFT_DIR;
+use crate::rootfs::{RootFS, RootfsShift};
+use crate::subid::IdMappingPolicy;
+use crate::{apparmor, id_allocator, tty, user_ns, utils};
 
 // Builder that can be used to configure the properties of a new container
 pub struct InitContainerBuilder {
@@ -22,6 +26,9 @@ pub struct InitContainerBuilder {
     detached: bool,
     no_pivot: bool,
     as_sibling: bool,
+    hook_timeout: Option<Duration>,
+    id_mapping_policy: IdMappingPolicy,
+    rootfs_shift: RootfsShift,
 }
 
 impl InitContainerBuilder {
@@ -35,6 +42,9 @@ impl InitContainerBuilder {
             detached: true,
             no_pivot: false,
             as_sibling: false,
+            hook_timeout: None,
+            id_mapping_policy: IdMappingPolicy::default(),
+            rootfs_shift: RootfsShift::default(),
         }
     }
 
@@ -61,20 +71,99 @@ impl InitContainerBuilder {
         self
     }
 
+    /// Sets the timeout of the hooks which don't set a timeout themselves. The
+    /// timeout is kept in the container state in whole seconds, so it also
+    /// applies to the hooks run on start and delete.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .with_hook_timeout(Some(Duration::from_secs(30)));
+    /// ```
+    pub fn with_hook_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.hook_timeout = timeout;
+        self
+    }
+
+    /// Sets how the ids of the container are mapped to those of the host, if
+    /// it has a new user namespace. The mappings of the spec are used by
+    /// default.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::subid::{IdMappingPolicy, SubIdPolicy};
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .with_id_mapping_policy(IdMappingPolicy::SubIds(SubIdPolicy::new(
+    ///     nix::unistd::getuid(),
+    /// )));
+    /// ```
+    pub fn with_id_mapping_policy(mut self, policy: IdMappingPolicy) -> Self {
+        self.id_mapping_policy = policy;
+        self
+    }
+
+    /// Sets how the ownership of the rootfs is shifted to the ids of the
+    /// user namespace, for kernels or filesystems without idmapped mounts
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::rootfs::RootfsShift;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_init("/var/run/docker/bundle")
+    /// .with_rootfs_shift(RootfsShift::Overlay);
+    /// ```
+    pub fn with_rootfs_shift(mut self, rootfs_shift: RootfsShift) -> Self {
+        self.rootfs_shift = rootfs_shift;
+        self
+    }
+
     /// Creates a new container
     pub fn build(self) -> Result<Container, LibcontainerError> {
-        let spec = self.load_spec()?;
+        let mut spec = self.load_spec()?;
         let container_dir = self.create_container_dir()?;
+        // ids are allocated once the container exists, so that deleting it
+        // releases them
+        self.apply_id_mapping_policy(&mut spec)?;
+        // the mappings of a new user namespace may come from the policy, so
+        // they are validated once it is applied
+        if let Err(err) = utils::validate_spec_for_new_user_ns(&spec) {
+            self.remove_container_dir(&container_dir);
+            return Err(err);
+        }
 
         let mut container = self.create_container_state(&container_dir)?;
         container
             .set_systemd(self.use_systemd)
+            .set_hook_timeout(self.hook_timeout)
             .set_annotations(spec.annotations().clone());
 
         let notify_path = container_dir.join(NOTIFY_FILE);
         // convert path of root file system of the container to absolute path
         let rootfs = fs::canonicalize(spec.root().as_ref().ok_or(MissingSpecError::Root)?.path())
             .map_err(LibcontainerError::OtherIO)?;
+        let rootfs = self.shift_rootfs(&spec, &container_dir, &mut container, rootfs)?;
 
         // if socket file path is given in commandline options,
         // get file descriptors of console socket
@@ -90,7 +179,9 @@ impl InitContainerBuilder {
 
         let user_ns_config = UserNamespaceConfig::new(&spec)?;
 
-        let config = YoukiConfig::from_spec(&spec, container.id())?;
+        let mut config = YoukiConfig::from_spec(&spec, container.id())?;
+        config.use_systemd = self.use_systemd;
+        config.hook_timeout = self.hook_timeout.map(|timeout| timeout.as_secs());
         config.save(&container_dir).map_err(|err| {
             tracing::error!(?container_dir, "failed to save config: {}", err);
             err
@@ -116,11 +207,15 @@ impl InitContainerBuilder {
             stdout: self.base.stdout,
             stderr: self.base.stderr,
             as_sibling: self.as_sibling,
+            trace_context: self.base.trace_context,
+            sub_cgroup: None,
+            cgroup_frozen: false,
         };
 
-        builder_impl.create()?;
+        let (_, timings) = builder_impl.create()?;
 
         container.refresh_state()?;
+        container.startup_timings = timings;
 
         Ok(container)
     }
@@ -146,6 +241,17 @@ impl InitContainerBuilder {
         Ok(container_dir)
     }
 
+    /// Removes the directory of a container which failed validation, together
+    /// with the ids allocated to it
+    fn remove_container_dir(&self, container_dir: &Path) {
+        if let Err(err) = id_allocator::release(&self.base.root_path, &self.base.container_id) {
+            tracing::warn!(?err, "failed to release user namespace ids");
+        }
+        if let Err(err) = fs::remove_dir_all(container_dir) {
+            tracing::warn!(?err, ?container_dir, "failed to remove container directory");
+        }
+    }
+
     fn load_spec(&self) -> Result<Spec, LibcontainerError> {
         let source_spec_path = self.bundle.join("config.json");
         let mut spec = Spec::load(source_spec_path)?;
@@ -159,6 +265,54 @@ impl InitContainerBuilder {
         Ok(spec)
     }
 
+    fn apply_id_mapping_policy(&self, spec: &mut Spec) -> Result<(), LibcontainerError> {
+        let Some(linux) = spec.linux_mut() else {
+            return Ok(());
+        };
+        let new_user_ns = linux.namespaces().as_ref().map_or(false, |namespaces| {
+            namespaces
+                .iter()
+                .any(|ns| ns.typ() == LinuxNamespaceType::User && ns.path().is_none())
+        });
+        if !new_user_ns {
+            return Ok(());
+        }
+
+        self.id_mapping_policy
+            .apply(&self.base.container_id, linux)
+            .map_err(|err| {
+                tracing::error!(?err, "failed to apply id mapping policy");
+                LibcontainerError::UserNamespace(err.into())
+            })
+    }
+
+    /// Shifts the ownership of the rootfs if requested, and returns the
+    /// rootfs the container uses
+    fn shift_rootfs(
+        &self,
+        spec: &Spec,
+        container_dir: &Path,
+        container: &mut Container,
+        rootfs: PathBuf,
+    ) -> Result<PathBuf, LibcontainerError> {
+        let linux = spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
+        let shifted = RootFS::new().shift_ownership(
+            self.rootfs_shift,
+            linux,
+            &rootfs,
+            &container_dir.join(SHIFT_DIR),
+        )?;
+
+        let Some(shifted) = shifted else {
+            return Ok(rootfs);
+        };
+        let rootfs = shifted.rootfs.clone();
+        // the shifted rootfs is recorded right away, so that deleting a
+        // container which fails to be created removes it too
+        container.set_shifted_rootfs(Some(shifted)).save()?;
+        Ok(rootfs)
+    }
+
     fn validate_spec(spec: &Spec) -> Result<(), LibcontainerError> {
         let version = spec.version();
         if !version.starts_with("1.") {
@@ -200,8 +354,6 @@ impl InitContainerBuilder {
             }
         }
 
-        utils::validate_spec_for_new_user_ns(spec)?;
-
         Ok(())
     }
 
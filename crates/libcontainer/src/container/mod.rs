@@ -10,14 +10,18 @@ mod builder_impl;
 mod container;
 mod container_checkpoint;
 mod container_delete;
+mod container_device;
 mod container_events;
 mod container_kill;
 mod container_pause;
 mod container_resume;
 mod container_start;
+mod container_update;
+pub mod gc;
 pub mod init_builder;
 pub mod state;
 pub mod tenant_builder;
 pub use container::{CheckpointOptions, Container};
 pub use container_checkpoint::CheckpointError;
+pub use container_device::DeviceHotplugError;
 pub use state::{ContainerProcessState, ContainerStatus, State};
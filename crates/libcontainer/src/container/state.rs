@@ -7,9 +7,12 @@ use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Utc};
+use oci_spec::runtime::LinuxResources;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+use crate::rootfs::ShiftedRootfs;
+
 /// Indicates status of the container
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
 #[serde(rename_all = "camelCase")]
@@ -116,6 +119,19 @@ pub struct State {
     pub use_systemd: bool,
     // Specifies if the Intel RDT subdirectory needs be cleaned up.
     pub clean_up_intel_rdt_subdirectory: Option<bool>,
+    // Pid of the helper process providing the built-in network
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_helper_pid: Option<i32>,
+    // Pid of the helper process auditing the capabilities of the container
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capability_audit_pid: Option<i32>,
+    // Resources applied to the cgroup of a rootless container, which lack
+    // those the delegated controllers can't enforce
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied_resources: Option<LinuxResources>,
+    // Rootfs whose ownership was shifted to the ids of the user namespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shifted_rootfs: Option<ShiftedRootfs>,
 }
 
 impl State {
@@ -138,6 +154,10 @@ impl State {
             creator: None,
             use_systemd: false,
             clean_up_intel_rdt_subdirectory: None,
+            network_helper_pid: None,
+            capability_audit_pid: None,
+            applied_resources: None,
+            shifted_rootfs: None,
         }
     }
 
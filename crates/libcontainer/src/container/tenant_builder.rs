@@ -9,7 +9,9 @@ use std::rc::Rc;
 use std::str::FromStr;
 
 use caps::Capability;
-use nix::fcntl::OFlag;
+use libcgroups::common::{AnyCgroupManager, CgroupConfig, CgroupManager, FreezerState};
+use libcgroups::sub_cgroup::SubCgroup;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag, OFlag};
 use nix::unistd::{pipe2, read, Pid};
 use oci_spec::runtime::{
     Capabilities as SpecCapabilities, Capability as SpecCapability, LinuxBuilder,
@@ -19,14 +21,15 @@ use oci_spec::runtime::{
 use procfs::process::Namespace;
 
 use super::builder::ContainerBuilder;
-use super::Container;
+use super::{Container, ContainerStatus};
 use crate::capabilities::CapabilityExt;
 use crate::container::builder_impl::ContainerBuilderImpl;
-use crate::error::{ErrInvalidSpec, LibcontainerError, MissingSpecError};
+use crate::error::{ErrInvalidSpec, ErrInvalidTenant, LibcontainerError, MissingSpecError};
 use crate::notify_socket::NotifySocket;
 use crate::process::args::ContainerType;
+use crate::process::failure::{ContainerProcessError, ProcessFailure};
 use crate::user_ns::UserNamespaceConfig;
-use crate::{tty, utils};
+use crate::{apparmor, selinux, tty, utils};
 
 const NAMESPACE_TYPES: &[&str] = &["ipc", "uts", "net", "pid", "mnt", "cgroup"];
 const TENANT_NOTIFY: &str = "tenant-notify-";
@@ -67,6 +70,10 @@ pub struct TenantContainerBuilder {
     additional_gids: Vec<u32>,
     user: Option<u32>,
     group: Option<u32>,
+    sub_cgroup: Option<SubCgroup>,
+    apparmor_profile: Option<String>,
+    process_label: Option<String>,
+    ignore_paused: bool,
 }
 
 /// This is a helper function to get capabilities for tenant container, based on
@@ -78,7 +85,10 @@ fn get_capabilities(
 ) -> Result<LinuxCapabilities, LibcontainerError> {
     let mut caps: Vec<Capability> = Vec::with_capacity(additional.len());
     for cap in additional {
-        caps.push(Capability::from_str(cap)?);
+        caps.push(
+            Capability::from_str(cap)
+                .map_err(|_| ErrInvalidTenant::UnknownCapability(cap.to_owned()))?,
+        );
     }
     let caps: SpecCapabilities = caps.iter().map(|c| SpecCapability::from_cap(*c)).collect();
 
@@ -162,6 +172,10 @@ impl TenantContainerBuilder {
             additional_gids: vec![],
             user: None,
             group: None,
+            sub_cgroup: None,
+            apparmor_profile: None,
+            process_label: None,
+            ignore_paused: false,
         }
     }
 
@@ -188,6 +202,7 @@ impl TenantContainerBuilder {
         self
     }
 
+    /// Adds capabilities to the ones of the container process
     pub fn with_capabilities(mut self, capabilities: Vec<String>) -> Self {
         self.capabilities = capabilities;
         self
@@ -225,10 +240,69 @@ impl TenantContainerBuilder {
         self
     }
 
+    /// Sets the AppArmor profile of the process. By default the process runs
+    /// with the profile of the container process.
+    pub fn with_apparmor_profile(mut self, profile: Option<String>) -> Self {
+        self.apparmor_profile = profile;
+        self
+    }
+
+    /// Sets the SELinux label of the process. By default the label isn't
+    /// changed.
+    pub fn with_process_label(mut self, label: Option<String>) -> Self {
+        self.process_label = label;
+        self
+    }
+
+    /// Sets a cgroup below the cgroup of the container the process is placed
+    /// in. It is created if it doesn't exist yet.
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use libcgroups::sub_cgroup::SubCgroup;
+    /// # use libcontainer::container::builder::ContainerBuilder;
+    /// # use libcontainer::syscall::syscall::SyscallType;
+    ///
+    /// ContainerBuilder::new(
+    ///     "74f1a4cb3801".to_owned(),
+    ///     SyscallType::default(),
+    /// )
+    /// .as_tenant()
+    /// .with_sub_cgroup(Some("probes".parse::<SubCgroup>().unwrap()));
+    /// ```
+    pub fn with_sub_cgroup(mut self, sub_cgroup: Option<SubCgroup>) -> Self {
+        self.sub_cgroup = sub_cgroup;
+        self
+    }
+
+    /// Allows to execute a process in a paused container. The process is
+    /// frozen before it executes the payload, until the container is resumed.
+    /// Building doesn't wait for the payload to be executed then, so a failure
+    /// to execute it isn't returned.
+    pub fn with_ignore_paused(mut self, ignore_paused: bool) -> Self {
+        self.ignore_paused = ignore_paused;
+        self
+    }
+
     /// Joins an existing container
     pub fn build(self) -> Result<Pid, LibcontainerError> {
+        self.validate_preserve_fds()?;
         let container_dir = self.lookup_container_dir()?;
         let container = self.load_container_state(container_dir.clone())?;
+
+        // A process joining a frozen cgroup freezes in the middle of its
+        // setup, so this has to be known before the process is created.
+        let cmanager = libcgroups::common::create_cgroup_manager(CgroupConfig {
+            cgroup_path: container.spec()?.cgroup_path,
+            systemd_cgroup: container.systemd(),
+            container_name: container.id().to_string(),
+        })?;
+        let cgroup_frozen =
+            container.status() == ContainerStatus::Paused || Self::is_cgroup_frozen(&cmanager);
+        if cgroup_frozen && !self.ignore_paused {
+            tracing::error!(id = ?container.id(), "cannot exec in a paused container");
+            return Err(LibcontainerError::Paused);
+        }
         let mut spec = self.load_init_spec(&container)?;
         self.adapt_spec_for_tenant(&mut spec, &container)?;
 
@@ -271,9 +345,22 @@ impl TenantContainerBuilder {
             stdout: self.base.stdout,
             stderr: self.base.stderr,
             as_sibling: self.as_sibling,
+            trace_context: self.base.trace_context,
+            sub_cgroup: self.sub_cgroup.clone(),
+            cgroup_frozen,
         };
 
-        let pid = builder_impl.create()?;
+        let (pid, _) = builder_impl.create()?;
+
+        if cgroup_frozen {
+            // The process is set up and waits for the start signal, so it
+            // freezes before it executes the payload once it joined. It only
+            // gets to exec when the container is resumed.
+            cmanager.add_task(pid)?;
+            if let Some(sub_cgroup) = &self.sub_cgroup {
+                sub_cgroup.join(pid)?;
+            }
+        }
 
         let mut notify_socket = NotifySocket::new(notify_path);
         notify_socket.notify_container_start()?;
@@ -286,6 +373,18 @@ impl TenantContainerBuilder {
         // here with `RawFd`.
         drop(write_end);
 
+        // Waiting for the exec of a frozen process would block until the
+        // container is resumed
+        if !cgroup_frozen {
+            Self::wait_for_exec(read_end)?;
+        }
+
+        Ok(pid)
+    }
+
+    /// Waits until the process executed its payload, which closes the write
+    /// end of the pipe, or reported an error through it
+    fn wait_for_exec(read_end: OwnedFd) -> Result<(), LibcontainerError> {
         let mut err_str_buf = Vec::new();
 
         loop {
@@ -293,15 +392,21 @@ impl TenantContainerBuilder {
             match read(read_end.as_raw_fd(), &mut buf).map_err(LibcontainerError::OtherSyscall)? {
                 0 => {
                     if err_str_buf.is_empty() {
-                        return Ok(pid);
-                    } else {
-                        return Err(LibcontainerError::Other(
-                            String::from_utf8_lossy(&err_str_buf).to_string(),
-                        ));
+                        return Ok(());
                     }
+                    // The init process reports a structured failure, fall back
+                    // to the raw message if it can't be parsed.
+                    return Err(
+                        match serde_json::from_slice::<ProcessFailure>(&err_str_buf) {
+                            Ok(failure) => Box::new(ContainerProcessError::from(failure)).into(),
+                            Err(_) => LibcontainerError::Other(
+                                String::from_utf8_lossy(&err_str_buf).to_string(),
+                            ),
+                        },
+                    );
                 }
-                _ => {
-                    err_str_buf.extend(buf);
+                n => {
+                    err_str_buf.extend(&buf[..n]);
                 }
             }
         }
@@ -318,16 +423,8 @@ impl TenantContainerBuilder {
     }
 
     fn load_init_spec(&self, container: &Container) -> Result<Spec, LibcontainerError> {
-        let spec_path = container.bundle().join("config.json");
-
-        let mut spec = Spec::load(&spec_path).map_err(|err| {
-            tracing::error!(path = ?spec_path, ?err, "failed to load spec");
-            err
-        })?;
-
+        let spec = container.effective_spec()?;
         Self::validate_spec(&spec)?;
-
-        spec.canonicalize_rootfs(container.bundle())?;
         Ok(spec)
     }
 
@@ -421,9 +518,21 @@ impl TenantContainerBuilder {
         Ok(())
     }
 
+    fn is_cgroup_frozen(cmanager: &AnyCgroupManager) -> bool {
+        match cmanager.freezer_state() {
+            Ok(state) => state == FreezerState::Frozen,
+            Err(err) => {
+                // Without a freezer the cgroup can't be frozen either
+                tracing::debug!(?err, "failed to read the freezer state");
+                false
+            }
+        }
+    }
+
     fn load_container_state(&self, container_dir: PathBuf) -> Result<Container, LibcontainerError> {
         let container = Container::load(container_dir)?;
-        if !container.can_exec() {
+        // Whether a paused container can be joined is checked with the freezer
+        if !container.can_exec() && container.status() != ContainerStatus::Paused {
             tracing::error!(status = ?container.status(), "cannot exec as container");
             return Err(LibcontainerError::IncorrectStatus);
         }
@@ -454,6 +563,14 @@ impl TenantContainerBuilder {
             let capabilities = get_capabilities(&self.capabilities, spec)?;
             process_builder = process_builder.capabilities(capabilities);
 
+            if let Some(profile) = self.get_apparmor_profile(spec)? {
+                process_builder = process_builder.apparmor_profile(profile);
+            }
+
+            if let Some(label) = self.get_process_label(spec)? {
+                process_builder = process_builder.selinux_label(label);
+            }
+
             let mut user_builder = UserBuilder::default();
 
             if !self.additional_gids.is_empty() {
@@ -508,6 +625,74 @@ impl TenantContainerBuilder {
         Ok(process_spec)
     }
 
+    fn validate_preserve_fds(&self) -> Result<(), LibcontainerError> {
+        let preserve_fds = self.base.preserve_fds;
+        if preserve_fds < 0 {
+            tracing::error!(preserve_fds, "invalid number of preserved fds");
+            Err(ErrInvalidTenant::NegativePreserveFds(preserve_fds))?;
+        }
+
+        // The preserved fds are inherited from the caller, starting after stdio
+        for fd in 3..3 + preserve_fds {
+            validate_preserved_fd(fd)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_apparmor_profile(&self, spec: &Spec) -> Result<Option<String>, LibcontainerError> {
+        let container_profile = spec
+            .process()
+            .as_ref()
+            .and_then(|process| process.apparmor_profile().clone());
+        let Some(profile) = &self.apparmor_profile else {
+            return Ok(container_profile);
+        };
+
+        if let Some(container_profile) = container_profile {
+            if profile == "unconfined" && container_profile != "unconfined" {
+                tracing::error!(?container_profile, "cannot exec unconfined");
+                Err(ErrInvalidTenant::AppArmorUnconfined(container_profile))?;
+            }
+        }
+
+        if !apparmor::is_enabled().unwrap_or(false) {
+            tracing::error!(?profile, "apparmor is not enabled");
+            Err(ErrInvalidTenant::AppArmorNotEnabled(profile.to_owned()))?;
+        }
+
+        Ok(Some(profile.to_owned()))
+    }
+
+    fn get_process_label(&self, spec: &Spec) -> Result<Option<String>, LibcontainerError> {
+        let container_label = spec
+            .process()
+            .as_ref()
+            .and_then(|process| process.selinux_label().clone());
+        let Some(label) = &self.process_label else {
+            return Ok(None);
+        };
+
+        if !selinux::is_valid_label(label) {
+            tracing::error!(?label, "invalid process label");
+            Err(ErrInvalidTenant::InvalidProcessLabel(label.to_owned()))?;
+        }
+
+        // The level carries the categories which separate containers from
+        // each other, so it must not change.
+        if let Some(level) = container_label.as_deref().and_then(selinux::label_level) {
+            if selinux::label_level(label) != Some(level) {
+                tracing::error!(?label, ?level, "process label changes the level");
+                Err(ErrInvalidTenant::ProcessLabelLevel {
+                    label: label.to_owned(),
+                    level: level.to_owned(),
+                })?;
+            }
+        }
+
+        Ok(Some(label.to_owned()))
+    }
+
     fn get_working_dir(&self) -> Result<Option<PathBuf>, LibcontainerError> {
         if let Some(cwd) = &self.cwd {
             if cwd.is_relative() {
@@ -615,16 +800,36 @@ impl TenantContainerBuilder {
     }
 }
 
+/// Checks that the caller passed in the fd. Those survived the exec of
+/// youki, so they aren't close-on-exec, unlike the fds youki opens itself,
+/// e.g. for logging, which may take the numbers after stdio as well.
+fn validate_preserved_fd(fd: i32) -> Result<(), LibcontainerError> {
+    let flags = fcntl(fd, FcntlArg::F_GETFD).map_err(|err| {
+        tracing::error!(fd, ?err, "preserved fd is not open");
+        ErrInvalidTenant::PreserveFdNotOpen(fd)
+    })?;
+    if FdFlag::from_bits_truncate(flags).contains(FdFlag::FD_CLOEXEC) {
+        tracing::error!(fd, "preserved fd was opened by youki");
+        Err(ErrInvalidTenant::PreserveFdNotOpen(fd))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
+    use std::os::fd::AsRawFd;
 
     use caps::Capability as Cap;
+    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
     use oci_spec::runtime::{
         Capabilities, Capability as SpecCap, LinuxCapabilities, ProcessBuilder, Spec, SpecBuilder,
     };
 
-    use super::{get_capabilities, LibcontainerError};
+    use super::{get_capabilities, validate_preserved_fd, LibcontainerError};
     use crate::capabilities::CapabilityExt;
+    use crate::container::builder::ContainerBuilder;
+    use crate::error::ErrInvalidTenant;
+    use crate::syscall::syscall::SyscallType;
 
     fn get_spec(caps: LinuxCapabilities) -> Spec {
         SpecBuilder::default()
@@ -767,4 +972,110 @@ mod test {
 
         Ok(())
     }
+
+    fn get_labeled_spec(label: &str, profile: &str) -> Spec {
+        SpecBuilder::default()
+            .process(
+                ProcessBuilder::default()
+                    .selinux_label(label)
+                    .apparmor_profile(profile)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_process_label() -> Result<(), LibcontainerError> {
+        let spec = get_labeled_spec("system_u:system_r:container_t:s0:c1,c2", "youki");
+        // without a label asked for, the label isn't changed
+        let builder = ContainerBuilder::new("test".to_owned(), SyscallType::default()).as_tenant();
+        assert_eq!(builder.get_process_label(&spec)?, None);
+
+        let builder = builder.with_process_label(Some("system_u:system_r:spc_t:s0:c1,c2".into()));
+        assert_eq!(
+            builder.get_process_label(&spec)?.as_deref(),
+            Some("system_u:system_r:spc_t:s0:c1,c2")
+        );
+
+        let builder = builder.with_process_label(Some("system_u:system_r:spc_t:s0".into()));
+        assert!(matches!(
+            builder.get_process_label(&spec),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::ProcessLabelLevel { .. }
+            ))
+        ));
+
+        let builder = builder.with_process_label(Some("spc_t".into()));
+        assert!(matches!(
+            builder.get_process_label(&spec),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::InvalidProcessLabel(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apparmor_profile() -> Result<(), LibcontainerError> {
+        let spec = get_labeled_spec("system_u:system_r:container_t:s0", "youki");
+        let builder = ContainerBuilder::new("test".to_owned(), SyscallType::default()).as_tenant();
+        assert_eq!(
+            builder.get_apparmor_profile(&spec)?.as_deref(),
+            Some("youki")
+        );
+
+        let builder = builder.with_apparmor_profile(Some("unconfined".into()));
+        assert!(matches!(
+            builder.get_apparmor_profile(&spec),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::AppArmorUnconfined(_)
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_preserve_fds() {
+        let builder = ContainerBuilder::new("test".to_owned(), SyscallType::default())
+            .with_preserved_fds(-1)
+            .as_tenant();
+        assert!(matches!(
+            builder.validate_preserve_fds(),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::NegativePreserveFds(-1)
+            ))
+        ));
+
+        // fds are opened from the lowest free number, so one after a large
+        // number of preserved fds isn't open
+        let builder = ContainerBuilder::new("test".to_owned(), SyscallType::default())
+            .with_preserved_fds(4000)
+            .as_tenant();
+        assert!(matches!(
+            builder.validate_preserve_fds(),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::PreserveFdNotOpen(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_validate_preserved_fd() {
+        // files opened by the process itself are close-on-exec
+        let file = std::fs::File::open("/dev/null").unwrap();
+        let fd = file.as_raw_fd();
+        assert!(matches!(
+            validate_preserved_fd(fd),
+            Err(LibcontainerError::InvalidTenant(
+                ErrInvalidTenant::PreserveFdNotOpen(_)
+            ))
+        ));
+
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty())).unwrap();
+        assert!(validate_preserved_fd(fd).is_ok());
+    }
 }
@@ -1,3 +1,7 @@
+use crate::process::channel::ChannelError;
+use crate::process::container_main_process::ProcessError;
+use crate::process::failure::{ContainerProcessError, FailedProcess};
+
 #[derive(Debug, thiserror::Error)]
 pub enum MissingSpecError {
     #[error("missing process in spec")]
@@ -14,6 +18,8 @@ pub enum MissingSpecError {
 pub enum LibcontainerError {
     #[error("failed to perform operation due to incorrect container status")]
     IncorrectStatus,
+    #[error("container is paused")]
+    Paused,
     #[error("container already exists")]
     Exist,
     #[error("container state directory does not exist")]
@@ -32,6 +38,8 @@ pub enum LibcontainerError {
     MissingSpec(#[from] MissingSpecError),
     #[error("invalid runtime spec")]
     InvalidSpec(#[from] ErrInvalidSpec),
+    #[error(transparent)]
+    InvalidTenant(#[from] ErrInvalidTenant),
 
     // Errors from submodules and other errors
     #[error(transparent)]
@@ -39,6 +47,12 @@ pub enum LibcontainerError {
     #[error(transparent)]
     UserNamespace(#[from] crate::user_ns::UserNamespaceError),
     #[error(transparent)]
+    Network(#[from] crate::network::NetworkError),
+    #[error(transparent)]
+    Rootfs(#[from] crate::rootfs::RootfsError),
+    #[error(transparent)]
+    CapabilityAudit(#[from] crate::capability_audit::CapabilityAuditError),
+    #[error(transparent)]
     NotifyListener(#[from] crate::notify_socket::NotifyListenerError),
     #[error(transparent)]
     Config(#[from] crate::config::ConfigError),
@@ -49,7 +63,11 @@ pub enum LibcontainerError {
     #[error("oci spec error")]
     Spec(#[from] oci_spec::OciSpecError),
     #[error(transparent)]
-    MainProcess(#[from] crate::process::container_main_process::ProcessError),
+    MainProcess(ProcessError),
+    #[error(transparent)]
+    IntermediateProcess(Box<ContainerProcessError>),
+    #[error(transparent)]
+    InitProcess(Box<ContainerProcessError>),
     #[error(transparent)]
     Procfs(#[from] procfs::ProcError),
     #[error(transparent)]
@@ -60,8 +78,12 @@ pub enum LibcontainerError {
     CgroupCreate(#[from] libcgroups::common::CreateCgroupSetupError),
     #[error(transparent)]
     CgroupGet(#[from] libcgroups::common::GetCgroupSetupError),
+    #[error(transparent)]
+    SubCgroup(#[from] libcgroups::sub_cgroup::SubCgroupError),
     #[error[transparent]]
     Checkpoint(#[from] crate::container::CheckpointError),
+    #[error(transparent)]
+    DeviceHotplug(#[from] crate::container::DeviceHotplugError),
     #[error[transparent]]
     CreateContainerError(#[from] CreateContainerError),
 
@@ -78,6 +100,24 @@ pub enum LibcontainerError {
     Other(String),
 }
 
+impl From<ProcessError> for LibcontainerError {
+    fn from(err: ProcessError) -> Self {
+        match err {
+            ProcessError::Channel(ChannelError::ProcessFailed(err)) => err.into(),
+            err => LibcontainerError::MainProcess(err),
+        }
+    }
+}
+
+impl From<Box<ContainerProcessError>> for LibcontainerError {
+    fn from(err: Box<ContainerProcessError>) -> Self {
+        match err.process() {
+            FailedProcess::Intermediate => LibcontainerError::IntermediateProcess(err),
+            FailedProcess::Init => LibcontainerError::InitProcess(err),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ErrInvalidID {
     #[error("container id can't be empty")]
@@ -100,7 +140,27 @@ pub enum ErrInvalidSpec {
     Scheduler,
 }
 
+/// Options of a process executed in an existing container which would go
+/// beyond what the container allows or can't be applied
 #[derive(Debug, thiserror::Error)]
+pub enum ErrInvalidTenant {
+    #[error("unknown capability {0}")]
+    UnknownCapability(String),
+    #[error("AppArmor profile {0} was requested, but AppArmor is not enabled")]
+    AppArmorNotEnabled(String),
+    #[error("process can't run unconfined in a container confined by AppArmor profile {0}")]
+    AppArmorUnconfined(String),
+    #[error("invalid SELinux process label {0}")]
+    InvalidProcessLabel(String),
+    #[error("process label {label} doesn't keep the level {level} of the container")]
+    ProcessLabelLevel { label: String, level: String },
+    #[error("number of preserved fds must not be negative, got {0}")]
+    NegativePreserveFds(i32),
+    #[error("preserved fd {0} was not passed in by the caller")]
+    PreserveFdNotOpen(i32),
+}
+
+#[derive(Debug)]
 pub struct CreateContainerError(Box<LibcontainerError>, Option<Box<LibcontainerError>>);
 
 impl CreateContainerError {
@@ -110,6 +170,11 @@ impl CreateContainerError {
     ) -> Self {
         Self(Box::new(run_error), cleanup_error.map(Box::new))
     }
+
+    /// The error which made the creation fail
+    pub fn run_error(&self) -> &LibcontainerError {
+        &self.0
+    }
 }
 
 impl std::fmt::Display for CreateContainerError {
@@ -122,11 +187,25 @@ impl std::fmt::Display for CreateContainerError {
     }
 }
 
+impl std::error::Error for CreateContainerError {
+    // The message of the run error is already part of the message, so the
+    // chain continues with its source.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::error::Error;
+
     use libcgroups::common::CreateCgroupSetupError;
+    use nix::errno::Errno;
 
-    use super::{CreateContainerError, ErrInvalidID};
+    use super::{CreateContainerError, ErrInvalidID, LibcontainerError};
+    use crate::process::channel::ChannelError;
+    use crate::process::container_main_process::ProcessError;
+    use crate::process::failure::{FailedProcess, ProcessFailure};
 
     #[test]
     fn test_create_container() {
@@ -149,4 +228,32 @@ mod tests {
             msg
         );
     }
+
+    #[test]
+    fn test_init_process_error() {
+        let failure = ProcessFailure {
+            process: FailedProcess::Init,
+            stage: "rootfs".to_owned(),
+            syscall: Some("mount".to_owned()),
+            errno: Some(Errno::ENOENT as i32),
+            path: None,
+            chain: vec!["failed to prepare rootfs".to_owned()],
+        };
+        let err = LibcontainerError::from(ProcessError::Channel(failure.into()));
+        let LibcontainerError::InitProcess(process_err) = &err else {
+            panic!("expected an init process error, got {err:?}");
+        };
+        assert_eq!(process_err.stage(), "rootfs");
+        assert_eq!(process_err.errno(), Some(Errno::ENOENT));
+
+        // The source chain of the init process is kept through the creation error
+        let create_container_err = CreateContainerError::new(err, None);
+        assert_eq!(
+            create_container_err.source().unwrap().to_string(),
+            "failed to prepare rootfs"
+        );
+
+        let err = LibcontainerError::from(ProcessError::Channel(ChannelError::MissingSeccompFds));
+        assert!(matches!(err, LibcontainerError::MainProcess(_)));
+    }
 }
@@ -0,0 +1,202 @@
+//! Helper processes which run next to a container, e.g. to provide its
+//! network. They are detached from the runtime, so that they outlive
+//! `youki create`, and usually exit on their own once the init process of the
+//! container is gone.
+use std::fs;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::fcntl::{open, OFlag};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal::{self, SigHandler, SigSet, Signal};
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType};
+use nix::sys::stat::Mode;
+use nix::sys::wait::waitpid;
+use nix::unistd::{self, ForkResult, Pid};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HelperProcessError {
+    #[error("failed to start helper process")]
+    Start(#[source] nix::Error),
+    #[error("failed to communicate with helper process")]
+    Communicate(#[source] nix::Error),
+    #[error("helper process exited before it was ready")]
+    ExitedEarly,
+    #[error("helper process failed: {0}")]
+    Failed(String),
+}
+
+type Result<T> = std::result::Result<T, HelperProcessError>;
+
+#[derive(Debug, Serialize, Deserialize)]
+enum HelperStatus {
+    Ready(i32),
+    Failed(String),
+}
+
+/// Lets the helper report that it is ready, which [`spawn`] waits for
+pub(crate) struct Readiness {
+    sock: OwnedFd,
+}
+
+impl Readiness {
+    pub fn ready(&self) -> Result<()> {
+        self.send(&HelperStatus::Ready(unistd::getpid().as_raw()))
+    }
+
+    fn send(&self, status: &HelperStatus) -> Result<()> {
+        let buf = serde_json::to_vec(status)
+            .map_err(|err| HelperProcessError::Failed(err.to_string()))?;
+        unistd::write(&self.sock, &buf).map_err(HelperProcessError::Communicate)?;
+        Ok(())
+    }
+}
+
+/// Forks a helper process named `name`, which runs `run`. The fds in `keep`
+/// stay open in the helper, all others except stdio, which is redirected to
+/// /dev/null, are closed. Returns the pid of the helper once it is ready; if
+/// `run` fails before, its error is returned.
+pub(crate) fn spawn<F>(name: &str, keep: &[RawFd], run: F) -> Result<Pid>
+where
+    F: FnOnce(&Readiness) -> std::result::Result<(), String>,
+{
+    let (parent_sock, child_sock) = socket::socketpair(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        None,
+        SockFlag::SOCK_CLOEXEC,
+    )
+    .map_err(HelperProcessError::Start)?;
+
+    // The helper is forked twice, so that it is reparented away from the
+    // runtime and isn't left as a zombie of it.
+    match unsafe { unistd::fork() }.map_err(HelperProcessError::Start)? {
+        ForkResult::Child => {
+            drop(parent_sock);
+            let readiness = Readiness { sock: child_sock };
+            let _ = unistd::setsid();
+            let status = match unsafe { unistd::fork() } {
+                Ok(ForkResult::Child) => {
+                    let mut keep = keep.to_vec();
+                    keep.push(readiness.sock.as_raw_fd());
+                    match detach(name, &keep)
+                        .map_err(|err| err.to_string())
+                        .and_then(|_| run(&readiness))
+                    {
+                        Ok(()) => 0,
+                        Err(err) => {
+                            let _ = readiness.send(&HelperStatus::Failed(err));
+                            1
+                        }
+                    }
+                }
+                Ok(ForkResult::Parent { .. }) => 0,
+                Err(err) => {
+                    let _ = readiness.send(&HelperStatus::Failed(err.to_string()));
+                    1
+                }
+            };
+            std::process::exit(status);
+        }
+        ForkResult::Parent { child } => {
+            drop(child_sock);
+            waitpid(child, None).map_err(HelperProcessError::Start)?;
+
+            let mut buf = [0u8; 4096];
+            let n = unistd::read(parent_sock.as_raw_fd(), &mut buf)
+                .map_err(HelperProcessError::Communicate)?;
+            if n == 0 {
+                return Err(HelperProcessError::ExitedEarly);
+            }
+            match serde_json::from_slice(&buf[..n]) {
+                Ok(HelperStatus::Ready(pid)) => Ok(Pid::from_raw(pid)),
+                Ok(HelperStatus::Failed(err)) => Err(HelperProcessError::Failed(err)),
+                Err(err) => Err(HelperProcessError::Failed(format!("invalid status: {err}"))),
+            }
+        }
+    }
+}
+
+/// Sends SIGTERM to the helper, if it is still running.
+pub(crate) fn stop(pid: Pid, name: &str) -> Result<()> {
+    if !is_helper(pid, name) {
+        return Ok(());
+    }
+
+    match signal::kill(pid, Signal::SIGTERM) {
+        Ok(()) | Err(Errno::ESRCH) => Ok(()),
+        Err(err) => Err(HelperProcessError::Communicate(err)),
+    }
+}
+
+/// Waits up to `timeout` for the helper to exit on its own. Returns whether
+/// it is gone.
+pub(crate) fn wait(pid: Pid, name: &str, timeout: Duration) -> Result<bool> {
+    if !is_helper(pid, name) {
+        return Ok(true);
+    }
+
+    // The helper is detached, so it can't be waited for with waitpid
+    if let Some(pidfd) = pidfd_open(pid) {
+        let mut fds = [PollFd::new(pidfd.as_fd(), PollFlags::POLLIN)];
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        return match poll(&mut fds, timeout) {
+            Ok(n) => Ok(n > 0),
+            Err(err) => Err(HelperProcessError::Communicate(err)),
+        };
+    }
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if signal::kill(pid, None) == Err(Errno::ESRCH) {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(!is_helper(pid, name))
+}
+
+/// Returns a pidfd of a process, which becomes readable once it exits. Older
+/// kernels don't have pidfds, helpers have to check periodically for the
+/// process then.
+pub(crate) fn pidfd_open(pid: Pid) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    if fd < 0 {
+        return None;
+    }
+    // SAFETY: pidfd_open returned a new fd
+    Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// Checks if the process is still the helper, the pid may have been reused
+/// after the helper exited.
+fn is_helper(pid: Pid, name: &str) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/comm")).map_or(false, |comm| comm.trim_end() == name)
+}
+
+fn detach(name: &str, keep: &[RawFd]) -> Result<()> {
+    let _ = prctl::set_name(name);
+    // The runtime blocks signals while it forwards them to the container
+    let _ = SigSet::all().thread_unblock();
+    let _ = unsafe { signal::signal(Signal::SIGTERM, SigHandler::SigDfl) };
+
+    // Nobody is waiting on the stdio of the runtime on our behalf
+    let null =
+        open("/dev/null", OFlag::O_RDWR, Mode::empty()).map_err(HelperProcessError::Start)?;
+    for fd in 0..3 {
+        unistd::dup2(null, fd).map_err(HelperProcessError::Start)?;
+    }
+
+    let fds: Vec<RawFd> = fs::read_dir("/proc/self/fd")
+        .map_err(|err| HelperProcessError::Failed(err.to_string()))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    for fd in fds {
+        if fd > 2 && !keep.contains(&fd) {
+            let _ = unistd::close(fd);
+        }
+    }
+    Ok(())
+}
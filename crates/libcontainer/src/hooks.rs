@@ -1,28 +1,102 @@
 use std::collections::HashMap;
-use std::io::{ErrorKind, Write};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
 use std::os::unix::prelude::CommandExt;
-use std::path::Path;
-use std::{process, thread, time};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{process, thread};
 
 use nix::sys::signal;
 use nix::unistd::Pid;
 use oci_spec::runtime::Hook;
 
-use crate::container::Container;
+use crate::container::{Container, State};
 use crate::utils;
 
+/// Maximum number of bytes kept of the stdout and of the stderr of a hook
+const MAX_OUTPUT_SIZE: usize = 4096;
+/// How long to wait for the end of the output of a hook after it exited. A
+/// hook may leave processes behind which keep its stdout or stderr open.
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// The point of the container lifecycle hooks are run at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    Prestart,
+    CreateRuntime,
+    CreateContainer,
+    StartContainer,
+    Poststart,
+    Poststop,
+}
+
+impl fmt::Display for HookPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Named like the hooks in the runtime spec
+        let name = match self {
+            HookPhase::Prestart => "prestart",
+            HookPhase::CreateRuntime => "createRuntime",
+            HookPhase::CreateContainer => "createContainer",
+            HookPhase::StartContainer => "startContainer",
+            HookPhase::Poststart => "poststart",
+            HookPhase::Poststop => "poststop",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Captured stdout and stderr of a hook, truncated to `MAX_OUTPUT_SIZE` bytes
+/// each
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl fmt::Display for HookOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.stdout.is_empty() {
+            write!(f, ", stdout: {:?}", self.stdout)?;
+        }
+        if !self.stderr.is_empty() {
+            write!(f, ", stderr: {:?}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum HookError {
-    #[error("failed to execute hook command")]
-    CommandExecute(#[source] std::io::Error),
+    #[error("failed to execute {phase} hook {path:?}")]
+    CommandExecute {
+        phase: HookPhase,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("failed to encode container state")]
     EncodeContainerState(#[source] serde_json::Error),
-    #[error("hook command exited with non-zero exit code: {0}")]
-    NonZeroExitCode(i32),
-    #[error("hook command was killed by a signal")]
-    Killed,
-    #[error("failed to execute hook command due to a timeout")]
-    Timeout,
+    #[error("{phase} hook {path:?} exited with non-zero exit code {exit_code}{output}")]
+    NonZeroExitCode {
+        phase: HookPhase,
+        path: PathBuf,
+        exit_code: i32,
+        output: HookOutput,
+    },
+    #[error("{phase} hook {path:?} was killed by a signal{output}")]
+    Killed {
+        phase: HookPhase,
+        path: PathBuf,
+        output: HookOutput,
+    },
+    #[error("{phase} hook {path:?} timed out after {timeout:?}{output}")]
+    Timeout {
+        phase: HookPhase,
+        path: PathBuf,
+        timeout: Duration,
+        output: HookOutput,
+    },
     #[error("container state is required to run hook")]
     MissingContainerState,
     #[error("failed to write container state to stdin")]
@@ -31,120 +105,247 @@ pub enum HookError {
 
 type Result<T> = std::result::Result<T, HookError>;
 
+/// Runs the hooks of a phase one after another. Hooks without a timeout of
+/// their own are run with the default hook timeout of the container, if set.
 pub fn run_hooks(
     hooks: Option<&Vec<Hook>>,
+    phase: HookPhase,
     container: Option<&Container>,
     cwd: Option<&Path>,
 ) -> Result<()> {
-    let state = &(container.ok_or(HookError::MissingContainerState)?.state);
+    let container = container.ok_or(HookError::MissingContainerState)?;
 
     if let Some(hooks) = hooks {
         for hook in hooks {
-            let mut hook_command = process::Command::new(hook.path());
+            let span = tracing::info_span!(
+                "hook",
+                %phase,
+                path = %hook.path().display(),
+                duration_ms = tracing::field::Empty,
+            );
+            let _enter = span.enter();
+            let start = Instant::now();
+            let res = run_hook(hook, phase, &container.state, container.hook_timeout(), cwd);
+            let duration_ms = start.elapsed().as_millis() as u64;
+            span.record("duration_ms", duration_ms);
+            tracing::debug!(duration_ms, success = res.is_ok(), "hook finished");
+            res?;
+        }
+    }
 
-            if let Some(cwd) = cwd {
-                hook_command.current_dir(cwd);
-            }
+    Ok(())
+}
 
-            // Based on OCI spec, the first argument of the args vector is the
-            // arg0, which can be different from the path.  For example, path
-            // may be "/usr/bin/true" and arg0 is set to "true". However, rust
-            // command differentiates arg0 from args, where rust command arg
-            // doesn't include arg0. So we have to make the split arg0 from the
-            // rest of args.
-            if let Some((arg0, args)) = hook.args().as_ref().and_then(|a| a.split_first()) {
-                tracing::debug!("run_hooks arg0: {:?}, args: {:?}", arg0, args);
-                hook_command.arg0(arg0).args(args)
-            } else {
-                hook_command.arg0(hook.path().display().to_string())
-            };
-
-            let envs: HashMap<String, String> = if let Some(env) = hook.env() {
-                utils::parse_env(env)
-            } else {
-                HashMap::new()
-            };
-            tracing::debug!("run_hooks envs: {:?}", envs);
-
-            let mut hook_process = hook_command
-                .env_clear()
-                .envs(envs)
-                .stdin(process::Stdio::piped())
-                .spawn()
-                .map_err(HookError::CommandExecute)?;
-            let hook_process_pid = Pid::from_raw(hook_process.id() as i32);
-            // Based on the OCI spec, we need to pipe the container state into
-            // the hook command through stdin.
-            if let Some(stdin) = &mut hook_process.stdin {
-                // We want to ignore BrokenPipe here. A BrokenPipe indicates
-                // either the hook is crashed/errored or it ran successfully.
-                // Either way, this is an indication that the hook command
-                // finished execution.  If the hook command was successful,
-                // which we will check later in this function, we should not
-                // fail this step here. We still want to check for all the other
-                // error, in the case that the hook command is waiting for us to
-                // write to stdin.
-                let encoded_state =
-                    serde_json::to_string(state).map_err(HookError::EncodeContainerState)?;
-                if let Err(e) = stdin.write_all(encoded_state.as_bytes()) {
-                    if e.kind() != ErrorKind::BrokenPipe {
-                        // Not a broken pipe. The hook command may be waiting
-                        // for us.
-                        let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
-                        return Err(HookError::WriteContainerState(e));
-                    }
-                }
+fn run_hook(
+    hook: &Hook,
+    phase: HookPhase,
+    state: &State,
+    default_timeout: Option<Duration>,
+    cwd: Option<&Path>,
+) -> Result<()> {
+    let mut hook_command = process::Command::new(hook.path());
+
+    if let Some(cwd) = cwd {
+        hook_command.current_dir(cwd);
+    }
+
+    // Based on OCI spec, the first argument of the args vector is the
+    // arg0, which can be different from the path.  For example, path
+    // may be "/usr/bin/true" and arg0 is set to "true". However, rust
+    // command differentiates arg0 from args, where rust command arg
+    // doesn't include arg0. So we have to make the split arg0 from the
+    // rest of args.
+    if let Some((arg0, args)) = hook.args().as_ref().and_then(|a| a.split_first()) {
+        tracing::debug!("run_hooks arg0: {:?}, args: {:?}", arg0, args);
+        hook_command.arg0(arg0).args(args)
+    } else {
+        hook_command.arg0(hook.path().display().to_string())
+    };
+
+    let envs: HashMap<String, String> = if let Some(env) = hook.env() {
+        utils::parse_env(env)
+    } else {
+        HashMap::new()
+    };
+    tracing::debug!("run_hooks envs: {:?}", envs);
+
+    let mut hook_process = hook_command
+        .env_clear()
+        .envs(envs)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|err| HookError::CommandExecute {
+            phase,
+            path: hook.path().to_owned(),
+            source: err,
+        })?;
+    let hook_process_pid = Pid::from_raw(hook_process.id() as i32);
+    // The output is read while the hook runs, so a hook writing more than
+    // fits into the pipe doesn't block. It is passed through as well, as if
+    // the hook had inherited stdout and stderr.
+    let stdout = hook_process
+        .stdout
+        .take()
+        .map(|stdout| OutputCapture::spawn(stdout, std::io::stdout()));
+    let stderr = hook_process
+        .stderr
+        .take()
+        .map(|stderr| OutputCapture::spawn(stderr, std::io::stderr()));
+    let output = move || HookOutput {
+        stdout: stdout.map(OutputCapture::finish).unwrap_or_default(),
+        stderr: stderr.map(OutputCapture::finish).unwrap_or_default(),
+    };
+
+    // Based on the OCI spec, we need to pipe the container state into
+    // the hook command through stdin.
+    if let Some(stdin) = &mut hook_process.stdin {
+        // We want to ignore BrokenPipe here. A BrokenPipe indicates
+        // either the hook is crashed/errored or it ran successfully.
+        // Either way, this is an indication that the hook command
+        // finished execution.  If the hook command was successful,
+        // which we will check later in this function, we should not
+        // fail this step here. We still want to check for all the other
+        // error, in the case that the hook command is waiting for us to
+        // write to stdin.
+        let encoded_state =
+            serde_json::to_string(state).map_err(HookError::EncodeContainerState)?;
+        if let Err(e) = stdin.write_all(encoded_state.as_bytes()) {
+            if e.kind() != ErrorKind::BrokenPipe {
+                // Not a broken pipe. The hook command may be waiting
+                // for us.
+                let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
+                return Err(HookError::WriteContainerState(e));
             }
+        }
+    }
 
-            let res = if let Some(timeout_sec) = hook.timeout() {
-                // Rust does not make it easy to handle executing a command and
-                // timeout. Here we decided to wait for the command in a
-                // different thread, so the main thread is not blocked. We use a
-                // channel shared between main thread and the wait thread, since
-                // the channel has timeout functions out of the box. Rust won't
-                // let us copy the Command structure, so we can't share it
-                // between the wait thread and main thread. Therefore, we will
-                // use pid to identify the process and send a kill signal. This
-                // is what the Command.kill() does under the hood anyway. When
-                // timeout, we have to kill the process and clean up properly.
-                let (s, r) = std::sync::mpsc::channel();
-                thread::spawn(move || {
-                    let res = hook_process.wait();
-                    let _ = s.send(res);
+    let timeout = hook
+        .timeout()
+        .map(|timeout_sec| Duration::from_secs(timeout_sec as u64))
+        .or(default_timeout);
+    let res = if let Some(timeout) = timeout {
+        // Rust does not make it easy to handle executing a command and
+        // timeout. Here we decided to wait for the command in a
+        // different thread, so the main thread is not blocked. We use a
+        // channel shared between main thread and the wait thread, since
+        // the channel has timeout functions out of the box. Rust won't
+        // let us copy the Command structure, so we can't share it
+        // between the wait thread and main thread. Therefore, we will
+        // use pid to identify the process and send a kill signal. This
+        // is what the Command.kill() does under the hood anyway. When
+        // timeout, we have to kill the process and clean up properly.
+        let (s, r) = mpsc::channel();
+        thread::spawn(move || {
+            let res = hook_process.wait();
+            let _ = s.send(res);
+        });
+        match r.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // Kill the process. There is no need to further clean
+                // up because we will be error out.
+                let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
+                return Err(HookError::Timeout {
+                    phase,
+                    path: hook.path().to_owned(),
+                    timeout,
+                    output: output(),
                 });
-                match r.recv_timeout(time::Duration::from_secs(timeout_sec as u64)) {
-                    Ok(res) => res,
-                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                        // Kill the process. There is no need to further clean
-                        // up because we will be error out.
-                        let _ = signal::kill(hook_process_pid, signal::Signal::SIGKILL);
-                        return Err(HookError::Timeout);
-                    }
-                    Err(_) => {
-                        unreachable!();
+            }
+            Err(_) => {
+                unreachable!();
+            }
+        }
+    } else {
+        hook_process.wait()
+    };
+
+    let exit_status = res.map_err(|err| HookError::CommandExecute {
+        phase,
+        path: hook.path().to_owned(),
+        source: err,
+    })?;
+    let output = output();
+    match exit_status.code() {
+        Some(0) => Ok(()),
+        Some(exit_code) => Err(HookError::NonZeroExitCode {
+            phase,
+            path: hook.path().to_owned(),
+            exit_code,
+            output,
+        }),
+        None => Err(HookError::Killed {
+            phase,
+            path: hook.path().to_owned(),
+            output,
+        }),
+    }
+}
+
+#[derive(Default)]
+struct CapturedOutput {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+/// Reads a stdout or stderr pipe of a hook in a background thread, and
+/// passes what it reads through to `sink`
+struct OutputCapture {
+    captured: Arc<Mutex<CapturedOutput>>,
+    done: mpsc::Receiver<()>,
+}
+
+impl OutputCapture {
+    fn spawn(
+        mut reader: impl Read + Send + 'static,
+        mut sink: impl Write + Send + 'static,
+    ) -> Self {
+        let captured = Arc::new(Mutex::new(CapturedOutput::default()));
+        let (done_sender, done) = mpsc::channel();
+        let thread_captured = captured.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        let _ = sink.write_all(&buf[..n]);
+                        // Keep reading past the limit, as the hook would
+                        // block on a full pipe otherwise.
+                        let mut captured =
+                            thread_captured.lock().unwrap_or_else(|e| e.into_inner());
+                        let room = MAX_OUTPUT_SIZE - captured.data.len();
+                        captured.data.extend_from_slice(&buf[..n.min(room)]);
+                        captured.truncated |= n > room;
                     }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
                 }
-            } else {
-                hook_process.wait()
-            };
-
-            match res {
-                Ok(exit_status) => match exit_status.code() {
-                    Some(0) => Ok(()),
-                    Some(exit_code) => Err(HookError::NonZeroExitCode(exit_code)),
-                    None => Err(HookError::Killed),
-                },
-                Err(e) => Err(HookError::CommandExecute(e)),
-            }?;
-        }
+            }
+            let _ = done_sender.send(());
+        });
+
+        Self { captured, done }
     }
 
-    Ok(())
+    fn finish(self) -> String {
+        let _ = self.done.recv_timeout(OUTPUT_GRACE_PERIOD);
+        let captured = self.captured.lock().unwrap_or_else(|e| e.into_inner());
+        let mut output = String::from_utf8_lossy(&captured.data)
+            .trim_end()
+            .to_owned();
+        if captured.truncated {
+            output.push_str("...");
+        }
+        output
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use std::{env, fs};
+    use std::{env, fs, time};
 
     use anyhow::{bail, Context, Result};
     use oci_spec::runtime::HookBuilder;
@@ -176,7 +377,8 @@ mod test {
     fn test_run_hook() -> Result<()> {
         {
             let default_container: Container = Default::default();
-            run_hooks(None, Some(&default_container), None).context("Failed simple test")?;
+            run_hooks(None, HookPhase::Prestart, Some(&default_container), None)
+                .context("Failed simple test")?;
         }
 
         {
@@ -185,7 +387,13 @@ mod test {
 
             let hook = HookBuilder::default().path("true").build()?;
             let hooks = Some(vec![hook]);
-            run_hooks(hooks.as_ref(), Some(&default_container), None).context("Failed true")?;
+            run_hooks(
+                hooks.as_ref(),
+                HookPhase::Prestart,
+                Some(&default_container),
+                None,
+            )
+            .context("Failed true")?;
         }
 
         {
@@ -205,8 +413,13 @@ mod test {
                 .env(vec![String::from("key=value")])
                 .build()?;
             let hooks = Some(vec![hook]);
-            run_hooks(hooks.as_ref(), Some(&default_container), None)
-                .context("Failed printenv test")?;
+            run_hooks(
+                hooks.as_ref(),
+                HookPhase::Prestart,
+                Some(&default_container),
+                None,
+            )
+            .context("Failed printenv test")?;
         }
 
         {
@@ -224,8 +437,13 @@ mod test {
                 ])
                 .build()?;
             let hooks = Some(vec![hook]);
-            run_hooks(hooks.as_ref(), Some(&default_container), Some(tmp.path()))
-                .context("Failed pwd test")?;
+            run_hooks(
+                hooks.as_ref(),
+                HookPhase::Prestart,
+                Some(&default_container),
+                Some(tmp.path()),
+            )
+            .context("Failed pwd test")?;
         }
 
         Ok(())
@@ -248,11 +466,16 @@ mod test {
             .timeout(1)
             .build()?;
         let hooks = Some(vec![hook]);
-        match run_hooks(hooks.as_ref(), Some(&default_container), None) {
+        match run_hooks(
+            hooks.as_ref(),
+            HookPhase::Prestart,
+            Some(&default_container),
+            None,
+        ) {
             Ok(_) => {
                 bail!("The test expects the hook to error out with timeout. Should not execute cleanly");
             }
-            Err(HookError::Timeout) => {}
+            Err(HookError::Timeout { .. }) => {}
             Err(err) => {
                 bail!(
                     "The test expects the hook to error out with timeout. Got error: {}",
@@ -263,4 +486,69 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    #[serial]
+    fn test_run_hook_default_timeout() -> Result<()> {
+        let mut container: Container = Default::default();
+        container.set_hook_timeout(Some(time::Duration::from_secs(1)));
+        let hook = HookBuilder::default()
+            .path("sleep")
+            .args(vec![String::from("sleep"), String::from("10")])
+            .build()?;
+        let hooks = Some(vec![hook]);
+        match run_hooks(hooks.as_ref(), HookPhase::Poststop, Some(&container), None) {
+            Err(HookError::Timeout { phase, timeout, .. }) => {
+                assert_eq!(phase, HookPhase::Poststop);
+                assert_eq!(timeout, time::Duration::from_secs(1));
+            }
+            res => bail!("expected the hook to time out, got {:?}", res),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_hook_output() -> Result<()> {
+        let default_container: Container = Default::default();
+        let hook = HookBuilder::default()
+            .path("bash")
+            .args(vec![
+                String::from("bash"),
+                String::from("-c"),
+                String::from("echo out; head -c 10000 /dev/zero | tr '\\0' e >&2; exit 3"),
+            ])
+            .build()?;
+        let hooks = Some(vec![hook]);
+        let err = match run_hooks(
+            hooks.as_ref(),
+            HookPhase::CreateRuntime,
+            Some(&default_container),
+            None,
+        ) {
+            Err(err) => err,
+            Ok(_) => bail!("expected the hook to fail"),
+        };
+        let HookError::NonZeroExitCode {
+            phase,
+            path,
+            exit_code,
+            output,
+        } = &err
+        else {
+            bail!("expected a non-zero exit code, got {:?}", err);
+        };
+        assert_eq!(*phase, HookPhase::CreateRuntime);
+        assert_eq!(path, Path::new("bash"));
+        assert_eq!(*exit_code, 3);
+        assert_eq!(output.stdout, "out");
+        assert_eq!(output.stderr.len(), MAX_OUTPUT_SIZE + 3);
+        assert!(output.stderr.ends_with("..."));
+        assert!(err.to_string().starts_with(
+            "createRuntime hook \"bash\" exited with non-zero exit code 3, stdout: \"out\""
+        ));
+
+        Ok(())
+    }
 }
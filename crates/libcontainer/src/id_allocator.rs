@@ -0,0 +1,313 @@
+//! Automatic allocation of ids for the user namespaces of rootful containers,
+//! i.e. "userns=auto". Every container gets a block of ids of a pool which no
+//! other container uses, so that the containers are isolated from each other
+//! as well as from the host. The allocations are recorded in the root path,
+//! until the containers are deleted.
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use nix::fcntl::{Flock, FlockArg};
+use oci_spec::runtime::{LinuxIdMapping, LinuxIdMappingBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::subid::{SubIdRange, DEFAULT_ID_COUNT};
+use crate::utils;
+
+const ALLOCATIONS_FILE: &str = "userns-allocations.json";
+const LOCK_FILE: &str = "userns-allocations.lock";
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdAllocatorError {
+    #[error("automatic user namespaces are only available for rootful containers")]
+    Rootless,
+    #[error("failed to lock {path:?}")]
+    Lock { path: PathBuf, source: nix::Error },
+    #[error("failed to access id allocations in {path:?}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid id allocations in {path:?}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("no free block of {0} ids left in the pool")]
+    Exhausted(u32),
+    #[error("failed to build id mapping")]
+    Build(#[from] oci_spec::OciSpecError),
+}
+
+type Result<T> = std::result::Result<T, IdAllocatorError>;
+
+/// A block of host ids allocated to a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdBlock {
+    pub start: u32,
+    pub size: u32,
+}
+
+impl IdBlock {
+    fn end(&self) -> u64 {
+        self.start as u64 + self.size as u64
+    }
+
+    fn overlaps(&self, other: &IdBlock) -> bool {
+        (self.start as u64) < other.end() && (other.start as u64) < self.end()
+    }
+
+    /// Maps the ids of the container from 0 on to the block
+    pub fn mapping(&self) -> Result<LinuxIdMapping> {
+        Ok(LinuxIdMappingBuilder::default()
+            .container_id(0_u32)
+            .host_id(self.start)
+            .size(self.size)
+            .build()?)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Allocations {
+    containers: BTreeMap<String, IdBlock>,
+}
+
+/// Allocates blocks of ids of a pool to containers. The same block is used
+/// for the uids and the gids of a container.
+///
+/// # Example
+///
+/// ```no_run
+/// use libcontainer::id_allocator::IdAllocator;
+/// use libcontainer::subid::SubIdRange;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let allocator = IdAllocator::new(
+///     "/run/youki",
+///     vec![SubIdRange {
+///         start: 1 << 20,
+///         count: 1 << 30,
+///     }],
+/// );
+/// let block = allocator.allocate("74f1a4cb3801")?;
+/// println!("ids {} to {}", block.start, block.start + block.size - 1);
+/// allocator.release("74f1a4cb3801")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdAllocator {
+    root_path: PathBuf,
+    pool: Vec<SubIdRange>,
+    block_size: u32,
+}
+
+impl IdAllocator {
+    pub fn new<P: Into<PathBuf>>(root_path: P, pool: Vec<SubIdRange>) -> Self {
+        Self {
+            root_path: root_path.into(),
+            pool,
+            block_size: DEFAULT_ID_COUNT,
+        }
+    }
+
+    /// Sets the count of ids allocated to a container
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Allocates a free block to a container, or returns the one it already
+    /// has
+    pub fn allocate(&self, container_id: &str) -> Result<IdBlock> {
+        if utils::rootless_required().map_err(|err| IdAllocatorError::Io {
+            path: self.root_path.clone(),
+            source: err,
+        })? {
+            return Err(IdAllocatorError::Rootless);
+        }
+
+        self.update(|allocations| {
+            if let Some(block) = allocations.containers.get(container_id) {
+                return Ok(*block);
+            }
+
+            let block = self
+                .free_block(allocations)
+                .ok_or(IdAllocatorError::Exhausted(self.block_size))?;
+            tracing::debug!(container_id, ?block, "allocated user namespace ids");
+            allocations
+                .containers
+                .insert(container_id.to_owned(), block);
+            Ok(block)
+        })
+    }
+
+    /// Releases the block of a container, if it has one
+    pub fn release(&self, container_id: &str) -> Result<()> {
+        release(&self.root_path, container_id)
+    }
+
+    fn free_block(&self, allocations: &Allocations) -> Option<IdBlock> {
+        let size = self.block_size as u64;
+        self.pool.iter().find_map(|range| {
+            let end = range.start as u64 + range.count as u64;
+            let mut start = range.start as u64;
+            while start + size <= end && start + size <= u32::MAX as u64 + 1 {
+                let block = IdBlock {
+                    start: start as u32,
+                    size: self.block_size,
+                };
+                match allocations
+                    .containers
+                    .values()
+                    .find(|allocated| allocated.overlaps(&block))
+                {
+                    // continue after the block in the way, which may have
+                    // another size
+                    Some(allocated) => start = start.max(allocated.end()),
+                    None => return Some(block),
+                }
+            }
+            None
+        })
+    }
+
+    fn update<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Allocations) -> Result<T>,
+    {
+        update(&self.root_path, f)
+    }
+}
+
+/// Releases the block of a container in a root path, if it has one
+pub fn release(root_path: &Path, container_id: &str) -> Result<()> {
+    if !root_path.join(ALLOCATIONS_FILE).exists() {
+        return Ok(());
+    }
+
+    update(root_path, |allocations| {
+        if let Some(block) = allocations.containers.remove(container_id) {
+            tracing::debug!(container_id, ?block, "released user namespace ids");
+        }
+        Ok(())
+    })
+}
+
+/// Runs `f` on the allocations of a root path, which are locked meanwhile,
+/// and saves them afterwards
+fn update<T, F>(root_path: &Path, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Allocations) -> Result<T>,
+{
+    let io_err = |path: &Path| {
+        let path = path.to_owned();
+        move |source| IdAllocatorError::Io { path, source }
+    };
+
+    let lock_path = root_path.join(LOCK_FILE);
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(io_err(&lock_path))?;
+    let _lock = Flock::lock(lock_file, FlockArg::LockExclusive).map_err(|(_, err)| {
+        IdAllocatorError::Lock {
+            path: lock_path.clone(),
+            source: err,
+        }
+    })?;
+
+    let path = root_path.join(ALLOCATIONS_FILE);
+    let mut allocations = match File::open(&path) {
+        Ok(file) => serde_json::from_reader(file).map_err(|source| IdAllocatorError::Parse {
+            path: path.clone(),
+            source,
+        })?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Allocations::default(),
+        Err(err) => return Err(io_err(&path)(err)),
+    };
+
+    let result = f(&mut allocations)?;
+
+    // the allocations are replaced at once, so that a crash can't leave
+    // them half written
+    let tmp_path = root_path.join(format!("{ALLOCATIONS_FILE}.tmp"));
+    let content = serde_json::to_vec(&allocations).map_err(|source| IdAllocatorError::Parse {
+        path: path.clone(),
+        source,
+    })?;
+    fs::write(&tmp_path, content).map_err(io_err(&tmp_path))?;
+    fs::rename(&tmp_path, &path).map_err(io_err(&path))?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn allocator(root_path: &Path) -> IdAllocator {
+        IdAllocator::new(
+            root_path,
+            vec![
+                SubIdRange {
+                    start: 100000,
+                    count: 20,
+                },
+                SubIdRange {
+                    start: 200000,
+                    count: 10,
+                },
+            ],
+        )
+        .with_block_size(10)
+    }
+
+    #[test]
+    fn test_allocate_and_release() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let allocator = allocator(tmp.path());
+
+        let first = allocator.allocate("first")?;
+        let second = allocator.allocate("second")?;
+        let third = allocator.allocate("third")?;
+        assert_eq!(
+            (first.start, second.start, third.start),
+            (100000, 100010, 200000)
+        );
+        assert_eq!(allocator.allocate("second")?, second);
+        assert!(matches!(
+            allocator.allocate("fourth"),
+            Err(IdAllocatorError::Exhausted(10))
+        ));
+
+        // released blocks are allocated again
+        allocator.release("first")?;
+        assert_eq!(allocator.allocate("fourth")?.start, 100000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocate_around_other_sizes() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        allocator(tmp.path()).with_block_size(5).allocate("small")?;
+
+        let block = allocator(tmp.path()).allocate("large")?;
+        assert_eq!(block.start, 100005);
+        assert_eq!(block.mapping()?.host_id(), 100005);
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_without_allocations() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        release(tmp.path(), "unknown")?;
+        assert!(!tmp.path().join(ALLOCATIONS_FILE).exists());
+        Ok(())
+    }
+}
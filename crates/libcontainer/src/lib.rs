@@ -1,19 +1,27 @@
 pub mod apparmor;
 pub mod capabilities;
+pub mod capability_audit;
 pub mod channel;
 pub mod config;
+pub mod console;
 pub mod container;
 pub mod error;
+pub mod helper_process;
 pub mod hooks;
+pub mod id_allocator;
 pub mod namespaces;
+pub mod network;
 pub mod notify_socket;
 pub mod process;
 pub mod rootfs;
 #[cfg(feature = "libseccomp")]
 pub mod seccomp;
+pub mod selinux;
 pub mod signal;
+pub mod subid;
 pub mod syscall;
 pub mod test_utils;
+pub mod timing;
 pub mod tty;
 pub mod user_ns;
 pub mod utils;
@@ -0,0 +1,93 @@
+//! The network helper process, which relays the traffic of the tap device of
+//! the container through the userspace network stack until the init process
+//! of the container exits.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::time::Instant;
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::signal;
+use nix::unistd::Pid;
+
+use super::stack::Stack;
+use super::{NetworkError, Result, SlirpConfig};
+use crate::helper_process::{self, Readiness};
+
+/// Name of the helper process, checked before it is signalled
+const HELPER_NAME: &str = "youki:[NET]";
+/// How long the helper sleeps at most when no traffic arrives
+const POLL_TIMEOUT_MS: u16 = 100;
+
+/// Starts the network helper for the container, relaying the traffic of the
+/// tap device, which was created in the network namespace of the container.
+/// Returns the pid of the helper once it has bound the forwarded ports.
+pub fn start(config: &SlirpConfig, tap: OwnedFd, init_pid: Pid) -> Result<Pid> {
+    let keep = [tap.as_raw_fd()];
+    let pid = helper_process::spawn(HELPER_NAME, &keep, |readiness| {
+        run(config, tap, init_pid, readiness).map_err(|err| err.to_string())
+    })?;
+    Ok(pid)
+}
+
+/// Stops the network helper, if it is still running. Usually it has already
+/// exited together with the init process of the container.
+pub fn stop(pid: Pid) -> Result<()> {
+    Ok(helper_process::stop(pid, HELPER_NAME)?)
+}
+
+fn run(config: &SlirpConfig, tap: OwnedFd, init_pid: Pid, readiness: &Readiness) -> Result<()> {
+    let pidfd = helper_process::pidfd_open(init_pid);
+    let mut stack = Stack::new(config).map_err(NetworkError::BindPort)?;
+    readiness.ready()?;
+
+    let mut tap = File::from(tap);
+    let mut buf = vec![0u8; config.mtu as usize + 14];
+    loop {
+        let (tap_ready, init_exited) = {
+            let mut fds = vec![PollFd::new(tap.as_fd(), PollFlags::POLLIN)];
+            if let Some(pidfd) = &pidfd {
+                fds.push(PollFd::new(pidfd.as_fd(), PollFlags::POLLIN));
+            }
+            fds.extend(stack.poll_fds());
+            match poll(&mut fds, PollTimeout::from(POLL_TIMEOUT_MS)) {
+                Ok(_) | Err(Errno::EINTR) => {}
+                Err(err) => return Err(NetworkError::Relay(err)),
+            }
+
+            let ready = |fd: &PollFd| fd.revents().map_or(false, |revents| !revents.is_empty());
+            let init_exited = match &pidfd {
+                Some(_) => ready(&fds[1]),
+                None => signal::kill(init_pid, None) == Err(Errno::ESRCH),
+            };
+            (ready(&fds[0]), init_exited)
+        };
+        if init_exited {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        if tap_ready {
+            loop {
+                match tap.read(&mut buf) {
+                    Ok(n) => stack.handle_frame(&buf[..n], now),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    // The tap device is gone with the network namespace
+                    Err(_) => return Ok(()),
+                }
+            }
+        }
+
+        stack.poll(now);
+        for frame in stack.take_frames() {
+            match tap.write(&frame) {
+                Ok(_) => {}
+                // The frame is dropped, the container retransmits
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => return Ok(()),
+            }
+        }
+    }
+}
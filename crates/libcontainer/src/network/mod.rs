@@ -0,0 +1,258 @@
+//! Built-in networking for containers which can't get a veth pair, most
+//! notably rootless ones. A helper process owns a tap device in the network
+//! namespace of the container and relays its traffic through a userspace
+//! network stack, like slirp4netns does. It is enabled by annotation:
+//!
+//! ```json
+//! "annotations": {
+//!     "run.oci.youki.network": "slirp",
+//!     "run.oci.youki.network.ports": "8080:80,127.0.0.1:5353:53/udp"
+//! }
+//! ```
+//!
+//! The container gets the address 10.0.2.100/24, with 10.0.2.2 as default
+//! gateway. Like slirp4netns with `--disable-host-loopback`, the loopback of
+//! the host can't be reached from the container, unless the
+//! `run.oci.youki.network.host-loopback` annotation is set to `true`, which
+//! makes connections to the gateway reach the loopback of the host.
+mod helper;
+pub mod packet;
+pub mod stack;
+mod tap;
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::str::FromStr;
+
+pub use helper::{start, stop};
+pub(crate) use tap::create_tap;
+
+use self::packet::MacAddr;
+
+/// Selects the network of the container
+pub const NETWORK_ANNOTATION: &str = "run.oci.youki.network";
+/// Comma separated ports of the host forwarded into the container, as
+/// `[host ip:]host port:container port[/tcp|/udp]`
+pub const PORTS_ANNOTATION: &str = "run.oci.youki.network.ports";
+/// Whether connections to the gateway reach the loopback of the host, `true`
+/// or `false`, which is the default
+pub const HOST_LOOPBACK_ANNOTATION: &str = "run.oci.youki.network.host-loopback";
+const SLIRP: &str = "slirp";
+
+#[derive(Debug, thiserror::Error)]
+pub enum NetworkError {
+    #[error("unsupported network {0:?}, only {SLIRP:?} is supported")]
+    UnsupportedNetwork(String),
+    #[error("invalid port forward {0:?}, expected [host ip:]host port:container port[/tcp|/udp]")]
+    InvalidPortForward(String),
+    #[error("invalid value {0:?} of {HOST_LOOPBACK_ANNOTATION}, expected true or false")]
+    InvalidHostLoopback(String),
+    #[error("built-in networking requires a new network namespace")]
+    NoNetworkNamespace,
+    #[error("failed to create tap device")]
+    CreateTap(#[source] nix::Error),
+    #[error("failed to configure tap device: {request}")]
+    ConfigureTap {
+        request: &'static str,
+        source: nix::Error,
+    },
+    #[error("failed to bind forwarded port")]
+    BindPort(#[source] std::io::Error),
+    #[error("network helper failed")]
+    Helper(#[from] crate::helper_process::HelperProcessError),
+    #[error("failed to relay traffic of the tap device")]
+    Relay(#[source] nix::Error),
+}
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// A port of the host forwarded to a port of the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortForward {
+    pub protocol: Protocol,
+    pub host: SocketAddrV4,
+    pub container_port: u16,
+}
+
+impl FromStr for PortForward {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || NetworkError::InvalidPortForward(s.to_owned());
+        let (ports, protocol) = match s.rsplit_once('/') {
+            Some((ports, "tcp")) => (ports, Protocol::Tcp),
+            Some((ports, "udp")) => (ports, Protocol::Udp),
+            Some(_) => return Err(invalid()),
+            None => (s, Protocol::Tcp),
+        };
+
+        let parts: Vec<&str> = ports.split(':').collect();
+        let (ip, host_port, container_port) = match parts.as_slice() {
+            [host_port, container_port] => (Ipv4Addr::UNSPECIFIED, host_port, container_port),
+            [ip, host_port, container_port] => (
+                ip.parse().map_err(|_| invalid())?,
+                host_port,
+                container_port,
+            ),
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self {
+            protocol,
+            host: SocketAddrV4::new(ip, host_port.parse().map_err(|_| invalid())?),
+            container_port: container_port.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Addresses of the network of the container and the ports to forward
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlirpConfig {
+    pub container_ip: Ipv4Addr,
+    pub gateway_ip: Ipv4Addr,
+    pub prefix_len: u8,
+    pub container_mac: MacAddr,
+    pub gateway_mac: MacAddr,
+    pub mtu: u16,
+    pub port_forwards: Vec<PortForward>,
+    /// Whether connections to the gateway are relayed to the loopback of the
+    /// host
+    pub host_loopback: bool,
+}
+
+impl Default for SlirpConfig {
+    fn default() -> Self {
+        // The addresses slirp4netns uses
+        Self {
+            container_ip: Ipv4Addr::new(10, 0, 2, 100),
+            gateway_ip: Ipv4Addr::new(10, 0, 2, 2),
+            prefix_len: 24,
+            container_mac: [0x02, 0x50, 0x0a, 0x00, 0x02, 0x64],
+            gateway_mac: [0x52, 0x55, 0x0a, 0x00, 0x02, 0x02],
+            mtu: 1500,
+            port_forwards: Vec::new(),
+            host_loopback: false,
+        }
+    }
+}
+
+impl SlirpConfig {
+    /// Returns the configuration requested by the annotations of a spec, if
+    /// any.
+    pub fn from_annotations(annotations: &HashMap<String, String>) -> Result<Option<Self>> {
+        match annotations.get(NETWORK_ANNOTATION).map(String::as_str) {
+            None => return Ok(None),
+            Some(SLIRP) => {}
+            Some(network) => return Err(NetworkError::UnsupportedNetwork(network.to_owned())),
+        }
+
+        let port_forwards = annotations
+            .get(PORTS_ANNOTATION)
+            .map(|ports| {
+                ports
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|port| !port.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        let host_loopback = match annotations
+            .get(HOST_LOOPBACK_ANNOTATION)
+            .map(String::as_str)
+        {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(value) => return Err(NetworkError::InvalidHostLoopback(value.to_owned())),
+        };
+
+        Ok(Some(Self {
+            port_forwards,
+            host_loopback,
+            ..Default::default()
+        }))
+    }
+
+    fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(
+            u32::MAX
+                .checked_shl(32 - self.prefix_len as u32)
+                .unwrap_or(0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_forward() {
+        assert_eq!(
+            "8080:80".parse::<PortForward>().unwrap(),
+            PortForward {
+                protocol: Protocol::Tcp,
+                host: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080),
+                container_port: 80,
+            }
+        );
+        assert_eq!(
+            "127.0.0.1:5353:53/udp".parse::<PortForward>().unwrap(),
+            PortForward {
+                protocol: Protocol::Udp,
+                host: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 5353),
+                container_port: 53,
+            }
+        );
+        for invalid in ["80", "8080:80/sctp", "host:8080:80", "8080:http", "1:2:3:4"] {
+            assert!(
+                invalid.parse::<PortForward>().is_err(),
+                "{invalid} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_from_annotations() {
+        let mut annotations = HashMap::new();
+        assert_eq!(SlirpConfig::from_annotations(&annotations).unwrap(), None);
+
+        annotations.insert(NETWORK_ANNOTATION.to_owned(), "bridge".to_owned());
+        assert!(matches!(
+            SlirpConfig::from_annotations(&annotations),
+            Err(NetworkError::UnsupportedNetwork(_))
+        ));
+
+        annotations.insert(NETWORK_ANNOTATION.to_owned(), SLIRP.to_owned());
+        annotations.insert(
+            PORTS_ANNOTATION.to_owned(),
+            "8080:80, 5353:53/udp".to_owned(),
+        );
+        let config = SlirpConfig::from_annotations(&annotations)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.port_forwards.len(), 2);
+        assert_eq!(config.port_forwards[1].protocol, Protocol::Udp);
+        assert_eq!(config.netmask(), Ipv4Addr::new(255, 255, 255, 0));
+        assert!(!config.host_loopback);
+
+        annotations.insert(HOST_LOOPBACK_ANNOTATION.to_owned(), "true".to_owned());
+        let config = SlirpConfig::from_annotations(&annotations)
+            .unwrap()
+            .unwrap();
+        assert!(config.host_loopback);
+
+        annotations.insert(HOST_LOOPBACK_ANNOTATION.to_owned(), "yes".to_owned());
+        assert!(matches!(
+            SlirpConfig::from_annotations(&annotations),
+            Err(NetworkError::InvalidHostLoopback(_))
+        ));
+    }
+}
@@ -0,0 +1,559 @@
+//! Parsing and building of the few protocols the userspace network stack
+//! speaks: Ethernet, ARP, IPv4, ICMP echo, UDP and TCP.
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+pub type MacAddr = [u8; 6];
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_PSH: u8 = 0x08;
+pub const TCP_ACK: u8 = 0x10;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ARP_LEN: usize = 28;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const TCP_HEADER_LEN: usize = 20;
+const TCP_OPTION_MSS: u8 = 2;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn read_ip(buf: &[u8], offset: usize) -> Ipv4Addr {
+    Ipv4Addr::new(
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    )
+}
+
+fn read_mac(buf: &[u8], offset: usize) -> MacAddr {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&buf[offset..offset + 6]);
+    mac
+}
+
+/// The internet checksum of RFC 1071, continued from `initial`
+fn checksum_with(initial: u32, data: &[u8]) -> u16 {
+    let mut sum = initial;
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub fn checksum(data: &[u8]) -> u16 {
+    checksum_with(0, data)
+}
+
+/// Checksum of a TCP or UDP segment, including the IPv4 pseudo header
+fn transport_checksum(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, segment: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for ip in [src, dst] {
+        let octets = ip.octets();
+        sum += u16::from_be_bytes([octets[0], octets[1]]) as u32;
+        sum += u16::from_be_bytes([octets[2], octets[3]]) as u32;
+    }
+    sum += protocol as u32;
+    sum += segment.len() as u32;
+    checksum_with(sum, segment)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EthernetFrame<'a> {
+    pub dst: MacAddr,
+    pub src: MacAddr,
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> EthernetFrame<'a> {
+    pub fn parse(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < ETHERNET_HEADER_LEN {
+            return None;
+        }
+        Some(Self {
+            dst: read_mac(buf, 0),
+            src: read_mac(buf, 6),
+            ethertype: read_u16(buf, 12),
+            payload: &buf[ETHERNET_HEADER_LEN..],
+        })
+    }
+}
+
+pub fn ethernet_frame(dst: MacAddr, src: MacAddr, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArpRequest {
+    pub sender_mac: MacAddr,
+    pub sender_ip: Ipv4Addr,
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpRequest {
+    /// Parses an ARP request for an IPv4 address, other ARP packets are
+    /// ignored.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < ARP_LEN
+            || read_u16(buf, 0) != 1
+            || read_u16(buf, 2) != ETHERTYPE_IPV4
+            || buf[4] != 6
+            || buf[5] != 4
+            || read_u16(buf, 6) != ARP_REQUEST
+        {
+            return None;
+        }
+        Some(Self {
+            sender_mac: read_mac(buf, 8),
+            sender_ip: read_ip(buf, 14),
+            target_ip: read_ip(buf, 24),
+        })
+    }
+
+    /// Builds the reply which resolves the target address to `mac`
+    pub fn reply(&self, mac: MacAddr) -> Vec<u8> {
+        let mut arp = Vec::with_capacity(ARP_LEN);
+        arp.extend_from_slice(&1u16.to_be_bytes());
+        arp.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        arp.extend_from_slice(&[6, 4]);
+        arp.extend_from_slice(&ARP_REPLY.to_be_bytes());
+        arp.extend_from_slice(&mac);
+        arp.extend_from_slice(&self.target_ip.octets());
+        arp.extend_from_slice(&self.sender_mac);
+        arp.extend_from_slice(&self.sender_ip.octets());
+        arp
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Ipv4Packet<'a> {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub protocol: u8,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// Parses an IPv4 packet. Fragments aren't supported, the container gets
+    /// a MTU which makes them unnecessary.
+    pub fn parse(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < IPV4_HEADER_LEN || buf[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = ((buf[0] & 0x0f) as usize) * 4;
+        let total_len = read_u16(buf, 2) as usize;
+        if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > buf.len() {
+            return None;
+        }
+        let more_fragments = buf[6] & 0x20 != 0;
+        let fragment_offset = read_u16(buf, 6) & 0x1fff;
+        if more_fragments || fragment_offset != 0 || checksum(&buf[..header_len]) != 0 {
+            return None;
+        }
+        Some(Self {
+            src: read_ip(buf, 12),
+            dst: read_ip(buf, 16),
+            protocol: buf[9],
+            payload: &buf[header_len..total_len],
+        })
+    }
+}
+
+pub fn ipv4_packet(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> Vec<u8> {
+    let total_len = (IPV4_HEADER_LEN + payload.len()) as u16;
+    let mut packet = Vec::with_capacity(total_len as usize);
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    // Identification isn't needed, don't fragment is set
+    packet.extend_from_slice(&[0, 0, 0x40, 0]);
+    packet.extend_from_slice(&[64, protocol, 0, 0]);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+    let sum = checksum(&packet);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Splits a packet built by `ipv4_packet` into fragments which fit into the
+/// MTU, see RFC 791. Packets which fit are returned as they are.
+pub fn fragment(packet: Vec<u8>, mtu: usize, id: u16) -> Vec<Vec<u8>> {
+    if packet.len() <= mtu {
+        return vec![packet];
+    }
+    let (header, payload) = packet.split_at(IPV4_HEADER_LEN);
+    // The offsets of fragments are counted in units of 8 bytes
+    let chunk_len = (mtu - IPV4_HEADER_LEN) & !7;
+    payload
+        .chunks(chunk_len)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            let offset = idx * chunk_len;
+            let more_fragments = offset + chunk.len() < payload.len();
+            let mut fragment = Vec::with_capacity(IPV4_HEADER_LEN + chunk.len());
+            fragment.extend_from_slice(header);
+            fragment[2..4].copy_from_slice(&((IPV4_HEADER_LEN + chunk.len()) as u16).to_be_bytes());
+            fragment[4..6].copy_from_slice(&id.to_be_bytes());
+            let flags = if more_fragments { 0x2000 } else { 0 };
+            fragment[6..8].copy_from_slice(&(flags | (offset / 8) as u16).to_be_bytes());
+            fragment[10..12].copy_from_slice(&[0, 0]);
+            let sum = checksum(&fragment);
+            fragment[10..12].copy_from_slice(&sum.to_be_bytes());
+            fragment.extend_from_slice(chunk);
+            fragment
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct IcmpEcho<'a> {
+    /// Identifier, sequence number and data of the request
+    pub body: &'a [u8],
+}
+
+impl<'a> IcmpEcho<'a> {
+    pub fn parse_request(buf: &'a [u8]) -> Option<Self> {
+        if buf.len() < 8 || buf[0] != ICMP_ECHO_REQUEST || buf[1] != 0 || checksum(buf) != 0 {
+            return None;
+        }
+        Some(Self { body: &buf[4..] })
+    }
+
+    pub fn reply(&self) -> Vec<u8> {
+        let mut icmp = vec![ICMP_ECHO_REPLY, 0, 0, 0];
+        icmp.extend_from_slice(self.body);
+        let sum = checksum(&icmp);
+        icmp[2..4].copy_from_slice(&sum.to_be_bytes());
+        icmp
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UdpDatagram<'a> {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> UdpDatagram<'a> {
+    pub fn parse(ip: &Ipv4Packet<'a>) -> Option<Self> {
+        let buf = ip.payload;
+        if buf.len() < UDP_HEADER_LEN {
+            return None;
+        }
+        let len = read_u16(buf, 4) as usize;
+        if len < UDP_HEADER_LEN || len > buf.len() {
+            return None;
+        }
+        // A zero checksum means the sender didn't compute one
+        if read_u16(buf, 6) != 0 && transport_checksum(ip.src, ip.dst, PROTO_UDP, &buf[..len]) != 0
+        {
+            return None;
+        }
+        Some(Self {
+            src_port: read_u16(buf, 0),
+            dst_port: read_u16(buf, 2),
+            payload: &buf[UDP_HEADER_LEN..len],
+        })
+    }
+}
+
+/// Builds a UDP datagram wrapped into an IPv4 packet
+pub fn udp_packet(src: SocketAddrV4, dst: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+    let len = (UDP_HEADER_LEN + payload.len()) as u16;
+    let mut udp = Vec::with_capacity(len as usize);
+    udp.extend_from_slice(&src.port().to_be_bytes());
+    udp.extend_from_slice(&dst.port().to_be_bytes());
+    udp.extend_from_slice(&len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+    let sum = match transport_checksum(*src.ip(), *dst.ip(), PROTO_UDP, &udp) {
+        // Zero is transmitted as all ones
+        0 => 0xffff,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+    ipv4_packet(*src.ip(), *dst.ip(), PROTO_UDP, &udp)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TcpSegment<'a> {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub mss: Option<u16>,
+    pub payload: &'a [u8],
+}
+
+impl<'a> TcpSegment<'a> {
+    pub fn parse(ip: &Ipv4Packet<'a>) -> Option<Self> {
+        let buf = ip.payload;
+        if buf.len() < TCP_HEADER_LEN {
+            return None;
+        }
+        let header_len = ((buf[12] >> 4) as usize) * 4;
+        if header_len < TCP_HEADER_LEN
+            || header_len > buf.len()
+            || transport_checksum(ip.src, ip.dst, PROTO_TCP, buf) != 0
+        {
+            return None;
+        }
+
+        Some(Self {
+            src_port: read_u16(buf, 0),
+            dst_port: read_u16(buf, 2),
+            seq: read_u32(buf, 4),
+            ack: read_u32(buf, 8),
+            flags: buf[13],
+            window: read_u16(buf, 14),
+            mss: parse_mss(&buf[TCP_HEADER_LEN..header_len]),
+            payload: &buf[header_len..],
+        })
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Length in sequence space, SYN and FIN count as one byte
+    pub fn seq_len(&self) -> u32 {
+        self.payload.len() as u32 + self.has(TCP_SYN) as u32 + self.has(TCP_FIN) as u32
+    }
+}
+
+fn parse_mss(mut options: &[u8]) -> Option<u16> {
+    while let Some(&kind) = options.first() {
+        match kind {
+            // End of options
+            0 => return None,
+            // No operation
+            1 => options = &options[1..],
+            _ => {
+                let len = *options.get(1)? as usize;
+                if len < 2 || len > options.len() {
+                    return None;
+                }
+                if kind == TCP_OPTION_MSS && len == 4 {
+                    return Some(read_u16(options, 2));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+    None
+}
+
+/// Header fields of a TCP segment to build
+#[derive(Debug, Clone, Copy)]
+pub struct TcpHeader {
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub mss: Option<u16>,
+}
+
+/// Builds a TCP segment wrapped into an IPv4 packet
+pub fn tcp_packet(header: &TcpHeader, payload: &[u8]) -> Vec<u8> {
+    let options_len = if header.mss.is_some() { 4 } else { 0 };
+    let header_len = TCP_HEADER_LEN + options_len;
+    let mut tcp = Vec::with_capacity(header_len + payload.len());
+    tcp.extend_from_slice(&header.src.port().to_be_bytes());
+    tcp.extend_from_slice(&header.dst.port().to_be_bytes());
+    tcp.extend_from_slice(&header.seq.to_be_bytes());
+    tcp.extend_from_slice(&header.ack.to_be_bytes());
+    tcp.extend_from_slice(&[((header_len / 4) as u8) << 4, header.flags]);
+    tcp.extend_from_slice(&header.window.to_be_bytes());
+    // Checksum and urgent pointer
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    if let Some(mss) = header.mss {
+        tcp.extend_from_slice(&[TCP_OPTION_MSS, 4]);
+        tcp.extend_from_slice(&mss.to_be_bytes());
+    }
+    tcp.extend_from_slice(payload);
+    let sum = transport_checksum(*header.src.ip(), *header.dst.ip(), PROTO_TCP, &tcp);
+    tcp[16..18].copy_from_slice(&sum.to_be_bytes());
+    ipv4_packet(*header.src.ip(), *header.dst.ip(), PROTO_TCP, &tcp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTAINER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 100), 40000);
+    const REMOTE: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 2), 80);
+
+    #[test]
+    fn test_checksum() {
+        // Example of RFC 1071
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&data), !0xddf2);
+        assert_eq!(checksum(&[0x01]), !0x0100);
+    }
+
+    #[test]
+    fn test_tcp_roundtrip() {
+        let header = TcpHeader {
+            src: CONTAINER,
+            dst: REMOTE,
+            seq: 1000,
+            ack: 2000,
+            flags: TCP_SYN | TCP_ACK,
+            window: 65535,
+            mss: Some(1460),
+        };
+        let packet = tcp_packet(&header, b"hello");
+        let ip = Ipv4Packet::parse(&packet).unwrap();
+        assert_eq!(ip.src, *CONTAINER.ip());
+        assert_eq!(ip.dst, *REMOTE.ip());
+        assert_eq!(ip.protocol, PROTO_TCP);
+
+        let segment = TcpSegment::parse(&ip).unwrap();
+        assert_eq!(segment.src_port, CONTAINER.port());
+        assert_eq!(segment.dst_port, REMOTE.port());
+        assert_eq!(segment.seq, 1000);
+        assert_eq!(segment.ack, 2000);
+        assert!(segment.has(TCP_SYN) && segment.has(TCP_ACK) && !segment.has(TCP_FIN));
+        assert_eq!(segment.mss, Some(1460));
+        assert_eq!(segment.payload, b"hello");
+        assert_eq!(segment.seq_len(), 6);
+
+        // A corrupted segment is dropped
+        let mut corrupted = packet.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert!(TcpSegment::parse(&Ipv4Packet::parse(&corrupted).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_udp_roundtrip() {
+        let packet = udp_packet(CONTAINER, REMOTE, b"query");
+        let ip = Ipv4Packet::parse(&packet).unwrap();
+        let datagram = UdpDatagram::parse(&ip).unwrap();
+        assert_eq!(datagram.src_port, CONTAINER.port());
+        assert_eq!(datagram.dst_port, REMOTE.port());
+        assert_eq!(datagram.payload, b"query");
+    }
+
+    #[test]
+    fn test_ipv4_fragments() {
+        let mut packet = udp_packet(CONTAINER, REMOTE, b"query");
+        // More fragments flag
+        packet[6] |= 0x20;
+        packet[10..12].copy_from_slice(&[0, 0]);
+        let sum = checksum(&packet[..IPV4_HEADER_LEN]);
+        packet[10..12].copy_from_slice(&sum.to_be_bytes());
+        assert!(Ipv4Packet::parse(&packet).is_none());
+    }
+
+    #[test]
+    fn test_fragment() {
+        let payload: Vec<u8> = (0..100).collect();
+        let packet = udp_packet(REMOTE, CONTAINER, &payload);
+        assert_eq!(fragment(packet.clone(), 1500, 7), vec![packet.clone()]);
+
+        // 108 bytes of UDP split into 40, 40 and 28 bytes
+        let fragments = fragment(packet.clone(), 63, 7);
+        assert_eq!(fragments.len(), 3);
+        let mut reassembled = Vec::new();
+        for (idx, fragment) in fragments.iter().enumerate() {
+            assert!(fragment.len() <= 63);
+            assert_eq!(checksum(&fragment[..IPV4_HEADER_LEN]), 0);
+            assert_eq!(read_u16(fragment, 2) as usize, fragment.len());
+            assert_eq!(read_u16(fragment, 4), 7);
+            let flags_offset = read_u16(fragment, 6);
+            assert_eq!(flags_offset & 0x2000 != 0, idx < 2);
+            assert_eq!((flags_offset & 0x1fff) as usize * 8, reassembled.len());
+            reassembled.extend_from_slice(&fragment[IPV4_HEADER_LEN..]);
+        }
+        assert_eq!(reassembled, packet[IPV4_HEADER_LEN..]);
+    }
+
+    #[test]
+    fn test_arp_reply() {
+        let container_mac = [2, 0, 0, 0, 0, 1];
+        let gateway_mac = [2, 0, 0, 0, 0, 2];
+        let mut request = Vec::new();
+        request.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+        request.extend_from_slice(&container_mac);
+        request.extend_from_slice(&CONTAINER.ip().octets());
+        request.extend_from_slice(&[0; 6]);
+        request.extend_from_slice(&REMOTE.ip().octets());
+
+        let request = ArpRequest::parse(&request).unwrap();
+        assert_eq!(request.target_ip, *REMOTE.ip());
+        let reply = request.reply(gateway_mac);
+        assert_eq!(read_u16(&reply, 6), ARP_REPLY);
+        assert_eq!(read_mac(&reply, 8), gateway_mac);
+        assert_eq!(read_ip(&reply, 14), *REMOTE.ip());
+        assert_eq!(read_mac(&reply, 18), container_mac);
+        assert_eq!(read_ip(&reply, 24), *CONTAINER.ip());
+    }
+
+    #[test]
+    fn test_icmp_echo() {
+        let mut request = vec![
+            ICMP_ECHO_REQUEST,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0,
+            7,
+            b'p',
+            b'i',
+            b'n',
+            b'g',
+        ];
+        let sum = checksum(&request);
+        request[2..4].copy_from_slice(&sum.to_be_bytes());
+
+        let echo = IcmpEcho::parse_request(&request).unwrap();
+        let reply = echo.reply();
+        assert_eq!(reply[0], ICMP_ECHO_REPLY);
+        assert_eq!(checksum(&reply), 0);
+        assert_eq!(&reply[4..], &request[4..]);
+    }
+}
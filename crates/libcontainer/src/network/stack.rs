@@ -0,0 +1,1382 @@
+//! A minimal userspace network stack in the spirit of slirp. Frames written by
+//! the container to its tap device are terminated here and their payload is
+//! relayed over ordinary sockets of the host, so no privileges are needed on
+//! the host side. If enabled, the gateway address stands for the loopback
+//! address of the host, which can't be reached otherwise. Ports of the host
+//! are forwarded by opening connections into the container through the same
+//! stack.
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::os::fd::{AsFd, AsRawFd};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::socket::{self, sockopt, AddressFamily, SockFlag, SockType, SockaddrIn};
+
+use super::packet::{
+    self, ArpRequest, EthernetFrame, IcmpEcho, Ipv4Packet, MacAddr, TcpHeader, TcpSegment,
+    UdpDatagram, ETHERTYPE_ARP, ETHERTYPE_IPV4, PROTO_ICMP, PROTO_TCP, PROTO_UDP, TCP_ACK, TCP_FIN,
+    TCP_PSH, TCP_RST, TCP_SYN,
+};
+use super::{PortForward, Protocol, SlirpConfig};
+
+/// The window we advertise. Data of the container is written to the host
+/// right away, so there is no buffer to account for.
+const RECEIVE_WINDOW: u16 = u16::MAX;
+/// Data read from the host, which waits to be acknowledged by the container
+const MAX_SEND_BUFFER: usize = 256 * 1024;
+/// MSS to assume if the peer doesn't announce one, see RFC 9293
+const DEFAULT_MSS: usize = 536;
+const INITIAL_RTO: Duration = Duration::from_millis(200);
+const MAX_RTO: Duration = Duration::from_secs(8);
+const MAX_RETRIES: u32 = 10;
+const UDP_TIMEOUT: Duration = Duration::from_secs(60);
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+const TCP_HEADERS_LEN: usize = 40;
+/// The largest datagram which fits into an IPv4 packet
+const MAX_DATAGRAM_LEN: usize = 65507;
+
+/// The endpoints of a flow. The remote address is the one the container
+/// sees, before the gateway is translated to the loopback of the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowKey {
+    container: SocketAddrV4,
+    remote: SocketAddrV4,
+}
+
+/// Serial number arithmetic of RFC 1982
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TcpState {
+    /// The container sent a SYN, the connection of the host is in progress
+    Connecting,
+    /// A forwarded port was connected, the SYN was sent to the container
+    SynSent,
+    /// The connection of the host succeeded, the SYN-ACK was sent
+    SynReceived,
+    Established,
+}
+
+#[derive(Debug)]
+struct TcpConnection {
+    key: FlowKey,
+    state: TcpState,
+    host: TcpStream,
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_max: u32,
+    snd_wnd: u32,
+    rcv_nxt: u32,
+    mss: usize,
+    /// Data of the host starting at `snd_una`
+    buffer: VecDeque<u8>,
+    host_eof: bool,
+    fin_sent: bool,
+    fin_acked: bool,
+    container_fin: bool,
+    rto: Duration,
+    retries: u32,
+    last_sent: Instant,
+}
+
+impl TcpConnection {
+    /// Handles the SYN of the container by connecting to the remote address
+    fn connect(
+        key: FlowKey,
+        host_addr: SocketAddrV4,
+        syn: &TcpSegment,
+        max_mss: usize,
+        now: Instant,
+    ) -> io::Result<Self> {
+        let fd = socket::socket(
+            AddressFamily::Inet,
+            SockType::Stream,
+            SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+            None,
+        )?;
+        match socket::connect(fd.as_raw_fd(), &SockaddrIn::from(host_addr)) {
+            Ok(()) | Err(Errno::EINPROGRESS) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let iss = fastrand::u32(..);
+        Ok(Self {
+            key,
+            state: TcpState::Connecting,
+            host: TcpStream::from(fd),
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_max: iss,
+            snd_wnd: syn.window as u32,
+            rcv_nxt: syn.seq.wrapping_add(1),
+            mss: syn.mss.map_or(DEFAULT_MSS, usize::from).min(max_mss),
+            buffer: VecDeque::new(),
+            host_eof: false,
+            fin_sent: false,
+            fin_acked: false,
+            container_fin: false,
+            rto: INITIAL_RTO,
+            retries: 0,
+            last_sent: now,
+        })
+    }
+
+    /// Opens a connection to the container for an accepted connection of a
+    /// forwarded port
+    fn open(
+        key: FlowKey,
+        host: TcpStream,
+        mss: usize,
+        now: Instant,
+        out: &mut Vec<Vec<u8>>,
+    ) -> io::Result<Self> {
+        host.set_nonblocking(true)?;
+        let iss = fastrand::u32(..);
+        let connection = Self {
+            key,
+            state: TcpState::SynSent,
+            host,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss.wrapping_add(1),
+            snd_max: iss.wrapping_add(1),
+            snd_wnd: 0,
+            rcv_nxt: 0,
+            mss,
+            buffer: VecDeque::new(),
+            host_eof: false,
+            fin_sent: false,
+            fin_acked: false,
+            container_fin: false,
+            rto: INITIAL_RTO,
+            retries: 0,
+            last_sent: now,
+        };
+        connection.send_syn(out);
+        Ok(connection)
+    }
+
+    fn header(&self, seq: u32, flags: u8) -> TcpHeader {
+        TcpHeader {
+            src: self.key.remote,
+            dst: self.key.container,
+            seq,
+            ack: self.rcv_nxt,
+            flags,
+            window: RECEIVE_WINDOW,
+            mss: None,
+        }
+    }
+
+    fn send(&self, seq: u32, flags: u8, payload: &[u8], out: &mut Vec<Vec<u8>>) {
+        out.push(packet::tcp_packet(&self.header(seq, flags), payload));
+    }
+
+    fn send_syn(&self, out: &mut Vec<Vec<u8>>) {
+        let mut header = self.header(self.iss, TCP_SYN);
+        header.mss = Some(self.mss as u16);
+        if self.state == TcpState::SynReceived {
+            header.flags |= TCP_ACK;
+        } else {
+            header.ack = 0;
+        }
+        out.push(packet::tcp_packet(&header, &[]));
+    }
+
+    fn reset(&self, out: &mut Vec<Vec<u8>>) {
+        let _ = self.host.shutdown(Shutdown::Both);
+        self.send(self.snd_nxt, TCP_RST | TCP_ACK, &[], out);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.container_fin && self.fin_acked
+    }
+
+    /// Handles a segment of the container. Returns if the connection is
+    /// still alive.
+    fn on_segment(&mut self, segment: &TcpSegment, now: Instant, out: &mut Vec<Vec<u8>>) -> bool {
+        if segment.has(TCP_RST) {
+            tracing::debug!(key = ?self.key, "connection reset by the container");
+            let _ = self.host.shutdown(Shutdown::Both);
+            return false;
+        }
+
+        match self.state {
+            // The container retransmitted its SYN, the host isn't connected yet
+            TcpState::Connecting => return true,
+            TcpState::SynSent => {
+                if segment.has(TCP_SYN)
+                    && segment.has(TCP_ACK)
+                    && segment.ack == self.iss.wrapping_add(1)
+                {
+                    self.state = TcpState::Established;
+                    self.rcv_nxt = segment.seq.wrapping_add(1);
+                    self.snd_una = segment.ack;
+                    self.snd_wnd = segment.window as u32;
+                    if let Some(mss) = segment.mss {
+                        self.mss = self.mss.min(mss as usize);
+                    }
+                    self.acked(now);
+                    self.send(self.snd_nxt, TCP_ACK, &[], out);
+                    self.transmit(now, false, out);
+                }
+                return true;
+            }
+            TcpState::SynReceived => {
+                if segment.has(TCP_SYN) {
+                    // Our SYN-ACK got lost
+                    self.send_syn(out);
+                    return true;
+                }
+                if !segment.has(TCP_ACK) || segment.ack != self.iss.wrapping_add(1) {
+                    return true;
+                }
+                self.state = TcpState::Established;
+                self.snd_una = segment.ack;
+                self.acked(now);
+            }
+            TcpState::Established => {
+                if segment.has(TCP_SYN) {
+                    // Our ACK of the SYN-ACK got lost
+                    self.send(self.snd_nxt, TCP_ACK, &[], out);
+                    return true;
+                }
+            }
+        }
+
+        if segment.has(TCP_ACK) {
+            self.on_ack(segment.ack, segment.window, now);
+        }
+
+        if !segment.payload.is_empty() || segment.has(TCP_FIN) {
+            if segment.seq == self.rcv_nxt && !self.container_fin {
+                let written = match self.write_host(segment.payload) {
+                    Ok(written) => written,
+                    Err(err) => {
+                        tracing::debug!(?err, key = ?self.key, "failed to write to the host");
+                        self.reset(out);
+                        return false;
+                    }
+                };
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(written as u32);
+                if written == segment.payload.len() && segment.has(TCP_FIN) {
+                    self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+                    self.container_fin = true;
+                    let _ = self.host.shutdown(Shutdown::Write);
+                }
+            }
+            // Acknowledges what was taken, or asks for the missing data
+            self.send(self.snd_nxt, TCP_ACK, &[], out);
+        }
+
+        self.transmit(now, false, out);
+        !self.is_closed()
+    }
+
+    fn on_ack(&mut self, ack: u32, window: u16, now: Instant) {
+        if seq_lt(ack, self.snd_una) || seq_lt(self.snd_max, ack) {
+            return;
+        }
+        self.snd_wnd = window as u32;
+        if ack == self.snd_una {
+            return;
+        }
+
+        let acked = ack.wrapping_sub(self.snd_una) as usize;
+        let data = acked.min(self.buffer.len());
+        self.buffer.drain(..data);
+        if acked > data && self.fin_sent {
+            self.fin_acked = true;
+        }
+        self.snd_una = ack;
+        if seq_lt(self.snd_nxt, ack) {
+            self.snd_nxt = ack;
+        }
+        self.acked(now);
+    }
+
+    fn acked(&mut self, now: Instant) {
+        self.retries = 0;
+        self.rto = INITIAL_RTO;
+        self.last_sent = now;
+    }
+
+    fn write_host(&mut self, mut data: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while !data.is_empty() {
+            match self.host.write(data) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    written += n;
+                    data = &data[n..];
+                }
+                // The container retransmits what we couldn't take
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(written)
+    }
+
+    fn read_host(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 16 * 1024];
+        while !self.host_eof && self.buffer.len() < MAX_SEND_BUFFER {
+            let max = buf.len().min(MAX_SEND_BUFFER - self.buffer.len());
+            match self.host.read(&mut buf[..max]) {
+                Ok(0) => self.host_eof = true,
+                Ok(n) => self.buffer.extend(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the data of the host the window of the container allows. A
+    /// probe sends one byte into a zero window.
+    fn transmit(&mut self, now: Instant, probe: bool, out: &mut Vec<Vec<u8>>) {
+        if self.state != TcpState::Established {
+            return;
+        }
+        if self.snd_una == self.snd_nxt {
+            self.last_sent = now;
+        }
+
+        let mut offset = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+        while offset < self.buffer.len() {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+            let mut window = self.snd_wnd.saturating_sub(in_flight) as usize;
+            if probe && in_flight == 0 {
+                window = window.max(1);
+            }
+            if window == 0 {
+                break;
+            }
+            let len = self.mss.min(window).min(self.buffer.len() - offset);
+            let payload: Vec<u8> = self.buffer.range(offset..offset + len).copied().collect();
+            self.send(self.snd_nxt, TCP_ACK | TCP_PSH, &payload, out);
+            self.snd_nxt = self.snd_nxt.wrapping_add(len as u32);
+            offset += len;
+        }
+
+        if self.host_eof && !self.fin_acked && offset == self.buffer.len() && !self.fin_sent {
+            self.send(self.snd_nxt, TCP_FIN | TCP_ACK, &[], out);
+            self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            self.fin_sent = true;
+        }
+        if seq_lt(self.snd_max, self.snd_nxt) {
+            self.snd_max = self.snd_nxt;
+        }
+    }
+
+    /// Returns the result of the connection of the host, if it finished
+    fn connect_result(&self) -> Option<io::Result<()>> {
+        let mut fds = [PollFd::new(self.host.as_fd(), PollFlags::POLLOUT)];
+        match poll(&mut fds, PollTimeout::ZERO) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(err.into())),
+        }
+        match socket::getsockopt(&self.host, sockopt::SocketError) {
+            Ok(0) => Some(Ok(())),
+            Ok(errno) => Some(Err(io::Error::from_raw_os_error(errno))),
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+
+    /// Makes progress on the host side and on timers. Returns if the
+    /// connection is still alive.
+    fn poll(&mut self, now: Instant, out: &mut Vec<Vec<u8>>) -> bool {
+        if self.state == TcpState::Connecting {
+            match self.connect_result() {
+                None => return true,
+                Some(Ok(())) => {
+                    self.state = TcpState::SynReceived;
+                    self.snd_nxt = self.iss.wrapping_add(1);
+                    self.snd_max = self.snd_nxt;
+                    self.last_sent = now;
+                    self.send_syn(out);
+                }
+                Some(Err(err)) => {
+                    tracing::debug!(?err, key = ?self.key, "failed to connect");
+                    // The SYN of the container is answered with a reset
+                    let mut header = self.header(0, TCP_RST | TCP_ACK);
+                    header.ack = self.rcv_nxt;
+                    out.push(packet::tcp_packet(&header, &[]));
+                    return false;
+                }
+            }
+        }
+
+        if let Err(err) = self.read_host() {
+            tracing::debug!(?err, key = ?self.key, "failed to read from the host");
+            self.reset(out);
+            return false;
+        }
+        self.transmit(now, false, out);
+
+        let zero_window = self.snd_una == self.snd_nxt
+            && self.snd_wnd == 0
+            && !self.buffer.is_empty()
+            && self.state == TcpState::Established;
+        if (self.snd_una != self.snd_nxt || zero_window)
+            && now.duration_since(self.last_sent) >= self.rto
+        {
+            self.retries += 1;
+            if self.retries > MAX_RETRIES {
+                tracing::debug!(key = ?self.key, "connection timed out");
+                self.reset(out);
+                return false;
+            }
+            self.rto = (self.rto * 2).min(MAX_RTO);
+            self.last_sent = now;
+            match self.state {
+                TcpState::SynSent | TcpState::SynReceived => self.send_syn(out),
+                TcpState::Established => {
+                    // Go back to the first unacknowledged byte
+                    self.snd_nxt = self.snd_una;
+                    if !self.fin_acked {
+                        self.fin_sent = false;
+                    }
+                    self.transmit(now, true, out);
+                }
+                TcpState::Connecting => {}
+            }
+        }
+
+        !self.is_closed()
+    }
+
+    fn poll_flags(&self) -> PollFlags {
+        match self.state {
+            TcpState::Connecting => PollFlags::POLLOUT,
+            _ if self.host_eof || self.buffer.len() >= MAX_SEND_BUFFER => PollFlags::empty(),
+            _ => PollFlags::POLLIN,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UdpFlow {
+    socket: UdpSocket,
+    last_used: Instant,
+}
+
+/// A client of a forwarded UDP port, seen by the container as coming from
+/// the gateway
+#[derive(Debug)]
+struct UdpForwardClient {
+    forward: usize,
+    client: SocketAddr,
+    last_used: Instant,
+}
+
+#[derive(Debug)]
+enum ForwardSocket {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+}
+
+#[derive(Debug)]
+struct Forward {
+    socket: ForwardSocket,
+    container_port: u16,
+}
+
+#[derive(Debug)]
+pub struct Stack {
+    container_ip: Ipv4Addr,
+    gateway_ip: Ipv4Addr,
+    host_loopback: bool,
+    container_mac: MacAddr,
+    gateway_mac: MacAddr,
+    mtu: usize,
+    tcp: HashMap<FlowKey, TcpConnection>,
+    udp: HashMap<FlowKey, UdpFlow>,
+    forwards: Vec<Forward>,
+    /// Clients of forwarded UDP ports by the port of the gateway they use
+    udp_forward_clients: HashMap<u16, UdpForwardClient>,
+    next_port: u16,
+    /// Identification of the next packet which has to be fragmented
+    next_ip_id: u16,
+    frames: Vec<Vec<u8>>,
+}
+
+impl Stack {
+    /// Creates the stack and binds the forwarded ports on the host.
+    pub fn new(config: &SlirpConfig) -> io::Result<Self> {
+        let forwards = config
+            .port_forwards
+            .iter()
+            .map(Self::bind_forward)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            container_ip: config.container_ip,
+            gateway_ip: config.gateway_ip,
+            host_loopback: config.host_loopback,
+            container_mac: config.container_mac,
+            gateway_mac: config.gateway_mac,
+            mtu: config.mtu as usize,
+            tcp: HashMap::new(),
+            udp: HashMap::new(),
+            forwards,
+            udp_forward_clients: HashMap::new(),
+            next_port: FIRST_EPHEMERAL_PORT,
+            next_ip_id: 0,
+            frames: Vec::new(),
+        })
+    }
+
+    fn bind_forward(forward: &PortForward) -> io::Result<Forward> {
+        let socket = match forward.protocol {
+            Protocol::Tcp => {
+                let listener = TcpListener::bind(forward.host)?;
+                listener.set_nonblocking(true)?;
+                ForwardSocket::Tcp(listener)
+            }
+            Protocol::Udp => {
+                let socket = UdpSocket::bind(forward.host)?;
+                socket.set_nonblocking(true)?;
+                ForwardSocket::Udp(socket)
+            }
+        };
+        Ok(Forward {
+            socket,
+            container_port: forward.container_port,
+        })
+    }
+
+    /// The addresses forwarded ports are bound to, e.g. to find out the port
+    /// picked for port 0
+    pub fn forwarded_addrs(&self) -> Vec<io::Result<SocketAddr>> {
+        self.forwards
+            .iter()
+            .map(|forward| match &forward.socket {
+                ForwardSocket::Tcp(listener) => listener.local_addr(),
+                ForwardSocket::Udp(socket) => socket.local_addr(),
+            })
+            .collect()
+    }
+
+    /// Takes the frames to be written to the tap device
+    pub fn take_frames(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.frames)
+    }
+
+    /// The host sockets of the stack with the events it waits for
+    pub fn poll_fds(&self) -> Vec<PollFd<'_>> {
+        let forwards = self.forwards.iter().map(|forward| match &forward.socket {
+            ForwardSocket::Tcp(listener) => PollFd::new(listener.as_fd(), PollFlags::POLLIN),
+            ForwardSocket::Udp(socket) => PollFd::new(socket.as_fd(), PollFlags::POLLIN),
+        });
+        let tcp = self
+            .tcp
+            .values()
+            .map(|connection| PollFd::new(connection.host.as_fd(), connection.poll_flags()));
+        let udp = self
+            .udp
+            .values()
+            .map(|flow| PollFd::new(flow.socket.as_fd(), PollFlags::POLLIN));
+        forwards.chain(tcp).chain(udp).collect()
+    }
+
+    fn emit(&mut self, ethertype: u16, payload: &[u8]) {
+        self.frames.push(packet::ethernet_frame(
+            self.container_mac,
+            self.gateway_mac,
+            ethertype,
+            payload,
+        ));
+    }
+
+    fn emit_packets(&mut self, packets: Vec<Vec<u8>>) {
+        for packet in packets {
+            // Only datagrams may exceed the MTU, segments respect the MSS
+            let id = self.next_ip_id;
+            self.next_ip_id = self.next_ip_id.wrapping_add(1);
+            for fragment in packet::fragment(packet, self.mtu, id) {
+                self.emit(ETHERTYPE_IPV4, &fragment);
+            }
+        }
+    }
+
+    /// The address on the host of an address the container talks to, if the
+    /// container may reach it. Like slirp4netns, the loopback of the host is
+    /// only reachable if enabled, also when the container addresses it
+    /// directly, which it can with raw sockets.
+    fn host_addr(&self, remote: SocketAddrV4) -> Option<SocketAddrV4> {
+        let ip = remote.ip();
+        if *ip == self.gateway_ip {
+            self.host_loopback
+                .then(|| SocketAddrV4::new(Ipv4Addr::LOCALHOST, remote.port()))
+        } else if ip.is_loopback() || ip.is_unspecified() {
+            self.host_loopback.then_some(remote)
+        } else {
+            Some(remote)
+        }
+    }
+
+    /// A port of the gateway which isn't used by a forwarded connection, if
+    /// any is left
+    fn allocate_port(&mut self) -> Option<u16> {
+        for _ in FIRST_EPHEMERAL_PORT..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = self
+                .next_port
+                .checked_add(1)
+                .unwrap_or(FIRST_EPHEMERAL_PORT);
+            let remote = SocketAddrV4::new(self.gateway_ip, port);
+            let in_use = self.udp_forward_clients.contains_key(&port)
+                || self.tcp.keys().any(|key| key.remote == remote);
+            if !in_use {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// Handles a frame the container wrote to its tap device
+    pub fn handle_frame(&mut self, frame: &[u8], now: Instant) {
+        let Some(ethernet) = EthernetFrame::parse(frame) else {
+            return;
+        };
+        self.container_mac = ethernet.src;
+
+        match ethernet.ethertype {
+            ETHERTYPE_ARP => {
+                if let Some(request) = ArpRequest::parse(ethernet.payload) {
+                    if request.target_ip == self.gateway_ip {
+                        let reply = request.reply(self.gateway_mac);
+                        self.emit(ETHERTYPE_ARP, &reply);
+                    }
+                }
+            }
+            ETHERTYPE_IPV4 => {
+                if let Some(ip) = Ipv4Packet::parse(ethernet.payload) {
+                    match ip.protocol {
+                        PROTO_ICMP => self.handle_icmp(&ip),
+                        PROTO_TCP => self.handle_tcp(&ip, now),
+                        PROTO_UDP => self.handle_udp(&ip, now),
+                        _ => {}
+                    }
+                }
+            }
+            // IPv6 isn't supported
+            _ => {}
+        }
+    }
+
+    fn handle_icmp(&mut self, ip: &Ipv4Packet) {
+        // Only the gateway answers pings, ICMP can't be relayed without
+        // privileges
+        if ip.dst != self.gateway_ip {
+            return;
+        }
+        if let Some(echo) = IcmpEcho::parse_request(ip.payload) {
+            let reply = packet::ipv4_packet(ip.dst, ip.src, PROTO_ICMP, &echo.reply());
+            self.emit(ETHERTYPE_IPV4, &reply);
+        }
+    }
+
+    fn handle_tcp(&mut self, ip: &Ipv4Packet, now: Instant) {
+        let Some(segment) = TcpSegment::parse(ip) else {
+            return;
+        };
+        let key = FlowKey {
+            container: SocketAddrV4::new(ip.src, segment.src_port),
+            remote: SocketAddrV4::new(ip.dst, segment.dst_port),
+        };
+        let mut out = Vec::new();
+
+        if let Some(connection) = self.tcp.get_mut(&key) {
+            if !connection.on_segment(&segment, now, &mut out) {
+                self.tcp.remove(&key);
+            }
+        } else if segment.has(TCP_SYN) && !segment.has(TCP_ACK) && !segment.has(TCP_RST) {
+            let max_mss = self.mtu - TCP_HEADERS_LEN;
+            match self.host_addr(key.remote) {
+                Some(host_addr) => {
+                    match TcpConnection::connect(key, host_addr, &segment, max_mss, now) {
+                        Ok(connection) => {
+                            tracing::debug!(?key, ?host_addr, "new tcp connection");
+                            self.tcp.insert(key, connection);
+                        }
+                        Err(err) => {
+                            tracing::debug!(?err, ?key, "failed to create socket");
+                            out.push(Self::reset_for(&key, &segment));
+                        }
+                    }
+                }
+                None => {
+                    tracing::debug!(?key, "refusing connection to the loopback of the host");
+                    out.push(Self::reset_for(&key, &segment));
+                }
+            }
+        } else if !segment.has(TCP_RST) {
+            out.push(Self::reset_for(&key, &segment));
+        }
+
+        self.emit_packets(out);
+    }
+
+    /// The reset answering a segment without a connection, see RFC 9293
+    fn reset_for(key: &FlowKey, segment: &TcpSegment) -> Vec<u8> {
+        let mut header = TcpHeader {
+            src: key.remote,
+            dst: key.container,
+            seq: 0,
+            ack: 0,
+            flags: TCP_RST,
+            window: 0,
+            mss: None,
+        };
+        if segment.has(TCP_ACK) {
+            header.seq = segment.ack;
+        } else {
+            header.flags |= TCP_ACK;
+            header.ack = segment.seq.wrapping_add(segment.seq_len());
+        }
+        packet::tcp_packet(&header, &[])
+    }
+
+    fn handle_udp(&mut self, ip: &Ipv4Packet, now: Instant) {
+        let Some(datagram) = UdpDatagram::parse(ip) else {
+            return;
+        };
+        let key = FlowKey {
+            container: SocketAddrV4::new(ip.src, datagram.src_port),
+            remote: SocketAddrV4::new(ip.dst, datagram.dst_port),
+        };
+
+        // A reply to a client of a forwarded port
+        if *key.remote.ip() == self.gateway_ip {
+            if let Some(client) = self.udp_forward_clients.get_mut(&key.remote.port()) {
+                client.last_used = now;
+                if let ForwardSocket::Udp(socket) = &self.forwards[client.forward].socket {
+                    if let Err(err) = socket.send_to(datagram.payload, client.client) {
+                        tracing::debug!(?err, client = ?client.client, "failed to send datagram");
+                    }
+                }
+                return;
+            }
+        }
+
+        let Some(host_addr) = self.host_addr(key.remote) else {
+            tracing::debug!(?key, "dropping datagram to the loopback of the host");
+            return;
+        };
+        let flow = match self.udp.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match Self::udp_socket(host_addr) {
+                Ok(socket) => {
+                    tracing::debug!(?key, ?host_addr, "new udp flow");
+                    entry.insert(UdpFlow {
+                        socket,
+                        last_used: now,
+                    })
+                }
+                Err(err) => {
+                    tracing::debug!(?err, ?key, "failed to create socket");
+                    return;
+                }
+            },
+        };
+        flow.last_used = now;
+        if let Err(err) = flow.socket.send(datagram.payload) {
+            tracing::debug!(?err, ?key, "failed to send datagram");
+        }
+    }
+
+    fn udp_socket(remote: SocketAddrV4) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.connect(remote)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+
+    /// Makes progress on the sockets of the host and on timers
+    pub fn poll(&mut self, now: Instant) {
+        self.poll_forwards(now);
+
+        let mut out = Vec::new();
+        self.tcp
+            .retain(|_, connection| connection.poll(now, &mut out));
+        self.emit_packets(out);
+
+        let mut out = Vec::new();
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        self.udp.retain(|key, flow| {
+            loop {
+                match flow.socket.recv(&mut buf) {
+                    Ok(n) => {
+                        flow.last_used = now;
+                        out.push(packet::udp_packet(key.remote, key.container, &buf[..n]));
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        // E.g. the port is closed, the container will time out
+                        tracing::debug!(?err, ?key, "failed to receive datagram");
+                        break;
+                    }
+                }
+            }
+            now.duration_since(flow.last_used) < UDP_TIMEOUT
+        });
+        self.emit_packets(out);
+
+        self.udp_forward_clients
+            .retain(|_, client| now.duration_since(client.last_used) < UDP_TIMEOUT);
+    }
+
+    fn poll_forwards(&mut self, now: Instant) {
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        for index in 0..self.forwards.len() {
+            let container =
+                SocketAddrV4::new(self.container_ip, self.forwards[index].container_port);
+            loop {
+                match &self.forwards[index].socket {
+                    ForwardSocket::Tcp(listener) => {
+                        let stream = match listener.accept() {
+                            Ok((stream, _)) => stream,
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(err) => {
+                                tracing::warn!(?err, "failed to accept forwarded connection");
+                                break;
+                            }
+                        };
+                        let Some(port) = self.allocate_port() else {
+                            tracing::warn!("no port left to forward connection");
+                            continue;
+                        };
+                        let key = FlowKey {
+                            container,
+                            remote: SocketAddrV4::new(self.gateway_ip, port),
+                        };
+                        let mut out = Vec::new();
+                        let mss = self.mtu - TCP_HEADERS_LEN;
+                        match TcpConnection::open(key, stream, mss, now, &mut out) {
+                            Ok(connection) => {
+                                tracing::debug!(?key, "new forwarded tcp connection");
+                                self.tcp.insert(key, connection);
+                            }
+                            Err(err) => tracing::debug!(?err, ?key, "failed to forward"),
+                        }
+                        self.emit_packets(out);
+                    }
+                    ForwardSocket::Udp(socket) => {
+                        let (n, client) = match socket.recv_from(&mut buf) {
+                            Ok(received) => received,
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                            Err(err) => {
+                                tracing::debug!(?err, "failed to receive forwarded datagram");
+                                break;
+                            }
+                        };
+                        let port = match self
+                            .udp_forward_clients
+                            .iter()
+                            .find(|(_, c)| c.forward == index && c.client == client)
+                        {
+                            Some((port, _)) => *port,
+                            None => {
+                                let Some(port) = self.allocate_port() else {
+                                    tracing::warn!("no port left to forward datagram");
+                                    continue;
+                                };
+                                self.udp_forward_clients.insert(
+                                    port,
+                                    UdpForwardClient {
+                                        forward: index,
+                                        client,
+                                        last_used: now,
+                                    },
+                                );
+                                port
+                            }
+                        };
+                        if let Some(client) = self.udp_forward_clients.get_mut(&port) {
+                            client.last_used = now;
+                        }
+                        let src = SocketAddrV4::new(self.gateway_ip, port);
+                        let packet = packet::udp_packet(src, container, &buf[..n]);
+                        self.emit_packets(vec![packet]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+    use crate::network::packet::ethernet_frame;
+
+    const CONTAINER_MAC: MacAddr = [0x02, 0x50, 0x0a, 0x00, 0x02, 0x64];
+
+    fn config(port_forwards: Vec<PortForward>) -> SlirpConfig {
+        SlirpConfig {
+            port_forwards,
+            host_loopback: true,
+            ..Default::default()
+        }
+    }
+
+    fn container_addr(config: &SlirpConfig, port: u16) -> SocketAddrV4 {
+        SocketAddrV4::new(config.container_ip, port)
+    }
+
+    fn frame(ip_packet: &[u8], config: &SlirpConfig) -> Vec<u8> {
+        ethernet_frame(config.gateway_mac, CONTAINER_MAC, ETHERTYPE_IPV4, ip_packet)
+    }
+
+    struct Segment {
+        seq: u32,
+        ack: u32,
+        flags: u8,
+        mss: Option<u16>,
+        payload: Vec<u8>,
+        src: SocketAddrV4,
+    }
+
+    fn tcp_segments(frames: &[Vec<u8>]) -> Vec<Segment> {
+        frames
+            .iter()
+            .filter_map(|frame| {
+                let ethernet = EthernetFrame::parse(frame)?;
+                assert_eq!(ethernet.dst, CONTAINER_MAC);
+                let ip = Ipv4Packet::parse(ethernet.payload)?;
+                let segment = TcpSegment::parse(&ip)?;
+                Some(Segment {
+                    seq: segment.seq,
+                    ack: segment.ack,
+                    flags: segment.flags,
+                    mss: segment.mss,
+                    payload: segment.payload.to_vec(),
+                    src: SocketAddrV4::new(ip.src, segment.src_port),
+                })
+            })
+            .collect()
+    }
+
+    /// Polls the stack until it emits frames matching the predicate
+    fn poll_until(stack: &mut Stack, mut done: impl FnMut(&[Vec<u8>]) -> bool) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for _ in 0..500 {
+            stack.poll(Instant::now());
+            frames.extend(stack.take_frames());
+            if done(&frames) {
+                return frames;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("stack didn't emit the expected frames");
+    }
+
+    fn send_tcp(
+        stack: &mut Stack,
+        config: &SlirpConfig,
+        header: TcpHeader,
+        payload: &[u8],
+    ) -> Vec<Vec<u8>> {
+        let packet = packet::tcp_packet(&header, payload);
+        stack.handle_frame(&frame(&packet, config), Instant::now());
+        stack.take_frames()
+    }
+
+    #[test]
+    fn test_tcp_to_host() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+
+        let container = container_addr(&config, 40000);
+        // The gateway stands for the loopback of the host
+        let remote = SocketAddrV4::new(config.gateway_ip, port);
+        let header = |seq, ack, flags| TcpHeader {
+            src: container,
+            dst: remote,
+            seq,
+            ack,
+            flags,
+            window: 65535,
+            mss: None,
+        };
+
+        let frames = send_tcp(
+            &mut stack,
+            &config,
+            TcpHeader {
+                mss: Some(1460),
+                ..header(1000, 0, TCP_SYN)
+            },
+            &[],
+        );
+        assert!(frames.is_empty());
+        let (mut peer, _) = server.accept().unwrap();
+
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let syn_ack = &tcp_segments(&frames)[0];
+        assert_eq!(syn_ack.flags, TCP_SYN | TCP_ACK);
+        assert_eq!(syn_ack.ack, 1001);
+        assert_eq!(syn_ack.src, remote);
+        assert_eq!(syn_ack.mss, Some(1460));
+        let iss = syn_ack.seq;
+
+        // Data of the container is written to the host and acknowledged
+        let frames = send_tcp(
+            &mut stack,
+            &config,
+            header(1001, iss + 1, TCP_ACK | TCP_PSH),
+            b"ping",
+        );
+        let ack = &tcp_segments(&frames)[0];
+        assert_eq!(ack.ack, 1005);
+        let mut buf = [0u8; 4];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Data of the host is sent to the container, followed by a FIN
+        peer.write_all(b"pong").unwrap();
+        peer.shutdown(Shutdown::Write).unwrap();
+        let frames = poll_until(&mut stack, |frames| {
+            tcp_segments(frames).iter().any(|s| s.flags & TCP_FIN != 0)
+        });
+        let segments = tcp_segments(&frames);
+        let data: Vec<u8> = segments.iter().flat_map(|s| s.payload.clone()).collect();
+        assert_eq!(data, b"pong");
+        let fin = segments.last().unwrap();
+        assert_eq!(fin.seq, iss + 5);
+
+        // Both sides closed, the connection is gone
+        let frames = send_tcp(
+            &mut stack,
+            &config,
+            header(1005, iss + 6, TCP_ACK | TCP_FIN),
+            &[],
+        );
+        assert_eq!(tcp_segments(&frames)[0].ack, 1006);
+        assert!(stack.tcp.is_empty());
+        assert_eq!(peer.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tcp_connection_refused() {
+        let port = {
+            let server = TcpListener::bind("127.0.0.1:0").unwrap();
+            server.local_addr().unwrap().port()
+        };
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let header = TcpHeader {
+            src: container_addr(&config, 40001),
+            dst: SocketAddrV4::new(config.gateway_ip, port),
+            seq: 7,
+            ack: 0,
+            flags: TCP_SYN,
+            window: 65535,
+            mss: None,
+        };
+        send_tcp(&mut stack, &config, header, &[]);
+
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let reset = &tcp_segments(&frames)[0];
+        assert_eq!(reset.flags, TCP_RST | TCP_ACK);
+        assert_eq!(reset.ack, 8);
+        assert!(stack.tcp.is_empty());
+    }
+
+    #[test]
+    fn test_host_loopback_disabled() {
+        // Bound to all addresses, so any address of the loopback reaches them
+        let tcp_server = TcpListener::bind("0.0.0.0:0").unwrap();
+        let udp_server = UdpSocket::bind("0.0.0.0:0").unwrap();
+        udp_server.set_nonblocking(true).unwrap();
+        let config = SlirpConfig::default();
+        let mut stack = Stack::new(&config).unwrap();
+
+        let loopback_addrs = [
+            config.gateway_ip,
+            Ipv4Addr::new(127, 0, 0, 2),
+            Ipv4Addr::UNSPECIFIED,
+        ];
+        for (idx, ip) in loopback_addrs.into_iter().enumerate() {
+            let header = TcpHeader {
+                src: container_addr(&config, 40004 + idx as u16),
+                dst: SocketAddrV4::new(ip, tcp_server.local_addr().unwrap().port()),
+                seq: 7,
+                ack: 0,
+                flags: TCP_SYN,
+                window: 65535,
+                mss: None,
+            };
+            let frames = send_tcp(&mut stack, &config, header, &[]);
+            let reset = &tcp_segments(&frames)[0];
+            assert_eq!(reset.flags, TCP_RST | TCP_ACK, "connection to {ip}");
+            assert!(stack.tcp.is_empty());
+
+            let packet = packet::udp_packet(
+                container_addr(&config, 5354 + idx as u16),
+                SocketAddrV4::new(ip, udp_server.local_addr().unwrap().port()),
+                b"query",
+            );
+            stack.handle_frame(&frame(&packet, &config), Instant::now());
+            assert!(stack.udp.is_empty(), "datagram to {ip}");
+        }
+
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            udp_server.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn test_udp_reply_fragmented() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let container = container_addr(&config, 5355);
+        let remote = SocketAddrV4::new(config.gateway_ip, port);
+
+        let packet = packet::udp_packet(container, remote, b"query");
+        stack.handle_frame(&frame(&packet, &config), Instant::now());
+        let mut buf = [0u8; 16];
+        let (_, client) = server.recv_from(&mut buf).unwrap();
+
+        // e.g. a large DNS answer
+        let answer = vec![0x42; 4000];
+        server.send_to(&answer, client).unwrap();
+        let frames = poll_until(&mut stack, |frames| frames.len() >= 3);
+        assert_eq!(frames.len(), 3);
+        let mut payload = Vec::new();
+        for frame in &frames {
+            let ethernet = EthernetFrame::parse(frame).unwrap();
+            assert!(ethernet.payload.len() <= config.mtu as usize);
+            // the UDP header is in the first fragment
+            payload.extend_from_slice(&ethernet.payload[20..]);
+        }
+        assert_eq!(payload[8..], answer[..]);
+    }
+
+    #[test]
+    fn test_allocate_port_exhausted() {
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let client = || UdpForwardClient {
+            forward: 0,
+            client: "127.0.0.1:4000".parse().unwrap(),
+            last_used: Instant::now(),
+        };
+        for port in FIRST_EPHEMERAL_PORT..u16::MAX {
+            stack.udp_forward_clients.insert(port, client());
+        }
+        assert_eq!(stack.allocate_port(), Some(u16::MAX));
+        stack.udp_forward_clients.insert(u16::MAX, client());
+        assert_eq!(stack.allocate_port(), None);
+    }
+
+    #[test]
+    fn test_tcp_without_connection() {
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let header = TcpHeader {
+            src: container_addr(&config, 40002),
+            dst: SocketAddrV4::new(config.gateway_ip, 80),
+            seq: 7,
+            ack: 42,
+            flags: TCP_ACK,
+            window: 65535,
+            mss: None,
+        };
+        let frames = send_tcp(&mut stack, &config, header, b"stray");
+        let reset = &tcp_segments(&frames)[0];
+        assert_eq!(reset.flags, TCP_RST);
+        assert_eq!(reset.seq, 42);
+    }
+
+    #[test]
+    fn test_tcp_retransmit() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let container = container_addr(&config, 40003);
+        let remote = SocketAddrV4::new(config.gateway_ip, port);
+        let header = |seq, ack, flags| TcpHeader {
+            src: container,
+            dst: remote,
+            seq,
+            ack,
+            flags,
+            window: 65535,
+            mss: None,
+        };
+
+        send_tcp(&mut stack, &config, header(0, 0, TCP_SYN), &[]);
+        let (mut peer, _) = server.accept().unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let iss = tcp_segments(&frames)[0].seq;
+        send_tcp(&mut stack, &config, header(1, iss + 1, TCP_ACK), &[]);
+
+        peer.write_all(b"lost").unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        assert_eq!(tcp_segments(&frames)[0].payload, b"lost");
+        // Not acknowledged, so the data is sent again
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let segment = &tcp_segments(&frames)[0];
+        assert_eq!(segment.payload, b"lost");
+        assert_eq!(segment.seq, iss + 1);
+    }
+
+    #[test]
+    fn test_udp_to_host() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+        let container = container_addr(&config, 5353);
+        let remote = SocketAddrV4::new(config.gateway_ip, port);
+
+        let packet = packet::udp_packet(container, remote, b"query");
+        stack.handle_frame(&frame(&packet, &config), Instant::now());
+        let mut buf = [0u8; 16];
+        let (n, client) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"query");
+
+        server.send_to(b"answer", client).unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let ethernet = EthernetFrame::parse(&frames[0]).unwrap();
+        let ip = Ipv4Packet::parse(ethernet.payload).unwrap();
+        assert_eq!(ip.src, config.gateway_ip);
+        assert_eq!(ip.dst, config.container_ip);
+        let datagram = UdpDatagram::parse(&ip).unwrap();
+        assert_eq!(datagram.src_port, port);
+        assert_eq!(datagram.dst_port, 5353);
+        assert_eq!(datagram.payload, b"answer");
+    }
+
+    #[test]
+    fn test_arp_and_ping_gateway() {
+        let config = config(vec![]);
+        let mut stack = Stack::new(&config).unwrap();
+
+        let mut arp = vec![0, 1, 8, 0, 6, 4, 0, 1];
+        arp.extend_from_slice(&CONTAINER_MAC);
+        arp.extend_from_slice(&config.container_ip.octets());
+        arp.extend_from_slice(&[0; 6]);
+        arp.extend_from_slice(&config.gateway_ip.octets());
+        let request = ethernet_frame([0xff; 6], CONTAINER_MAC, ETHERTYPE_ARP, &arp);
+        stack.handle_frame(&request, Instant::now());
+        let frames = stack.take_frames();
+        let reply = EthernetFrame::parse(&frames[0]).unwrap();
+        assert_eq!(reply.ethertype, ETHERTYPE_ARP);
+        assert_eq!(&reply.payload[8..14], &config.gateway_mac);
+
+        let mut echo = vec![8, 0, 0, 0, 0, 1, 0, 1];
+        let sum = packet::checksum(&echo);
+        echo[2..4].copy_from_slice(&sum.to_be_bytes());
+        let ping = packet::ipv4_packet(config.container_ip, config.gateway_ip, PROTO_ICMP, &echo);
+        stack.handle_frame(&frame(&ping, &config), Instant::now());
+        let frames = stack.take_frames();
+        let ethernet = EthernetFrame::parse(&frames[0]).unwrap();
+        let ip = Ipv4Packet::parse(ethernet.payload).unwrap();
+        assert_eq!(ip.protocol, PROTO_ICMP);
+        assert_eq!(ip.payload[0], 0);
+    }
+
+    #[test]
+    fn test_tcp_port_forward() {
+        let config = config(vec!["127.0.0.1:0:8080".parse().unwrap()]);
+        let mut stack = Stack::new(&config).unwrap();
+        let host_addr = stack.forwarded_addrs()[0].as_ref().unwrap().to_owned();
+
+        let mut client = TcpStream::connect(host_addr).unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let syn = &tcp_segments(&frames)[0];
+        assert_eq!(syn.flags, TCP_SYN);
+        assert_eq!(*syn.src.ip(), config.gateway_ip);
+        let remote = syn.src;
+        let container = container_addr(&config, 8080);
+        let header = |seq, ack, flags| TcpHeader {
+            src: container,
+            dst: remote,
+            seq,
+            ack,
+            flags,
+            window: 65535,
+            mss: None,
+        };
+
+        let frames = send_tcp(
+            &mut stack,
+            &config,
+            TcpHeader {
+                mss: Some(1460),
+                ..header(5000, syn.seq + 1, TCP_SYN | TCP_ACK)
+            },
+            &[],
+        );
+        let ack = &tcp_segments(&frames)[0];
+        assert_eq!(ack.flags, TCP_ACK);
+        assert_eq!(ack.ack, 5001);
+
+        client.write_all(b"request").unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        assert_eq!(tcp_segments(&frames)[0].payload, b"request");
+
+        send_tcp(
+            &mut stack,
+            &config,
+            header(5001, syn.seq + 8, TCP_ACK | TCP_PSH),
+            b"response",
+        );
+        let mut buf = [0u8; 8];
+        client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"response");
+    }
+
+    #[test]
+    fn test_udp_port_forward() {
+        let config = config(vec!["127.0.0.1:0:53/udp".parse().unwrap()]);
+        let mut stack = Stack::new(&config).unwrap();
+        let host_addr = stack.forwarded_addrs()[0].as_ref().unwrap().to_owned();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"query", host_addr).unwrap();
+        let frames = poll_until(&mut stack, |frames| !frames.is_empty());
+        let ethernet = EthernetFrame::parse(&frames[0]).unwrap();
+        let ip = Ipv4Packet::parse(ethernet.payload).unwrap();
+        let datagram = UdpDatagram::parse(&ip).unwrap();
+        assert_eq!(ip.dst, config.container_ip);
+        assert_eq!(datagram.dst_port, 53);
+        assert_eq!(datagram.payload, b"query");
+
+        // The reply goes back to the client
+        let reply = packet::udp_packet(
+            container_addr(&config, 53),
+            SocketAddrV4::new(ip.src, datagram.src_port),
+            b"answer",
+        );
+        stack.handle_frame(&frame(&reply, &config), Instant::now());
+        let mut buf = [0u8; 16];
+        let n = client.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"answer");
+    }
+}
@@ -0,0 +1,129 @@
+//! Creation of the tap device in the network namespace of the container. The
+//! configuration is done with the legacy ioctls, which is all a single
+//! interface with a default route needs.
+use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use nix::errno::Errno;
+use nix::fcntl::{open, OFlag};
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType};
+use nix::sys::stat::Mode;
+
+use super::{NetworkError, Result, SlirpConfig};
+
+const TUN_DEVICE: &str = "/dev/net/tun";
+const TAP_NAME: &str = "tap0";
+const LOOPBACK_NAME: &str = "lo";
+
+fn ifreq(name: &str) -> libc::ifreq {
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(name.as_bytes()) {
+        *dst = *src as libc::c_char;
+    }
+    ifr
+}
+
+fn sockaddr(ip: Ipv4Addr) -> libc::sockaddr {
+    let sin = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from_ne_bytes(ip.octets()),
+        },
+        sin_zero: [0; 8],
+    };
+    // SAFETY: sockaddr_in is the IPv4 variant of sockaddr of the same size
+    unsafe { std::mem::transmute::<libc::sockaddr_in, libc::sockaddr>(sin) }
+}
+
+fn ioctl<T>(fd: RawFd, request: libc::c_ulong, name: &'static str, arg: &mut T) -> Result<()> {
+    // The type of the request differs between libcs
+    #[allow(clippy::useless_conversion)]
+    let ret = unsafe { libc::ioctl(fd, request.try_into().unwrap(), arg as *mut T) };
+    Errno::result(ret)
+        .map(drop)
+        .map_err(|err| NetworkError::ConfigureTap {
+            request: name,
+            source: err,
+        })
+}
+
+fn set_up(fd: RawFd, name: &str) -> Result<()> {
+    let mut ifr = ifreq(name);
+    ioctl(fd, libc::SIOCGIFFLAGS, "SIOCGIFFLAGS", &mut ifr)?;
+    unsafe { ifr.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short };
+    ioctl(fd, libc::SIOCSIFFLAGS, "SIOCSIFFLAGS", &mut ifr)
+}
+
+/// Creates and configures the tap device. Has to be called from within the
+/// network namespace of the container, with CAP_NET_ADMIN in its user
+/// namespace.
+pub(crate) fn create_tap(config: &SlirpConfig) -> Result<OwnedFd> {
+    let tap = open(
+        TUN_DEVICE,
+        OFlag::O_RDWR | OFlag::O_CLOEXEC | OFlag::O_NONBLOCK,
+        Mode::empty(),
+    )
+    .map_err(NetworkError::CreateTap)?;
+    // SAFETY: open returned a new fd
+    let tap = unsafe { OwnedFd::from_raw_fd(tap) };
+    let mut ifr = ifreq(TAP_NAME);
+    ifr.ifr_ifru.ifru_flags = (libc::IFF_TAP | libc::IFF_NO_PI) as libc::c_short;
+    #[allow(clippy::useless_conversion)]
+    let ret = unsafe {
+        libc::ioctl(
+            tap.as_raw_fd(),
+            libc::TUNSETIFF.try_into().unwrap(),
+            &mut ifr as *mut libc::ifreq,
+        )
+    };
+    Errno::result(ret).map_err(NetworkError::CreateTap)?;
+
+    let sock = socket::socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(NetworkError::CreateTap)?;
+    let fd = sock.as_raw_fd();
+
+    let mut ifr = ifreq(TAP_NAME);
+    unsafe {
+        ifr.ifr_ifru.ifru_hwaddr.sa_family = libc::ARPHRD_ETHER;
+        for (dst, src) in ifr
+            .ifr_ifru
+            .ifru_hwaddr
+            .sa_data
+            .iter_mut()
+            .zip(config.container_mac)
+        {
+            *dst = src as libc::c_char;
+        }
+    }
+    ioctl(fd, libc::SIOCSIFHWADDR, "SIOCSIFHWADDR", &mut ifr)?;
+
+    let mut ifr = ifreq(TAP_NAME);
+    ifr.ifr_ifru.ifru_mtu = config.mtu as libc::c_int;
+    ioctl(fd, libc::SIOCSIFMTU, "SIOCSIFMTU", &mut ifr)?;
+
+    let mut ifr = ifreq(TAP_NAME);
+    ifr.ifr_ifru.ifru_addr = sockaddr(config.container_ip);
+    ioctl(fd, libc::SIOCSIFADDR, "SIOCSIFADDR", &mut ifr)?;
+
+    let mut ifr = ifreq(TAP_NAME);
+    ifr.ifr_ifru.ifru_netmask = sockaddr(config.netmask());
+    ioctl(fd, libc::SIOCSIFNETMASK, "SIOCSIFNETMASK", &mut ifr)?;
+
+    set_up(fd, TAP_NAME)?;
+    set_up(fd, LOOPBACK_NAME)?;
+
+    let mut route: libc::rtentry = unsafe { std::mem::zeroed() };
+    route.rt_dst = sockaddr(Ipv4Addr::UNSPECIFIED);
+    route.rt_genmask = sockaddr(Ipv4Addr::UNSPECIFIED);
+    route.rt_gateway = sockaddr(config.gateway_ip);
+    route.rt_flags = libc::RTF_UP | libc::RTF_GATEWAY;
+    ioctl(fd, libc::SIOCADDRT, "SIOCADDRT", &mut route)?;
+
+    Ok(tap)
+}
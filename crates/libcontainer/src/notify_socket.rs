@@ -1,5 +1,6 @@
 use std::env;
 use std::io::prelude::*;
+use std::net::Shutdown;
 use std::os::fd::FromRawFd;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::{UnixListener, UnixStream};
@@ -7,8 +8,13 @@ use std::path::{Path, PathBuf};
 
 use nix::unistd::{self, close};
 
+use crate::timing::PhaseTiming;
+
 pub const NOTIFY_FILE: &str = "notify.sock";
 
+const START_MESSAGE: &str = "start container";
+const START_WITH_REPORT_MESSAGE: &str = "start container with report";
+
 #[derive(Debug, thiserror::Error)]
 pub enum NotifyListenerError {
     #[error("failed to chdir {path} while creating notify socket: {source}")]
@@ -35,6 +41,8 @@ pub enum NotifyListenerError {
     Read(#[source] std::io::Error),
     #[error("failed to send start container")]
     SendStartContainer(#[source] std::io::Error),
+    #[error("failed to report startup phases")]
+    ReportPhases(#[source] std::io::Error),
 }
 
 type Result<T> = std::result::Result<T, NotifyListenerError>;
@@ -77,7 +85,7 @@ impl NotifyListener {
         Ok(Self { socket: stream })
     }
 
-    pub fn wait_for_container_start(&self) -> Result<()> {
+    pub fn wait_for_container_start(&self) -> Result<ContainerStart> {
         match self.socket.accept() {
             Ok((mut socket, _)) => {
                 let mut response = String::new();
@@ -85,11 +93,13 @@ impl NotifyListener {
                     .read_to_string(&mut response)
                     .map_err(NotifyListenerError::Read)?;
                 tracing::debug!("received: {}", response);
+                Ok(ContainerStart {
+                    socket,
+                    wants_report: response == START_WITH_REPORT_MESSAGE,
+                })
             }
             Err(e) => Err(NotifyListenerError::Accept(e))?,
         }
-
-        Ok(())
     }
 
     pub fn close(&self) -> Result<()> {
@@ -115,6 +125,32 @@ impl Clone for NotifyListener {
     }
 }
 
+/// An accepted start request. A caller of start which asked for a report
+/// waits until the connection is closed, which happens at the latest when the
+/// payload is exec'd.
+pub struct ContainerStart {
+    socket: UnixStream,
+    wants_report: bool,
+}
+
+impl ContainerStart {
+    /// If the caller of start waits for the startup phases to be reported
+    pub fn wants_report(&self) -> bool {
+        self.wants_report
+    }
+
+    /// Reports the startup phases which ran after the start signal back to
+    /// the caller of start.
+    pub fn report(mut self, phases: &[PhaseTiming]) -> Result<()> {
+        let buf = serde_json::to_vec(phases)
+            .map_err(|err| NotifyListenerError::ReportPhases(err.into()))?;
+        self.socket
+            .write_all(&buf)
+            .map_err(NotifyListenerError::ReportPhases)?;
+        Ok(())
+    }
+}
+
 pub struct NotifySocket {
     path: PathBuf,
 }
@@ -126,7 +162,29 @@ impl NotifySocket {
         }
     }
 
+    /// Signals the container to start
     pub fn notify_container_start(&mut self) -> Result<()> {
+        self.send_start(START_MESSAGE)?;
+        tracing::debug!("notify finished");
+        Ok(())
+    }
+
+    /// Signals the container to start and waits until the container process
+    /// is about to exec the payload. Returns the startup phases the container
+    /// process ran after the start signal.
+    pub fn notify_container_start_with_report(&mut self) -> Result<Vec<PhaseTiming>> {
+        let mut stream = self.send_start(START_WITH_REPORT_MESSAGE)?;
+        // The container process closes the connection without a report if it
+        // fails after the start signal, so an empty report is not an error.
+        let mut report = Vec::new();
+        stream
+            .read_to_end(&mut report)
+            .map_err(NotifyListenerError::Read)?;
+        tracing::debug!("notify finished");
+        Ok(serde_json::from_slice(&report).unwrap_or_default())
+    }
+
+    fn send_start(&self, message: &str) -> Result<UnixStream> {
         tracing::debug!("notify container start");
         let cwd = env::current_dir().map_err(NotifyListenerError::GetCwd)?;
         let workdir = self
@@ -148,14 +206,16 @@ impl NotifySocket {
                 name: socket_name.to_str().unwrap().to_owned(),
             })?;
         stream
-            .write_all(b"start container")
+            .write_all(message.as_bytes())
+            .map_err(NotifyListenerError::SendStartContainer)?;
+        stream
+            .shutdown(Shutdown::Write)
             .map_err(NotifyListenerError::SendStartContainer)?;
-        tracing::debug!("notify finished");
         unistd::chdir(&cwd).map_err(|e| NotifyListenerError::Chdir {
             source: e,
             path: cwd,
         })?;
-        Ok(())
+        Ok(stream)
     }
 }
 
@@ -181,11 +241,38 @@ mod test {
             move || {
                 // We clone the listener and listen on the cloned listener to
                 // make sure the cloned fd functions correctly.
-                listener.wait_for_container_start().unwrap();
+                let start = listener.wait_for_container_start().unwrap();
+                assert!(!start.wants_report());
             }
         });
 
         socket.notify_container_start().unwrap();
         thread_handle.join().unwrap();
     }
+
+    #[test]
+    fn test_notify_report_phases() {
+        let tempdir = tempdir().unwrap();
+        let socket_path = tempdir.path().join("notify.sock");
+        let listener = NotifyListener::new(&socket_path).unwrap();
+        let mut socket = NotifySocket::new(socket_path.clone());
+        let timing = PhaseTiming {
+            phase: crate::timing::Phase::Exec,
+            process: crate::timing::PhaseProcess::Init,
+            start_unix_nano: 1,
+            duration_nano: 2,
+        };
+        let thread_handle = std::thread::spawn({
+            let timing = timing.clone();
+            move || {
+                let start = listener.wait_for_container_start().unwrap();
+                assert!(start.wants_report());
+                start.report(&[timing]).unwrap();
+            }
+        });
+
+        let phases = socket.notify_container_start_with_report().unwrap();
+        thread_handle.join().unwrap();
+        assert_eq!(phases, vec![timing]);
+    }
 }
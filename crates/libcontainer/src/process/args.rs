@@ -3,9 +3,11 @@ use std::path::PathBuf;
 use std::rc::Rc;
 
 use libcgroups::common::CgroupConfig;
-use oci_spec::runtime::Spec;
+use libcgroups::sub_cgroup::SubCgroup;
+use oci_spec::runtime::{LinuxResources, Spec};
 
 use crate::container::Container;
+use crate::network::SlirpConfig;
 use crate::notify_socket::NotifyListener;
 use crate::syscall::syscall::SyscallType;
 use crate::user_ns::UserNamespaceConfig;
@@ -38,6 +40,15 @@ pub struct ContainerArgs {
     pub user_ns_config: Option<UserNamespaceConfig>,
     /// Cgroup Manager Config
     pub cgroup_config: CgroupConfig,
+    /// Resources applied to the cgroup of an init container. For rootless
+    /// containers only those the delegated controllers can enforce.
+    pub resources: Option<LinuxResources>,
+    /// Sub-cgroup of the container a tenant process should be placed in
+    pub sub_cgroup: Option<SubCgroup>,
+    /// The cgroup of the container is frozen, so a tenant process is only
+    /// placed into it once it runs, as it would freeze during its setup
+    /// otherwise
+    pub cgroup_frozen: bool,
     /// If the container is to be run in detached mode
     pub detached: bool,
     /// Manage the functions that actually run on the container
@@ -52,4 +63,10 @@ pub struct ContainerArgs {
     pub stderr: Option<RawFd>,
     // Indicate if the init process should be a sibling of the main process.
     pub as_sibling: bool,
+    /// Built-in network of an init container, its tap device is created by
+    /// the init process and relayed by a helper process
+    pub network: Option<SlirpConfig>,
+    /// W3C trace context of the caller, sent to the intermediate and init
+    /// processes so their spans join the caller's trace.
+    pub trace_context: Option<String>,
 }
@@ -1,9 +1,12 @@
+use std::os::fd::{FromRawFd, OwnedFd};
 use std::os::unix::prelude::{AsRawFd, RawFd};
 
 use nix::unistd::Pid;
 
 use crate::channel::{channel, Receiver, Sender};
+use crate::process::failure::{ContainerProcessError, ProcessFailure};
 use crate::process::message::Message;
+use crate::timing::PhaseTiming;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ChannelError {
@@ -22,10 +25,16 @@ pub enum ChannelError {
     BaseChannelError(#[from] crate::channel::ChannelError),
     #[error("missing fds from seccomp request")]
     MissingSeccompFds,
-    #[error("exec process failed with error {0}")]
-    ExecError(String),
-    #[error("intermediate process error {0}")]
-    OtherError(String),
+    #[error("missing fd of the network tap device")]
+    MissingNetworkTap,
+    #[error(transparent)]
+    ProcessFailed(Box<ContainerProcessError>),
+}
+
+impl From<ProcessFailure> for ChannelError {
+    fn from(failure: ProcessFailure) -> Self {
+        ChannelError::ProcessFailed(Box::new(failure.into()))
+    }
 }
 
 // Channel Design
@@ -65,6 +74,13 @@ impl MainSender {
         Ok(())
     }
 
+    // sends the tap device created in the network namespace of the container
+    pub fn network_tap(&mut self, fd: RawFd) -> Result<(), ChannelError> {
+        self.sender.send_fds(Message::NetworkTap, &[fd])?;
+
+        Ok(())
+    }
+
     pub fn intermediate_ready(&mut self, pid: Pid) -> Result<(), ChannelError> {
         // Send over the IntermediateReady follow by the pid.
         tracing::debug!("sending init pid ({:?})", pid);
@@ -73,19 +89,23 @@ impl MainSender {
         Ok(())
     }
 
-    pub fn init_ready(&mut self) -> Result<(), ChannelError> {
-        self.sender.send(Message::InitReady)?;
+    // sends the startup phases the process has gone through so far
+    pub fn phase_timings(&mut self, timings: Vec<PhaseTiming>) -> Result<(), ChannelError> {
+        self.sender.send(Message::PhaseTimings(timings))?;
 
         Ok(())
     }
 
-    pub fn exec_failed(&mut self, err: String) -> Result<(), ChannelError> {
-        self.sender.send(Message::ExecFailed(err))?;
+    pub fn init_ready(&mut self) -> Result<(), ChannelError> {
+        self.sender.send(Message::InitReady)?;
+
         Ok(())
     }
 
-    pub fn send_error(&mut self, err: String) -> Result<(), ChannelError> {
-        self.sender.send(Message::OtherError(err))?;
+    // reports the error the intermediate or init process failed with
+    pub fn process_failed(&mut self, failure: ProcessFailure) -> Result<(), ChannelError> {
+        self.sender
+            .send(Message::ProcessFailed(Box::new(failure)))?;
         Ok(())
     }
 
@@ -114,8 +134,7 @@ impl MainReceiver {
 
         match msg {
             Message::IntermediateReady(pid) => Ok(Pid::from_raw(pid)),
-            Message::ExecFailed(err) => Err(ChannelError::ExecError(err)),
-            Message::OtherError(err) => Err(ChannelError::OtherError(err)),
+            Message::ProcessFailed(failure) => Err((*failure).into()),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::IntermediateReady(0),
                 received: msg,
@@ -133,6 +152,7 @@ impl MainReceiver {
             })?;
         match msg {
             Message::WriteMapping => Ok(()),
+            Message::ProcessFailed(failure) => Err((*failure).into()),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::WriteMapping,
                 received: msg,
@@ -162,6 +182,7 @@ impl MainReceiver {
                 }?;
                 Ok(fd)
             }
+            Message::ProcessFailed(failure) => Err((*failure).into()),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::SeccompNotify,
                 received: msg,
@@ -169,6 +190,48 @@ impl MainReceiver {
         }
     }
 
+    pub fn wait_for_network_tap(&mut self) -> Result<OwnedFd, ChannelError> {
+        let (msg, fds) = self.receiver.recv_with_fds::<[RawFd; 1]>().map_err(|err| {
+            ChannelError::ReceiveError {
+                msg: "waiting for network tap".to_string(),
+                source: err,
+            }
+        })?;
+
+        match msg {
+            Message::NetworkTap => match fds {
+                // SAFETY: the fd was just received and is owned by nobody else
+                Some(fds) if !fds.is_empty() => Ok(unsafe { OwnedFd::from_raw_fd(fds[0]) }),
+                _ => Err(ChannelError::MissingNetworkTap),
+            },
+            Message::ProcessFailed(failure) => Err((*failure).into()),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::NetworkTap,
+                received: msg,
+            }),
+        }
+    }
+
+    /// Waits for the intermediate or init process to send the startup phases
+    /// it has gone through so far
+    pub fn wait_for_phase_timings(&mut self) -> Result<Vec<PhaseTiming>, ChannelError> {
+        let msg = self
+            .receiver
+            .recv()
+            .map_err(|err| ChannelError::ReceiveError {
+                msg: "waiting for phase timings".to_string(),
+                source: err,
+            })?;
+        match msg {
+            Message::PhaseTimings(timings) => Ok(timings),
+            Message::ProcessFailed(failure) => Err((*failure).into()),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::PhaseTimings(Vec::new()),
+                received: msg,
+            }),
+        }
+    }
+
     /// Waits for associated init process to send ready message
     /// and return the pid of init process which is forked by init process
     pub fn wait_for_init_ready(&mut self) -> Result<(), ChannelError> {
@@ -181,10 +244,7 @@ impl MainReceiver {
             })?;
         match msg {
             Message::InitReady => Ok(()),
-            // this case in unique and known enough to have a special error format
-            Message::ExecFailed(err) => Err(ChannelError::ExecError(format!(
-                "error in executing process : {err}"
-            ))),
+            Message::ProcessFailed(failure) => Err((*failure).into()),
             msg => Err(ChannelError::UnexpectedMessage {
                 expected: Message::InitReady,
                 received: msg,
@@ -219,6 +279,14 @@ impl IntermediateSender {
         Ok(())
     }
 
+    // passes the trace context of the caller, so spans created in the
+    // intermediate process join the trace of the main process
+    pub fn trace_context(&mut self, ctx: Option<String>) -> Result<(), ChannelError> {
+        self.sender.send(Message::TraceContext(ctx))?;
+
+        Ok(())
+    }
+
     pub fn close(&self) -> Result<(), ChannelError> {
         self.sender.close()?;
 
@@ -231,6 +299,25 @@ pub struct IntermediateReceiver {
 }
 
 impl IntermediateReceiver {
+    /// Waits for the main process to send the trace context. This is the first
+    /// message the intermediate process receives.
+    pub fn wait_for_trace_context(&mut self) -> Result<Option<String>, ChannelError> {
+        let msg = self
+            .receiver
+            .recv()
+            .map_err(|err| ChannelError::ReceiveError {
+                msg: "waiting for trace context".to_string(),
+                source: err,
+            })?;
+        match msg {
+            Message::TraceContext(ctx) => Ok(ctx),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::TraceContext(None),
+                received: msg,
+            }),
+        }
+    }
+
     // wait until the parent process has finished writing the id mappings
     pub fn wait_for_mapping_ack(&mut self) -> Result<(), ChannelError> {
         tracing::debug!("waiting for mapping ack");
@@ -273,6 +360,12 @@ impl InitSender {
         Ok(())
     }
 
+    pub fn trace_context(&mut self, ctx: Option<String>) -> Result<(), ChannelError> {
+        self.sender.send(Message::TraceContext(ctx))?;
+
+        Ok(())
+    }
+
     pub fn close(&self) -> Result<(), ChannelError> {
         self.sender.close()?;
 
@@ -285,6 +378,25 @@ pub struct InitReceiver {
 }
 
 impl InitReceiver {
+    /// Waits for the main process to send the trace context. This is the first
+    /// message the init process receives.
+    pub fn wait_for_trace_context(&mut self) -> Result<Option<String>, ChannelError> {
+        let msg = self
+            .receiver
+            .recv()
+            .map_err(|err| ChannelError::ReceiveError {
+                msg: "waiting for trace context".to_string(),
+                source: err,
+            })?;
+        match msg {
+            Message::TraceContext(ctx) => Ok(ctx),
+            msg => Err(ChannelError::UnexpectedMessage {
+                expected: Message::TraceContext(None),
+                received: msg,
+            }),
+        }
+    }
+
     pub fn wait_for_seccomp_request_done(&mut self) -> Result<(), ChannelError> {
         let msg = self
             .receiver
@@ -392,6 +504,59 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[serial]
+    fn test_channel_trace_context() -> Result<()> {
+        let (sender, receiver) = &mut init_channel()?;
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                wait::waitpid(child, None)?;
+                let ctx = receiver.wait_for_trace_context()?;
+                receiver.close()?;
+                assert_eq!(ctx.as_deref(), Some(traceparent));
+            }
+            unistd::ForkResult::Child => {
+                sender
+                    .trace_context(Some(traceparent.to_owned()))
+                    .with_context(|| "Failed to send trace context")?;
+                sender.close()?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    #[serial]
+    fn test_channel_phase_timings() -> Result<()> {
+        let (sender, receiver) = &mut main_channel()?;
+        let timing = PhaseTiming {
+            phase: crate::timing::Phase::Rootfs,
+            process: crate::timing::PhaseProcess::Init,
+            start_unix_nano: 1,
+            duration_nano: 2,
+        };
+        match unsafe { unistd::fork()? } {
+            unistd::ForkResult::Parent { child } => {
+                wait::waitpid(child, None)?;
+                let timings = receiver.wait_for_phase_timings()?;
+                receiver.close()?;
+                assert_eq!(timings, vec![timing]);
+            }
+            unistd::ForkResult::Child => {
+                sender
+                    .phase_timings(vec![timing])
+                    .with_context(|| "Failed to send phase timings")?;
+                sender.close()?;
+                std::process::exit(0);
+            }
+        };
+
+        Ok(())
+    }
+
     #[test]
     #[serial]
     fn test_channel_init_ready() -> Result<()> {
@@ -1,8 +1,9 @@
 use std::os::fd::FromRawFd;
 
 use libcgroups::common::CgroupManager;
+use libcgroups::sub_cgroup::SubCgroup;
 use nix::unistd::{close, write, Gid, Pid, Uid};
-use oci_spec::runtime::{LinuxNamespace, LinuxNamespaceType, LinuxResources};
+use oci_spec::runtime::{LinuxNamespaceType, LinuxResources};
 use procfs::process::Process;
 
 use super::args::{ContainerArgs, ContainerType};
@@ -11,7 +12,9 @@ use super::fork::CloneCb;
 use super::init::process as init_process;
 use crate::error::MissingSpecError;
 use crate::namespaces::Namespaces;
+use crate::process::failure::{FailedProcess, FailureContext, ProcessFailure};
 use crate::process::{channel, fork};
+use crate::timing::{Phase, PhaseProcess, StartupTimings};
 
 #[derive(Debug, thiserror::Error)]
 pub enum IntermediateProcessError {
@@ -35,6 +38,30 @@ pub enum IntermediateProcessError {
     Other(String),
 }
 
+impl FailureContext for IntermediateProcessError {
+    fn stage(&self) -> &'static str {
+        match self {
+            IntermediateProcessError::Channel(_) => "channel",
+            IntermediateProcessError::Namespace(_) => "namespaces",
+            IntermediateProcessError::Syscall(_) => "syscall",
+            IntermediateProcessError::InitProcess(_) => "clone_init",
+            IntermediateProcessError::Cgroup(_) => "cgroups",
+            IntermediateProcessError::Procfs(_) => "procfs",
+            IntermediateProcessError::ExecNotify(_) => "exec_notify",
+            IntermediateProcessError::MissingSpec(_) => "spec",
+            IntermediateProcessError::Other(_) => "other",
+        }
+    }
+
+    fn syscall(&self) -> Option<&'static str> {
+        match self {
+            IntermediateProcessError::InitProcess(_) => Some("clone"),
+            IntermediateProcessError::ExecNotify(_) => Some("close"),
+            _ => None,
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, IntermediateProcessError>;
 
 pub fn container_intermediate_process(
@@ -45,6 +72,16 @@ pub fn container_intermediate_process(
 ) -> Result<()> {
     let (inter_sender, inter_receiver) = intermediate_chan;
     let (init_sender, init_receiver) = init_chan;
+    let trace_context = inter_receiver.wait_for_trace_context()?;
+    // The intermediate process is a new process, so its spans start a new
+    // root which joins the caller's trace through the `traceparent` field.
+    let _span = tracing::info_span!(
+        parent: None,
+        "intermediate_process",
+        traceparent = trace_context.as_deref()
+    )
+    .entered();
+    let mut timings = StartupTimings::new(PhaseProcess::Intermediate);
     let command = args.syscall.create_syscall();
     let spec = &args.spec;
     let linux = spec.linux().as_ref().ok_or(MissingSpecError::Linux)?;
@@ -61,19 +98,36 @@ pub fn container_intermediate_process(
     // mapped to an unprivileged user by the user namespace however.
     // In addition this needs to be done before we enter the cgroup namespace as
     // the cgroup of the process will form the root of the cgroup hierarchy in
-    // the cgroup namespace.
-    apply_cgroups(
-        &cgroup_manager,
-        linux.resources().as_ref(),
-        matches!(args.container_type, ContainerType::InitContainer),
-    )?;
+    // the cgroup namespace. A tenant of a paused container would freeze in the
+    // middle of its setup here, so the tenant builder moves it into the frozen
+    // cgroup once it is set up, before it executes the payload.
+    if !args.cgroup_frozen {
+        timings.measure(Phase::Cgroups, || {
+            apply_cgroups(
+                &cgroup_manager,
+                args.resources.as_ref(),
+                matches!(args.container_type, ContainerType::InitContainer),
+            )?;
+            // The init process inherits the cgroup of this process
+            if let Some(sub_cgroup) = &args.sub_cgroup {
+                join_sub_cgroup(sub_cgroup)?;
+            }
+            Ok::<_, IntermediateProcessError>(())
+        })?;
+    }
 
     // if new user is specified in specification, this will be true and new
     // namespace will be created, check
     // https://man7.org/linux/man-pages/man7/user_namespaces.7.html for more
     // information
     if let Some(user_namespace) = namespaces.get(LinuxNamespaceType::User)? {
-        setup_userns(&namespaces, user_namespace, main_sender, inter_receiver)?;
+        timings.measure(Phase::Namespaces, || {
+            namespaces.unshare_or_setns(user_namespace)
+        })?;
+        // the main process writes the mappings, which is timed there
+        if user_namespace.path().is_none() {
+            request_id_mapping(main_sender, inter_receiver)?;
+        }
 
         // After UID and GID mapping is configured correctly in the Youki main
         // process, We want to make sure continue as the root user inside the
@@ -97,9 +151,15 @@ pub fn container_intermediate_process(
 
     // Pid namespace requires an extra fork to enter, so we enter pid namespace now.
     if let Some(pid_namespace) = namespaces.get(LinuxNamespaceType::Pid)? {
-        namespaces.unshare_or_setns(pid_namespace)?;
+        timings.measure(Phase::Namespaces, || {
+            namespaces.unshare_or_setns(pid_namespace)
+        })?;
     }
 
+    // The timings are sent before the init process exists, so that they
+    // can't race with the messages the init process sends to the main process.
+    main_sender.phase_timings(timings.into_phases())?;
+
     let cb: CloneCb = {
         Box::new(|| {
             if let Err(ret) = prctl::set_name("youki:[2:INIT]") {
@@ -122,11 +182,12 @@ pub fn container_intermediate_process(
                 Ok(_) => 0,
                 Err(e) => {
                     tracing::error!("failed to initialize container process: {e}");
-                    if let Err(err) = main_sender.exec_failed(e.to_string()) {
+                    let failure = ProcessFailure::new(FailedProcess::Init, &e);
+                    if let Err(err) = main_sender.process_failed(failure.clone()) {
                         tracing::error!(?err, "failed sending error to main sender");
                     }
                     if let ContainerType::TenantContainer { exec_notify_fd } = args.container_type {
-                        let buf = format!("{e}");
+                        let buf = serde_json::to_string(&failure).unwrap_or_else(|_| e.to_string());
                         let exec_notify_fd =
                             unsafe { std::os::fd::OwnedFd::from_raw_fd(exec_notify_fd) };
                         if let Err(err) = write(&exec_notify_fd, buf.as_bytes()) {
@@ -189,17 +250,9 @@ pub fn container_intermediate_process(
     Ok(())
 }
 
-fn setup_userns(
-    namespaces: &Namespaces,
-    user_namespace: &LinuxNamespace,
-    sender: &mut MainSender,
-    receiver: &mut IntermediateReceiver,
-) -> Result<()> {
-    namespaces.unshare_or_setns(user_namespace)?;
-    if user_namespace.path().is_some() {
-        return Ok(());
-    }
-
+/// Has the main process write the uid and gid mappings of the new user
+/// namespace the process is in
+fn request_id_mapping(sender: &mut MainSender, receiver: &mut IntermediateReceiver) -> Result<()> {
     tracing::debug!("creating new user namespace");
     // child needs to be dumpable, otherwise the non root parent is not
     // allowed to write the uid/gid maps
@@ -226,6 +279,14 @@ fn setup_userns(
     Ok(())
 }
 
+fn join_sub_cgroup(sub_cgroup: &SubCgroup) -> Result<()> {
+    let pid = Pid::from_raw(Process::myself()?.pid());
+    sub_cgroup.join(pid).map_err(|err| {
+        tracing::error!(?pid, ?err, %sub_cgroup, "failed to join sub-cgroup");
+        IntermediateProcessError::Cgroup(err.to_string())
+    })
+}
+
 fn apply_cgroups<
     C: CgroupManager<Error = E> + ?Sized,
     E: std::error::Error + Send + Sync + 'static,
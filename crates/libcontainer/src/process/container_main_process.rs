@@ -1,11 +1,15 @@
+use std::os::fd::OwnedFd;
+
 use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd::Pid;
 
 use crate::process::args::ContainerArgs;
+use crate::process::failure::{FailedProcess, ProcessFailure};
 use crate::process::fork::{self, CloneCb};
 use crate::process::intel_rdt::setup_intel_rdt;
 use crate::process::{channel, container_intermediate_process};
 use crate::syscall::SyscallError;
+use crate::timing::{Phase, StartupTimings};
 use crate::user_ns::UserNamespaceConfig;
 
 #[derive(Debug, thiserror::Error)]
@@ -33,7 +37,10 @@ pub enum ProcessError {
 
 type Result<T> = std::result::Result<T, ProcessError>;
 
-pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bool)> {
+pub fn container_main_process(
+    container_args: &ContainerArgs,
+    timings: &mut StartupTimings,
+) -> Result<(Pid, bool, Option<OwnedFd>)> {
     // We use a set of channels to communicate between parent and child process.
     // Each channel is uni-directional. Because we will pass these channel to
     // cloned process, we have to be deligent about closing any unused channel.
@@ -59,7 +66,8 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
                 Ok(_) => 0,
                 Err(err) => {
                     tracing::error!("failed to run intermediate process {}", err);
-                    match main_sender.send_error(err.to_string()) {
+                    let failure = ProcessFailure::new(FailedProcess::Intermediate, &err);
+                    match main_sender.process_failed(failure) {
                         Ok(_) => {}
                         Err(e) => {
                             tracing::error!(
@@ -94,17 +102,22 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
     })?;
 
     let (mut inter_sender, inter_receiver) = inter_chan;
-    #[cfg(feature = "libseccomp")]
     let (mut init_sender, init_receiver) = init_chan;
-    #[cfg(not(feature = "libseccomp"))]
-    let (init_sender, init_receiver) = init_chan;
+
+    // The trace context is the first message both the intermediate and the
+    // init process wait for, so that every span they create can be joined to
+    // the trace of the caller.
+    inter_sender.trace_context(container_args.trace_context.clone())?;
+    init_sender.trace_context(container_args.trace_context.clone())?;
 
     // If creating a container with new user namespace, the intermediate process will ask
     // the main process to set up uid and gid mapping, once the intermediate
     // process enters into a new user namespace.
     if let Some(config) = &container_args.user_ns_config {
         main_receiver.wait_for_mapping_request()?;
-        setup_mapping(config, intermediate_pid)?;
+        timings.measure(Phase::UidMapping, || {
+            setup_mapping(config, intermediate_pid)
+        })?;
         inter_sender.mapping_written()?;
     }
 
@@ -115,11 +128,20 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         err
     })?;
 
+    timings.extend(main_receiver.wait_for_phase_timings()?);
+
     // The intermediate process will send the init pid once it forks the init
     // process.  The intermediate process should exit after this point.
     let init_pid = main_receiver.wait_for_intermediate_ready()?;
     let mut need_to_clean_up_intel_rdt_subdirectory = false;
 
+    // The init process sends the tap device right after it has set up the
+    // namespaces, the network helper relays its traffic.
+    let network_tap = match container_args.network {
+        Some(_) => Some(main_receiver.wait_for_network_tap()?),
+        None => None,
+    };
+
     if let Some(linux) = container_args.spec.linux() {
         #[cfg(feature = "libseccomp")]
         if let Some(seccomp) = linux.seccomp() {
@@ -161,6 +183,7 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         err
     })?;
 
+    timings.extend(main_receiver.wait_for_phase_timings()?);
     main_receiver.wait_for_init_ready().map_err(|err| {
         tracing::error!("failed to wait for init ready: {}", err);
         err
@@ -206,7 +229,11 @@ pub fn container_main_process(container_args: &ContainerArgs) -> Result<(Pid, bo
         Err(err) => return Err(ProcessError::WaitIntermediateProcess(err)),
     };
 
-    Ok((init_pid, need_to_clean_up_intel_rdt_subdirectory))
+    Ok((
+        init_pid,
+        need_to_clean_up_intel_rdt_subdirectory,
+        network_tap,
+    ))
 }
 
 fn setup_mapping(config: &UserNamespaceConfig, pid: Pid) -> Result<()> {
@@ -0,0 +1,258 @@
+//! Structured errors of the intermediate and init processes. They are sent to
+//! the main process, where they are turned back into errors which keep the
+//! failing stage, the syscall, the errno, the path and the source chain,
+//! instead of a single flattened string.
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use nix::errno::Errno;
+use serde::{Deserialize, Serialize};
+
+use crate::syscall::SyscallError;
+
+/// The container process an error occurred in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailedProcess {
+    Intermediate,
+    Init,
+}
+
+impl fmt::Display for FailedProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailedProcess::Intermediate => write!(f, "intermediate"),
+            FailedProcess::Init => write!(f, "init"),
+        }
+    }
+}
+
+/// Error of a container process as sent over the channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessFailure {
+    pub process: FailedProcess,
+    /// Setup step which failed, e.g. `rootfs` or `hooks`
+    pub stage: String,
+    pub syscall: Option<String>,
+    pub errno: Option<i32>,
+    pub path: Option<PathBuf>,
+    /// Messages of the error and of its sources, outermost first
+    pub chain: Vec<String>,
+}
+
+/// Details a process error can add to its failure report
+pub trait FailureContext: Error + 'static {
+    fn stage(&self) -> &'static str;
+
+    fn syscall(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Finds the errno of the innermost error in the chain which carries one
+fn find_errno(err: &(dyn Error + 'static)) -> Option<i32> {
+    let mut errno = None;
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(e) = err.downcast_ref::<Errno>() {
+            errno = Some(*e as i32);
+        } else if let Some(e) = err.downcast_ref::<std::io::Error>() {
+            errno = e.raw_os_error().or(errno);
+        } else if let Some(SyscallError::Nix(e)) = err.downcast_ref::<SyscallError>() {
+            errno = Some(*e as i32);
+        } else if let Some(SyscallError::IO(e)) = err.downcast_ref::<SyscallError>() {
+            errno = e.raw_os_error().or(errno);
+        }
+        current = err.source();
+    }
+    errno
+}
+
+impl ProcessFailure {
+    pub fn new<E: FailureContext>(process: FailedProcess, err: &E) -> Self {
+        let mut chain = Vec::new();
+        let mut current: Option<&(dyn Error + 'static)> = Some(err);
+        while let Some(err) = current {
+            let msg = err.to_string();
+            // Transparent errors repeat the message of their source
+            if chain.last() != Some(&msg) {
+                chain.push(msg);
+            }
+            current = err.source();
+        }
+
+        Self {
+            process,
+            stage: err.stage().to_owned(),
+            syscall: err.syscall().map(str::to_owned),
+            errno: find_errno(err),
+            path: err.path(),
+            chain,
+        }
+    }
+}
+
+impl fmt::Display for ProcessFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} process failed at {}", self.process, self.stage)?;
+        let mut details = Vec::new();
+        if let Some(syscall) = &self.syscall {
+            details.push(format!("syscall {syscall}"));
+        }
+        if let Some(path) = &self.path {
+            details.push(format!("path {}", path.display()));
+        }
+        if let Some(errno) = self.errno {
+            let errno = Errno::from_raw(errno);
+            details.push(format!("errno {errno:?}: {}", errno.desc()));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// An error message of the source chain of a container process error
+#[derive(Debug)]
+struct ChainedError {
+    msg: String,
+    source: Option<Box<ChainedError>>,
+}
+
+impl fmt::Display for ChainedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for ChainedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
+}
+
+/// Error of a container process, reconstructed in the main process
+#[derive(Debug)]
+pub struct ContainerProcessError {
+    failure: ProcessFailure,
+    source: Option<Box<ChainedError>>,
+}
+
+impl ContainerProcessError {
+    pub fn process(&self) -> FailedProcess {
+        self.failure.process
+    }
+
+    pub fn stage(&self) -> &str {
+        &self.failure.stage
+    }
+
+    pub fn syscall(&self) -> Option<&str> {
+        self.failure.syscall.as_deref()
+    }
+
+    pub fn errno(&self) -> Option<Errno> {
+        self.failure.errno.map(Errno::from_raw)
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.failure.path.as_ref()
+    }
+}
+
+impl From<ProcessFailure> for ContainerProcessError {
+    fn from(failure: ProcessFailure) -> Self {
+        let source = failure.chain.iter().rev().fold(None, |source, msg| {
+            Some(Box::new(ChainedError {
+                msg: msg.to_owned(),
+                source,
+            }))
+        });
+        Self { failure, source }
+    }
+}
+
+impl fmt::Display for ContainerProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.failure)
+    }
+}
+
+impl Error for ContainerProcessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    enum TestError {
+        #[error("failed to mount path")]
+        Mount {
+            path: PathBuf,
+            #[source]
+            source: SyscallError,
+        },
+    }
+
+    impl FailureContext for TestError {
+        fn stage(&self) -> &'static str {
+            "rootfs"
+        }
+
+        fn syscall(&self) -> Option<&'static str> {
+            Some("mount")
+        }
+
+        fn path(&self) -> Option<PathBuf> {
+            match self {
+                TestError::Mount { path, .. } => Some(path.to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_failure() {
+        let err = TestError::Mount {
+            path: PathBuf::from("/proc"),
+            source: SyscallError::Nix(Errno::EACCES),
+        };
+        let failure = ProcessFailure::new(FailedProcess::Init, &err);
+        assert_eq!(failure.stage, "rootfs");
+        assert_eq!(failure.syscall.as_deref(), Some("mount"));
+        assert_eq!(failure.errno, Some(Errno::EACCES as i32));
+        assert_eq!(failure.path, Some(PathBuf::from("/proc")));
+        // The transparent SyscallError doesn't add another message
+        assert_eq!(
+            failure.chain,
+            vec!["failed to mount path".to_owned(), Errno::EACCES.to_string()]
+        );
+
+        let json = serde_json::to_string(&failure).unwrap();
+        let received: ProcessFailure = serde_json::from_str(&json).unwrap();
+        assert_eq!(received, failure);
+
+        let err = ContainerProcessError::from(received);
+        assert_eq!(err.errno(), Some(Errno::EACCES));
+        assert_eq!(
+            err.to_string(),
+            "init process failed at rootfs (syscall mount, path /proc, errno EACCES: Permission denied)"
+        );
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), "failed to mount path");
+        assert_eq!(
+            source.source().unwrap().to_string(),
+            Errno::EACCES.to_string()
+        );
+        assert!(source.source().unwrap().source().is_none());
+    }
+}
@@ -1,19 +1,34 @@
+use std::path::PathBuf;
+
 use crate::namespaces::NamespaceError;
 use crate::process::channel;
+use crate::process::failure::FailureContext;
 #[cfg(feature = "libseccomp")]
 use crate::seccomp;
 use crate::syscall::SyscallError;
 use crate::workload::{ExecutorSetEnvsError, ExecutorValidationError};
-use crate::{apparmor, hooks, notify_socket, rootfs, tty, workload};
+use crate::{apparmor, hooks, notify_socket, rootfs, selinux, tty, workload};
 
 #[derive(Debug, thiserror::Error)]
 pub enum InitProcessError {
-    #[error("failed to set sysctl")]
-    Sysctl(#[source] std::io::Error),
-    #[error("failed to mount path as readonly")]
-    MountPathReadonly(#[source] SyscallError),
-    #[error("failed to mount path as masked")]
-    MountPathMasked(#[source] SyscallError),
+    #[error("failed to set sysctl {path:?}")]
+    Sysctl {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to mount path {path:?} as readonly")]
+    MountPathReadonly {
+        path: PathBuf,
+        #[source]
+        source: SyscallError,
+    },
+    #[error("failed to mount path {path:?} as masked")]
+    MountPathMasked {
+        path: PathBuf,
+        #[source]
+        source: SyscallError,
+    },
     #[error(transparent)]
     Namespaces(#[from] NamespaceError),
     #[error("failed to set hostname")]
@@ -36,6 +51,8 @@ pub enum InitProcessError {
     SyscallOther(#[source] SyscallError),
     #[error("failed apparmor")]
     AppArmor(#[source] apparmor::AppArmorError),
+    #[error("failed to set selinux process label")]
+    Selinux(#[source] selinux::SelinuxError),
     #[error("invalid umask")]
     InvalidUmask(u32),
     #[error(transparent)]
@@ -63,8 +80,72 @@ pub enum InitProcessError {
     SchedSetattr(String),
     #[error("failed to verify if current working directory is safe")]
     InvalidCwd(#[source] nix::Error),
+    #[error("failed to set up network")]
+    Network(#[source] crate::network::NetworkError),
     #[error("missing linux section in spec")]
     NoLinux,
     #[error("missing process section in spec")]
     NoProcess,
 }
+
+impl FailureContext for InitProcessError {
+    fn stage(&self) -> &'static str {
+        match self {
+            InitProcessError::Sysctl { .. } => "sysctl",
+            InitProcessError::MountPathReadonly { .. } => "readonly_paths",
+            InitProcessError::MountPathMasked { .. } => "masked_paths",
+            InitProcessError::Namespaces(_) => "namespaces",
+            InitProcessError::SetHostname(_) => "hostname",
+            InitProcessError::SetDomainname(_) => "domainname",
+            InitProcessError::ReopenDevNull(_) => "reopen_dev_null",
+            InitProcessError::NixOther(_) => "syscall",
+            InitProcessError::MissingSpec(_) => "spec",
+            InitProcessError::Tty(_) => "tty",
+            InitProcessError::Hooks(_) => "hooks",
+            InitProcessError::RootFS(_) => "rootfs",
+            InitProcessError::SyscallOther(_) => "syscall",
+            InitProcessError::AppArmor(_) => "apparmor",
+            InitProcessError::Selinux(_) => "selinux",
+            InitProcessError::InvalidUmask(_) => "umask",
+            #[cfg(feature = "libseccomp")]
+            InitProcessError::Seccomp(_) => "seccomp",
+            InitProcessError::InvalidExecutable(_) => "executable",
+            InitProcessError::Io(_) => "io",
+            InitProcessError::Channel(_) => "channel",
+            InitProcessError::SetGroupDisabled => "additional_gids",
+            InitProcessError::NotifyListener(_) => "notify_socket",
+            InitProcessError::Workload(_) => "exec",
+            InitProcessError::WorkloadValidation(_) => "exec_validation",
+            InitProcessError::WorkloadSetEnvs(_) => "env",
+            InitProcessError::IoPriorityClass(_) => "io_priority",
+            InitProcessError::SchedSetattr(_) => "scheduler",
+            InitProcessError::InvalidCwd(_) => "cwd",
+            InitProcessError::Network(_) => "network",
+            InitProcessError::NoLinux | InitProcessError::NoProcess => "spec",
+        }
+    }
+
+    fn syscall(&self) -> Option<&'static str> {
+        match self {
+            InitProcessError::Sysctl { .. } => Some("write"),
+            InitProcessError::MountPathReadonly { .. }
+            | InitProcessError::MountPathMasked { .. } => Some("mount"),
+            InitProcessError::SetHostname(_) => Some("sethostname"),
+            InitProcessError::SetDomainname(_) => Some("setdomainname"),
+            InitProcessError::ReopenDevNull(_) => Some("open"),
+            InitProcessError::SchedSetattr(_) => Some("sched_setattr"),
+            InitProcessError::InvalidCwd(_) => Some("getcwd"),
+            _ => None,
+        }
+    }
+
+    fn path(&self) -> Option<PathBuf> {
+        match self {
+            InitProcessError::Sysctl { path, .. }
+            | InitProcessError::MountPathReadonly { path, .. }
+            | InitProcessError::MountPathMasked { path, .. } => Some(path.to_owned()),
+            InitProcessError::ReopenDevNull(_) => Some(PathBuf::from("/dev/null")),
+            _ => None,
+        }
+    }
+}
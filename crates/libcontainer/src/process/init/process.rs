@@ -17,6 +17,7 @@ use super::context::InitContext;
 use super::error::InitProcessError;
 use super::Result;
 use crate::error::MissingSpecError;
+use crate::hooks::HookPhase;
 use crate::namespaces::Namespaces;
 use crate::process::args::{ContainerArgs, ContainerType};
 use crate::process::channel;
@@ -24,8 +25,9 @@ use crate::rootfs::RootFS;
 #[cfg(feature = "libseccomp")]
 use crate::seccomp;
 use crate::syscall::{Syscall, SyscallError};
+use crate::timing::{Phase, PhaseProcess, PhaseTimer, StartupTimings};
 use crate::user_ns::UserNamespaceConfig;
-use crate::{apparmor, capabilities, hooks, tty, utils};
+use crate::{apparmor, capabilities, hooks, network, selinux, tty, utils};
 
 // Some variables are unused in the case where libseccomp feature is not enabled.
 #[allow(unused_variables)]
@@ -34,7 +36,15 @@ pub fn container_init_process(
     main_sender: &mut channel::MainSender,
     init_receiver: &mut channel::InitReceiver,
 ) -> Result<()> {
+    let trace_context = init_receiver.wait_for_trace_context()?;
+    let span = tracing::info_span!(
+        parent: None,
+        "init_process",
+        traceparent = trace_context.as_deref()
+    )
+    .entered();
     let mut ctx = InitContext::try_from(args)?;
+    let mut timings = StartupTimings::new(PhaseProcess::Init);
 
     setsid().map_err(|err| {
         tracing::error!(?err, "failed to setsid to create a session");
@@ -66,7 +76,23 @@ pub fn container_init_process(
         }
     }
 
-    apply_rest_namespaces(&ctx.ns, ctx.spec, ctx.syscall.as_ref())?;
+    timings.measure(Phase::Namespaces, || {
+        apply_rest_namespaces(&ctx.ns, ctx.spec, ctx.syscall.as_ref())
+    })?;
+
+    // The tap device of the network helper is created here, where we are
+    // privileged in the network namespace and the devices of the host are
+    // still reachable.
+    if let Some(config) = &args.network {
+        let tap = network::create_tap(config).map_err(|err| {
+            tracing::error!(?err, "failed to create network tap device");
+            InitProcessError::Network(err)
+        })?;
+        main_sender.network_tap(tap.as_raw_fd()).map_err(|err| {
+            tracing::error!(?err, "failed to send network tap device");
+            err
+        })?;
+    }
 
     if let Some(true) = ctx.process.no_new_privileges() {
         let _ = prctl::set_no_new_privileges(true);
@@ -76,23 +102,28 @@ pub fn container_init_process(
         // create_container hook needs to be called after the namespace setup, but
         // before pivot_root is called. This runs in the container namespaces.
         if let Some(hooks) = ctx.hooks {
-            hooks::run_hooks(hooks.create_container().as_ref(), ctx.container, None).map_err(
-                |err| {
+            timings
+                .measure(Phase::CreateContainerHooks, || {
+                    hooks::run_hooks(
+                        hooks.create_container().as_ref(),
+                        HookPhase::CreateContainer,
+                        ctx.container,
+                        None,
+                    )
+                })
+                .map_err(|err| {
                     tracing::error!(?err, "failed to run create container hooks");
                     InitProcessError::Hooks(err)
-                },
-            )?;
+                })?;
         }
         let in_user_ns = utils::is_in_new_userns().map_err(InitProcessError::Io)?;
         let bind_service = ctx.ns.get(LinuxNamespaceType::User)?.is_some() || in_user_ns;
         let rootfs = RootFS::new();
-        rootfs
-            .prepare_rootfs(
-                ctx.spec,
-                ctx.rootfs,
-                bind_service,
-                ctx.ns.get(LinuxNamespaceType::Cgroup)?.is_some(),
-            )
+        let cgroup_ns = ctx.ns.get(LinuxNamespaceType::Cgroup)?.is_some();
+        timings
+            .measure(Phase::Rootfs, || {
+                rootfs.prepare_rootfs(ctx.spec, ctx.rootfs, bind_service, cgroup_ns)
+            })
             .map_err(|err| {
                 tracing::error!(?err, "failed to prepare rootfs");
                 InitProcessError::RootFS(err)
@@ -102,7 +133,9 @@ pub fn container_init_process(
         // we use pivot_root, but if we are on the host mount namespace, we will
         // use simple chroot. Scary things will happen if you try to pivot_root
         // in the host mount namespace...
-        do_pivot_root(ctx.syscall.as_ref(), &ctx.ns, args.no_pivot, ctx.rootfs)?;
+        timings.measure(Phase::PivotRoot, || {
+            do_pivot_root(ctx.syscall.as_ref(), &ctx.ns, args.no_pivot, ctx.rootfs)
+        })?;
 
         // As we have changed the root mount, from here on
         // logs are no longer visible in journalctl
@@ -132,6 +165,17 @@ pub fn container_init_process(
         })?;
     }
 
+    // Only processes executed in a running container, e.g. by exec
+    // --process-label, are labeled so far.
+    if matches!(args.container_type, ContainerType::TenantContainer { .. }) {
+        if let Some(label) = ctx.process.selinux_label() {
+            selinux::set_exec_label(label).map_err(|err| {
+                tracing::error!(?err, "failed to set selinux process label");
+                InitProcessError::Selinux(err)
+            })?;
+        }
+    }
+
     if ctx.rootfs_ro {
         ctx.syscall
             .mount(
@@ -282,13 +326,15 @@ pub fn container_init_process(
     #[cfg(feature = "libseccomp")]
     if let Some(seccomp) = ctx.linux.seccomp() {
         if ctx.process.no_new_privileges().is_none() {
-            let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
-                tracing::error!(?err, "failed to initialize seccomp");
-                err
-            })?;
-            sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
-                tracing::error!(?err, "failed to sync seccomp");
-                err
+            timings.measure(Phase::Seccomp, || {
+                let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
+                    tracing::error!(?err, "failed to initialize seccomp");
+                    err
+                })?;
+                sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
+                    tracing::error!(?err, "failed to sync seccomp");
+                    err
+                })
             })?;
         }
     }
@@ -329,13 +375,15 @@ pub fn container_init_process(
     #[cfg(feature = "libseccomp")]
     if let Some(seccomp) = ctx.linux.seccomp() {
         if ctx.process.no_new_privileges().is_some() {
-            let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
-                tracing::error!(?err, "failed to initialize seccomp");
-                err
-            })?;
-            sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
-                tracing::error!(?err, "failed to sync seccomp");
-                err
+            timings.measure(Phase::Seccomp, || {
+                let notify_fd = seccomp::initialize_seccomp(seccomp).map_err(|err| {
+                    tracing::error!(?err, "failed to initialize seccomp");
+                    err
+                })?;
+                sync_seccomp(notify_fd, main_sender, init_receiver).map_err(|err| {
+                    tracing::error!(?err, "failed to sync seccomp");
+                    err
+                })
             })?;
         }
     }
@@ -359,6 +407,7 @@ pub fn container_init_process(
     // payload.  Note, because we are already inside the pid namespace, the pid
     // outside the pid namespace should be recorded by the intermediate process
     // already.
+    main_sender.phase_timings(timings.into_phases())?;
     main_sender.init_ready().map_err(|err| {
         tracing::error!(
             ?err,
@@ -372,7 +421,8 @@ pub fn container_init_process(
     })?;
 
     // listing on the notify socket for container start command
-    ctx.notify_listener
+    let container_start = ctx
+        .notify_listener
         .wait_for_container_start()
         .map_err(|err| {
             tracing::error!(?err, "failed to wait for container start");
@@ -383,16 +433,29 @@ pub fn container_init_process(
         err
     })?;
 
+    // The phases after the start signal are reported to the caller of start
+    // over the notify socket instead, since the main process is gone by now,
+    // if it asked for them.
+    let mut timings = StartupTimings::new(PhaseProcess::Init);
+    let exec_timer = PhaseTimer::start();
+
     // create_container hook needs to be called after the namespace setup, but
     // before pivot_root is called. This runs in the container namespaces.
     if matches!(args.container_type, ContainerType::InitContainer) {
         if let Some(hooks) = ctx.hooks {
-            hooks::run_hooks(hooks.start_container().as_ref(), ctx.container, None).map_err(
-                |err| {
+            timings
+                .measure(Phase::StartContainerHooks, || {
+                    hooks::run_hooks(
+                        hooks.start_container().as_ref(),
+                        HookPhase::StartContainer,
+                        ctx.container,
+                        None,
+                    )
+                })
+                .map_err(|err| {
                     tracing::error!(?err, "failed to run start container hooks");
                     err
-                },
-            )?;
+                })?;
         }
     }
 
@@ -401,6 +464,18 @@ pub fn container_init_process(
         Err(MissingSpecError::Args)?;
     }
 
+    timings.record(Phase::Exec, exec_timer);
+    if container_start.wants_report() {
+        // The report is best effort, the caller of start may have gone away.
+        if let Err(err) = container_start.report(&timings.into_phases()) {
+            tracing::warn!(?err, "failed to report startup phases");
+        }
+    }
+
+    // The span has to be closed before exec, otherwise it is never exported
+    // because exec replaces the process image.
+    drop(span.exit());
+
     args.executor.exec(ctx.spec).map_err(|err| {
         tracing::error!(?err, "failed to execute payload");
         err
@@ -421,9 +496,9 @@ fn sysctl(kernel_params: &HashMap<String, String>) -> Result<()> {
             value,
             kernel_param
         );
-        fs::write(path, value.as_bytes()).map_err(|err| {
+        fs::write(&path, value.as_bytes()).map_err(|err| {
             tracing::error!("failed to set sysctl {kernel_param}={value}: {err}");
-            InitProcessError::Sysctl(err)
+            InitProcessError::Sysctl { path, source: err }
         })?;
     }
 
@@ -450,7 +525,10 @@ fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
         }
 
         tracing::error!(?path, ?err, "failed to mount path as readonly");
-        return Err(InitProcessError::MountPathReadonly(err));
+        return Err(InitProcessError::MountPathReadonly {
+            path: path.to_owned(),
+            source: err,
+        });
     }
 
     syscall
@@ -468,7 +546,10 @@ fn readonly_path(path: &Path, syscall: &dyn Syscall) -> Result<()> {
         )
         .map_err(|err| {
             tracing::error!(?path, ?err, "failed to remount path as readonly");
-            InitProcessError::MountPathReadonly(err)
+            InitProcessError::MountPathReadonly {
+                path: path.to_owned(),
+                source: err,
+            }
         })?;
 
     tracing::debug!("readonly path {:?} mounted", path);
@@ -504,7 +585,10 @@ fn masked_path(path: &Path, mount_label: &Option<String>, syscall: &dyn Syscall)
                     )
                     .map_err(|err| {
                         tracing::error!(?path, ?err, "failed to mount path as masked using tempfs");
-                        InitProcessError::MountPathMasked(err)
+                        InitProcessError::MountPathMasked {
+                            path: path.to_owned(),
+                            source: err,
+                        }
                     })?;
             }
             _ => {
@@ -513,7 +597,10 @@ fn masked_path(path: &Path, mount_label: &Option<String>, syscall: &dyn Syscall)
                     ?err,
                     "failed to mount path as masked using /dev/null"
                 );
-                return Err(InitProcessError::MountPathMasked(err));
+                return Err(InitProcessError::MountPathMasked {
+                    path: path.to_owned(),
+                    source: err,
+                });
             }
         }
     }
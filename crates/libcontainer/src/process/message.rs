@@ -2,6 +2,9 @@ use core::fmt;
 
 use serde::{Deserialize, Serialize};
 
+use crate::process::failure::ProcessFailure;
+use crate::timing::PhaseTiming;
+
 /// Used as a wrapper for messages to be sent between child and parent processes
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Message {
@@ -11,8 +14,10 @@ pub enum Message {
     MappingWritten,
     SeccompNotify,
     SeccompNotifyDone,
-    ExecFailed(String),
-    OtherError(String),
+    NetworkTap,
+    TraceContext(Option<String>),
+    PhaseTimings(Vec<PhaseTiming>),
+    ProcessFailed(Box<ProcessFailure>),
 }
 
 impl fmt::Display for Message {
@@ -24,8 +29,10 @@ impl fmt::Display for Message {
             Message::MappingWritten => write!(f, "MappingWritten"),
             Message::SeccompNotify => write!(f, "SeccompNotify"),
             Message::SeccompNotifyDone => write!(f, "SeccompNotifyDone"),
-            Message::ExecFailed(s) => write!(f, "ExecFailed({})", s),
-            Message::OtherError(s) => write!(f, "OtherError({})", s),
+            Message::NetworkTap => write!(f, "NetworkTap"),
+            Message::TraceContext(ctx) => write!(f, "TraceContext({:?})", ctx),
+            Message::PhaseTimings(timings) => write!(f, "PhaseTimings({})", timings.len()),
+            Message::ProcessFailed(failure) => write!(f, "ProcessFailed({})", failure),
         }
     }
 }
@@ -5,6 +5,7 @@ pub mod args;
 pub mod channel;
 pub mod container_intermediate_process;
 pub mod container_main_process;
+pub mod failure;
 mod fork;
 pub mod init;
 pub mod intel_rdt;
@@ -9,6 +9,8 @@ pub mod device;
 pub use device::Device;
 
 pub(super) mod mount;
+pub mod shift;
+pub use shift::{RootfsShift, ShiftedRootfs};
 pub(super) mod symlink;
 
 pub mod utils;
@@ -27,6 +29,8 @@ pub enum RootfsError {
     Mount(#[from] mount::MountError),
     #[error(transparent)]
     Device(#[from] device::DeviceError),
+    #[error(transparent)]
+    Shift(#[from] shift::ShiftError),
 }
 
 type Result<T> = std::result::Result<T, RootfsError>;
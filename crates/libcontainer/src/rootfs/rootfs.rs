@@ -1,17 +1,19 @@
 use std::collections::HashSet;
 use std::path::Path;
 
+use nix::errno::Errno;
 use nix::mount::MsFlags;
 use oci_spec::runtime::{Linux, Spec};
 
 use super::device::Device;
 use super::mount::{Mount, MountOptions};
+use super::shift::{self, RootfsShift, ShiftError, ShiftedRootfs};
 use super::symlink::Symlink;
 use super::utils::default_devices;
 use super::{Result, RootfsError};
 use crate::error::MissingSpecError;
 use crate::syscall::syscall::create_syscall;
-use crate::syscall::Syscall;
+use crate::syscall::{Syscall, SyscallError};
 
 /// Holds information about rootfs
 pub struct RootFS {
@@ -125,6 +127,89 @@ impl RootFS {
         Ok(())
     }
 
+    /// Prepares a rootfs whose ownership is shifted to the ids the user
+    /// namespace of the container maps, in `dir` on the host. Returns `None`
+    /// if there is nothing to shift, i.e. the mode is `None` or the spec has
+    /// no uid and gid mappings.
+    pub fn shift_ownership(
+        &self,
+        mode: RootfsShift,
+        linux: &Linux,
+        rootfs: &Path,
+        dir: &Path,
+    ) -> Result<Option<ShiftedRootfs>> {
+        let uid_mappings = linux.uid_mappings().as_deref().unwrap_or_default();
+        let gid_mappings = linux.gid_mappings().as_deref().unwrap_or_default();
+        if mode == RootfsShift::None || (uid_mappings.is_empty() && gid_mappings.is_empty()) {
+            return Ok(None);
+        }
+
+        tracing::debug!(?mode, ?rootfs, ?dir, "shift ownership of rootfs");
+        let shifted = ShiftedRootfs::new(mode, dir);
+        let create_dir = |path: &Path| {
+            std::fs::create_dir_all(path).map_err(|err| ShiftError::Prepare {
+                path: path.to_owned(),
+                source: err,
+            })
+        };
+        create_dir(dir)?;
+
+        match mode {
+            RootfsShift::None => unreachable!(),
+            RootfsShift::Overlay => {
+                let (upper, work) = (dir.join("upper"), dir.join("work"));
+                for path in [&upper, &work, &shifted.rootfs] {
+                    create_dir(path)?;
+                }
+                self.mount_overlay(rootfs, &upper, &work, &shifted.rootfs)?;
+            }
+            RootfsShift::Copy => shift::copy_rootfs(rootfs, &shifted.rootfs)?,
+        }
+
+        if let Err(err) = shift::shift_tree(&shifted.rootfs, uid_mappings, gid_mappings) {
+            tracing::error!(?err, rootfs = ?shifted.rootfs, "failed to shift ownership of rootfs");
+            if let Err(err) = shifted.remove() {
+                tracing::warn!(?err, "failed to remove shifted rootfs");
+            }
+            return Err(err.into());
+        }
+
+        Ok(Some(shifted))
+    }
+
+    /// Mounts an overlay of the rootfs. With metacopy, shifting the ownership
+    /// only copies up the metadata of the files, but older kernels don't
+    /// support it.
+    fn mount_overlay(&self, lower: &Path, upper: &Path, work: &Path, target: &Path) -> Result<()> {
+        let options = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            lower.display(),
+            upper.display(),
+            work.display()
+        );
+        let mount = |data: &str| {
+            self.syscall.mount(
+                Some(Path::new("overlay")),
+                target,
+                Some("overlay"),
+                MsFlags::empty(),
+                Some(data),
+            )
+        };
+
+        match mount(&format!("{options},metacopy=on")) {
+            Err(SyscallError::Nix(Errno::EINVAL)) => {
+                tracing::debug!("overlay doesn't support metacopy, mounting without it");
+                mount(&options)
+            }
+            result => result,
+        }
+        .map_err(|err| {
+            tracing::error!(?err, ?target, "failed to mount overlay of rootfs");
+            err.into()
+        })
+    }
+
     /// Change propagation type of rootfs as specified in spec.
     pub fn adjust_root_mount_propagation(&self, linux: &Linux) -> Result<()> {
         let rootfs_propagation = linux.rootfs_propagation().as_deref();
@@ -0,0 +1,301 @@
+//! Shifting of the ownership of a rootfs to the ids a user namespace maps,
+//! for kernels or filesystems without idmapped mounts. Otherwise files owned
+//! by host root, as rootfs usually are, would show up as owned by nobody in
+//! the container, which couldn't write them.
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use nix::errno::Errno;
+use nix::fcntl::AtFlags;
+use nix::mount::{umount2, MntFlags};
+use nix::unistd::{fchownat, Gid, Uid};
+use oci_spec::runtime::LinuxIdMapping;
+use serde::{Deserialize, Serialize};
+
+/// Directory in the container directory holding the shifted rootfs
+pub const SHIFT_DIR: &str = "rootfs-shift";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShiftError {
+    #[error("failed to prepare {path:?} for the shifted rootfs")]
+    Prepare {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to copy rootfs {rootfs:?}: {stderr}")]
+    Copy { rootfs: PathBuf, stderr: String },
+    #[error("failed to shift the ownership of {path:?}")]
+    Chown { path: PathBuf, source: nix::Error },
+    #[error("failed to unmount the shifted rootfs {path:?}")]
+    Unmount { path: PathBuf, source: nix::Error },
+    #[error("failed to remove the shifted rootfs {path:?}")]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+type Result<T> = std::result::Result<T, ShiftError>;
+
+/// How the ownership of the rootfs of a user-namespaced container is shifted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RootfsShift {
+    /// The rootfs is used as it is
+    #[default]
+    None,
+    /// An overlay on top of the rootfs whose upper layer holds the shifted
+    /// ownership. Only metadata is copied up where the kernel supports it.
+    Overlay,
+    /// A copy of the rootfs, which shares the data with it on filesystems
+    /// supporting reflinks, whose ownership is shifted
+    Copy,
+}
+
+/// A shifted rootfs, as recorded in the state of a container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftedRootfs {
+    pub mode: RootfsShift,
+    /// Directory holding everything the shift created
+    pub dir: PathBuf,
+    /// The shifted rootfs the container uses
+    pub rootfs: PathBuf,
+}
+
+impl ShiftedRootfs {
+    pub(crate) fn new(mode: RootfsShift, dir: &Path) -> Self {
+        let rootfs = match mode {
+            RootfsShift::Overlay => dir.join("merged"),
+            _ => dir.join("rootfs"),
+        };
+        Self {
+            mode,
+            dir: dir.to_owned(),
+            rootfs,
+        }
+    }
+
+    /// Unmounts and removes the shifted rootfs. It may be gone already.
+    pub fn remove(&self) -> Result<()> {
+        if self.mode == RootfsShift::Overlay {
+            match umount2(&self.rootfs, MntFlags::MNT_DETACH) {
+                Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => {}
+                Err(err) => {
+                    return Err(ShiftError::Unmount {
+                        path: self.rootfs.clone(),
+                        source: err,
+                    })
+                }
+            }
+        }
+
+        match fs::remove_dir_all(&self.dir) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(ShiftError::Remove {
+                path: self.dir.clone(),
+                source: err,
+            }),
+        }
+    }
+}
+
+/// Copies the rootfs, with reflinks where the filesystem supports them
+pub(super) fn copy_rootfs(rootfs: &Path, target: &Path) -> Result<()> {
+    let output = Command::new("cp")
+        .arg("--archive")
+        .arg("--reflink=auto")
+        .arg("--no-target-directory")
+        .arg(rootfs)
+        .arg(target)
+        .output()
+        .map_err(|err| ShiftError::Prepare {
+            path: target.to_owned(),
+            source: err,
+        })?;
+
+    if !output.status.success() {
+        return Err(ShiftError::Copy {
+            rootfs: rootfs.to_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Changes the owner of every file below `root` to the host ids the owner
+/// maps to, i.e. the owners are taken as the ids in the container. Mounts
+/// below `root` are left alone, as are files whose owner isn't mapped.
+pub(super) fn shift_tree(
+    root: &Path,
+    uid_mappings: &[LinuxIdMapping],
+    gid_mappings: &[LinuxIdMapping],
+) -> Result<()> {
+    let metadata = fs::symlink_metadata(root).map_err(|err| ShiftError::Prepare {
+        path: root.to_owned(),
+        source: err,
+    })?;
+    let mut pending = vec![(root.to_owned(), metadata)];
+
+    while let Some((path, metadata)) = pending.pop() {
+        shift_file(&path, &metadata, uid_mappings, gid_mappings)?;
+        if !metadata.is_dir() {
+            continue;
+        }
+
+        let entries = fs::read_dir(&path).map_err(|err| ShiftError::Prepare {
+            path: path.clone(),
+            source: err,
+        })?;
+        for entry in entries {
+            let entry = entry.map_err(|err| ShiftError::Prepare {
+                path: path.clone(),
+                source: err,
+            })?;
+            let child = entry.path();
+            let child_metadata =
+                fs::symlink_metadata(&child).map_err(|err| ShiftError::Prepare {
+                    path: child.clone(),
+                    source: err,
+                })?;
+            if child_metadata.dev() == metadata.dev() {
+                pending.push((child, child_metadata));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn shift_file(
+    path: &Path,
+    metadata: &fs::Metadata,
+    uid_mappings: &[LinuxIdMapping],
+    gid_mappings: &[LinuxIdMapping],
+) -> Result<()> {
+    let uid = map_id(uid_mappings, metadata.uid());
+    let gid = map_id(gid_mappings, metadata.gid());
+    if uid.is_none() && gid.is_none() {
+        tracing::trace!(?path, "owner of file isn't mapped, not shifting it");
+        return Ok(());
+    }
+
+    fchownat(
+        None,
+        path,
+        uid.map(Uid::from_raw),
+        gid.map(Gid::from_raw),
+        AtFlags::AT_SYMLINK_NOFOLLOW,
+    )
+    .map_err(|err| ShiftError::Chown {
+        path: path.to_owned(),
+        source: err,
+    })?;
+
+    // changing the owner clears the setuid and setgid bits
+    let mode = metadata.mode();
+    if !metadata.is_symlink() && mode & (libc::S_ISUID | libc::S_ISGID) != 0 {
+        fs::set_permissions(path, Permissions::from_mode(mode)).map_err(|err| {
+            ShiftError::Prepare {
+                path: path.to_owned(),
+                source: err,
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Maps an id of the container to the id of the host. Ids mapped beyond the
+/// range of ids of the host are unmapped.
+fn map_id(mappings: &[LinuxIdMapping], id: u32) -> Option<u32> {
+    mappings.iter().find_map(|mapping| {
+        let offset = id.checked_sub(mapping.container_id())?;
+        if offset < mapping.size() {
+            mapping.host_id().checked_add(offset)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use oci_spec::runtime::LinuxIdMappingBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_map_id() -> Result<()> {
+        let mappings = vec![
+            LinuxIdMappingBuilder::default()
+                .container_id(0_u32)
+                .host_id(100000_u32)
+                .size(1000_u32)
+                .build()?,
+            LinuxIdMappingBuilder::default()
+                .container_id(1000_u32)
+                .host_id(1000_u32)
+                .size(1_u32)
+                .build()?,
+        ];
+
+        assert_eq!(map_id(&mappings, 0), Some(100000));
+        assert_eq!(map_id(&mappings, 999), Some(100999));
+        assert_eq!(map_id(&mappings, 1000), Some(1000));
+        assert_eq!(map_id(&mappings, 1001), None);
+
+        let overflowing = vec![LinuxIdMappingBuilder::default()
+            .container_id(0_u32)
+            .host_id(u32::MAX)
+            .size(2_u32)
+            .build()?];
+        assert_eq!(map_id(&overflowing, 0), Some(u32::MAX));
+        assert_eq!(map_id(&overflowing, 1), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shift_tree() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let binary = tmp.path().join("bin/su");
+        fs::create_dir_all(binary.parent().unwrap())?;
+        fs::write(&binary, "")?;
+        fs::set_permissions(&binary, Permissions::from_mode(0o4755))?;
+        std::os::unix::fs::symlink("missing", tmp.path().join("link"))?;
+
+        // chown to the owner itself works without privileges
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = nix::unistd::getgid().as_raw();
+        let mapping = |id: u32| {
+            LinuxIdMappingBuilder::default()
+                .container_id(id)
+                .host_id(id)
+                .size(1_u32)
+                .build()
+        };
+        shift_tree(tmp.path(), &[mapping(uid)?], &[mapping(gid)?])?;
+
+        let metadata = fs::metadata(&binary)?;
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+        assert_eq!(metadata.mode() & 0o7777, 0o4755);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_shifted_rootfs() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let shifted = ShiftedRootfs::new(RootfsShift::Copy, &tmp.path().join(SHIFT_DIR));
+        fs::create_dir_all(shifted.rootfs.join("etc"))?;
+
+        shifted.remove()?;
+        assert!(!shifted.dir.exists());
+        // removing it again is fine, e.g. when delete is retried
+        shifted.remove()?;
+        Ok(())
+    }
+}
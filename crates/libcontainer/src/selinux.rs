@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::Path;
+
+use crate::utils;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SelinuxError {
+    #[error("failed to set SELinux exec label")]
+    SetExecLabel {
+        path: std::path::PathBuf,
+        label: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    EnsureProcfs(#[from] utils::EnsureProcfsError),
+}
+
+type Result<T> = std::result::Result<T, SelinuxError>;
+
+const SELINUXFS_ENFORCE_PATH: &str = "/sys/fs/selinux/enforce";
+const EXEC_LABEL_PATH: &str = "/proc/thread-self/attr/exec";
+
+/// Checks if SELinux has been enabled on the system.
+pub fn is_enabled() -> bool {
+    Path::new(SELINUXFS_ENFORCE_PATH).exists()
+}
+
+/// Checks if a label has the `user:role:type[:level]` form of a SELinux
+/// context.
+pub fn is_valid_label(label: &str) -> bool {
+    let parts: Vec<&str> = label.splitn(4, ':').collect();
+    parts.len() >= 3 && parts.iter().all(|part| !part.is_empty())
+}
+
+/// Returns the `level` of a `user:role:type:level` label
+pub fn label_level(label: &str) -> Option<&str> {
+    label.splitn(4, ':').nth(3)
+}
+
+/// Sets the label the process will run with after its next execve. Like
+/// runc, this is a no-op on systems without SELinux.
+pub fn set_exec_label(label: &str) -> Result<()> {
+    if label.is_empty() || !is_enabled() {
+        return Ok(());
+    }
+
+    let path = Path::new(EXEC_LABEL_PATH);
+    utils::ensure_procfs(path).map_err(SelinuxError::EnsureProcfs)?;
+    fs::write(path, label).map_err(|err| SelinuxError::SetExecLabel {
+        path: path.to_owned(),
+        label: label.to_owned(),
+        source: err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label() {
+        assert!(is_valid_label("system_u:system_r:container_t:s0:c1,c2"));
+        assert!(is_valid_label("system_u:system_r:container_t"));
+        assert!(!is_valid_label("container_t"));
+        assert!(!is_valid_label("system_u::container_t"));
+        assert_eq!(
+            label_level("system_u:system_r:container_t:s0:c1,c2"),
+            Some("s0:c1,c2")
+        );
+        assert_eq!(label_level("system_u:system_r:container_t"), None);
+    }
+}
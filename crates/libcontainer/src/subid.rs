@@ -0,0 +1,420 @@
+//! Subordinate ids of users, as assigned in /etc/subuid and /etc/subgid, and
+//! the policies for mapping the ids of a container to those of the host.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nix::unistd::Uid;
+use oci_spec::runtime::{Linux, LinuxIdMapping, LinuxIdMappingBuilder};
+
+use crate::id_allocator::{IdAllocator, IdAllocatorError};
+use crate::utils;
+
+pub const SUBUID_PATH: &str = "/etc/subuid";
+pub const SUBGID_PATH: &str = "/etc/subgid";
+
+/// Count of ids mapped into a container by default, which covers the ids
+/// which distributions assign
+pub const DEFAULT_ID_COUNT: u32 = 65536;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubIdError {
+    #[error("failed to read {path:?}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unknown user {0}")]
+    UnknownUser(u32),
+    #[error("{user} has {available} subordinate ids in {path:?}, but {requested} are needed")]
+    NotEnoughIds {
+        user: String,
+        path: PathBuf,
+        available: u64,
+        requested: u32,
+    },
+    #[error("{kind} mappings overlap: {first:?} and {second:?}")]
+    Overlap {
+        kind: &'static str,
+        first: LinuxIdMapping,
+        second: LinuxIdMapping,
+    },
+    #[error("{0} mapping exceeds the range of ids")]
+    OutOfRange(&'static str),
+    #[error("failed to build id mapping")]
+    Build(#[from] oci_spec::OciSpecError),
+    #[error(transparent)]
+    Allocator(#[from] IdAllocatorError),
+}
+
+type Result<T> = std::result::Result<T, SubIdError>;
+
+/// A range of subordinate ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubIdRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+/// Parses the ranges of a user in the content of /etc/subuid or /etc/subgid.
+/// Entries name their owner either by name or by uid.
+pub fn parse_ranges(content: &str, name: &str, uid: u32) -> Vec<SubIdRange> {
+    let uid = uid.to_string();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let owner = fields.next()?;
+            if owner != name && owner != uid {
+                return None;
+            }
+            let start = fields.next()?.parse().ok()?;
+            let count = fields.next()?.parse().ok()?;
+            if fields.next().is_some() || count == 0 {
+                tracing::warn!(line, "ignoring invalid subordinate id entry");
+                return None;
+            }
+            Some(SubIdRange { start, count })
+        })
+        .collect()
+}
+
+/// Reads the ranges of a user from /etc/subuid or /etc/subgid. A missing file
+/// assigns no ranges.
+pub fn read_ranges(path: &Path, name: &str, uid: u32) -> Result<Vec<SubIdRange>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(parse_ranges(&content, name, uid)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(SubIdError::Read {
+            path: path.to_owned(),
+            source: err,
+        }),
+    }
+}
+
+/// Maps `count` ids of a container, starting at `container_id`, to the
+/// ranges, in the order they are assigned. Ranges which aren't contiguous
+/// give a mapping each.
+pub fn map_ranges(
+    ranges: &[SubIdRange],
+    container_id: u32,
+    count: u32,
+) -> Option<Vec<LinuxIdMapping>> {
+    let mut mappings = Vec::new();
+    let mut next = container_id;
+    let mut remaining = count;
+    for range in ranges {
+        if remaining == 0 {
+            break;
+        }
+        let size = range.count.min(remaining);
+        mappings.push(
+            LinuxIdMappingBuilder::default()
+                .container_id(next)
+                .host_id(range.start)
+                .size(size)
+                .build()
+                .ok()?,
+        );
+        next = next.checked_add(size)?;
+        remaining -= size;
+    }
+
+    (remaining == 0).then_some(mappings)
+}
+
+/// Checks that neither the ids of the container nor those of the host of
+/// the mappings overlap, which the kernel refuses
+pub fn validate_mappings(kind: &'static str, mappings: &[LinuxIdMapping]) -> Result<()> {
+    let range = |start: u32, size: u32| {
+        let end = start as u64 + size as u64;
+        if end > u32::MAX as u64 + 1 {
+            return Err(SubIdError::OutOfRange(kind));
+        }
+        Ok(start as u64..end)
+    };
+    let overlaps =
+        |a: &std::ops::Range<u64>, b: &std::ops::Range<u64>| a.start < b.end && b.start < a.end;
+
+    for (i, first) in mappings.iter().enumerate() {
+        let first_container = range(first.container_id(), first.size())?;
+        let first_host = range(first.host_id(), first.size())?;
+        for second in &mappings[i + 1..] {
+            let second_container = range(second.container_id(), second.size())?;
+            let second_host = range(second.host_id(), second.size())?;
+            if overlaps(&first_container, &second_container) || overlaps(&first_host, &second_host)
+            {
+                return Err(SubIdError::Overlap {
+                    kind,
+                    first: *first,
+                    second: *second,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Maps the ids of a container to the subordinate ids of a user. The root of
+/// the container is mapped to the user itself, unless it is disabled, and
+/// the ids from 1 on to the ranges of the user.
+#[derive(Debug, Clone)]
+pub struct SubIdPolicy {
+    uid: Uid,
+    count: u32,
+    map_root_to_user: bool,
+    subuid_path: PathBuf,
+    subgid_path: PathBuf,
+}
+
+impl SubIdPolicy {
+    pub fn new(uid: Uid) -> Self {
+        Self {
+            uid,
+            count: DEFAULT_ID_COUNT,
+            map_root_to_user: true,
+            subuid_path: PathBuf::from(SUBUID_PATH),
+            subgid_path: PathBuf::from(SUBGID_PATH),
+        }
+    }
+
+    /// Sets the count of ids mapped into the container
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Sets if the root of the container is mapped to the user and its
+    /// primary group, or to subordinate ids as well
+    pub fn with_root_mapped_to_user(mut self, map_root_to_user: bool) -> Self {
+        self.map_root_to_user = map_root_to_user;
+        self
+    }
+
+    /// Sets the files the subordinate ids are read from
+    pub fn with_subid_files<P: Into<PathBuf>>(mut self, subuid: P, subgid: P) -> Self {
+        self.subuid_path = subuid.into();
+        self.subgid_path = subgid.into();
+        self
+    }
+
+    /// Returns the uid and gid mappings
+    pub fn mappings(&self) -> Result<(Vec<LinuxIdMapping>, Vec<LinuxIdMapping>)> {
+        let user =
+            utils::get_unix_user(self.uid).ok_or(SubIdError::UnknownUser(self.uid.as_raw()))?;
+        let uid_mappings = self.mappings_of(&user.name, self.uid.as_raw(), &self.subuid_path)?;
+        let gid_mappings = self.mappings_of(&user.name, user.gid.as_raw(), &self.subgid_path)?;
+        Ok((uid_mappings, gid_mappings))
+    }
+
+    fn mappings_of(&self, name: &str, id: u32, path: &Path) -> Result<Vec<LinuxIdMapping>> {
+        // The entries of /etc/subgid are owned by users as well
+        let ranges = read_ranges(path, name, self.uid.as_raw())?;
+
+        let mut mappings = Vec::new();
+        let mut count = self.count;
+        if self.map_root_to_user && count > 0 {
+            mappings.push(
+                LinuxIdMappingBuilder::default()
+                    .container_id(0_u32)
+                    .host_id(id)
+                    .size(1_u32)
+                    .build()?,
+            );
+            count -= 1;
+        }
+
+        let first = mappings.len() as u32;
+        let subordinate =
+            map_ranges(&ranges, first, count).ok_or_else(|| SubIdError::NotEnoughIds {
+                user: name.to_owned(),
+                path: path.to_owned(),
+                available: ranges.iter().map(|r| r.count as u64).sum(),
+                requested: count,
+            })?;
+        mappings.extend(subordinate);
+        Ok(mappings)
+    }
+}
+
+/// How the ids of a container are mapped to those of the host, when the
+/// container has a new user namespace
+#[derive(Debug, Clone, Default)]
+pub enum IdMappingPolicy {
+    /// The mappings of the spec
+    #[default]
+    Spec,
+    /// The given uid and gid mappings
+    Explicit {
+        uid_mappings: Vec<LinuxIdMapping>,
+        gid_mappings: Vec<LinuxIdMapping>,
+    },
+    /// The subordinate ids of a user
+    SubIds(SubIdPolicy),
+    /// A block of ids allocated to the container, i.e. "userns=auto"
+    Auto(IdAllocator),
+}
+
+impl IdMappingPolicy {
+    /// Sets the mappings of the policy in the spec, and validates them
+    pub fn apply(&self, container_id: &str, linux: &mut Linux) -> Result<()> {
+        match self {
+            Self::Spec => {}
+            Self::Explicit {
+                uid_mappings,
+                gid_mappings,
+            } => {
+                linux.set_uid_mappings(Some(uid_mappings.clone()));
+                linux.set_gid_mappings(Some(gid_mappings.clone()));
+            }
+            Self::SubIds(policy) => {
+                let (uid_mappings, gid_mappings) = policy.mappings()?;
+                linux.set_uid_mappings(Some(uid_mappings));
+                linux.set_gid_mappings(Some(gid_mappings));
+            }
+            Self::Auto(allocator) => {
+                let mapping = allocator.allocate(container_id)?.mapping()?;
+                linux.set_uid_mappings(Some(vec![mapping]));
+                linux.set_gid_mappings(Some(vec![mapping]));
+            }
+        }
+
+        if let Some(uid_mappings) = linux.uid_mappings() {
+            validate_mappings("uid", uid_mappings)?;
+        }
+        if let Some(gid_mappings) = linux.gid_mappings() {
+            validate_mappings("gid", gid_mappings)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    fn mapping(container_id: u32, host_id: u32, size: u32) -> LinuxIdMapping {
+        LinuxIdMappingBuilder::default()
+            .container_id(container_id)
+            .host_id(host_id)
+            .size(size)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        let content = "\
+# comment
+alice:100000:65536
+1000:300000:1000
+bob:165536:65536
+alice:invalid:10
+alice:500000:0
+
+alice:400000:500
+";
+        assert_eq!(
+            parse_ranges(content, "alice", 1000),
+            vec![
+                SubIdRange {
+                    start: 100000,
+                    count: 65536
+                },
+                SubIdRange {
+                    start: 300000,
+                    count: 1000
+                },
+                SubIdRange {
+                    start: 400000,
+                    count: 500
+                },
+            ]
+        );
+        assert!(parse_ranges(content, "carol", 1001).is_empty());
+    }
+
+    #[test]
+    fn test_map_ranges() {
+        let ranges = [
+            SubIdRange {
+                start: 100000,
+                count: 10,
+            },
+            SubIdRange {
+                start: 300000,
+                count: 20,
+            },
+        ];
+
+        assert_eq!(
+            map_ranges(&ranges, 1, 15),
+            Some(vec![mapping(1, 100000, 10), mapping(11, 300000, 5)])
+        );
+        assert_eq!(map_ranges(&ranges, 0, 31), None);
+    }
+
+    #[test]
+    fn test_validate_mappings() {
+        assert!(validate_mappings("uid", &[mapping(0, 1000, 1), mapping(1, 100000, 10)]).is_ok());
+        // the container ids overlap
+        assert!(matches!(
+            validate_mappings("uid", &[mapping(0, 1000, 5), mapping(4, 100000, 10)]),
+            Err(SubIdError::Overlap { .. })
+        ));
+        // the host ids overlap
+        assert!(matches!(
+            validate_mappings("gid", &[mapping(0, 100005, 1), mapping(1, 100000, 10)]),
+            Err(SubIdError::Overlap { .. })
+        ));
+        assert!(matches!(
+            validate_mappings("uid", &[mapping(u32::MAX, 0, 2)]),
+            Err(SubIdError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            validate_mappings("gid", &[mapping(0, u32::MAX, 2)]),
+            Err(SubIdError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_subid_policy() -> Result<()> {
+        let uid = nix::unistd::getuid();
+        let user = utils::get_unix_user(uid).unwrap();
+        let tmp = tempfile::tempdir()?;
+        let subuid = tmp.path().join("subuid");
+        let subgid = tmp.path().join("subgid");
+        fs::write(
+            &subuid,
+            format!("{}:100000:5\n{}:200000:10\n", user.name, uid),
+        )?;
+        fs::write(&subgid, format!("{}:300000:20\n", user.name))?;
+
+        let policy = SubIdPolicy::new(uid)
+            .with_count(12)
+            .with_subid_files(&subuid, &subgid);
+        let (uid_mappings, gid_mappings) = policy.mappings()?;
+        assert_eq!(
+            uid_mappings,
+            vec![
+                mapping(0, uid.as_raw(), 1),
+                mapping(1, 100000, 5),
+                mapping(6, 200000, 6)
+            ]
+        );
+        assert_eq!(
+            gid_mappings,
+            vec![mapping(0, user.gid.as_raw(), 1), mapping(1, 300000, 11)]
+        );
+
+        let policy = policy.with_count(100);
+        assert!(matches!(
+            policy.mappings(),
+            Err(SubIdError::NotEnoughIds { available: 15, .. })
+        ));
+        Ok(())
+    }
+}
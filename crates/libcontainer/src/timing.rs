@@ -0,0 +1,202 @@
+//! Timings of the phases of a container start, e.g. to break down where the
+//! time of a cold start goes. Each process records the phases it runs itself,
+//! and the intermediate and init processes send theirs to the main process.
+use std::fmt;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// createRuntime hooks, run by the main process
+    CreateRuntimeHooks,
+    /// Applying the cgroup and its resources
+    Cgroups,
+    /// Entering the user and pid namespaces in the intermediate process, or
+    /// the remaining namespaces in the init process
+    Namespaces,
+    /// Writing the uid and gid mappings of a new user namespace
+    UidMapping,
+    /// createContainer hooks
+    CreateContainerHooks,
+    /// Mounting the rootfs and the mounts of the spec
+    Rootfs,
+    /// Entering the rootfs through pivot_root, or chroot
+    PivotRoot,
+    /// Loading the seccomp filter
+    Seccomp,
+    /// prestart hooks, run by the main process on start
+    PrestartHooks,
+    /// startContainer hooks
+    StartContainerHooks,
+    /// From the start signal to right before the execve of the payload,
+    /// including the startContainer hooks
+    Exec,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::CreateRuntimeHooks => "create_runtime_hooks",
+            Phase::Cgroups => "cgroups",
+            Phase::Namespaces => "namespaces",
+            Phase::UidMapping => "uid_mapping",
+            Phase::CreateContainerHooks => "create_container_hooks",
+            Phase::Rootfs => "rootfs",
+            Phase::PivotRoot => "pivot_root",
+            Phase::Seccomp => "seccomp",
+            Phase::PrestartHooks => "prestart_hooks",
+            Phase::StartContainerHooks => "start_container_hooks",
+            Phase::Exec => "exec",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The youki process a phase ran in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseProcess {
+    Main,
+    Intermediate,
+    Init,
+}
+
+impl fmt::Display for PhaseProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PhaseProcess::Main => "main",
+            PhaseProcess::Intermediate => "intermediate",
+            PhaseProcess::Init => "init",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    pub phase: Phase,
+    pub process: PhaseProcess,
+    /// Wall clock time the phase started at, in nanoseconds since the epoch
+    pub start_unix_nano: u64,
+    pub duration_nano: u64,
+}
+
+/// Start of a phase which can't be wrapped in a closure
+pub struct PhaseTimer {
+    start: SystemTime,
+    instant: Instant,
+}
+
+impl PhaseTimer {
+    pub fn start() -> Self {
+        Self {
+            start: SystemTime::now(),
+            instant: Instant::now(),
+        }
+    }
+}
+
+/// Phases recorded by one process, together with the phases received from the
+/// other processes.
+#[derive(Debug, Clone)]
+pub struct StartupTimings {
+    process: PhaseProcess,
+    phases: Vec<PhaseTiming>,
+}
+
+impl StartupTimings {
+    pub fn new(process: PhaseProcess) -> Self {
+        Self {
+            process,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Runs `f` and records its duration as `phase`. A phase is recorded even
+    /// if `f` fails, as a failed phase took time as well.
+    pub fn measure<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let timer = PhaseTimer::start();
+        let ret = f();
+        self.record(phase, timer);
+        ret
+    }
+
+    /// Records the time since `timer` was started as `phase`
+    pub fn record(&mut self, phase: Phase, timer: PhaseTimer) {
+        let duration = timer.instant.elapsed();
+        tracing::debug!(
+            %phase,
+            process = %self.process,
+            duration_us = duration.as_micros() as u64,
+            "startup phase finished"
+        );
+        self.phases.push(PhaseTiming {
+            phase,
+            process: self.process,
+            start_unix_nano: timer
+                .start
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default(),
+            duration_nano: duration.as_nanos() as u64,
+        });
+    }
+
+    /// Adds the phases recorded by another process
+    pub fn extend(&mut self, phases: impl IntoIterator<Item = PhaseTiming>) {
+        self.phases.extend(phases);
+    }
+
+    /// Returns the recorded phases ordered by their start time
+    pub fn phases(&self) -> Vec<PhaseTiming> {
+        let mut phases = self.phases.clone();
+        phases.sort_by_key(|timing| timing.start_unix_nano);
+        phases
+    }
+
+    pub fn into_phases(self) -> Vec<PhaseTiming> {
+        self.phases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure() {
+        let mut timings = StartupTimings::new(PhaseProcess::Init);
+        let ret: Result<(), &str> = timings.measure(Phase::Rootfs, || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            Err("failed")
+        });
+        assert!(ret.is_err());
+        timings.extend(vec![PhaseTiming {
+            phase: Phase::Cgroups,
+            process: PhaseProcess::Intermediate,
+            start_unix_nano: 1,
+            duration_nano: 1,
+        }]);
+
+        let phases = timings.phases();
+        assert_eq!(phases.len(), 2);
+        assert_eq!(phases[0].phase, Phase::Cgroups);
+        assert_eq!(phases[1].phase, Phase::Rootfs);
+        assert_eq!(phases[1].process, PhaseProcess::Init);
+        assert!(phases[1].duration_nano >= 10_000_000);
+    }
+
+    #[test]
+    fn test_phase_serialization() {
+        assert_eq!(
+            serde_json::to_string(&Phase::CreateContainerHooks).unwrap(),
+            format!("\"{}\"", Phase::CreateContainerHooks)
+        );
+        assert_eq!(
+            serde_json::to_string(&PhaseProcess::Intermediate).unwrap(),
+            format!("\"{}\"", PhaseProcess::Intermediate)
+        );
+    }
+}
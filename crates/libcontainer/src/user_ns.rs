@@ -80,6 +80,8 @@ pub enum UserNamespaceError {
     #[error(transparent)]
     IDMapping(#[from] MappingError),
     #[error(transparent)]
+    SubIds(#[from] crate::subid::SubIdError),
+    #[error(transparent)]
     OtherIO(#[from] std::io::Error),
 }
 
@@ -164,9 +166,12 @@ impl UserNamespaceConfig {
                 err
             })?;
             let mut user_ns_config = UserNamespaceConfig::try_from(linux)?;
-            if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
-                user_ns_config.newuidmap = Some(uid_binary);
-                user_ns_config.newgidmap = Some(gid_binary);
+            // Privileged callers write any number of mappings themselves
+            if !user_ns_config.privileged {
+                if let Some((uid_binary, gid_binary)) = lookup_map_binaries(linux)? {
+                    user_ns_config.newuidmap = Some(uid_binary);
+                    user_ns_config.newgidmap = Some(gid_binary);
+                }
             }
 
             Ok(Some(user_ns_config))
@@ -370,7 +375,8 @@ pub fn lookup_map_binaries(
     spec: &Linux,
 ) -> std::result::Result<Option<(PathBuf, PathBuf)>, MappingError> {
     if let Some(uid_mappings) = spec.uid_mappings() {
-        if uid_mappings.len() == 1 && uid_mappings.len() == 1 {
+        let gid_mappings = spec.gid_mappings().as_deref().unwrap_or_default();
+        if uid_mappings.len() == 1 && gid_mappings.len() <= 1 {
             return Ok(None);
         }
 
@@ -402,19 +408,24 @@ fn write_id_mapping(
 ) -> std::result::Result<(), MappingError> {
     tracing::debug!("Write ID mapping: {:?}", mappings);
 
-    match mappings.len() {
-        0 => return Err(MappingError::NoIDMapping),
-        1 => {
+    if mappings.is_empty() {
+        return Err(MappingError::NoIDMapping);
+    }
+
+    match map_binary {
+        // The kernel only takes all mappings in a single write
+        None => {
             let mapping = mappings
-                .first()
-                .and_then(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()).into())
-                .unwrap();
+                .iter()
+                .map(|m| format!("{} {} {}", m.container_id(), m.host_id(), m.size()))
+                .collect::<Vec<_>>()
+                .join("\n");
             std::fs::write(map_file, &mapping).map_err(|err| {
                 tracing::error!(?err, ?map_file, ?mapping, "failed to write uid/gid mapping");
                 MappingError::WriteIDMapping(err)
             })?;
         }
-        _ => {
+        Some(map_binary) => {
             let args: Vec<String> = mappings
                 .iter()
                 .flat_map(|m| {
@@ -426,10 +437,7 @@ fn write_id_mapping(
                 })
                 .collect();
 
-            // we can be certain here that map_binary will not be None,
-            // as in the lookup_map_binaries function, we return error
-            // if there are mappings.len() > 1 and binaries are not present
-            Command::new(map_binary.unwrap())
+            Command::new(map_binary)
                 .arg(pid.to_string())
                 .args(args)
                 .output()
@@ -643,4 +651,38 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_write_multiple_mappings() -> Result<()> {
+        let uid_mappings = vec![
+            LinuxIdMappingBuilder::default()
+                .host_id(1000_u32)
+                .container_id(0_u32)
+                .size(1_u32)
+                .build()?,
+            LinuxIdMappingBuilder::default()
+                .host_id(100000_u32)
+                .container_id(1_u32)
+                .size(65535_u32)
+                .build()?,
+        ];
+
+        let pid = getpid();
+        let tmp = tempfile::tempdir()?;
+        let id_mapper = UserNamespaceIDMapper::new_test(tmp.path().to_path_buf());
+        id_mapper.ensure_uid_path(&pid)?;
+
+        // without newuidmap, all mappings are written at once
+        let config = UserNamespaceConfig {
+            uid_mappings: Some(uid_mappings),
+            id_mapper: id_mapper.clone(),
+            ..Default::default()
+        };
+        config.write_uid_mapping(pid)?;
+        assert_eq!(
+            "0 1000 1\n1 100000 65535",
+            fs::read_to_string(id_mapper.get_uid_path(&pid))?
+        );
+        Ok(())
+    }
 }
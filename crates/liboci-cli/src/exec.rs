@@ -48,7 +48,8 @@ pub struct Exec {
     /// Pass N additional file descriptors to the container
     #[clap(long, default_value = "0")]
     pub preserve_fds: i32,
-    /// Allow exec in a paused container
+    /// Allow exec in a paused container. The command runs once the container
+    /// is resumed, a failure to execute it is not reported then
     #[clap(long)]
     pub ignore_paused: bool,
     /// Execute a process in a sub-cgroup
@@ -1,5 +1,6 @@
 //! Handles the creation of a new container
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::Result;
 use libcontainer::container::builder::ContainerBuilder;
@@ -13,9 +14,16 @@ use crate::workload::executor::default_executor;
 // can be given impression that is is running on a complete system, but on the system which
 // it is running, it is just another process, and has attributes such as pid, file descriptors, etc.
 // associated with it like any other process.
-pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
-    ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
+pub fn create(
+    args: Create,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    hook_timeout: Option<Duration>,
+    timing_report: Option<PathBuf>,
+) -> Result<()> {
+    let container = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
+        .with_trace_context(crate::observability::current_traceparent())
         .with_pid_file(args.pid_file.as_ref())?
         .with_console_socket(args.console_socket.as_ref())
         .with_root_path(root_path)?
@@ -23,9 +31,18 @@ pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<
         .validate_id()?
         .as_init(&args.bundle)
         .with_systemd(systemd_cgroup)
+        .with_hook_timeout(hook_timeout)
         .with_detach(true)
         .with_no_pivot(args.no_pivot)
         .build()?;
 
+    if let Some(path) = timing_report {
+        // The container is created at this point, failing here would make the
+        // caller believe it doesn't exist.
+        if let Err(err) = super::write_timing_report(&path, &container) {
+            tracing::error!(?err, "failed to write timing report");
+        }
+    }
+
     Ok(())
 }
@@ -1,9 +1,10 @@
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
+use libcontainer::container::gc;
 use liboci_cli::Delete;
 
-use crate::commands::{container_exists, load_container};
+use crate::commands::{construct_container_root, container_exists, load_container};
 
 pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
     tracing::debug!("start deleting {}", args.container_id);
@@ -11,7 +12,18 @@ pub fn delete(args: Delete, root_path: PathBuf) -> Result<()> {
         return Ok(());
     }
 
-    let mut container = load_container(root_path, &args.container_id)?;
+    let mut container = match load_container(&root_path, &args.container_id) {
+        Ok(container) => container,
+        // The state may be corrupt, but a forced delete still has to get rid
+        // of the container
+        Err(err) if args.force => {
+            tracing::warn!(?err, "failed to load container, removing it anyway");
+            let container_root = construct_container_root(&root_path, &args.container_id)?;
+            return gc::remove_corrupt(&container_root)
+                .with_context(|| format!("failed to delete container {}", args.container_id));
+        }
+        Err(err) => return Err(err),
+    };
     container
         .delete(args.force)
         .with_context(|| format!("failed to delete container {}", args.container_id))
@@ -0,0 +1,52 @@
+//! Contains functionality of the device command, which adds devices to or
+//! removes them from a running container
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+
+use crate::commands::load_container;
+
+/// Add or remove devices of a running container
+#[derive(Parser, Debug)]
+pub struct Device {
+    #[clap(subcommand)]
+    pub action: DeviceAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceAction {
+    /// Add a device of the host to the container, at the same absolute path.
+    /// Requires root.
+    Add {
+        #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
+        container_id: String,
+        host_path: PathBuf,
+    },
+    /// Remove a device from the container
+    Remove {
+        #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
+        container_id: String,
+        path: PathBuf,
+    },
+}
+
+pub fn device(args: Device, root_path: PathBuf) -> Result<()> {
+    match args.action {
+        DeviceAction::Add {
+            container_id,
+            host_path,
+        } => {
+            let mut container = load_container(root_path, &container_id)?;
+            container.add_device(&host_path).with_context(|| {
+                format!("failed to add device {host_path:?} to container {container_id}")
+            })
+        }
+        DeviceAction::Remove { container_id, path } => {
+            let mut container = load_container(root_path, &container_id)?;
+            container.remove_device(&path).with_context(|| {
+                format!("failed to remove device {path:?} from container {container_id}")
+            })
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Contains functionality of the devices command, which shows the device
+//! programs attached to the cgroup of a container
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+
+/// Show the device programs attached to the cgroup of a container, with the
+/// rules of those youki generated
+#[derive(Parser, Debug)]
+pub struct Devices {
+    /// format to display the programs: text or json (default: "text")
+    #[clap(short, long, default_value = "text")]
+    pub format: String,
+    #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
+    pub container_id: String,
+}
+
+#[cfg(feature = "cgroupsv2_devices")]
+pub fn devices(args: Devices, root_path: PathBuf) -> Result<()> {
+    use anyhow::Context;
+    use libcgroups::v2::devices::inspect::attached_programs;
+
+    use crate::commands::load_container;
+
+    if args.format != "text" && args.format != "json" {
+        bail!("unknown format: {}", args.format);
+    }
+
+    let container = load_container(root_path, &args.container_id)?;
+    let Some(pid) = container.pid() else {
+        bail!("container {} has no init process", args.container_id);
+    };
+    let cgroup_path = cgroup_path(pid)?;
+    let programs = attached_programs(&cgroup_path)
+        .with_context(|| format!("failed to inspect device programs of {cgroup_path:?}"))?;
+
+    if args.format == "json" {
+        println!("{}", serde_json::to_string_pretty(&programs)?);
+        return Ok(());
+    }
+
+    for prog in &programs {
+        let name = if prog.name.is_empty() {
+            "-"
+        } else {
+            &prog.name
+        };
+        let Some(emulator) = &prog.rules else {
+            println!("{}\t{}\tforeign", prog.id, name);
+            continue;
+        };
+        println!("{}\t{}\tyouki", prog.id, name);
+        let action = |allow: bool| if allow { "allow" } else { "deny" };
+        println!("\t{} a *:* rwm", action(emulator.default_allow));
+        for rule in &emulator.rules {
+            println!("\t{} {}", action(rule.allow()), rule);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "cgroupsv2_devices"))]
+pub fn devices(_: Devices, _: PathBuf) -> Result<()> {
+    bail!("youki was built without support for cgroup v2 device programs")
+}
+
+/// Finds the cgroup v2 directory of a process
+#[cfg(feature = "cgroupsv2_devices")]
+fn cgroup_path(pid: nix::unistd::Pid) -> Result<PathBuf> {
+    use anyhow::Context;
+
+    let proc_cgroup = format!("/proc/{pid}/cgroup");
+    let cgroups = std::fs::read_to_string(&proc_cgroup)
+        .with_context(|| format!("failed to read {proc_cgroup}"))?;
+    let Some(path) = cgroups.lines().find_map(|line| line.strip_prefix("0::")) else {
+        bail!("process {pid} is not in a cgroup v2 hierarchy");
+    };
+
+    Ok(
+        std::path::Path::new(libcgroups::common::DEFAULT_CGROUP_ROOT)
+            .join(path.trim_start_matches('/')),
+    )
+}
@@ -1,11 +1,13 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use libcgroups::sub_cgroup::SubCgroup;
 use libcontainer::container::builder::ContainerBuilder;
 use libcontainer::syscall::syscall::SyscallType;
 use liboci_cli::Exec;
 use nix::sys::wait::{waitpid, WaitStatus};
 
+use crate::terminal::PrivateConsoleSocket;
 use crate::workload::executor::default_executor;
 
 pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
@@ -13,12 +15,35 @@ pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
     // the remaining ones.
     let user = args.user.map(|(u, _)| u);
     let group = args.user.and_then(|(_, g)| g);
+    let sub_cgroup = args
+        .cgroup
+        .as_deref()
+        .map(str::parse::<SubCgroup>)
+        .transpose()
+        .context("invalid --cgroup")?;
+
+    // Without a console socket, the terminal of the process is attached to
+    // the one of youki
+    let private_socket = if args.tty && args.console_socket.is_none() {
+        if args.detach {
+            bail!("cannot allocate a terminal for a detached process without a console socket");
+        }
+        Some(PrivateConsoleSocket::new()?)
+    } else {
+        None
+    };
+    let console_socket = args
+        .console_socket
+        .clone()
+        .or_else(|| private_socket.as_ref().map(PrivateConsoleSocket::path));
 
     let pid = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
+        .with_trace_context(crate::observability::current_traceparent())
         .with_root_path(root_path)?
-        .with_console_socket(args.console_socket.as_ref())
+        .with_console_socket(console_socket.as_ref())
         .with_pid_file(args.pid_file.as_ref())?
+        .with_preserved_fds(args.preserve_fds)
         .validate_id()?
         .as_tenant()
         .with_detach(args.detach)
@@ -26,20 +51,31 @@ pub fn exec(args: Exec, root_path: PathBuf) -> Result<i32> {
         .with_env(args.env.clone().into_iter().collect())
         .with_process(args.process.as_ref())
         .with_no_new_privs(args.no_new_privs)
+        .with_capabilities(args.cap)
+        .with_apparmor_profile(args.apparmor)
+        .with_process_label(args.process_label)
         .with_container_args(args.command.clone())
         .with_additional_gids(args.additional_gids)
         .with_user(user)
         .with_group(group)
+        .with_sub_cgroup(sub_cgroup)
+        .with_ignore_paused(args.ignore_paused)
         .build()?;
 
     // See https://github.com/containers/youki/pull/1252 for a detailed explanation
-    // basically, if there is any error in starting exec, the build above will return error
+    // basically, if there is any error in starting exec, the build above will return error,
+    // unless the container is paused and the process only executes once it is resumed.
     // however, if the process does start, and detach is given, we do not wait for it
     // if not detached, then we wait for it using waitpid below
     if args.detach {
         return Ok(0);
     }
 
+    if let Some(private_socket) = private_socket {
+        let console = private_socket.accept()?;
+        return super::run::handle_foreground(pid, Some(console));
+    }
+
     match waitpid(pid, None)? {
         WaitStatus::Exited(_, status) => Ok(status),
         WaitStatus::Signaled(_, sig, _) => Ok(sig as i32),
@@ -0,0 +1,41 @@
+//! Contains functionality of the gc command, which cleans up the containers
+//! left behind in the root path
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use libcontainer::container::gc;
+
+/// Clean up dead containers, i.e. those which have exited, also with their
+/// bundle gone, or whose state is corrupt
+#[derive(Parser, Debug)]
+pub struct Gc {
+    /// Only print the containers which would be cleaned up
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+pub fn gc(args: Gc, root_path: PathBuf) -> Result<()> {
+    let root_path = fs::canonicalize(root_path)?;
+    let dead = gc::find_dead(&root_path).context("failed to find dead containers")?;
+
+    let mut failed = 0;
+    for container in &dead {
+        println!("{}\t{}", container.id, container.reason);
+        if args.dry_run {
+            continue;
+        }
+        // A container which can't be cleaned up shouldn't keep the others
+        if let Err(err) = gc::collect(container) {
+            tracing::error!(id = container.id, ?err, "failed to clean up container");
+            eprintln!("failed to clean up container {}: {err:?}", container.id);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("failed to clean up {failed} of {} containers", dead.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+//! Contains functionality of the inspect command, which prints the spec a
+//! container was created with
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::commands::load_container;
+
+/// Show the effective spec of a container, i.e. its config.json as youki
+/// applied it
+#[derive(Parser, Debug)]
+pub struct Inspect {
+    #[clap(value_parser = clap::builder::NonEmptyStringValueParser::new(), required = true)]
+    pub container_id: String,
+}
+
+pub fn inspect(args: Inspect, root_path: PathBuf) -> Result<()> {
+    let container = load_container(root_path, &args.container_id)?;
+    let spec = container
+        .effective_spec()
+        .with_context(|| format!("failed to get spec of container {}", args.container_id))?;
+    println!("{}", serde_json::to_string_pretty(&spec)?);
+    Ok(())
+}
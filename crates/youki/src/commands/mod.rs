@@ -9,10 +9,14 @@ pub mod checkpoint;
 pub mod completion;
 pub mod create;
 pub mod delete;
+pub mod device;
+pub mod devices;
 pub mod events;
 pub mod exec;
 pub mod features;
+pub mod gc;
 pub mod info;
+pub mod inspect;
 pub mod kill;
 pub mod list;
 pub mod pause;
@@ -65,3 +69,25 @@ fn create_cgroup_manager<P: AsRef<Path>>(
         },
     )?)
 }
+
+/// Writes the startup phases of a container as a JSON report
+fn write_timing_report(path: &Path, container: &Container) -> Result<()> {
+    let phases = container.startup_timings().phases();
+    let total_nano = match (
+        phases.first(),
+        phases
+            .iter()
+            .map(|p| p.start_unix_nano + p.duration_nano)
+            .max(),
+    ) {
+        (Some(first), Some(end)) => end - first.start_unix_nano,
+        _ => 0,
+    };
+    let report = serde_json::json!({
+        "id": container.id(),
+        "total_nano": total_nano,
+        "phases": phases,
+    });
+    fs::write(path, serde_json::to_vec_pretty(&report)?)
+        .with_context(|| format!("failed to write timing report to {}", path.display()))
+}
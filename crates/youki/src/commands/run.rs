@@ -1,6 +1,8 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use libcontainer::console::Console;
 use libcontainer::container::builder::ContainerBuilder;
 use libcontainer::syscall::syscall::SyscallType;
 use liboci_cli::Run;
@@ -9,25 +11,71 @@ use nix::sys::signalfd::SigSet;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 
+use crate::terminal::{bundle_wants_terminal, PrivateConsoleSocket, Terminal};
 use crate::workload::executor::default_executor;
 
-pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
+pub fn run(
+    args: Run,
+    root_path: PathBuf,
+    systemd_cgroup: bool,
+    hook_timeout: Option<Duration>,
+    timing_report: Option<PathBuf>,
+) -> Result<i32> {
+    // Without a console socket, the terminal of the container is attached to
+    // the one of youki
+    let private_socket = if args.console_socket.is_none() && bundle_wants_terminal(&args.bundle)? {
+        if args.detach {
+            bail!("cannot allocate a terminal for a detached container without a console socket");
+        }
+        Some(PrivateConsoleSocket::new()?)
+    } else {
+        None
+    };
+    let console_socket = args
+        .console_socket
+        .clone()
+        .or_else(|| private_socket.as_ref().map(PrivateConsoleSocket::path));
+
     let mut container = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(default_executor())
+        .with_trace_context(crate::observability::current_traceparent())
         .with_pid_file(args.pid_file.as_ref())?
-        .with_console_socket(args.console_socket.as_ref())
+        .with_console_socket(console_socket.as_ref())
         .with_root_path(root_path)?
         .with_preserved_fds(args.preserve_fds)
         .validate_id()?
         .as_init(&args.bundle)
         .with_systemd(systemd_cgroup)
+        .with_hook_timeout(hook_timeout)
         .with_detach(args.detach)
         .with_no_pivot(args.no_pivot)
         .build()?;
 
-    container
-        .start()
-        .with_context(|| format!("failed to start container {}", args.container_id))?;
+    let console = match private_socket.as_ref().map(PrivateConsoleSocket::accept) {
+        Some(Ok(master)) => Some(master),
+        Some(Err(err)) => {
+            container.delete(true)?;
+            return Err(err);
+        }
+        None => None,
+    };
+    drop(private_socket);
+
+    // only waiting for the report makes start block until the payload runs
+    if timing_report.is_some() {
+        container.start_with_timings()
+    } else {
+        container.start()
+    }
+    .with_context(|| format!("failed to start container {}", args.container_id))?;
+
+    if let Some(path) = timing_report {
+        // The container is running at this point, so a failed report must not
+        // leave it behind without its foreground process.
+        if let Err(err) = super::write_timing_report(&path, &container) {
+            tracing::error!(?err, "failed to write timing report");
+        }
+    }
 
     if args.detach {
         return Ok(0);
@@ -40,7 +88,7 @@ pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
         container.pid().is_some(),
         "expects a container init pid in the container state"
     );
-    let foreground_result = handle_foreground(container.pid().unwrap());
+    let foreground_result = handle_foreground(container.pid().unwrap(), console);
     // execute the destruction action after the container finishes running
     container.delete(true)?;
     // return result
@@ -50,9 +98,10 @@ pub fn run(args: Run, root_path: PathBuf, systemd_cgroup: bool) -> Result<i32> {
 // handle_foreground will match the `runc` behavior running the foreground mode.
 // The youki main process will wait and reap the container init process. The
 // youki main process also forwards most of the signals to the container init
-// process.
-#[tracing::instrument(level = "trace")]
-fn handle_foreground(init_pid: Pid) -> Result<i32> {
+// process. If the process has a pty master, its I/O is relayed to the stdio of
+// youki until the process exits.
+#[tracing::instrument(level = "trace", skip(console))]
+pub(super) fn handle_foreground(init_pid: Pid, console: Option<Console>) -> Result<i32> {
     tracing::trace!("waiting for container init process to exit");
     // We mask all signals here and forward most of the signals to the container
     // init process.
@@ -60,6 +109,16 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
     signal_set
         .thread_block()
         .with_context(|| "failed to call pthread_sigmask")?;
+    // The relay threads are only spawned now, so they inherit the signal mask
+    let terminal = console.map(Terminal::attach).transpose()?;
+    let exit_code = wait_foreground(init_pid, &signal_set, terminal.as_ref());
+    if let Some(terminal) = &terminal {
+        terminal.drain();
+    }
+    exit_code
+}
+
+fn wait_foreground(init_pid: Pid, signal_set: &SigSet, terminal: Option<&Terminal>) -> Result<i32> {
     loop {
         match signal_set
             .wait()
@@ -99,7 +158,9 @@ fn handle_foreground(init_pid: Pid) -> Result<i32> {
                 // the container process. Here, we just ignore the signal.
             }
             signal::SIGWINCH => {
-                // TODO: resize the terminal
+                if let Some(terminal) = terminal {
+                    terminal.resize();
+                }
             }
             signal => {
                 tracing::trace!(?signal, "forwarding signal");
@@ -162,7 +223,7 @@ mod tests {
                 match unsafe { unistd::fork()? } {
                     unistd::ForkResult::Parent { child } => {
                         // Inside P1.
-                        let _ = handle_foreground(child).map_err(|err| {
+                        let _ = handle_foreground(child, None).map_err(|err| {
                             // Since we are in a child process, we want to use trace to log the error.
                             let _ = tracing_subscriber::fmt()
                                 .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
@@ -204,7 +265,7 @@ mod tests {
                 match unsafe { unistd::fork()? } {
                     unistd::ForkResult::Parent { child } => {
                         // Inside P1.
-                        handle_foreground(child)?;
+                        handle_foreground(child, None)?;
                         wait::waitpid(child, None)?;
                     }
                     unistd::ForkResult::Child => {
@@ -1,16 +1,14 @@
 use std::path::PathBuf;
 use std::{fs, io};
 
-use anyhow::Result;
-use libcgroups::common::{CgroupManager, ControllerOpt};
-use libcgroups::{self};
+use anyhow::{Context, Result};
 use libcontainer::oci_spec::runtime::{LinuxPidsBuilder, LinuxResources, LinuxResourcesBuilder};
 use liboci_cli::Update;
 
-use crate::commands::create_cgroup_manager;
+use crate::commands::load_container;
 
 pub fn update(args: Update, root_path: PathBuf) -> Result<()> {
-    let cmanager = create_cgroup_manager(root_path, &args.container_id)?;
+    let mut container = load_container(root_path, &args.container_id)?;
 
     let linux_res: LinuxResources;
     if let Some(resources_path) = args.resources {
@@ -29,11 +27,7 @@ pub fn update(args: Update, root_path: PathBuf) -> Result<()> {
         linux_res = builder.build()?;
     }
 
-    cmanager.apply(&ControllerOpt {
-        resources: &linux_res,
-        disable_oom_killer: false,
-        oom_score_adj: None,
-        freezer_state: None,
-    })?;
-    Ok(())
+    container
+        .update(&linux_res)
+        .with_context(|| format!("failed to update container {}", args.container_id))
 }
@@ -4,8 +4,12 @@
 mod commands;
 mod observability;
 mod rootpath;
+mod terminal;
 mod workload;
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 use anyhow::{Context, Result};
 use clap::{crate_version, CommandFactory, Parser};
 use liboci_cli::{CommonCmd, GlobalOpts, StandardCmd};
@@ -21,8 +25,26 @@ struct YoukiExtendOpts {
     /// set the log level (default is 'error')
     #[clap(long)]
     pub log_level: Option<String>,
+    /// Export trace spans to an OTLP/HTTP collector, e.g. http://localhost:4318
+    #[clap(long, conflicts_with = "otlp_file")]
+    pub otlp_endpoint: Option<String>,
+    /// Append trace spans to a file in the OTLP/JSON format
+    #[clap(long)]
+    pub otlp_file: Option<PathBuf>,
+    /// Write a JSON report of the startup phases of create and run to a file.
+    /// Can also be set through the YOUKI_TIMING_REPORT environment variable.
+    /// With it, run waits for the container process to exec the payload
+    /// before returning.
+    #[clap(long)]
+    pub timing_report: Option<PathBuf>,
+    /// Default timeout in seconds of the hooks which don't set a timeout
+    /// themselves
+    #[clap(long)]
+    pub hook_timeout: Option<u64>,
 }
 
+const TIMING_REPORT_ENV: &str = "YOUKI_TIMING_REPORT";
+
 /// output Youki version in Moby compatible format
 #[macro_export]
 macro_rules! youki_version {
@@ -69,6 +91,10 @@ enum SubCommand {
     // Youki specific extensions
     Info(info::Info),
     Completion(commands::completion::Completion),
+    Gc(commands::gc::Gc),
+    Inspect(commands::inspect::Inspect),
+    Device(commands::device::Device),
+    Devices(commands::devices::Devices),
 }
 
 /// This is the entry point in the container runtime. The binary is run by a high-level container runtime,
@@ -99,14 +125,31 @@ fn main() -> Result<()> {
         nix::unistd::geteuid(),
         std::env::args_os()
     );
+    // Root span of the command, which the spans of the container processes are
+    // attached to when traces are exported.
+    let command = std::env::args()
+        .skip(1)
+        .find(|arg| app.find_subcommand(arg).is_some())
+        .unwrap_or_default();
+    let root_span = tracing::info_span!("youki", command).entered();
+
     let root_path = rootpath::determine(opts.global.root)?;
     let systemd_cgroup = opts.global.systemd_cgroup;
+    let timing_report = opts
+        .youki_extend
+        .timing_report
+        .or_else(|| std::env::var_os(TIMING_REPORT_ENV).map(PathBuf::from));
+    let hook_timeout = opts.youki_extend.hook_timeout.map(Duration::from_secs);
 
     let cmd_result = match opts.subcmd {
         SubCommand::Standard(cmd) => match *cmd {
-            StandardCmd::Create(create) => {
-                commands::create::create(create, root_path, systemd_cgroup)
-            }
+            StandardCmd::Create(create) => commands::create::create(
+                create,
+                root_path,
+                systemd_cgroup,
+                hook_timeout,
+                timing_report,
+            ),
             StandardCmd::Start(start) => commands::start::start(start, root_path),
             StandardCmd::Kill(kill) => commands::kill::kill(kill, root_path),
             StandardCmd::Delete(delete) => commands::delete::delete(delete, root_path),
@@ -118,11 +161,11 @@ fn main() -> Result<()> {
             }
             CommonCmd::Events(events) => commands::events::events(events, root_path),
             CommonCmd::Exec(exec) => match commands::exec::exec(exec, root_path) {
-                Ok(exit_code) => std::process::exit(exit_code),
+                Ok(exit_code) => exit(root_span, exit_code),
                 Err(e) => {
                     tracing::error!("error in executing command: {:?}", e);
-                    eprintln!("exec failed : {e}");
-                    std::process::exit(-1);
+                    eprintln!("exec failed : {e:?}");
+                    exit(root_span, -1);
                 }
             },
             CommonCmd::Features(features) => commands::features::features(features),
@@ -130,14 +173,22 @@ fn main() -> Result<()> {
             CommonCmd::Pause(pause) => commands::pause::pause(pause, root_path),
             CommonCmd::Ps(ps) => commands::ps::ps(ps, root_path),
             CommonCmd::Resume(resume) => commands::resume::resume(resume, root_path),
-            CommonCmd::Run(run) => match commands::run::run(run, root_path, systemd_cgroup) {
-                Ok(exit_code) => std::process::exit(exit_code),
-                Err(e) => {
-                    tracing::error!("error in executing command: {:?}", e);
-                    eprintln!("run failed : {e}");
-                    std::process::exit(-1);
+            CommonCmd::Run(run) => {
+                match commands::run::run(
+                    run,
+                    root_path,
+                    systemd_cgroup,
+                    hook_timeout,
+                    timing_report,
+                ) {
+                    Ok(exit_code) => exit(root_span, exit_code),
+                    Err(e) => {
+                        tracing::error!("error in executing command: {:?}", e);
+                        eprintln!("run failed : {e:?}");
+                        exit(root_span, -1);
+                    }
                 }
-            },
+            }
             CommonCmd::Spec(spec) => commands::spec_json::spec(spec),
             CommonCmd::Update(update) => commands::update::update(update, root_path),
         },
@@ -146,6 +197,10 @@ fn main() -> Result<()> {
         SubCommand::Completion(completion) => {
             commands::completion::completion(completion, &mut app)
         }
+        SubCommand::Gc(gc) => commands::gc::gc(gc, root_path),
+        SubCommand::Inspect(inspect) => commands::inspect::inspect(inspect, root_path),
+        SubCommand::Device(device) => commands::device::device(device, root_path),
+        SubCommand::Devices(devices) => commands::devices::devices(devices, root_path),
     };
 
     if let Err(ref e) = cmd_result {
@@ -154,3 +209,10 @@ fn main() -> Result<()> {
     }
     cmd_result
 }
+
+/// Exits the process after closing the root span, which would otherwise never
+/// be exported since `std::process::exit` doesn't run destructors.
+fn exit(root_span: tracing::span::EnteredSpan, code: i32) -> ! {
+    drop(root_span);
+    std::process::exit(code)
+}
@@ -1,12 +1,18 @@
 use std::borrow::Cow;
-use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{bail, Context, Result};
 use tracing::Level;
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::prelude::*;
 
+mod json;
+mod otlp;
+
+pub use otlp::current_traceparent;
+
 const LOG_FORMAT_TEXT: &str = "text";
 const LOG_FORMAT_JSON: &str = "json";
 enum LogFormat {
@@ -51,6 +57,8 @@ pub struct ObservabilityConfig {
     pub log_format: Option<String>,
     #[allow(dead_code)]
     pub systemd_log: bool,
+    pub otlp_endpoint: Option<String>,
+    pub otlp_file: Option<PathBuf>,
 }
 
 impl From<&crate::Opts> for ObservabilityConfig {
@@ -61,6 +69,8 @@ impl From<&crate::Opts> for ObservabilityConfig {
             log_file: opts.global.log.to_owned(),
             log_format: opts.global.log_format.to_owned(),
             systemd_log: opts.youki_extend.systemd_log,
+            otlp_endpoint: opts.youki_extend.otlp_endpoint.to_owned(),
+            otlp_file: opts.youki_extend.otlp_file.to_owned(),
         }
     }
 }
@@ -94,9 +104,29 @@ where
     } else {
         None
     };
+    let otlp = match (config.otlp_endpoint.as_deref(), config.otlp_file.as_ref()) {
+        (Some(endpoint), _) => Some(otlp::OtlpTarget::endpoint(endpoint)?),
+        (None, Some(path)) => Some(otlp::OtlpTarget::file(path)?),
+        (None, None) => None,
+    }
+    .map(|target| {
+        // Spans are exported from the info level regardless of the log level,
+        // so the phases of a container start are visible in the trace even
+        // when logging is limited to errors. Events follow the log level.
+        otlp::OtlpLayer::new(target).with_filter(filter_fn(move |metadata| {
+            if metadata.is_span() {
+                *metadata.level() <= Level::INFO
+            } else {
+                *metadata.level() <= level
+            }
+        }))
+    });
+
+    // The log level is applied per layer rather than globally, since the otlp
+    // layer has a filter of its own.
     let subscriber = tracing_subscriber::registry()
-        .with(log_level_filter)
-        .with(systemd_journald);
+        .with(systemd_journald.with_filter(log_level_filter))
+        .with(otlp);
 
     // I really dislike how we have to specify individual branch for each
     // combination, but I can't find any better way to do this. The tracing
@@ -109,7 +139,8 @@ where
                 .with(
                     tracing_subscriber::fmt::layer()
                         .without_time()
-                        .with_writer(std::io::stderr),
+                        .with_writer(std::io::stderr)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
@@ -119,51 +150,62 @@ where
             subscriber
                 .with(
                     tracing_subscriber::fmt::layer()
-                        .json()
-                        .flatten_event(true)
-                        .with_span_list(false)
-                        .with_writer(std::io::stderr),
+                        .event_format(json::JsonFormat)
+                        .with_writer(std::io::stderr)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
         }
         (Some(path), LogFormat::Text) => {
             // Log file with text format
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(false)
-                .open(path)
-                .with_context(|| "failed to open log file")?;
+            let file = open_log_file(path)?;
             subscriber
-                .with(tracing_subscriber::fmt::layer().with_writer(file))
+                .with(
+                    tracing_subscriber::fmt::layer()
+                        .with_writer(file)
+                        .with_filter(log_level_filter),
+                )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
         }
         (Some(path), LogFormat::Json) => {
             // Log file with JSON format
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(false)
-                .open(path)
-                .with_context(|| "failed to open log file")?;
+            let file = open_log_file(path)?;
             subscriber
                 .with(
                     tracing_subscriber::fmt::layer()
-                        .json()
-                        .flatten_event(true)
-                        .with_span_list(false)
-                        .with_writer(file),
+                        .event_format(json::JsonFormat)
+                        .with_writer(file)
+                        .with_filter(log_level_filter),
                 )
                 .try_init()
                 .map_err(|e| anyhow::anyhow!("failed to init logger: {}", e))?;
         }
     }
 
+    // A panic is only printed to stderr by default, so the log would end with
+    // an earlier error line, which shims then report as the cause.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("{info}");
+        default_hook(info);
+    }));
+
     Ok(())
 }
 
+/// Opens the log file in append mode. A shim passes the same log file to every
+/// runtime call of a container, and the intermediate and init processes keep
+/// writing to it, so the file must never be written from the start again.
+fn open_log_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| "failed to open log file")
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -303,8 +345,41 @@ mod tests {
             if data.is_empty() {
                 Err("logfile should not be empty")?;
             }
-            serde_json::from_str::<serde_json::Value>(&data)
+            let line = serde_json::from_str::<serde_json::Value>(&data)
                 .map_err(|err| format!("failed to parse {data}: {err:?}"))?;
+            // The fields read by containerd shims
+            if line["level"] != "error" || line["msg"] != "testing json log" {
+                Err(format!("unexpected level or msg in {data}"))?;
+            }
+            if !line["time"].is_string() {
+                Err(format!("missing time in {data}"))?;
+            }
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_logfile_append() -> Result<()> {
+        libcontainer::test_utils::test_in_child_process(|| {
+            let temp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let log_file = Path::join(temp_dir.path(), "test.log");
+            let previous = "{\"level\":\"info\",\"msg\":\"previous call\"}\n";
+            std::fs::write(&log_file, previous)
+                .map_err(|err| format!("failed to write the logfile: {err:?}"))?;
+            let config = ObservabilityConfig {
+                log_file: Some(log_file.clone()),
+                log_format: Some(LOG_FORMAT_JSON.to_owned()),
+                ..Default::default()
+            };
+            init(config).map_err(|err| TestCallbackError::Other(err.into()))?;
+            tracing::error!("testing append");
+            let data = std::fs::read_to_string(&log_file)
+                .map_err(|err| format!("failed to read the logfile: {err:?}"))?;
+            if !data.starts_with(previous) || data.lines().count() != 2 {
+                Err(format!("the logfile should have been appended to: {data}"))?;
+            }
             Ok(())
         })?;
 
@@ -0,0 +1,617 @@
+//! Export of tracing spans in the OTLP/JSON encoding.
+//!
+//! Spans are either appended to a file, one `ExportTraceServiceRequest` per
+//! line (the format read by the collector's `otlpjsonfile` receiver), or
+//! posted to the `/v1/traces` endpoint of an OTLP/HTTP collector.
+//!
+//! The intermediate and init processes are cloned from the youki main process
+//! rather than exec'd, and the init process eventually execs the container
+//! payload. A batching exporter with a background thread would lose spans in
+//! those processes, so every span is exported synchronously when it closes.
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{Layer, Registry};
+
+/// Name of the span field carrying a W3C trace context. A root span with this
+/// field joins the remote trace instead of starting a new one.
+const TRACEPARENT_FIELD: &str = "traceparent";
+const DEFAULT_HTTP_PORT: u16 = 4318;
+const DEFAULT_HTTP_PATH: &str = "/v1/traces";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(1);
+/// Upper bound of events recorded per span, so a chatty span can't grow
+/// without limit.
+const MAX_EVENTS_PER_SPAN: usize = 128;
+
+/// Where the spans are exported to
+#[derive(Debug)]
+pub enum OtlpTarget {
+    File(File),
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+}
+
+impl OtlpTarget {
+    pub fn file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .with_context(|| format!("failed to open otlp file {:?}", path.as_ref()))?;
+        Ok(Self::File(file))
+    }
+
+    /// Parses an endpoint in the form of `http://host[:port][/path]`. The
+    /// port defaults to 4318 and the path to `/v1/traces`.
+    pub fn endpoint(endpoint: &str) -> Result<Self> {
+        let Some(rest) = endpoint.strip_prefix("http://") else {
+            bail!("unsupported otlp endpoint {endpoint}: only http:// is supported");
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse::<u16>()
+                    .with_context(|| format!("invalid port in otlp endpoint {endpoint}"))?,
+            ),
+            _ => (authority, DEFAULT_HTTP_PORT),
+        };
+        if host.is_empty() {
+            bail!("missing host in otlp endpoint {endpoint}");
+        }
+        let path = match path {
+            "" | "/" => DEFAULT_HTTP_PATH,
+            path => path,
+        };
+
+        Ok(Self::Http {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// A keep-alive connection to the collector, together with the pid of the
+/// process which opened it. A connection inherited from the parent process is
+/// never used, since the parent keeps writing to the same socket.
+struct Connection {
+    pid: u32,
+    stream: TcpStream,
+}
+
+pub struct OtlpLayer {
+    target: OtlpTarget,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl OtlpLayer {
+    pub fn new(target: OtlpTarget) -> Self {
+        Self {
+            target,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Opens the collector connection of the current process if there is
+    /// none yet. This is called whenever a process starts its root span,
+    /// which for the init process happens before it joins the container
+    /// network namespace, where the collector may not be reachable.
+    fn connect(&self) {
+        if let OtlpTarget::Http { host, port, .. } = &self.target {
+            let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+            let pid = std::process::id();
+            if connection.as_ref().is_some_and(|c| c.pid == pid) {
+                return;
+            }
+            *connection = open_stream(host, *port)
+                .ok()
+                .map(|stream| Connection { pid, stream });
+        }
+    }
+
+    fn export(&self, span: Value) {
+        let request = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        string_attribute("service.name", "youki"),
+                        string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+                        {"key": "process.pid", "value": {"intValue": std::process::id().to_string()}},
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "youki"},
+                    "spans": [span],
+                }],
+            }]
+        });
+        let mut body = request.to_string();
+
+        // Export errors are dropped on purpose. Reporting them through tracing
+        // would recurse into this layer, and by the time the init process
+        // exports its spans, stderr belongs to the container.
+        match &self.target {
+            OtlpTarget::File(file) => {
+                body.push('\n');
+                // The file is opened with O_APPEND and every request is
+                // written with a single write, so lines written by the main,
+                // intermediate and init processes don't interleave.
+                let mut writer: &File = file;
+                let _ = writer.write_all(body.as_bytes());
+            }
+            OtlpTarget::Http { host, port, path } => {
+                let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+                let pid = std::process::id();
+                let reusable = connection.as_ref().is_some_and(|c| c.pid == pid);
+                if reusable {
+                    let stream = &mut connection.as_mut().unwrap().stream;
+                    if post(stream, host, path, &body).is_ok() {
+                        return;
+                    }
+                }
+                // The connection is stale or missing, retry once with a new one.
+                *connection = open_stream(host, *port).ok().and_then(|mut stream| {
+                    post(&mut stream, host, path, &body).ok()?;
+                    Some(Connection { pid, stream })
+                });
+            }
+        }
+    }
+}
+
+fn open_stream(host: &str, port: u16) -> std::io::Result<TcpStream> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address for host"))?;
+    let stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Sends a single request on a keep-alive connection and consumes the whole
+/// response, so the connection can be reused for the next span.
+fn post(stream: &mut TcpStream, host: &str, path: &str, body: &str) -> std::io::Result<()> {
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut response_body = vec![0; content_length];
+    reader.read_exact(&mut response_body)?;
+
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("collector responded with {}", status.trim()),
+        )),
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+static SEEDED_PID: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a random number for trace and span ids. The random generator state
+/// is copied into the cloned intermediate and init processes, so it is
+/// reseeded whenever the pid changes to keep the ids of the processes apart.
+fn random_u64() -> u64 {
+    let pid = std::process::id();
+    if SEEDED_PID.swap(pid, Ordering::Relaxed) != pid {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        fastrand::seed(nanos ^ (u64::from(pid) << 32) ^ fastrand::u64(..));
+    }
+    // Zero is an invalid trace and span id.
+    fastrand::u64(1..)
+}
+
+/// Formats a W3C trace context, see https://www.w3.org/TR/trace-context/
+fn format_traceparent(trace_id: u128, span_id: u64) -> String {
+    format!("00-{trace_id:032x}-{span_id:016x}-01")
+}
+
+fn parse_traceparent(traceparent: &str) -> Option<(u128, u64)> {
+    let mut parts = traceparent.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let _flags = parts.next()?;
+    if version.len() != 2 || trace_id.len() != 32 || span_id.len() != 16 {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+    let span_id = u64::from_str_radix(span_id, 16).ok()?;
+    if trace_id == 0 || span_id == 0 {
+        return None;
+    }
+    Some((trace_id, span_id))
+}
+
+/// Returns the W3C trace context of the current span, if spans are exported.
+/// This is passed to libcontainer so that the spans of the intermediate and
+/// init processes join the trace of the youki command.
+pub fn current_traceparent() -> Option<String> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            let span = registry.span(id)?;
+            let extensions = span.extensions();
+            let data = extensions.get::<SpanData>()?;
+            Some(format_traceparent(data.trace_id, data.span_id))
+        })
+        .flatten()
+}
+
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    start: SystemTime,
+    attributes: Vec<Value>,
+    events: Vec<Value>,
+    error: bool,
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    attributes: Vec<Value>,
+    message: Option<String>,
+    traceparent: Option<String>,
+}
+
+impl FieldVisitor {
+    fn push(&mut self, field: &Field, value: Value) {
+        self.attributes
+            .push(json!({"key": field.name(), "value": value}));
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            TRACEPARENT_FIELD => self.traceparent = Some(value.to_owned()),
+            "message" => self.message = Some(value.to_owned()),
+            _ => self.push(field, json!({"stringValue": value})),
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, json!({"intValue": value.to_string()}));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, json!({"intValue": value.to_string()}));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, json!({"boolValue": value}));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, json!({"doubleValue": value}));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+
+        let local_parent = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|data| (data.trace_id, data.span_id))
+        });
+        let remote_parent = visitor.traceparent.as_deref().and_then(parse_traceparent);
+        let (trace_id, parent_span_id) = match remote_parent.or(local_parent) {
+            Some((trace_id, span_id)) => (trace_id, Some(span_id)),
+            None => (
+                (u128::from(random_u64()) << 64) | u128::from(random_u64()),
+                None,
+            ),
+        };
+        if local_parent.is_none() {
+            self.connect();
+        }
+
+        let metadata = span.metadata();
+        let mut attributes = visitor.attributes;
+        attributes.push(string_attribute("code.namespace", metadata.target()));
+        span.extensions_mut().insert(SpanData {
+            trace_id,
+            span_id: random_u64(),
+            parent_span_id,
+            start: SystemTime::now(),
+            attributes,
+            events: Vec::new(),
+            error: false,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<SpanData>() {
+            data.attributes.extend(visitor.attributes);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let Some(data) = extensions.get_mut::<SpanData>() else {
+            return;
+        };
+        let metadata = event.metadata();
+        if *metadata.level() == Level::ERROR {
+            data.error = true;
+        }
+        if data.events.len() >= MAX_EVENTS_PER_SPAN {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let mut attributes = visitor.attributes;
+        attributes.push(string_attribute("level", metadata.level().as_str()));
+        data.events.push(json!({
+            "timeUnixNano": unix_nanos(SystemTime::now()),
+            "name": visitor.message.unwrap_or_else(|| metadata.name().to_owned()),
+            "attributes": attributes,
+        }));
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+
+        let mut exported = json!({
+            "traceId": format!("{:032x}", data.trace_id),
+            "spanId": format!("{:016x}", data.span_id),
+            "name": span.name(),
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(data.start),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": data.attributes,
+            "events": data.events,
+            // STATUS_CODE_ERROR or STATUS_CODE_UNSET
+            "status": {"code": if data.error { 2 } else { 0 }},
+        });
+        if let Some(parent_span_id) = data.parent_span_id {
+            exported["parentSpanId"] = json!(format!("{parent_span_id:016x}"));
+        }
+
+        self.export(exported);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    fn read_spans(path: &Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .expect("failed to read otlp file")
+            .lines()
+            .map(|line| {
+                let request: Value = serde_json::from_str(line).expect("invalid otlp json");
+                request["resourceSpans"][0]["scopeSpans"][0]["spans"][0].clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let (trace_id, span_id) = parse_traceparent(traceparent).unwrap();
+        assert_eq!(trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(span_id, 0x00f067aa0ba902b7);
+        assert_eq!(format_traceparent(trace_id, span_id), traceparent);
+
+        assert!(parse_traceparent("").is_none());
+        assert!(parse_traceparent("00-4bf92f3577b34da6-00f067aa0ba902b7-01").is_none());
+        assert!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+    }
+
+    #[test]
+    fn test_endpoint() -> Result<()> {
+        let tests = [
+            (
+                "http://localhost",
+                "localhost",
+                DEFAULT_HTTP_PORT,
+                DEFAULT_HTTP_PATH,
+            ),
+            (
+                "http://127.0.0.1:4319/",
+                "127.0.0.1",
+                4319,
+                DEFAULT_HTTP_PATH,
+            ),
+            ("http://collector:80/custom", "collector", 80, "/custom"),
+        ];
+        for (endpoint, want_host, want_port, want_path) in tests {
+            match OtlpTarget::endpoint(endpoint)? {
+                OtlpTarget::Http { host, port, path } => {
+                    assert_eq!(host, want_host);
+                    assert_eq!(port, want_port);
+                    assert_eq!(path, want_path);
+                }
+                target => panic!("unexpected target {target:?}"),
+            }
+        }
+        assert!(OtlpTarget::endpoint("https://localhost").is_err());
+        assert!(OtlpTarget::endpoint("http://localhost:port").is_err());
+        assert!(OtlpTarget::endpoint("http://:4318").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_to_file() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("spans.json");
+        let subscriber =
+            tracing_subscriber::registry().with(OtlpLayer::new(OtlpTarget::file(&path)?));
+
+        let (traceparent, remote) = tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root", id = "container");
+            let traceparent = root.in_scope(|| {
+                let _child = tracing::info_span!("child").entered();
+                tracing::error!("something failed");
+                current_traceparent()
+            });
+            drop(root);
+
+            let remote = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+            let _span = tracing::info_span!(parent: None, "remote", traceparent = remote).entered();
+            (traceparent, remote)
+        });
+
+        let spans = read_spans(&path);
+        assert_eq!(spans.len(), 3);
+        let (child, root, remote_child) = (&spans[0], &spans[1], &spans[2]);
+        assert_eq!(child["name"], "child");
+        assert_eq!(root["name"], "root");
+        assert_eq!(child["traceId"], root["traceId"]);
+        assert_eq!(child["parentSpanId"], root["spanId"]);
+        assert!(root.get("parentSpanId").is_none());
+        assert_eq!(child["status"]["code"], 2);
+        assert_eq!(child["events"][0]["name"], "something failed");
+        assert_eq!(root["attributes"][0]["key"], "id");
+        assert_eq!(
+            traceparent,
+            Some(format!(
+                "00-{}-{}-01",
+                child["traceId"].as_str().unwrap(),
+                child["spanId"].as_str().unwrap()
+            ))
+        );
+
+        assert_eq!(remote_child["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(remote_child["parentSpanId"], "00f067aa0ba902b7");
+        assert!(remote.ends_with("-01"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_to_collector() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let collector = std::thread::spawn(move || -> Result<Vec<String>> {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut requests = Vec::new();
+            // Both spans must arrive on the same keep-alive connection.
+            for _ in 0..2 {
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line)?;
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header)?;
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("Content-Length:") {
+                        content_length = value.trim().parse()?;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body)?;
+                (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")?;
+                requests.push(format!("{}{}", request_line, String::from_utf8(body)?));
+            }
+            Ok(requests)
+        });
+
+        let target = OtlpTarget::endpoint(&format!("http://127.0.0.1:{port}"))?;
+        let subscriber = tracing_subscriber::registry().with(OtlpLayer::new(target));
+        tracing::subscriber::with_default(subscriber, || {
+            let _root = tracing::info_span!("root").entered();
+            let _child = tracing::info_span!("child").entered();
+        });
+
+        let requests = collector.join().expect("collector panicked")?;
+        assert!(requests[0].starts_with("POST /v1/traces HTTP/1.1"));
+        assert!(requests[0].contains("\"name\":\"child\""));
+        assert!(requests[1].contains("\"name\":\"root\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! Terminal of a container process attached to the terminal of youki itself.
+//! Used when a process should get a terminal, but the caller didn't pass a
+//! console socket to receive the pty master with.
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use libcontainer::console::{Console, ConsoleSocket};
+use libcontainer::oci_spec::runtime::Spec;
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::isatty;
+
+const CONSOLE_SOCKET: &str = "console.sock";
+/// Time to wait for the remaining output once the process exited. Processes
+/// left in the background may keep the pty open, so we can't wait for EOF.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A console socket in a private directory, which is handed to the container
+/// builder in place of one given by the caller.
+pub struct PrivateConsoleSocket {
+    dir: PathBuf,
+    socket: ConsoleSocket,
+}
+
+impl PrivateConsoleSocket {
+    pub fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "youki-console-{}-{}",
+            std::process::id(),
+            fastrand::u32(..)
+        ));
+        // Only we may connect, otherwise anyone could hand us a terminal
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let socket = match ConsoleSocket::bind(dir.join(CONSOLE_SOCKET)) {
+            Ok(socket) => socket,
+            Err(err) => {
+                let _ = fs::remove_dir(&dir);
+                return Err(err.into());
+            }
+        };
+
+        Ok(Self { dir, socket })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.socket.path().to_owned()
+    }
+
+    /// Receives the pty master sent by the container process.
+    pub fn accept(&self) -> Result<Console> {
+        Ok(self.socket.accept()?)
+    }
+}
+
+impl Drop for PrivateConsoleSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Puts the terminal on stdin into raw mode, so that line editing and signal
+/// generating keys are handled by the terminal of the container. The
+/// previous mode is restored when dropped.
+pub struct RawMode {
+    original: Termios,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<Option<Self>> {
+        let stdin = io::stdin();
+        if !isatty(stdin.as_raw_fd()).unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let original = termios::tcgetattr(stdin.as_fd()).context("failed to get terminal mode")?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(stdin.as_fd(), SetArg::TCSANOW, &raw)
+            .context("failed to set terminal to raw mode")?;
+
+        Ok(Some(Self { original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(err) = termios::tcsetattr(io::stdin().as_fd(), SetArg::TCSANOW, &self.original) {
+            tracing::warn!(?err, "failed to restore terminal mode");
+        }
+    }
+}
+
+/// The pty of a container process, with its I/O relayed to the stdio of youki.
+pub struct Terminal {
+    console: Console,
+    output_done: mpsc::Receiver<()>,
+    _raw_mode: Option<RawMode>,
+}
+
+impl Terminal {
+    /// Starts to relay I/O between stdio and the pty master. The threads
+    /// inherit the signal mask of the caller, which should have blocked the
+    /// signals it wants to handle itself.
+    pub fn attach(console: Console) -> Result<Self> {
+        let raw_mode = RawMode::enable()?;
+
+        let input = console.try_clone()?;
+        thread::spawn(move || {
+            // Stops once stdin is closed or the pty is gone
+            let _ = input.copy_input(&mut io::stdin().lock());
+        });
+
+        let output = console.try_clone()?;
+        let (done_tx, output_done) = mpsc::channel();
+        thread::spawn(move || {
+            if let Err(err) = output.copy_output(&mut io::stdout().lock()) {
+                tracing::debug!(?err, "stopped relaying terminal output");
+            }
+            let _ = done_tx.send(());
+        });
+
+        let terminal = Self {
+            console,
+            output_done,
+            _raw_mode: raw_mode,
+        };
+        terminal.resize();
+
+        Ok(terminal)
+    }
+
+    /// Applies the window size of the terminal of youki to the pty.
+    pub fn resize(&self) {
+        if let Err(err) = self.console.resize_from(io::stdin()) {
+            tracing::debug!(?err, "failed to resize terminal");
+        }
+    }
+
+    /// Waits for the remaining output of the process to be written.
+    pub fn drain(&self) {
+        let _ = self.output_done.recv_timeout(DRAIN_TIMEOUT);
+    }
+}
+
+/// Returns if the process of a bundle asks for a terminal.
+pub fn bundle_wants_terminal(bundle: &Path) -> Result<bool> {
+    let spec = Spec::load(bundle.join("config.json"))
+        .with_context(|| format!("failed to load spec of bundle {}", bundle.display()))?;
+    Ok(spec
+        .process()
+        .as_ref()
+        .and_then(|p| p.terminal())
+        .unwrap_or(false))
+}
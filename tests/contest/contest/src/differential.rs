@@ -0,0 +1,489 @@
+//! Differential mode, which runs the tests against a runtime and a reference
+//! runtime and reports where they behave differently.
+//!
+//! To see how the runtimes behave, contest puts itself in front of them: the
+//! tests run a link to contest named like the runtime, as some tests depend
+//! on the name, which runs the actual runtime and records the outcome of each
+//! call, i.e. its exit code, whether it wrote to stderr and for the state
+//! command the state it returned.
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use test_framework::{Report, TestManager, TestResult, TestRun};
+
+use crate::utils::support::set_runtime_path;
+
+/// Environment variable naming the runtime contest runs in front of
+pub const PROXY_RUNTIME_ENV: &str = "CONTEST_PROXY_RUNTIME";
+/// Environment variable naming the file the runtime calls are recorded in
+const PROXY_TRACE_ENV: &str = "CONTEST_PROXY_TRACE";
+/// Environment variable the tests tell the runtime they run against by
+const RUNTIME_KIND_ENV: &str = "RUNTIME_KIND";
+
+/// Commands whose output is captured. Others, such as create, leave
+/// processes behind which keep stdout and stderr open, so their output
+/// can't be waited for.
+const CAPTURED_COMMANDS: &[&str] = &["state", "start", "kill", "delete", "pause", "resume"];
+
+/// Outcome of a call of the runtime
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct RuntimeCall {
+    command: String,
+    exit_code: i32,
+    /// Whether the runtime wrote to stderr, if its output was captured
+    stderr: Option<bool>,
+    /// The state the runtime returned, without the values which differ
+    /// between runs such as the id or the pid
+    state: Option<String>,
+}
+
+impl std::fmt::Display for RuntimeCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} exited with {}", self.command, self.exit_code)?;
+        if self.stderr == Some(true) {
+            write!(f, ", wrote to stderr")?;
+        }
+        if let Some(state) = &self.state {
+            write!(f, ", returned {state}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the runtime given by the environment with the arguments contest was
+/// called with, recording the outcome. Returns the exit code of the runtime.
+pub fn proxy(runtime: OsString) -> Result<i32> {
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    let command = subcommand(&args);
+    let captured = CAPTURED_COMMANDS.contains(&command.as_str());
+
+    let mut cmd = Command::new(&runtime);
+    cmd.args(&args)
+        .env_remove(PROXY_RUNTIME_ENV)
+        .env_remove(PROXY_TRACE_ENV);
+    if captured {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let output = cmd
+        .spawn()
+        .with_context(|| format!("failed to run runtime {runtime:?}"))?
+        .wait_with_output()
+        .with_context(|| format!("failed to wait for runtime {runtime:?}"))?;
+    let exit_code = output
+        .status
+        .code()
+        .or_else(|| output.status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1);
+
+    let mut state = None;
+    if captured {
+        std::io::stdout().write_all(&output.stdout)?;
+        std::io::stderr().write_all(&output.stderr)?;
+        if command == "state" && output.status.success() {
+            state = serde_json::from_slice(&output.stdout)
+                .ok()
+                .map(|state| normalize_state(&state));
+        }
+    }
+
+    let call = RuntimeCall {
+        command,
+        exit_code,
+        stderr: captured.then_some(!output.stderr.is_empty()),
+        state,
+    };
+    if let Some(trace) = std::env::var_os(PROXY_TRACE_ENV) {
+        let mut line = serde_json::to_vec(&call)?;
+        line.push(b'\n');
+        // a single write to a file opened for appending isn't interleaved
+        // with those of the calls running in parallel
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&trace)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("failed to record runtime call in {trace:?}"))?;
+    }
+
+    Ok(exit_code)
+}
+
+/// The subcommand of a runtime call, i.e. the first argument which isn't a
+/// global option
+fn subcommand(args: &[OsString]) -> String {
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--root" | "--log" | "--log-format" => {
+                args.next();
+            }
+            arg if arg.starts_with('-') => {}
+            arg => return arg.to_owned(),
+        }
+    }
+    String::new()
+}
+
+/// Keeps the parts of a state which are the same for the same behavior
+fn normalize_state(state: &Value) -> String {
+    let mut normalized = serde_json::Map::new();
+    if let Some(status) = state.get("status") {
+        normalized.insert("status".into(), status.clone());
+    }
+    let has_pid = state
+        .get("pid")
+        .and_then(Value::as_i64)
+        .is_some_and(|pid| pid > 0);
+    normalized.insert("pid".into(), has_pid.into());
+    if let Some(annotations) = state.get("annotations").filter(|a| !a.is_null()) {
+        normalized.insert("annotations".into(), annotations.clone());
+    }
+    Value::Object(normalized).to_string()
+}
+
+/// Results of running a test group against one runtime
+struct GroupRun {
+    results: Vec<TestRun>,
+    calls: Vec<RuntimeCall>,
+}
+
+/// A runtime the tests are run against
+struct Runtime {
+    /// Name the runtime is referred to by in the results
+    name: String,
+    /// Kind of the runtime the tests see, i.e. its file name
+    kind: String,
+    path: PathBuf,
+    /// Link to contest the tests run instead of the runtime
+    proxy: PathBuf,
+}
+
+/// Runs tests against a runtime and a reference runtime
+pub struct Differential {
+    runtimes: [Runtime; 2],
+    // holds the links and the recorded calls, until it is dropped
+    _trace_dir: tempfile::TempDir,
+}
+
+impl Differential {
+    pub fn new(runtime: &Path, reference: &Path) -> Result<Self> {
+        let trace_dir = tempfile::tempdir().context("failed to create trace directory")?;
+        let contest = std::env::current_exe().context("failed to find contest executable")?;
+        let prepare = |idx: usize, path: &Path| -> Result<Runtime> {
+            let file_name = path
+                .file_name()
+                .with_context(|| format!("runtime {path:?} has no file name"))?;
+            let proxy = trace_dir.path().join(idx.to_string()).join(file_name);
+            fs::create_dir(proxy.parent().unwrap())?;
+            std::os::unix::fs::symlink(&contest, &proxy)
+                .with_context(|| format!("failed to link {proxy:?} to contest"))?;
+            let kind = file_name.to_string_lossy().into_owned();
+            Ok(Runtime {
+                name: kind.clone(),
+                kind,
+                path: path.to_owned(),
+                proxy,
+            })
+        };
+        let mut runtimes = [prepare(0, runtime)?, prepare(1, reference)?];
+        // e.g. two builds of youki are told apart by their paths
+        if runtimes[0].name == runtimes[1].name {
+            for runtime in &mut runtimes {
+                runtime.name = runtime.path.display().to_string();
+            }
+        }
+
+        Ok(Self {
+            runtimes,
+            _trace_dir: trace_dir,
+        })
+    }
+
+    /// Runs the selected tests against both runtimes. The groups run one after
+    /// the other, so the runtime calls are attributed to the right group, and
+    /// the containers left behind by a runtime are cleaned up by it. A
+    /// test fails if it fails with either runtime, and the runtime calls of
+    /// each group are compared as an extra test named runtime_calls.
+    pub fn run(
+        &self,
+        test_manager: &TestManager,
+        tests: Vec<(&str, Option<Vec<&str>>)>,
+    ) -> Result<Report> {
+        let mut report = Report::default();
+        for (group, tests) in &tests {
+            let Some(runtime) = self.run_group(test_manager, group, tests.as_deref(), 0)? else {
+                eprintln!("Error : Test Group {group} not found, skipping");
+                continue;
+            };
+            let reference = self
+                .run_group(test_manager, group, tests.as_deref(), 1)?
+                .with_context(|| format!("test group {group} not found"))?;
+            test_manager.record(&mut report, group, self.compare(runtime, reference));
+        }
+
+        Ok(report)
+    }
+
+    fn run_group(
+        &self,
+        test_manager: &TestManager,
+        group: &str,
+        tests: Option<&[&str]>,
+        runtime: usize,
+    ) -> Result<Option<GroupRun>> {
+        let runtime = &self.runtimes[runtime];
+        let trace = runtime.proxy.with_file_name(format!("{group}.trace"));
+        // no tests are running at this point, which could read the environment
+        let kind = std::env::var_os(RUNTIME_KIND_ENV);
+        set_runtime_path(&runtime.proxy);
+        std::env::set_var(RUNTIME_KIND_ENV, &runtime.kind);
+        std::env::set_var(PROXY_RUNTIME_ENV, &runtime.path);
+        std::env::set_var(PROXY_TRACE_ENV, &trace);
+        let results = test_manager.run_group(group, tests);
+        test_manager.run_cleanup();
+        std::env::remove_var(PROXY_RUNTIME_ENV);
+        std::env::remove_var(PROXY_TRACE_ENV);
+        match kind {
+            Some(kind) => std::env::set_var(RUNTIME_KIND_ENV, kind),
+            None => std::env::remove_var(RUNTIME_KIND_ENV),
+        }
+
+        let Some(results) = results else {
+            return Ok(None);
+        };
+        let calls = match fs::read_to_string(&trace) {
+            Ok(content) => content
+                .lines()
+                .map(|line| serde_json::from_str(line).context("invalid runtime call"))
+                .collect::<Result<_>>()?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read runtime calls {trace:?}"))
+            }
+        };
+
+        Ok(Some(GroupRun { results, calls }))
+    }
+
+    fn compare(&self, runtime: GroupRun, reference: GroupRun) -> Vec<TestRun> {
+        let (name, reference_name) = (&self.runtimes[0].name, &self.runtimes[1].name);
+        let mut reference_results: BTreeMap<_, _> = reference
+            .results
+            .into_iter()
+            .map(|(test, result, _)| (test, result))
+            .collect();
+
+        let mut compared = Vec::with_capacity(runtime.results.len() + 1);
+        for (test, result, duration) in runtime.results {
+            let outcome = match (result, reference_results.remove(test)) {
+                (_, None) => TestResult::Failed(anyhow!("not run with {reference_name}")),
+                // tests skip themselves where the runtime they run against,
+                // as told by RUNTIME_KIND, is known not to support them, so
+                // there is nothing to compare
+                (TestResult::Skipped, _) | (_, Some(TestResult::Skipped)) => TestResult::Skipped,
+                (TestResult::Passed, Some(TestResult::Passed)) => TestResult::Passed,
+                // failing the same way with both can't be told from failing
+                // differently, so the failure isn't hidden
+                (TestResult::Failed(err), Some(TestResult::Failed(reference_err))) => {
+                    TestResult::Failed(anyhow!(
+                        "failed with both {name}: {err}, and {reference_name}: {reference_err}"
+                    ))
+                }
+                (TestResult::Passed, Some(TestResult::Failed(err))) => TestResult::Failed(anyhow!(
+                    "passed with {name}, but failed with {reference_name}: {err}"
+                )),
+                (TestResult::Failed(err), Some(TestResult::Passed)) => TestResult::Failed(anyhow!(
+                    "failed with {name}, but passed with {reference_name}: {err}"
+                )),
+            };
+            compared.push((test, outcome, duration));
+        }
+        for test in reference_results.into_keys() {
+            compared.push((
+                test,
+                TestResult::Failed(anyhow!("not run with {name}")),
+                Default::default(),
+            ));
+        }
+
+        // tests of a group may run in parallel, and tests poll the state a
+        // number of times depending on timing, so only which outcomes the
+        // calls had is compared, not their order or how often they occurred
+        let calls: BTreeSet<_> = runtime.calls.into_iter().collect();
+        let reference_calls: BTreeSet<_> = reference.calls.into_iter().collect();
+        let differences: Vec<String> = calls
+            .difference(&reference_calls)
+            .map(|call| format!("{call}, only with {name}"))
+            .chain(
+                reference_calls
+                    .difference(&calls)
+                    .map(|call| format!("{call}, only with {reference_name}")),
+            )
+            .collect();
+        let outcome = if differences.is_empty() {
+            TestResult::Passed
+        } else {
+            TestResult::Failed(anyhow!(
+                "runtime calls differ\n\t{}",
+                differences.join("\n\t")
+            ))
+        };
+        compared.push(("runtime_calls", outcome, Default::default()));
+
+        compared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn differential() -> Differential {
+        let runtime = |name: &str| Runtime {
+            name: name.to_owned(),
+            kind: name.to_owned(),
+            path: PathBuf::from("/usr/bin").join(name),
+            proxy: PathBuf::from(name),
+        };
+        Differential {
+            runtimes: [runtime("youki"), runtime("runc")],
+            _trace_dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    fn call(command: &str, exit_code: i32) -> RuntimeCall {
+        RuntimeCall {
+            command: command.to_owned(),
+            exit_code,
+            stderr: Some(exit_code != 0),
+            state: None,
+        }
+    }
+
+    fn failed(message: &str) -> TestResult {
+        TestResult::Failed(anyhow!(message.to_owned()))
+    }
+
+    #[test]
+    fn test_subcommand() {
+        let subcommand = |args: &[&str]| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            subcommand(&args)
+        };
+        assert_eq!(subcommand(&["state", "id"]), "state");
+        assert_eq!(
+            subcommand(&["--root", "/run/youki", "--log", "delete", "kill", "id"]),
+            "kill"
+        );
+        assert_eq!(
+            subcommand(&["--debug", "--systemd-cgroup", "start", "id"]),
+            "start"
+        );
+        assert_eq!(subcommand(&["--root", "/run/youki"]), "");
+    }
+
+    #[test]
+    fn test_normalize_state() {
+        let normalized =
+            |state: Value| -> Value { serde_json::from_str(&normalize_state(&state)).unwrap() };
+        assert_eq!(
+            normalized(serde_json::json!({
+                "ociVersion": "1.0.2",
+                "id": "74f1a4cb3801",
+                "status": "running",
+                "pid": 4422,
+                "bundle": "/tmp/bundle",
+                "annotations": null,
+            })),
+            serde_json::json!({"status": "running", "pid": true})
+        );
+        assert_eq!(
+            normalized(serde_json::json!({
+                "id": "7ad1cf1bd5a3",
+                "status": "stopped",
+                "pid": 0,
+                "annotations": {"org.youki.test": "value"},
+            })),
+            serde_json::json!({
+                "status": "stopped",
+                "pid": false,
+                "annotations": {"org.youki.test": "value"},
+            })
+        );
+        assert_eq!(
+            normalized(serde_json::json!({"status": "created"})),
+            serde_json::json!({"status": "created", "pid": false})
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let runtime = GroupRun {
+            results: vec![
+                ("both_pass", TestResult::Passed, Default::default()),
+                ("both_fail", failed("exit code 1"), Default::default()),
+                ("reference_fails", TestResult::Passed, Default::default()),
+                ("runtime_fails", failed("exit code 2"), Default::default()),
+                ("skipped", TestResult::Skipped, Default::default()),
+                ("runtime_only", TestResult::Passed, Default::default()),
+            ],
+            calls: vec![call("create", 0), call("state", 0), call("state", 0)],
+        };
+        let reference = GroupRun {
+            results: vec![
+                ("both_pass", TestResult::Passed, Default::default()),
+                ("both_fail", failed("exit code 3"), Default::default()),
+                ("reference_fails", failed("exit code 4"), Default::default()),
+                ("runtime_fails", TestResult::Passed, Default::default()),
+                ("skipped", failed("unsupported"), Default::default()),
+                ("reference_only", TestResult::Passed, Default::default()),
+            ],
+            calls: vec![call("create", 0), call("state", 1)],
+        };
+
+        let compared = differential().compare(runtime, reference);
+        let outcome = |test: &str| {
+            let (_, result, _) = compared
+                .iter()
+                .find(|(name, _, _)| *name == test)
+                .unwrap_or_else(|| panic!("{test} not compared"));
+            match result {
+                TestResult::Passed => "passed".to_owned(),
+                TestResult::Skipped => "skipped".to_owned(),
+                TestResult::Failed(err) => err.to_string(),
+            }
+        };
+
+        assert_eq!(compared.len(), 8);
+        assert_eq!(outcome("both_pass"), "passed");
+        assert_eq!(
+            outcome("both_fail"),
+            "failed with both youki: exit code 1, and runc: exit code 3"
+        );
+        assert_eq!(
+            outcome("reference_fails"),
+            "passed with youki, but failed with runc: exit code 4"
+        );
+        assert_eq!(
+            outcome("runtime_fails"),
+            "failed with youki, but passed with runc: exit code 2"
+        );
+        assert_eq!(outcome("skipped"), "skipped");
+        assert_eq!(outcome("runtime_only"), "not run with runc");
+        assert_eq!(outcome("reference_only"), "not run with youki");
+        // the repeated state call doesn't count, the different exit code does
+        assert_eq!(
+            outcome("runtime_calls"),
+            "runtime calls differ\n\tstate exited with 0, only with youki\n\t\
+            state exited with 1, wrote to stderr, only with runc"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod differential;
 mod tests;
 mod utils;
 
@@ -6,9 +7,10 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use clap::Parser;
 use contest::logger;
-use test_framework::TestManager;
+use test_framework::{ReportFormat, TestManager};
 use tests::cgroups;
 
+use crate::differential::Differential;
 use crate::tests::delete::get_delete_test;
 use crate::tests::devices::get_devices_test;
 use crate::tests::domainname::get_domainname_tests;
@@ -19,13 +21,17 @@ use crate::tests::hostname::get_hostname_test;
 use crate::tests::intel_rdt::get_intel_rdt_test;
 use crate::tests::io_priority::get_io_priority_test;
 use crate::tests::kill::get_kill_test;
+use crate::tests::kill_no_effect::get_kill_no_effect_test;
 use crate::tests::lifecycle::{ContainerCreate, ContainerLifecycle};
 use crate::tests::linux_masked_paths::get_linux_masked_paths_tests;
 use crate::tests::linux_ns_itype::get_ns_itype_tests;
+use crate::tests::linux_uid_mappings::get_linux_uid_mappings_test;
+use crate::tests::misc_props::get_misc_props_test;
 use crate::tests::mounts_recursive::get_mounts_recursive_test;
 use crate::tests::no_pivot::get_no_pivot_test;
 use crate::tests::pidfile::get_pidfile_test;
 use crate::tests::process::get_process_test;
+use crate::tests::process_capabilities::get_process_capabilities_test;
 use crate::tests::process_capabilities_fail::get_process_capabilities_fail_test;
 use crate::tests::process_oom_score_adj::get_process_oom_score_adj_test;
 use crate::tests::process_rlimits::get_process_rlimits_test;
@@ -37,6 +43,7 @@ use crate::tests::rootfs_propagation::get_rootfs_propagation_test;
 use crate::tests::scheduler::get_scheduler_test;
 use crate::tests::seccomp::get_seccomp_test;
 use crate::tests::seccomp_notify::get_seccomp_notify_test;
+use crate::tests::state::get_state_test;
 use crate::tests::sysctl::get_sysctl_test;
 use crate::tests::tlb::get_tlb_test;
 use crate::utils::support::{set_runtime_path, set_runtimetest_path};
@@ -73,6 +80,16 @@ struct Run {
     /// -t group1::test1,test3 group2 group3::test5
     #[clap(short, long, num_args(1..), value_delimiter = ' ')]
     tests: Option<Vec<String>>,
+    /// Write a report of the results, format should be one of
+    /// junit, tap or json, eg
+    /// --report junit results.xml
+    #[clap(long, num_args(2), value_names(["FORMAT", "PATH"]))]
+    report: Option<Vec<String>>,
+    /// Path for a reference runtime. If given, the tests are run against both
+    /// runtimes, and differences in their outcomes and in the exit codes,
+    /// errors and states of the runtime calls are reported instead
+    #[clap(long)]
+    reference_runtime: Option<PathBuf>,
 }
 
 // parse test string given in commandline option as pair of testgroup name and tests belonging to that
@@ -91,6 +108,11 @@ fn parse_tests(tests: &[String]) -> Vec<(&str, Option<Vec<&str>>)> {
 }
 
 fn main() -> Result<()> {
+    // in differential mode, the tests run contest in place of the runtimes
+    if let Some(runtime) = std::env::var_os(differential::PROXY_RUNTIME_ENV) {
+        std::process::exit(differential::proxy(runtime)?);
+    }
+
     let opts: Opts = Opts::parse();
 
     if let Err(e) = logger::init(opts.debug) {
@@ -140,6 +162,11 @@ fn main() -> Result<()> {
     let masked_paths = get_linux_masked_paths_tests();
     let rootfs_propagation = get_rootfs_propagation_test();
     let process_capabilities_fail = get_process_capabilities_fail_test();
+    let process_capabilities = get_process_capabilities_test();
+    let linux_uid_mappings = get_linux_uid_mappings_test();
+    let state = get_state_test();
+    let kill_no_effect = get_kill_no_effect_test();
+    let misc_props = get_misc_props_test();
 
     tm.add_test_group(Box::new(cl));
     tm.add_test_group(Box::new(cc));
@@ -177,6 +204,11 @@ fn main() -> Result<()> {
     tm.add_test_group(Box::new(kill));
     tm.add_test_group(Box::new(rootfs_propagation));
     tm.add_test_group(Box::new(process_capabilities_fail));
+    tm.add_test_group(Box::new(process_capabilities));
+    tm.add_test_group(Box::new(linux_uid_mappings));
+    tm.add_test_group(Box::new(state));
+    tm.add_test_group(Box::new(kill_no_effect));
+    tm.add_test_group(Box::new(misc_props));
 
     tm.add_test_group(Box::new(io_priority_test));
     tm.add_cleanup(Box::new(cgroups::cleanup_v1));
@@ -212,11 +244,29 @@ fn run(opts: Run, test_manager: &TestManager) -> Result<()> {
     let runtimetest_path = get_abs_path(&opts.runtimetest);
     set_runtimetest_path(&runtimetest_path);
 
-    if let Some(tests) = opts.tests {
+    // the format is checked before running the tests, which takes a while
+    let report_to = match opts.report.as_deref() {
+        Some([format, path]) => Some((format.parse::<ReportFormat>()?, PathBuf::from(path))),
+        _ => None,
+    };
+
+    let report = if let Some(reference) = opts.reference_runtime {
+        let differential = Differential::new(&runtime_path, &get_abs_path(&reference))?;
+        let groups = test_manager.tests_groups();
+        let tests_to_run = match &opts.tests {
+            Some(tests) => parse_tests(tests),
+            None => groups.iter().map(|group| (group.as_str(), None)).collect(),
+        };
+        differential.run(test_manager, tests_to_run)?
+    } else if let Some(tests) = opts.tests {
         let tests_to_run = parse_tests(&tests);
-        test_manager.run_selected(tests_to_run);
+        test_manager.run_selected(tests_to_run)
     } else {
-        test_manager.run_all();
+        test_manager.run_all()
+    };
+
+    if let Some((format, path)) = report_to {
+        report.write(format, &path)?;
     }
 
     Ok(())
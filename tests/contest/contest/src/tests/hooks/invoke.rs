@@ -1,11 +1,12 @@
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context, Result};
 use oci_spec::runtime::{Hook, HookBuilder, HooksBuilder, ProcessBuilder, Spec, SpecBuilder};
 use test_framework::{Test, TestGroup, TestResult};
 
-use crate::utils::test_utils::{start_container, CreateOptions};
+use crate::utils::test_utils::{start_container, CreateOptions, State};
 use crate::utils::{create_container, delete_container, generate_uuid, prepare_bundle, set_config};
 
 const HOOK_OUTPUT_FILE: &str = "output";
@@ -96,8 +97,98 @@ fn get_test(test_name: &'static str) -> Test {
     )
 }
 
+// Appends the state the hook gets on its stdin to `output`, one per line
+fn save_state_hook(output: &Path) -> Hook {
+    let output = output.to_str().unwrap();
+    HookBuilder::default()
+        .path("/bin/sh")
+        .args(vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("cat >> {output}; echo >> {output}"),
+        ])
+        .build()
+        .expect("could not build hook")
+}
+
+fn read_states(output: &Path) -> Result<Vec<State>> {
+    let content = std::fs::read_to_string(output).context("cannot read hook output")?;
+    content
+        .lines()
+        .map(|line| serde_json::from_str(line).with_context(|| format!("invalid state {line:?}")))
+        .collect()
+}
+
+fn check_hook_state(hook: &str, state: &State, id: &str, status: &str) -> Result<()> {
+    if state.id != id {
+        bail!("{hook} hook got the state of {}, expected {id}", state.id);
+    }
+    if state.status != status {
+        bail!("{hook} hook got status {}, expected {status}", state.status);
+    }
+    Ok(())
+}
+
+// The poststart and poststop hooks get the state of the container, and the
+// poststop hooks only run once the container is deleted.
+fn hooks_state_test() -> TestResult {
+    let id = generate_uuid().to_string();
+    let bundle = prepare_bundle().unwrap();
+    let poststart = bundle.path().join("poststart");
+    let poststop = bundle.path().join("poststop");
+    let mut spec = get_spec();
+    spec.set_hooks(Some(
+        HooksBuilder::default()
+            .poststart(vec![save_state_hook(&poststart)])
+            .poststop(vec![save_state_hook(&poststop)])
+            .build()
+            .expect("could not build hooks"),
+    ));
+    set_config(&bundle, &spec).unwrap();
+
+    create_container(&id, &bundle, &CreateOptions::default())
+        .unwrap()
+        .wait()
+        .unwrap();
+    start_container(&id, &bundle).unwrap().wait().unwrap();
+    let result = (|| {
+        let states = read_states(&poststart)?;
+        match states.as_slice() {
+            [state] => check_hook_state("poststart", state, &id, "running")?,
+            _ => bail!("poststart hook ran {} times, expected once", states.len()),
+        }
+        if poststop.exists() {
+            bail!("poststop hook ran before the container was deleted");
+        }
+        Ok(())
+    })();
+    delete_container(&id, &bundle).unwrap().wait().unwrap();
+    if let Err(err) = result {
+        return TestResult::Failed(err);
+    }
+
+    let states = match read_states(&poststop) {
+        Ok(states) => states,
+        Err(err) => return TestResult::Failed(err),
+    };
+    let result = match states.as_slice() {
+        [state] => check_hook_state("poststop", state, &id, "stopped"),
+        _ => Err(anyhow!(
+            "poststop hook ran {} times, expected once",
+            states.len()
+        )),
+    };
+    match result {
+        Ok(()) => TestResult::Passed,
+        Err(err) => TestResult::Failed(err),
+    }
+}
+
 pub fn get_hooks_tests() -> TestGroup {
     let mut tg = TestGroup::new("hooks");
-    tg.add(vec![Box::new(get_test("hooks"))]);
+    tg.add(vec![
+        Box::new(get_test("hooks")),
+        Box::new(Test::new("hooks_state", Box::new(hooks_state_test))),
+    ]);
     tg
 }
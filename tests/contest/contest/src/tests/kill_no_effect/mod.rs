@@ -0,0 +1,42 @@
+use anyhow::anyhow;
+use test_framework::{test_result, Test, TestGroup, TestResult};
+
+use crate::tests::lifecycle::ContainerLifecycle;
+use crate::utils::test_utils::spec_with_args;
+
+// Killing a stopped container must fail and must not change its state.
+fn kill_no_effect_test() -> TestResult {
+    let spec = test_result!(spec_with_args(&["true"]));
+    let container = ContainerLifecycle::new();
+    if let TestResult::Failed(err) = container.run_until_stopped(spec) {
+        return TestResult::Failed(err);
+    }
+
+    let result = match container.kill() {
+        TestResult::Failed(_) => match container.status() {
+            Ok(status) if status == "stopped" => TestResult::Passed,
+            Ok(status) => TestResult::Failed(anyhow!(
+                "expected container to stay stopped after kill, but it is {status}"
+            )),
+            Err(err) => TestResult::Failed(err.context("failed to get state after kill")),
+        },
+        _ => TestResult::Failed(anyhow!(
+            "expected killing stopped container to fail, but it succeeded"
+        )),
+    };
+
+    match container.delete() {
+        TestResult::Failed(err) if matches!(result, TestResult::Passed) => {
+            TestResult::Failed(err.context("failed to delete container"))
+        }
+        _ => result,
+    }
+}
+
+pub fn get_kill_no_effect_test() -> TestGroup {
+    let mut test_group = TestGroup::new("kill_no_effect");
+    let test = Test::new("kill_no_effect_test", Box::new(kill_no_effect_test));
+    test_group.add(vec![Box::new(test)]);
+
+    test_group
+}
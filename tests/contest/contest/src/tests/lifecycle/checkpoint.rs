@@ -114,7 +114,7 @@ fn checkpoint(
 
     let runtime_path = get_runtime_path();
 
-    let checkpoint = Command::new(runtime_path)
+    let checkpoint = Command::new(&runtime_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .arg("--root")
@@ -1,5 +1,5 @@
 use tempfile::TempDir;
-use test_framework::{TestResult, TestableGroup};
+use test_framework::{timed, TestResult, TestRun, TestableGroup};
 
 use super::{create, delete, kill};
 use crate::utils::{generate_uuid, prepare_bundle};
@@ -86,21 +86,21 @@ impl TestableGroup for ContainerCreate {
         true
     }
 
-    fn run_all(&self) -> Vec<(&'static str, TestResult)> {
+    fn run_all(&self) -> Vec<TestRun> {
         vec![
-            ("empty_id", self.create_empty_id()),
-            ("valid_id", self.create_valid_id()),
-            ("duplicate_id", self.create_duplicate_id()),
+            timed("empty_id", || self.create_empty_id()),
+            timed("valid_id", || self.create_valid_id()),
+            timed("duplicate_id", || self.create_duplicate_id()),
         ]
     }
 
-    fn run_selected(&self, selected: &[&str]) -> Vec<(&'static str, TestResult)> {
+    fn run_selected(&self, selected: &[&str]) -> Vec<TestRun> {
         let mut ret = Vec::new();
         for name in selected {
             match *name {
-                "empty_id" => ret.push(("empty_id", self.create_empty_id())),
-                "valid_id" => ret.push(("valid_id", self.create_valid_id())),
-                "duplicate_id" => ret.push(("duplicate_id", self.create_duplicate_id())),
+                "empty_id" => ret.push(timed("empty_id", || self.create_empty_id())),
+                "valid_id" => ret.push(timed("valid_id", || self.create_valid_id())),
+                "duplicate_id" => ret.push(timed("duplicate_id", || self.create_duplicate_id())),
                 _ => eprintln!("No test named {name} in lifecycle"),
             };
         }
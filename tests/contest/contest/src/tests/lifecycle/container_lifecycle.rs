@@ -2,7 +2,7 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use oci_spec::runtime::Spec;
-use test_framework::{TestResult, TestableGroup};
+use test_framework::{timed, TestResult, TestRun, TestableGroup};
 
 use super::util::criu_installed;
 use super::{checkpoint, create, delete, exec, kill, start, state};
@@ -66,6 +66,11 @@ impl ContainerLifecycle {
         state::state(self.project_path.path(), &self.container_id).into()
     }
 
+    /// Returns the status of the container, as parsed from its state
+    pub fn status(&self) -> anyhow::Result<String> {
+        state::get_container_status(self.project_path.path(), &self.container_id)
+    }
+
     pub fn kill(&self) -> TestResult {
         let ret = kill::kill(self.project_path.path(), &self.container_id);
         // sleep a little, so the youki process actually gets the signal and shuts down
@@ -97,6 +102,23 @@ impl ContainerLifecycle {
         )
     }
 
+    /// Creates and starts the container with the spec and waits for its
+    /// process to exit. The container is deleted if any step fails.
+    pub fn run_until_stopped(&self, spec: Spec) -> TestResult {
+        if let TestResult::Failed(err) = self.create_with_spec(spec) {
+            return TestResult::Failed(err.context("failed to create container"));
+        }
+        if let TestResult::Failed(err) = self.start() {
+            let _ = self.delete();
+            return TestResult::Failed(err.context("failed to start container"));
+        }
+        if let TestResult::Failed(err) = self.wait_for_state("stopped", Duration::from_secs(5)) {
+            let _ = self.delete();
+            return TestResult::Failed(err);
+        }
+        TestResult::Passed
+    }
+
     /// Wait for the container to reach a specific state
     pub fn wait_for_state(&self, expected_state: &str, timeout: Duration) -> TestResult {
         use crate::tests::lifecycle::state;
@@ -127,42 +149,40 @@ impl TestableGroup for ContainerLifecycle {
         true
     }
 
-    fn run_all(&self) -> Vec<(&'static str, TestResult)> {
+    fn run_all(&self) -> Vec<TestRun> {
         vec![
-            ("create", self.create()),
-            ("start", self.start()),
+            timed("create", || self.create()),
+            timed("start", || self.start()),
             // ("exec", self.exec(vec!["echo", "Hello"], Some("Hello\n"))),
-            (
-                "checkpoint and leave running with --work-path /tmp",
-                self.checkpoint_leave_running_work_path_tmp(),
-            ),
-            (
-                "checkpoint and leave running",
-                self.checkpoint_leave_running(),
-            ),
-            ("kill", self.kill()),
-            ("state", self.state()),
-            ("delete", self.delete()),
+            timed("checkpoint and leave running with --work-path /tmp", || {
+                self.checkpoint_leave_running_work_path_tmp()
+            }),
+            timed("checkpoint and leave running", || {
+                self.checkpoint_leave_running()
+            }),
+            timed("kill", || self.kill()),
+            timed("state", || self.state()),
+            timed("delete", || self.delete()),
         ]
     }
 
-    fn run_selected(&self, selected: &[&str]) -> Vec<(&'static str, TestResult)> {
+    fn run_selected(&self, selected: &[&str]) -> Vec<TestRun> {
         let mut ret = Vec::new();
         for name in selected {
             match *name {
-                "create" => ret.push(("create", self.create())),
-                "start" => ret.push(("start", self.start())),
-                "checkpoint_leave_running_work_path_tmp" => ret.push((
+                "create" => ret.push(timed("create", || self.create())),
+                "start" => ret.push(timed("start", || self.start())),
+                "checkpoint_leave_running_work_path_tmp" => ret.push(timed(
                     "checkpoint and leave running with --work-path /tmp",
-                    self.checkpoint_leave_running_work_path_tmp(),
-                )),
-                "checkpoint_leave_running" => ret.push((
-                    "checkpoint and leave running",
-                    self.checkpoint_leave_running(),
+                    || self.checkpoint_leave_running_work_path_tmp(),
                 )),
-                "kill" => ret.push(("kill", self.kill())),
-                "state" => ret.push(("state", self.state())),
-                "delete" => ret.push(("delete", self.delete())),
+                "checkpoint_leave_running" => ret
+                    .push(timed("checkpoint and leave running", || {
+                        self.checkpoint_leave_running()
+                    })),
+                "kill" => ret.push(timed("kill", || self.kill())),
+                "state" => ret.push(timed("state", || self.state())),
+                "delete" => ret.push(timed("delete", || self.delete())),
                 _ => eprintln!("No test named {name} in lifecycle"),
             };
         }
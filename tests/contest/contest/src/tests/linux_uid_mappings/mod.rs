@@ -0,0 +1,70 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use oci_spec::runtime::{
+    LinuxIdMappingBuilder, LinuxNamespaceBuilder, LinuxNamespaceType, ProcessBuilder, Spec,
+};
+use test_framework::{test_result, ConditionalTest, TestGroup, TestResult};
+
+use crate::utils::test_inside_container;
+use crate::utils::test_utils::CreateOptions;
+
+fn create_spec() -> Result<Spec> {
+    let mut spec = Spec::default();
+    let linux = spec.linux_mut().as_mut().context("no linux in spec")?;
+
+    let mut namespaces = linux.namespaces().clone().unwrap_or_default();
+    namespaces.push(
+        LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::User)
+            .build()?,
+    );
+    linux.set_namespaces(Some(namespaces));
+    linux.set_uid_mappings(Some(vec![LinuxIdMappingBuilder::default()
+        .host_id(1000u32)
+        .container_id(0u32)
+        .size(2000u32)
+        .build()?]));
+    linux.set_gid_mappings(Some(vec![LinuxIdMappingBuilder::default()
+        .host_id(1000u32)
+        .container_id(0u32)
+        .size(3000u32)
+        .build()?]));
+
+    spec.set_process(Some(
+        ProcessBuilder::default()
+            .args(vec!["runtimetest".to_string(), "uid_mappings".to_string()])
+            .build()?,
+    ));
+    Ok(spec)
+}
+
+fn can_run() -> bool {
+    Path::new("/proc/self/ns/user").exists() && nix::unistd::geteuid().is_root()
+}
+
+fn uid_mappings_test() -> TestResult {
+    let spec = test_result!(create_spec());
+    test_inside_container(&spec, &CreateOptions::default(), &|rootfs| {
+        // The root of the container is an unprivileged user on the host, which
+        // must be able to reach the rootfs in the temporary directories
+        for dir in rootfs.ancestors().skip(1).take(2) {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    })
+}
+
+pub fn get_linux_uid_mappings_test() -> TestGroup {
+    let mut test_group = TestGroup::new("linux_uid_mappings");
+    let test = ConditionalTest::new(
+        "uid_mappings_test",
+        Box::new(can_run),
+        Box::new(uid_mappings_test),
+    );
+    test_group.add(vec![Box::new(test)]);
+
+    test_group
+}
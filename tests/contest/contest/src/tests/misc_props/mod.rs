@@ -0,0 +1,68 @@
+use std::fs;
+
+use oci_spec::runtime::{RootBuilder, Spec};
+use test_framework::{test_result, Test, TestGroup, TestResult};
+
+use crate::tests::lifecycle::ContainerLifecycle;
+use crate::utils::test_inside_container;
+use crate::utils::test_utils::{spec_with_args, CreateOptions};
+
+// Runs a container to completion and deletes it.
+fn run_to_completion(container: &ContainerLifecycle, spec: Spec) -> TestResult {
+    if let TestResult::Failed(err) = container.run_until_stopped(spec) {
+        return TestResult::Failed(err);
+    }
+    match container.delete() {
+        TestResult::Failed(err) => TestResult::Failed(err.context("failed to delete container")),
+        result => result,
+    }
+}
+
+// The id of a deleted container can be used again.
+fn reuse_id_test() -> TestResult {
+    let container = ContainerLifecycle::new();
+    for _ in 0..2 {
+        let spec = test_result!(spec_with_args(&["true"]));
+        if let TestResult::Failed(err) = run_to_completion(&container, spec) {
+            return TestResult::Failed(err);
+        }
+    }
+    TestResult::Passed
+}
+
+// A container whose process fails stops, and can be deleted as usual.
+fn failing_process_test() -> TestResult {
+    let spec = test_result!(spec_with_args(&["false"]));
+    run_to_completion(&ContainerLifecycle::new(), spec)
+}
+
+// The root of the bundle can be given as an absolute path.
+fn absolute_root_path_test() -> TestResult {
+    let spec = test_result!(spec_with_args(&["runtimetest", "hello_world"]));
+    test_inside_container(&spec, &CreateOptions::default(), &|rootfs| {
+        let config_path = rootfs.join("../config.json");
+        let mut spec = Spec::load(&config_path)?;
+        spec.set_root(Some(
+            RootBuilder::default()
+                .path(fs::canonicalize(rootfs)?)
+                .build()?,
+        ));
+        spec.save(&config_path)?;
+        Ok(())
+    })
+}
+
+pub fn get_misc_props_test() -> TestGroup {
+    let mut test_group = TestGroup::new("misc_props");
+    let reuse_id_test = Test::new("reuse_id_test", Box::new(reuse_id_test));
+    let failing_process_test = Test::new("failing_process_test", Box::new(failing_process_test));
+    let absolute_root_path_test =
+        Test::new("absolute_root_path_test", Box::new(absolute_root_path_test));
+    test_group.add(vec![
+        Box::new(reuse_id_test),
+        Box::new(failing_process_test),
+        Box::new(absolute_root_path_test),
+    ]);
+
+    test_group
+}
@@ -9,13 +9,17 @@ pub mod hostname;
 pub mod intel_rdt;
 pub mod io_priority;
 pub mod kill;
+pub mod kill_no_effect;
 pub mod lifecycle;
 pub mod linux_masked_paths;
 pub mod linux_ns_itype;
+pub mod linux_uid_mappings;
+pub mod misc_props;
 pub mod mounts_recursive;
 pub mod no_pivot;
 pub mod pidfile;
 pub mod process;
+pub mod process_capabilities;
 pub mod process_capabilities_fail;
 pub mod process_oom_score_adj;
 pub mod process_rlimits;
@@ -27,5 +31,6 @@ pub mod rootfs_propagation;
 pub mod scheduler;
 pub mod seccomp;
 pub mod seccomp_notify;
+pub mod state;
 pub mod sysctl;
 pub mod tlb;
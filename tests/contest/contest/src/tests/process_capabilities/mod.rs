@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use oci_spec::runtime::{
+    Capabilities, Capability, LinuxCapabilitiesBuilder, ProcessBuilder, Spec, SpecBuilder,
+};
+use test_framework::{test_result, Test, TestGroup, TestResult};
+
+use crate::utils::test_inside_container;
+use crate::utils::test_utils::CreateOptions;
+
+fn create_spec() -> Result<Spec> {
+    // The permitted capabilities of root after exec are those of the bounding
+    // set, so the sets are the same
+    let capabilities: Capabilities = HashSet::from([
+        Capability::Chown,
+        Capability::Kill,
+        Capability::NetBindService,
+        Capability::Setuid,
+    ]);
+    let process = ProcessBuilder::default()
+        .args(vec![
+            "runtimetest".to_string(),
+            "process_capabilities".to_string(),
+        ])
+        .capabilities(
+            LinuxCapabilitiesBuilder::default()
+                .bounding(capabilities.clone())
+                .effective(capabilities.clone())
+                .permitted(capabilities)
+                .inheritable(Capabilities::new())
+                .ambient(Capabilities::new())
+                .build()?,
+        )
+        .build()
+        .context("failed to build process spec")?;
+
+    SpecBuilder::default()
+        .process(process)
+        .build()
+        .context("failed to build spec")
+}
+
+fn process_capabilities_test() -> TestResult {
+    let spec = test_result!(create_spec());
+    test_inside_container(&spec, &CreateOptions::default(), &|_| Ok(()))
+}
+
+pub fn get_process_capabilities_test() -> TestGroup {
+    let mut test_group = TestGroup::new("process_capabilities");
+    let test = Test::new(
+        "process_capabilities_test",
+        Box::new(process_capabilities_test),
+    );
+    test_group.add(vec![Box::new(test)]);
+
+    test_group
+}
@@ -0,0 +1,92 @@
+use anyhow::{anyhow, bail, Result};
+use test_framework::{test_result, Test, TestGroup, TestResult};
+
+use crate::tests::lifecycle::ContainerLifecycle;
+use crate::utils::test_utils::spec_with_args;
+use crate::utils::{generate_uuid, get_state, prepare_bundle, test_outside_container, State};
+
+// The state of a container which doesn't exist can't be queried.
+fn state_non_existent_test() -> TestResult {
+    let bundle = test_result!(prepare_bundle());
+    let (stdout, stderr) = test_result!(get_state(&generate_uuid().to_string(), &bundle));
+    if stderr.is_empty() || serde_json::from_str::<State>(&stdout).is_ok() {
+        return TestResult::Failed(anyhow!(
+            "expected state of non existent container to fail, got : {stdout}"
+        ));
+    }
+    TestResult::Passed
+}
+
+// A created container has all required properties in its state.
+fn state_created_test() -> TestResult {
+    let spec = test_result!(spec_with_args(&["sleep", "30"]));
+    test_outside_container(&spec, &|data| {
+        let Some(state) = data.state else {
+            return TestResult::Failed(anyhow!(
+                "failed to get state of created container : {}",
+                data.state_err
+            ));
+        };
+        check_state(&state, &data.id, "created").into()
+    })
+}
+
+fn check_state(state: &State, id: &str, status: &str) -> Result<()> {
+    if state.oci_version.is_empty() {
+        bail!("state has no ociVersion : {state:?}");
+    }
+    if state.id != id {
+        bail!("state has id {}, expected {id}", state.id);
+    }
+    if state.status != status {
+        bail!("state has status {}, expected {status}", state.status);
+    }
+    if status != "stopped" && state.pid.is_none_or(|pid| pid <= 0) {
+        bail!("state of {status} container has no pid : {state:?}");
+    }
+    if !state.bundle.is_absolute() {
+        bail!(
+            "state has bundle {:?}, expected absolute path",
+            state.bundle
+        );
+    }
+    Ok(())
+}
+
+// The state of a container follows its lifecycle until it is deleted.
+fn state_lifecycle_test() -> TestResult {
+    let spec = test_result!(spec_with_args(&["true"]));
+    let container = ContainerLifecycle::new();
+
+    if let TestResult::Failed(err) = container.run_until_stopped(spec) {
+        return TestResult::Failed(err);
+    }
+    if let TestResult::Failed(err) = container.state() {
+        let _ = container.delete();
+        return TestResult::Failed(err);
+    }
+    if let TestResult::Failed(err) = container.delete() {
+        return TestResult::Failed(err.context("failed to delete container"));
+    }
+
+    // after delete, the container doesn't exist anymore
+    match container.state() {
+        TestResult::Failed(_) => TestResult::Passed,
+        _ => TestResult::Failed(anyhow!("state of deleted container succeeded")),
+    }
+}
+
+pub fn get_state_test() -> TestGroup {
+    let mut test_group = TestGroup::new("state");
+    let state_non_existent_test =
+        Test::new("state_non_existent_test", Box::new(state_non_existent_test));
+    let state_created_test = Test::new("state_created_test", Box::new(state_created_test));
+    let state_lifecycle_test = Test::new("state_lifecycle_test", Box::new(state_lifecycle_test));
+    test_group.add(vec![
+        Box::new(state_non_existent_test),
+        Box::new(state_created_test),
+        Box::new(state_lifecycle_test),
+    ]);
+
+    test_group
+}
@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+use std::io::Read;
 
+use anyhow::{anyhow, Context, Result};
 use oci_spec::runtime::{LinuxBuilder, ProcessBuilder, Spec, SpecBuilder};
-use test_framework::{Test, TestGroup, TestResult};
+use test_framework::{test_result, Test, TestGroup, TestResult};
 
-use crate::utils::test_inside_container;
 use crate::utils::test_utils::CreateOptions;
+use crate::utils::{
+    create_container, delete_container, generate_uuid, kill_container, prepare_bundle, set_config,
+    test_inside_container,
+};
 
 fn create_spec(sysctl: HashMap<String, String>) -> Spec {
     SpecBuilder::default()
@@ -36,10 +41,86 @@ fn sysctl_test() -> TestResult {
     })
 }
 
+// Unlike create_spec, this keeps the namespaces of the default spec, so the
+// parameters set are those of the container's own namespaces.
+fn create_namespaced_spec(sysctl: HashMap<String, String>) -> Result<Spec> {
+    let mut spec = Spec::default();
+    let mut linux = spec.linux().clone().context("default spec has no linux")?;
+    linux.set_sysctl(Some(sysctl));
+    spec.set_linux(Some(linux));
+    spec.set_process(Some(
+        ProcessBuilder::default()
+            .args(vec!["runtimetest".to_string(), "sysctl".to_string()])
+            .build()?,
+    ));
+    Ok(spec)
+}
+
+fn sysctl_multiple_test() -> TestResult {
+    let spec = test_result!(create_namespaced_spec(HashMap::from([
+        ("net.ipv4.ip_forward".to_string(), "1".to_string()),
+        ("net.ipv4.tcp_syncookies".to_string(), "0".to_string()),
+        ("kernel.shmmni".to_string(), "8192".to_string()),
+        ("kernel.msgmax".to_string(), "16384".to_string()),
+    ])));
+    test_inside_container(&spec, &CreateOptions::default(), &|_| Ok(()))
+}
+
+// A kernel parameter which doesn't exist can't be set, so the container must
+// not be created, and the error must name the parameter.
+fn sysctl_invalid_key_test() -> TestResult {
+    const KEY: &str = "kernel.youki_contest_nonexistent";
+    let spec = test_result!(create_namespaced_spec(HashMap::from([(
+        KEY.to_string(),
+        "1".to_string(),
+    )])));
+    let id = generate_uuid().to_string();
+    let bundle = test_result!(prepare_bundle());
+    test_result!(set_config(&bundle, &spec));
+
+    let mut create = test_result!(create_container(&id, &bundle, &CreateOptions::default()));
+    let status = test_result!(create.wait().context("failed to wait for create"));
+    let result = if status.success() {
+        TestResult::Failed(anyhow!(
+            "expected a container with an unknown kernel parameter not to be created, but it was"
+        ))
+    } else {
+        // the processes of a failed create are gone, so nothing keeps the
+        // pipe open
+        let mut stderr = String::new();
+        if let Some(mut pipe) = create.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        // the runtime may name the parameter by its path in /proc/sys
+        if stderr.contains(KEY) || stderr.contains(&KEY.replace('.', "/")) {
+            TestResult::Passed
+        } else {
+            TestResult::Failed(anyhow!(
+                "expected create to fail because of {KEY}, but it failed with : {stderr}"
+            ))
+        }
+    };
+
+    if let Ok(mut kill) = kill_container(&id, &bundle) {
+        let _ = kill.wait();
+    }
+    if let Ok(mut delete) = delete_container(&id, &bundle) {
+        let _ = delete.wait();
+    }
+    result
+}
+
 pub fn get_sysctl_test() -> TestGroup {
     let mut test_group = TestGroup::new("sysctl");
     let sysctl_test = Test::new("sysctl_test", Box::new(sysctl_test));
-    test_group.add(vec![Box::new(sysctl_test)]);
+    let sysctl_multiple_test = Test::new("sysctl_multiple_test", Box::new(sysctl_multiple_test));
+    let sysctl_invalid_key_test =
+        Test::new("sysctl_invalid_key_test", Box::new(sysctl_invalid_key_test));
+    test_group.add(vec![
+        Box::new(sysctl_test),
+        Box::new(sysctl_multiple_test),
+        Box::new(sysctl_invalid_key_test),
+    ]);
 
     test_group
 }
@@ -1,6 +1,7 @@
 use std::env;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
 use anyhow::{Context, Result};
 use flate2::read::GzDecoder;
@@ -11,15 +12,20 @@ use tar::Archive;
 use tempfile::TempDir;
 use uuid::Uuid;
 
-static RUNTIME_PATH: OnceCell<PathBuf> = OnceCell::new();
+// the differential mode replaces the runtime between runs of the tests
+static RUNTIME_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
 static RUNTIMETEST_PATH: OnceCell<PathBuf> = OnceCell::new();
 
 pub fn set_runtime_path(path: &Path) {
-    RUNTIME_PATH.set(path.to_owned()).unwrap();
+    *RUNTIME_PATH.write().unwrap() = Some(path.to_owned());
 }
 
-pub fn get_runtime_path() -> &'static PathBuf {
-    RUNTIME_PATH.get().expect("Runtime path is not set")
+pub fn get_runtime_path() -> PathBuf {
+    RUNTIME_PATH
+        .read()
+        .unwrap()
+        .clone()
+        .expect("Runtime path is not set")
 }
 
 pub fn set_runtimetest_path(path: &Path) {
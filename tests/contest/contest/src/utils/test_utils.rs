@@ -8,7 +8,7 @@ use std::thread::sleep;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
-use oci_spec::runtime::Spec;
+use oci_spec::runtime::{ProcessBuilder, Spec, SpecBuilder};
 use serde::{Deserialize, Serialize};
 use test_framework::{test_result, TestResult};
 
@@ -61,6 +61,19 @@ impl<'a> CreateOptions<'a> {
     }
 }
 
+/// Creates a spec whose process runs the given arguments
+pub fn spec_with_args(args: &[&str]) -> Result<Spec> {
+    SpecBuilder::default()
+        .process(
+            ProcessBuilder::default()
+                .args(args.iter().map(|&a| a.into()).collect::<Vec<String>>())
+                .build()
+                .context("failed to build process spec")?,
+        )
+        .build()
+        .context("failed to build spec")
+}
+
 fn create_container_command<P: AsRef<Path>>(id: &str, dir: P, options: &CreateOptions) -> Command {
     let mut command = Command::new(get_runtime_path());
     command
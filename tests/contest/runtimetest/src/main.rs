@@ -53,6 +53,8 @@ fn main() {
         "process_oom_score_adj" => tests::validate_process_oom_score_adj(&spec),
         "fd_control" => tests::validate_fd_control(&spec),
         "rootfs_propagation" => tests::validate_rootfs_propagation(&spec),
+        "uid_mappings" => tests::validate_uid_mappings(&spec),
+        "process_capabilities" => tests::validate_process_capabilities(&spec),
         _ => eprintln!("error due to unexpected execute test name: {execute_test}"),
     }
 }
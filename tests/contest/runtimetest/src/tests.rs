@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, read_dir, File};
@@ -6,6 +7,7 @@ use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::path::Path;
 
 use anyhow::{bail, Result};
+use caps::CapSet;
 use nix::errno::Errno;
 use nix::libc;
 use nix::mount::{mount, MsFlags};
@@ -964,3 +966,75 @@ pub fn validate_rootfs_propagation(spec: &Spec) {
         }
     }
 }
+
+pub fn validate_uid_mappings(spec: &Spec) {
+    let linux = spec.linux().as_ref().unwrap();
+    let expected = [
+        ("/proc/self/uid_map", linux.uid_mappings()),
+        ("/proc/self/gid_map", linux.gid_mappings()),
+    ];
+
+    for (path, mappings) in expected {
+        let Some(mappings) = mappings else {
+            continue;
+        };
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => return eprintln!("error due to fail to read {path}, error: {e}"),
+        };
+        // each line is "container_id host_id size", aligned with spaces
+        let actual: Vec<Vec<u32>> = content
+            .lines()
+            .map(|line| {
+                line.split_whitespace()
+                    .filter_map(|n| n.parse().ok())
+                    .collect()
+            })
+            .collect();
+        let expected: Vec<Vec<u32>> = mappings
+            .iter()
+            .map(|m| vec![m.container_id(), m.host_id(), m.size()])
+            .collect();
+        if actual != expected {
+            eprintln!("error due to {path} want {expected:?}, got {actual:?}");
+        }
+    }
+}
+
+pub fn validate_process_capabilities(spec: &Spec) {
+    let process = spec.process().as_ref().unwrap();
+    let Some(capabilities) = process.capabilities() else {
+        return eprintln!("error due to capabilities not set in spec");
+    };
+
+    let sets = [
+        (CapSet::Bounding, capabilities.bounding()),
+        (CapSet::Effective, capabilities.effective()),
+        (CapSet::Permitted, capabilities.permitted()),
+        (CapSet::Inheritable, capabilities.inheritable()),
+        (CapSet::Ambient, capabilities.ambient()),
+    ];
+    for (set, expected) in sets {
+        let Some(expected) = expected else {
+            continue;
+        };
+        let expected: Result<HashSet<caps::Capability>, _> = expected
+            .iter()
+            .map(|cap| format!("CAP_{cap}").parse())
+            .collect();
+        let expected = match expected {
+            Ok(expected) => expected,
+            Err(e) => {
+                eprintln!("error due to unknown {set:?} capability in spec, error: {e}");
+                continue;
+            }
+        };
+        match caps::read(None, set) {
+            Ok(actual) if actual == expected => {}
+            Ok(actual) => {
+                eprintln!("error due to {set:?} capabilities want {expected:?}, got {actual:?}")
+            }
+            Err(e) => eprintln!("error due to fail to read {set:?} capabilities, error: {e}"),
+        }
+    }
+}
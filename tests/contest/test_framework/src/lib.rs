@@ -1,10 +1,12 @@
 mod conditional_test;
+mod report;
 mod test;
 mod test_group;
 mod test_manager;
 pub mod testable;
 pub use conditional_test::ConditionalTest;
+pub use report::{Report, ReportFormat};
 pub use test::Test;
 pub use test_group::TestGroup;
 pub use test_manager::TestManager;
-pub use testable::{TestResult, Testable, TestableGroup};
+pub use testable::{timed, TestResult, TestRun, Testable, TestableGroup};
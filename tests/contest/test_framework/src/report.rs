@@ -0,0 +1,298 @@
+//! Reports of test runs in formats CI dashboards understand
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+
+use crate::testable::{TestResult, TestRun};
+
+/// Format of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Junit,
+    Tap,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "junit" => Ok(Self::Junit),
+            "tap" => Ok(Self::Tap),
+            "json" => Ok(Self::Json),
+            _ => bail!("unknown report format {s}, expected junit, tap or json"),
+        }
+    }
+}
+
+/// Results of the test groups which were run, in the order they finished
+#[derive(Default)]
+pub struct Report {
+    groups: Vec<(String, Vec<TestRun>)>,
+}
+
+impl Report {
+    /// add the results of a test group
+    pub fn add_group(&mut self, name: &str, results: Vec<TestRun>) {
+        self.groups.push((name.to_owned(), results));
+    }
+
+    /// write the report to a file
+    pub fn write(&self, format: ReportFormat, path: &Path) -> Result<()> {
+        let content = match format {
+            ReportFormat::Junit => self.junit(),
+            ReportFormat::Tap => self.tap(),
+            ReportFormat::Json => self.json()?,
+        };
+        fs::write(path, content).with_context(|| format!("failed to write report to {path:?}"))
+    }
+
+    fn tests(&self) -> impl Iterator<Item = (&str, &str, &TestResult, Duration)> {
+        self.groups.iter().flat_map(|(group, results)| {
+            results
+                .iter()
+                .map(move |(name, result, duration)| (group.as_str(), *name, result, *duration))
+        })
+    }
+
+    fn junit(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let all: Vec<&TestRun> = self.groups.iter().flat_map(|(_, r)| r).collect();
+        let _ = writeln!(out, "<testsuites name=\"contest\" {}>", summary(&all));
+        for (group, results) in &self.groups {
+            let _ = writeln!(
+                out,
+                "  <testsuite name=\"{}\" {}>",
+                xml_escape(group),
+                summary(&results.iter().collect::<Vec<_>>()),
+            );
+            for (name, result, duration) in results {
+                let _ = write!(
+                    out,
+                    "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    xml_escape(group),
+                    xml_escape(name),
+                    duration.as_secs_f64()
+                );
+                match result {
+                    TestResult::Passed => out.push_str("/>\n"),
+                    TestResult::Skipped => out.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                    TestResult::Failed(err) => {
+                        let _ = write!(
+                            out,
+                            ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                            xml_escape(&err.to_string()),
+                            xml_escape(&format!("{err:#}"))
+                        );
+                    }
+                }
+            }
+            out.push_str("  </testsuite>\n");
+        }
+        out.push_str("</testsuites>\n");
+        out
+    }
+
+    fn tap(&self) -> String {
+        let mut out = String::from("TAP version 13\n");
+        let _ = writeln!(out, "1..{}", self.tests().count());
+        for (idx, (group, name, result, duration)) in self.tests().enumerate() {
+            let ms = duration.as_millis();
+            match result {
+                TestResult::Passed => {
+                    let _ = writeln!(out, "ok {} - {group}::{name} # time={ms}ms", idx + 1);
+                }
+                TestResult::Skipped => {
+                    let _ = writeln!(out, "ok {} - {group}::{name} # SKIP", idx + 1);
+                }
+                TestResult::Failed(err) => {
+                    let _ = writeln!(out, "not ok {} - {group}::{name} # time={ms}ms", idx + 1);
+                    out.push_str("  ---\n  message: |\n");
+                    // control characters aren't allowed in YAML either
+                    let message: String = format!("{err:#}")
+                        .chars()
+                        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+                        .collect();
+                    for line in message.lines() {
+                        let _ = writeln!(out, "    {line}");
+                    }
+                    out.push_str("  ...\n");
+                }
+            }
+        }
+        out
+    }
+
+    fn json(&self) -> Result<String> {
+        let groups: Vec<_> = self
+            .groups
+            .iter()
+            .map(|(group, results)| {
+                let tests: Vec<_> = results
+                    .iter()
+                    .map(|(name, result, duration)| {
+                        let (outcome, message) = match result {
+                            TestResult::Passed => ("pass", None),
+                            TestResult::Skipped => ("skip", None),
+                            TestResult::Failed(err) => ("fail", Some(format!("{err:#}"))),
+                        };
+                        json!({
+                            "name": name,
+                            "outcome": outcome,
+                            "duration": duration.as_secs_f64(),
+                            "message": message,
+                        })
+                    })
+                    .collect();
+                json!({ "name": group, "tests": tests })
+            })
+            .collect();
+
+        Ok(serde_json::to_string_pretty(&json!({ "groups": groups }))?)
+    }
+}
+
+/// Attributes of a JUnit test suite counting its tests
+fn summary(results: &[&TestRun]) -> String {
+    let count = |f: fn(&TestResult) -> bool| results.iter().filter(|(_, r, _)| f(r)).count();
+    format!(
+        "tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+        results.len(),
+        count(|r| matches!(r, TestResult::Failed(_))),
+        count(|r| matches!(r, TestResult::Skipped)),
+        results
+            .iter()
+            .map(|(_, _, duration)| duration.as_secs_f64())
+            .sum::<f64>(),
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // control characters aren't allowed in XML 1.0
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn report() -> Report {
+        let failure = anyhow!("exit code 1\u{7}\nsecond line").context("run \"runtime\"");
+        let mut report = Report::default();
+        report.add_group(
+            "basic & <more>",
+            vec![
+                ("passes", TestResult::Passed, Duration::from_millis(1500)),
+                (
+                    "fails",
+                    TestResult::Failed(failure),
+                    Duration::from_millis(250),
+                ),
+                ("skips", TestResult::Skipped, Duration::ZERO),
+            ],
+        );
+        report
+    }
+
+    #[test]
+    fn test_junit() {
+        assert_eq!(
+            report().junit(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="contest" tests="3" failures="1" skipped="1" time="1.750">
+  <testsuite name="basic &amp; &lt;more&gt;" tests="3" failures="1" skipped="1" time="1.750">
+    <testcase classname="basic &amp; &lt;more&gt;" name="passes" time="1.500"/>
+    <testcase classname="basic &amp; &lt;more&gt;" name="fails" time="0.250">
+      <failure message="run &quot;runtime&quot;">run &quot;runtime&quot;: exit code 1
+second line</failure>
+    </testcase>
+    <testcase classname="basic &amp; &lt;more&gt;" name="skips" time="0.000">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn test_tap() {
+        assert_eq!(
+            report().tap(),
+            r#"TAP version 13
+1..3
+ok 1 - basic & <more>::passes # time=1500ms
+not ok 2 - basic & <more>::fails # time=250ms
+  ---
+  message: |
+    run "runtime": exit code 1
+    second line
+  ...
+ok 3 - basic & <more>::skips # SKIP
+"#
+        );
+    }
+
+    #[test]
+    fn test_json() -> Result<()> {
+        assert_eq!(
+            report().json()?,
+            r#"{
+  "groups": [
+    {
+      "name": "basic & <more>",
+      "tests": [
+        {
+          "duration": 1.5,
+          "message": null,
+          "name": "passes",
+          "outcome": "pass"
+        },
+        {
+          "duration": 0.25,
+          "message": "run \"runtime\": exit code 1\u0007\nsecond line",
+          "name": "fails",
+          "outcome": "fail"
+        },
+        {
+          "duration": 0.0,
+          "message": null,
+          "name": "skips",
+          "outcome": "skip"
+        }
+      ]
+    }
+  ]
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(
+            xml_escape("<a href='x'>\"&\"</a>\u{1b}[0m\tend\r\n"),
+            "&lt;a href=&apos;x&apos;&gt;&quot;&amp;&quot;&lt;/a&gt;[0m\tend\n"
+        );
+    }
+}
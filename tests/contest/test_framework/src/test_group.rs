@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 
 use crossbeam::thread;
 
-use crate::testable::{TestResult, Testable, TestableGroup};
+use crate::testable::{timed, TestResult, TestRun, Testable, TestableGroup};
 
 /// Stores tests belonging to a group
 pub struct TestGroup {
@@ -51,19 +51,13 @@ impl TestableGroup for TestGroup {
     }
 
     /// run all the test from the test group
-    fn run_all(&self) -> Vec<(&'static str, TestResult)> {
+    fn run_all(&self) -> Vec<TestRun> {
         let mut ret = Vec::with_capacity(self.tests.len());
         if self.parallel {
             thread::scope(|s| {
                 let mut collector = Vec::with_capacity(self.tests.len());
                 for (_, t) in self.tests.iter() {
-                    let _t = s.spawn(move |_| {
-                        if t.can_run() {
-                            (t.get_name(), t.run())
-                        } else {
-                            (t.get_name(), TestResult::Skipped)
-                        }
-                    });
+                    let _t = s.spawn(move |_| run_test(t.as_ref()));
                     collector.push(_t);
                 }
                 for handle in collector {
@@ -73,18 +67,14 @@ impl TestableGroup for TestGroup {
             .unwrap();
         } else {
             for (_, t) in self.tests.iter() {
-                ret.push(if t.can_run() {
-                    (t.get_name(), t.run())
-                } else {
-                    (t.get_name(), TestResult::Skipped)
-                });
+                ret.push(run_test(t.as_ref()));
             }
         }
         ret
     }
 
     /// run selected test from the group
-    fn run_selected(&self, selected: &[&str]) -> Vec<(&'static str, TestResult)> {
+    fn run_selected(&self, selected: &[&str]) -> Vec<TestRun> {
         let selected_tests = self
             .tests
             .iter()
@@ -94,13 +84,7 @@ impl TestableGroup for TestGroup {
             thread::scope(|s| {
                 let mut collector = Vec::with_capacity(selected.len());
                 for (_, t) in selected_tests {
-                    let _t = s.spawn(move |_| {
-                        if t.can_run() {
-                            (t.get_name(), t.run())
-                        } else {
-                            (t.get_name(), TestResult::Skipped)
-                        }
-                    });
+                    let _t = s.spawn(move |_| run_test(t.as_ref()));
                     collector.push(_t);
                 }
                 for handle in collector {
@@ -110,13 +94,20 @@ impl TestableGroup for TestGroup {
             .unwrap();
         } else {
             for (_, t) in selected_tests {
-                ret.push(if t.can_run() {
-                    (t.get_name(), t.run())
-                } else {
-                    (t.get_name(), TestResult::Skipped)
-                });
+                ret.push(run_test(t.as_ref()));
             }
         }
         ret
     }
 }
+
+/// Runs a test if it can be run, otherwise it is skipped
+fn run_test(t: &(dyn Testable + Sync + Send)) -> TestRun {
+    timed(t.get_name(), || {
+        if t.can_run() {
+            t.run()
+        } else {
+            TestResult::Skipped
+        }
+    })
+}
@@ -4,7 +4,8 @@ use std::collections::BTreeMap;
 use anyhow::Result;
 use crossbeam::thread;
 
-use crate::testable::{TestResult, TestableGroup};
+use crate::report::Report;
+use crate::testable::{TestResult, TestRun, TestableGroup};
 
 type TestableGroupType = dyn TestableGroup + Sync + Send;
 
@@ -40,10 +41,10 @@ impl TestManager {
 
     /// Prints the given test results, usually used to print
     /// results of a test group
-    fn print_test_result(&self, name: &str, res: &[(&'static str, TestResult)]) {
+    fn print_test_result(&self, name: &str, res: &[TestRun]) {
         println!("# Start group {name}");
         let len = res.len();
-        for (idx, (name, res)) in res.iter().enumerate() {
+        for (idx, (name, res, _)) in res.iter().enumerate() {
             print!("{} / {} : {} : ", idx + 1, len, name);
             match res {
                 TestResult::Passed => {
@@ -59,8 +60,16 @@ impl TestManager {
         }
         println!("# End group {name}\n");
     }
+
+    /// Prints the results of a test group and adds them to the report
+    pub fn record(&self, report: &mut Report, name: &str, res: Vec<TestRun>) {
+        self.print_test_result(name, &res);
+        report.add_group(name, res);
+    }
+
     /// Run all tests from all tests group
-    pub fn run_all(&self) {
+    pub fn run_all(&self) -> Report {
+        let mut report = Report::default();
         thread::scope(|s| {
             let mut collector = Vec::with_capacity(self.test_groups.len());
             for (name, tg) in &self.test_groups {
@@ -71,7 +80,7 @@ impl TestManager {
                 collector.push((name, r));
             }
             for (name, handle) in collector {
-                self.print_test_result(name, &handle.join().unwrap());
+                self.record(&mut report, name, handle.join().unwrap());
             }
         })
         .unwrap();
@@ -79,18 +88,17 @@ impl TestManager {
             if tg.parallel() {
                 continue;
             }
-            self.print_test_result(name, &tg.run_all())
+            self.record(&mut report, name, tg.run_all());
         }
 
-        for cleaner in &self.cleanup {
-            if let Err(e) = cleaner() {
-                print!("Failed to cleanup: {e}");
-            }
-        }
+        self.run_cleanup();
+
+        report
     }
 
     /// Run only selected tests
-    pub fn run_selected(&self, tests: Vec<(&str, Option<Vec<&str>>)>) {
+    pub fn run_selected(&self, tests: Vec<(&str, Option<Vec<&str>>)>) -> Report {
+        let mut report = Report::default();
         thread::scope(|s| {
             let mut collector = Vec::with_capacity(tests.len());
             for (test_group_name, tests) in &tests {
@@ -108,7 +116,7 @@ impl TestManager {
                 }
             }
             for (name, handle) in collector {
-                self.print_test_result(name, &handle.join().unwrap());
+                self.record(&mut report, name, handle.join().unwrap());
             }
         })
         .unwrap();
@@ -117,18 +125,33 @@ impl TestManager {
                 if tg.parallel() {
                     continue;
                 }
-                self.print_test_result(
-                    test_group_name,
-                    &match tests {
-                        None => tg.run_all(),
-                        Some(tests) => tg.run_selected(tests),
-                    },
-                );
+                let res = match tests {
+                    None => tg.run_all(),
+                    Some(tests) => tg.run_selected(tests),
+                };
+                self.record(&mut report, test_group_name, res);
             } else {
                 // We've already printed errors for not finding tests
             }
         }
 
+        self.run_cleanup();
+
+        report
+    }
+
+    /// Run the selected tests of a single test group, or all of them if none
+    /// are selected. Returns None if there is no such group.
+    pub fn run_group(&self, name: &str, tests: Option<&[&str]>) -> Option<Vec<TestRun>> {
+        let tg = self.test_groups.get(name)?;
+        Some(match tests {
+            None => tg.run_all(),
+            Some(tests) => tg.run_selected(tests),
+        })
+    }
+
+    /// Run the cleanups, which is done after the tests by run_all and run_selected
+    pub fn run_cleanup(&self) {
         for cleaner in &self.cleanup {
             if let Err(e) = cleaner() {
                 print!("Failed to cleanup: {e}");
@@ -1,5 +1,6 @@
 //! Contains Basic setup for testing, testable trait and its result type
 use std::fmt::Debug;
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Error, Result};
 
@@ -24,6 +25,16 @@ impl<T> From<Result<T>> for TestResult {
     }
 }
 
+/// Name and result of a test which was run, and how long it took
+pub type TestRun = (&'static str, TestResult, Duration);
+
+/// Runs a test, measuring how long it takes
+pub fn timed(name: &'static str, test: impl FnOnce() -> TestResult) -> TestRun {
+    let start = Instant::now();
+    let result = test();
+    (name, result, start.elapsed())
+}
+
 /// This trait indicates that something can be run as a test, or is 'testable'
 /// This forms the basis of the framework, as all places where tests are done,
 /// expect structs which implement this
@@ -40,8 +51,8 @@ pub trait Testable {
 pub trait TestableGroup {
     fn get_name(&self) -> &'static str;
     fn parallel(&self) -> bool;
-    fn run_all(&self) -> Vec<(&'static str, TestResult)>;
-    fn run_selected(&self, selected: &[&str]) -> Vec<(&'static str, TestResult)>;
+    fn run_all(&self) -> Vec<TestRun>;
+    fn run_selected(&self, selected: &[&str]) -> Vec<TestRun>;
 }
 
 #[macro_export]